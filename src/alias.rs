@@ -135,7 +135,7 @@ where
 /// Split command line string into words.
 ///
 /// Single- and double-quoted substrings are preserved.
-fn split_command_line(line: &str) -> Result<Vec<String>, String> {
+pub(crate) fn split_command_line(line: &str) -> Result<Vec<String>, String> {
     let mut argv = Vec::new();
     let mut quote: char = '\0';
     let mut skip_spaces = true;
@@ -5,7 +5,7 @@
 use bstr::ByteSlice;
 use clap::Arg;
 
-use crate::branchloc::BranchLocator;
+use crate::{branchloc::BranchLocator, stack::DependencyPolicy};
 
 /// The `--branch`/`-b` option for selecting an alternative branch.
 pub(crate) fn branch_arg() -> Arg {
@@ -66,6 +66,55 @@ pub(crate) fn push_conflicts_arg() -> clap::Arg {
         .action(clap::ArgAction::Set)
 }
 
+/// The `--format` option selecting human or machine-readable output.
+pub(crate) fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .help("Output format: \"text\" or \"json\"")
+        .long_help(
+            "Output format: either \"text\" or \"json\".\n\
+             \n\
+             The default \"text\" format is meant for human consumption. The \"json\" \
+             format emits a single JSON object with a stable schema that is suitable \
+             for consumption by other tools. Display options only affect the \"text\" \
+             format.",
+        )
+        .hide_possible_values(true)
+        .value_name("format")
+        .value_parser(["text", "json"])
+        .default_value("text")
+}
+
+/// The `--deps` option determining how patch dependencies are handled.
+pub(crate) fn deps_arg() -> Arg {
+    Arg::new("deps")
+        .long("deps")
+        .help("\"refuse\", \"pull\", or \"ignore\" patch dependencies")
+        .long_help(
+            "Either \"refuse\", \"pull\", or \"ignore\" patch dependencies.\n\
+             \n\
+             Using `--deps=refuse` refuses to apply a patch unless all the patches it \
+             depends on are applied below it. This is the default behavior and also \
+             corresponds to the \"stgit.deps.policy\" variable being set to \
+             \"refuse\".\n\
+             \n\
+             Using `--deps=pull` pulls the dependencies of the affected patches into \
+             the operation such that they are applied below the patches that depend \
+             on them. Likewise, patches that depend on the affected patches are moved \
+             above them.\n\
+             \n\
+             Using `--deps=ignore` disregards patch dependencies.\n\
+             \n\
+             Dependencies are declared with `stg deps`. Dependencies between applied \
+             patches with overlapping changes are also computed when \
+             \"stgit.deps.hunks\" is set to \"true\".",
+        )
+        .hide_possible_values(true)
+        .value_name("policy")
+        .value_parser(["refuse", "pull", "ignore"])
+        .num_args(1)
+}
+
 pub(crate) fn committer_date_is_author_date_arg() -> clap::Arg {
     Arg::new("committer-date-is-author-date")
         .long("committer-date-is-author-date")
@@ -167,3 +216,20 @@ pub(crate) fn resolve_allow_push_conflicts(
         .map(|s| s == "allow")
         .unwrap_or_else(|| config.boolean("stgit.push.allow-conflicts").unwrap_or(true))
 }
+
+/// Determine the patch dependency policy from the `--deps` option or the config.
+pub(crate) fn resolve_dependency_policy(
+    config: &gix::config::Snapshot,
+    matches: &clap::ArgMatches,
+) -> anyhow::Result<DependencyPolicy> {
+    if let Some(policy) = get_one_str(matches, "deps") {
+        policy.parse()
+    } else if let Some(value) = config.string("stgit.deps.policy") {
+        value
+            .to_str()
+            .map_err(|_| anyhow::anyhow!("non-UTF-8 `stgit.deps.policy`"))?
+            .parse()
+    } else {
+        Ok(DependencyPolicy::Refuse)
+    }
+}
@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg abort` implementation.
+
+use anyhow::{anyhow, Result};
+use clap::ArgMatches;
+
+use super::r#continue::restore_autostash;
+use crate::{
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    stack::{InitializationPolicy, Stack, StackState},
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "abort",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Abort an operation interrupted by conflicts")
+        .long_about(
+            "Abort a 'stg push', 'stg rebase', or 'stg pull' operation that was \
+             interrupted by merge conflicts.\n\
+             \n\
+             The stack is reset to its state from before the operation started and \
+             any changes in the index and worktree, including conflicts, are \
+             discarded. If the operation stashed changes with '--autostash', the \
+             stash is applied after the stack is reset.\n\
+             \n\
+             See also 'stg continue' and 'stg skip'.",
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let pending = stack
+        .pending_operation()
+        .cloned()
+        .ok_or_else(|| anyhow!("no interrupted operation is pending"))?;
+
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .discard_changes(true)
+        .allow_bad_head(true)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            let commit = trans.repo().find_commit(pending.orig_state)?;
+            let orig_state = StackState::from_commit(trans.repo(), &commit)?;
+            trans.reset_to_state(orig_state)
+        })
+        .execute("abort")?;
+
+    restore_autostash(&repo, &pending)
+}
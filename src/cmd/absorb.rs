@@ -0,0 +1,244 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg absorb` implementation.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{anyhow, Result};
+use bstr::{BString, ByteSlice};
+use clap::ArgMatches;
+
+use crate::{
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::PatchName,
+    print_info_message,
+    stack::{InitializationPolicy, LineOwners, Stack, StackAccess, StackStateAccess},
+    stupid::{Stupid, StupidContext},
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "absorb",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Absorb local changes into the patches that last modified them")
+        .long_about(
+            "Absorb changes in the index and worktree into the applied patches that \
+             last modified the changed lines.\n\
+             \n\
+             The local changes are split into hunks. Each hunk is attributed to the \
+             applied patch that last modified the hunk's lines. For hunks that only \
+             add lines, the surrounding lines are considered instead. Each patch is \
+             then refreshed with the hunks attributed to it, as if by 'stg refresh \
+             --patch', and the patches above it are updated accordingly. All patches \
+             are updated in a single operation that may be undone with 'stg undo'.\n\
+             \n\
+             Hunks that cannot be attributed to exactly one patch, e.g. because their \
+             lines were last modified by several patches or by commits below the \
+             stack, are left in the worktree. Changes to binary files and to added or \
+             deleted files are also left in the worktree. Changes that are left \
+             behind remain staged if they were staged before.",
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let stupid = repo.stupid();
+
+    repo.check_repository_state()?;
+    stack.check_head_top_mismatch()?;
+    if stack.applied().is_empty() {
+        return Err(super::Error::NoAppliedPatches.into());
+    }
+
+    let statuses = stupid.statuses(None)?;
+    statuses.check_conflicts()?;
+    let changed_paths: Vec<_> = statuses.iter().map(|entry| entry.path()).collect();
+    if changed_paths.is_empty() {
+        return Err(anyhow!("no local changes to absorb"));
+    }
+
+    let head_tree_id = stack.get_branch_head().tree_id()?.detach();
+    let worktree_tree_id = stupid.with_temp_index(|stupid_temp| {
+        stupid_temp.read_tree(head_tree_id)?;
+        stupid_temp.update_index(Some(&changed_paths))?;
+        stupid_temp.write_tree()
+    })?;
+
+    let mut owners: LineOwners<usize> = LineOwners::default();
+    for (pos, patchname) in stack.applied().iter().enumerate() {
+        let commit = stack.get_patch_commit(patchname);
+        let parent_tree_id = commit.get_parent_commit()?.tree_id()?.detach();
+        for file_diff in
+            stupid.diff_tree_file_diffs(parent_tree_id, commit.tree_id()?.detach(), 0)?
+        {
+            owners.record(&file_diff, &pos);
+        }
+    }
+
+    // Group the hunks by owning patch, keyed by the patch's position in the stack.
+    let mut owned_diffs: BTreeMap<usize, BString> = BTreeMap::new();
+    let mut num_hunks = 0;
+    for file_diff in stupid.diff_tree_file_diffs(head_tree_id, worktree_tree_id, 0)? {
+        num_hunks += file_diff.hunks.len().max(1);
+        if file_diff.is_binary || file_diff.old_path.is_none() || file_diff.new_path.is_none() {
+            continue;
+        }
+        let mut owned_hunks: BTreeMap<usize, Vec<_>> = BTreeMap::new();
+        for hunk in &file_diff.hunks {
+            if let Some(pos) = owners.sole_hunk_owner(file_diff.path(), hunk) {
+                owned_hunks.entry(pos).or_default().push(hunk);
+            }
+        }
+        for (pos, hunks) in owned_hunks {
+            let diff = owned_diffs.entry(pos).or_default();
+            diff.extend_from_slice(&file_diff.to_diff_with_hunks(hunks));
+        }
+    }
+
+    let Some(&lowest_pos) = owned_diffs.keys().next() else {
+        print_info_message(
+            matches,
+            "No changes could be attributed to exactly one applied patch",
+        );
+        return Ok(());
+    };
+
+    // Rebuild the patches from the lowest owning patch to the top of the stack,
+    // refreshing each owning patch with its hunks.
+    let committer = repo.get_committer()?;
+    let mut absorbed_hunks = 0;
+    let mut absorbed_paths: BTreeSet<BString> = BTreeSet::new();
+    let mut updated: Vec<(PatchName, gix::ObjectId)> = Vec::new();
+    let first_commit = stack.get_patch_commit(&stack.applied()[lowest_pos]);
+    let mut parent_id = first_commit.get_parent_commit()?.id;
+    let mut parent_tree_id = first_commit.get_parent_commit()?.tree_id()?.detach();
+
+    for (pos, patchname) in stack.applied().iter().enumerate().skip(lowest_pos) {
+        let commit = stack.get_patch_commit(patchname);
+        let old_parent = commit.get_parent_commit()?;
+        let old_parent_tree_id = old_parent.tree_id()?.detach();
+        let mut tree_id = commit.tree_id()?.detach();
+
+        if old_parent_tree_id != parent_tree_id {
+            tree_id = if let Some(tree_id) =
+                repo.merge_trees_clean(old_parent_tree_id, parent_tree_id, tree_id)?
+            {
+                tree_id
+            } else {
+                apply_treediff_onto(&stupid, parent_tree_id, old_parent_tree_id, tree_id)?
+                    .ok_or_else(|| {
+                        anyhow!("absorbed changes prevent `{patchname}` from applying cleanly")
+                    })?
+            };
+        }
+
+        if let Some(diff) = owned_diffs.get(&pos) {
+            let absorb_tree_id = stupid.with_temp_index(|stupid_temp| {
+                stupid_temp.read_tree(head_tree_id)?;
+                stupid_temp.apply_unidiff_zero_to_index(diff.as_bstr())?;
+                stupid_temp.write_tree()
+            })?;
+            if let Some(new_tree_id) =
+                apply_treediff_onto(&stupid, tree_id, head_tree_id, absorb_tree_id)?
+            {
+                tree_id = new_tree_id;
+                for file_diff in stupid.diff_tree_file_diffs(head_tree_id, absorb_tree_id, 0)? {
+                    absorbed_hunks += file_diff.hunks.len();
+                    absorbed_paths.insert(file_diff.path().to_owned());
+                }
+            }
+        }
+
+        let commit_id = if tree_id == commit.tree_id()?.detach() && parent_id == old_parent.id {
+            commit.id
+        } else {
+            repo.commit_ex(
+                &commit.author_strict()?,
+                committer,
+                &commit.message_ex(),
+                tree_id,
+                [parent_id],
+            )?
+        };
+        if commit_id != commit.id {
+            updated.push((patchname.clone(), commit_id));
+        }
+        parent_id = commit_id;
+        parent_tree_id = tree_id;
+    }
+
+    if absorbed_hunks == 0 {
+        print_info_message(matches, "No changes could be absorbed without conflicts");
+        return Ok(());
+    }
+
+    // The absorbed hunks are folded into the index entries of the absorbed paths such
+    // that changes that were staged but not absorbed remain staged.
+    let index_tree_id = stupid.write_tree()?;
+    let new_index_tree_id = if let Some(tree_id) =
+        repo.merge_trees_clean(head_tree_id, index_tree_id, parent_tree_id)?
+    {
+        tree_id
+    } else if let Some(tree_id) =
+        apply_treediff_onto(&stupid, index_tree_id, head_tree_id, parent_tree_id)?
+    {
+        tree_id
+    } else {
+        parent_tree_id
+    };
+
+    stack
+        .setup_transaction()
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            for (patchname, commit_id) in &updated {
+                trans.update_patch(patchname, *commit_id)?;
+            }
+            Ok(())
+        })
+        .execute("absorb")?;
+
+    stupid.reset_paths(
+        new_index_tree_id,
+        absorbed_paths
+            .iter()
+            .map(|path| path.to_os_str().expect("paths on Windows must be utf8")),
+    )?;
+
+    if absorbed_hunks < num_hunks {
+        let num_left = num_hunks - absorbed_hunks;
+        let plural = if num_left == 1 { "" } else { "s" };
+        print_info_message(
+            matches,
+            &format!("{num_left} hunk{plural} left in the worktree"),
+        );
+    }
+
+    Ok(())
+}
+
+/// Apply the difference between two trees onto another tree.
+///
+/// Returns the resulting tree or `None` if the difference does not apply cleanly.
+pub(super) fn apply_treediff_onto(
+    stupid: &StupidContext,
+    onto_tree_id: gix::ObjectId,
+    base_tree_id: gix::ObjectId,
+    theirs_tree_id: gix::ObjectId,
+) -> Result<Option<gix::ObjectId>> {
+    stupid.with_temp_index(|stupid_temp| {
+        stupid_temp.read_tree(onto_tree_id)?;
+        if stupid_temp.apply_treediff_to_index(base_tree_id, theirs_tree_id, true)? {
+            stupid_temp.write_tree().map(Some)
+        } else {
+            Ok(None)
+        }
+    })
+}
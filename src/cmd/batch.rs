@@ -0,0 +1,405 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg batch` implementation.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Arg, ArgMatches};
+
+use crate::{
+    alias::split_command_line,
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::{PatchLocator, PatchName},
+    stack::{InitializationPolicy, Stack, StackStateAccess, StackTransaction},
+    stupid::Stupid,
+    wrap::Message,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "batch",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Run a script of stack operations atomically")
+        .long_about(
+            "Run a script of stack operations as a single atomic stack transaction.\n\
+             \n\
+             The script is read from <file>, or from standard input if <file> is \
+             omitted or is \"-\". Each line of the script contains one operation. \
+             Empty lines and lines starting with '#' are ignored. Words may be quoted \
+             using single or double quotes.\n\
+             \n\
+             The whole script is checked for syntax errors before any operation is \
+             performed. The operations are then performed in order within a single \
+             stack transaction. If any operation fails, all changes are rolled back \
+             and the stack, index, and work tree are left as they were. Otherwise, a \
+             single entry is recorded in the stack log such that the entire batch may \
+             be undone with one 'stg undo'. Pushing a patch that would result in merge \
+             conflicts causes the batch to fail.\n\
+             \n\
+             The following operations are supported:\n\
+             \n    \
+             new <patch> [-m <message>]\n    \
+             refresh\n    \
+             push [<patch>...]\n    \
+             pop [<patch>...]\n    \
+             goto <patch>\n    \
+             float <patch>...\n    \
+             sink <patch>...\n    \
+             hide <patch>...\n    \
+             unhide <patch>...\n    \
+             delete <patch>...\n    \
+             rename <old> <new>\n\
+             \n\
+             The 'new' operation creates an empty patch on top of the stack. The \
+             message defaults to the patch name. The 'refresh' operation refreshes the \
+             topmost patch with the changes to tracked files in the work tree and \
+             index. Without arguments, 'push' pushes the next unapplied patch and \
+             'pop' pops the topmost patch. Patches may be referred to by any patch \
+             locator, resolved against the stack as modified by the preceding \
+             operations.",
+        )
+        .arg(
+            Arg::new("file")
+                .help("Script file to run")
+                .value_name("file")
+                .value_parser(clap::value_parser!(PathBuf))
+                .value_hint(clap::ValueHint::FilePath),
+        )
+}
+
+/// Make the parser for a single line of a batch script.
+fn make_line_parser() -> clap::Command {
+    let patches_arg = |required: bool| {
+        Arg::new("patches")
+            .value_name("patch")
+            .num_args(1..)
+            .required(required)
+            .allow_hyphen_values(true)
+            .value_parser(clap::value_parser!(PatchLocator))
+    };
+    let patch_arg = |id: &'static str| {
+        Arg::new(id)
+            .value_name(id)
+            .required(true)
+            .allow_hyphen_values(true)
+            .value_parser(clap::value_parser!(PatchLocator))
+    };
+
+    clap::Command::new("batch")
+        .no_binary_name(true)
+        .subcommand_required(true)
+        .disable_help_flag(true)
+        .disable_help_subcommand(true)
+        .subcommand(
+            clap::Command::new("new")
+                .arg(
+                    Arg::new("patchname")
+                        .value_name("patch")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PatchName)),
+                )
+                .arg(
+                    Arg::new("message")
+                        .long("message")
+                        .short('m')
+                        .value_name("message")
+                        .allow_hyphen_values(true),
+                ),
+        )
+        .subcommand(clap::Command::new("refresh"))
+        .subcommand(clap::Command::new("push").arg(patches_arg(false)))
+        .subcommand(clap::Command::new("pop").arg(patches_arg(false)))
+        .subcommand(clap::Command::new("goto").arg(patch_arg("patch")))
+        .subcommand(clap::Command::new("float").arg(patches_arg(true)))
+        .subcommand(clap::Command::new("sink").arg(patches_arg(true)))
+        .subcommand(clap::Command::new("hide").arg(patches_arg(true)))
+        .subcommand(clap::Command::new("unhide").arg(patches_arg(true)))
+        .subcommand(clap::Command::new("delete").arg(patches_arg(true)))
+        .subcommand(
+            clap::Command::new("rename").arg(patch_arg("old")).arg(
+                Arg::new("new")
+                    .value_name("new")
+                    .required(true)
+                    .value_parser(clap::value_parser!(PatchName)),
+            ),
+        )
+}
+
+/// A parsed batch script operation.
+struct Operation {
+    line_number: usize,
+    name: String,
+    matches: ArgMatches,
+}
+
+/// Parse script into operations, reporting the first syntax error.
+fn parse_script(script: &str) -> Result<Vec<Operation>> {
+    let parser = make_line_parser();
+    let mut operations = Vec::new();
+    for (i, line) in script.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words = split_command_line(line).map_err(|e| anyhow!("line {line_number}: {e}"))?;
+        let mut matches = parser.clone().try_get_matches_from(words).map_err(|e| {
+            let rendered = e.render().to_string();
+            let msg = rendered.lines().next().unwrap_or_default();
+            anyhow!(
+                "line {line_number}: {}",
+                msg.strip_prefix("error: ").unwrap_or(msg)
+            )
+        })?;
+        let (name, sub_matches) = matches.remove_subcommand().expect("subcommand is required");
+        operations.push(Operation {
+            line_number,
+            name,
+            matches: sub_matches,
+        });
+    }
+    Ok(operations)
+}
+
+fn read_script(path: Option<&Path>) -> Result<String> {
+    match path {
+        Some(path) if path != Path::new("-") => std::fs::read_to_string(path)
+            .with_context(|| format!("reading batch script `{}`", path.display())),
+        _ => {
+            use std::io::Read;
+            let mut contents = String::new();
+            std::io::stdin()
+                .read_to_string(&mut contents)
+                .context("reading batch script from stdin")?;
+            Ok(contents)
+        }
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::AllowUninitialized)?;
+    let stupid = repo.stupid();
+
+    let script = read_script(matches.get_one::<PathBuf>("file").map(PathBuf::as_path))?;
+    let operations = parse_script(&script)?;
+    if operations.is_empty() {
+        return Ok(());
+    }
+
+    repo.check_repository_state()?;
+    let statuses = stupid.statuses(None)?;
+    statuses.check_conflicts()?;
+    stack.check_head_top_mismatch()?;
+
+    // Like `stg refresh`, changes to be refreshed are staged in the index up front.
+    let refresh_tree_id = if operations.iter().any(|op| op.name == "refresh") {
+        let refresh_paths =
+            super::refresh::determine_refresh_paths(&stupid, &statuses, None, false)?;
+        Some(super::refresh::write_tree(&stack, &refresh_paths, false)?)
+    } else {
+        statuses.check_index_and_worktree_clean()?;
+        None
+    };
+
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .allow_push_conflicts(false)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            for op in &operations {
+                perform(trans, &repo, op, refresh_tree_id).map_err(|e| {
+                    // The error is recreated such that transaction halts, e.g. due to
+                    // push conflicts, also cause the whole batch to be rolled back.
+                    anyhow!(
+                        "line {}: {e:#};\n\
+                         batch aborted (all changes rolled back)",
+                        op.line_number
+                    )
+                })?;
+            }
+            Ok(())
+        })
+        .execute("batch")?;
+
+    Ok(())
+}
+
+/// Perform a single batch operation within the transaction.
+fn perform(
+    trans: &mut StackTransaction,
+    repo: &gix::Repository,
+    op: &Operation,
+    refresh_tree_id: Option<gix::ObjectId>,
+) -> Result<()> {
+    let resolve = |trans: &StackTransaction, id: &str| -> Result<Vec<PatchName>> {
+        op.matches
+            .get_many::<PatchLocator>(id)
+            .map(|locs| {
+                locs.map(|loc| loc.resolve_name(trans).map_err(anyhow::Error::from))
+                    .collect()
+            })
+            .unwrap_or_else(|| Ok(Vec::new()))
+    };
+
+    match op.name.as_str() {
+        "new" => {
+            let patchname = op
+                .matches
+                .get_one::<PatchName>("patchname")
+                .expect("required argument");
+            if let Some(colliding_patchname) = trans.collides(patchname) {
+                return Err(anyhow!("patch `{colliding_patchname}` already exists"));
+            }
+            let message = op
+                .matches
+                .get_one::<String>("message")
+                .cloned()
+                .unwrap_or_else(|| patchname.to_string());
+            let top = trans.top().clone();
+            let commit_id = repo.commit_ex(
+                repo.get_author()?,
+                repo.get_committer()?,
+                &Message::from(message),
+                top.tree_id()?.detach(),
+                [top.id],
+            )?;
+            trans.new_applied(patchname, commit_id)
+        }
+
+        "refresh" => {
+            if trans.applied().is_empty() {
+                return Err(super::Error::NoAppliedPatches.into());
+            }
+            trans.refresh_top_from_tree(refresh_tree_id.expect("refresh tree is prepared"))
+        }
+
+        "push" => {
+            let patches = resolve(trans, "patches")?;
+            let patches = if patches.is_empty() {
+                vec![trans
+                    .unapplied()
+                    .first()
+                    .cloned()
+                    .ok_or_else(|| anyhow!("no unapplied patches"))?]
+            } else {
+                patches
+            };
+            if let Some(patchname) = patches.iter().find(|pn| trans.is_applied(pn)) {
+                return Err(anyhow!("patch `{patchname}` is already applied"));
+            }
+            trans.push_patches(&patches, false)
+        }
+
+        "pop" => {
+            let patches = resolve(trans, "patches")?;
+            let patches = if patches.is_empty() {
+                vec![trans
+                    .applied()
+                    .last()
+                    .cloned()
+                    .ok_or(super::Error::NoAppliedPatches)?]
+            } else {
+                patches
+            };
+            if let Some(patchname) = patches.iter().find(|pn| !trans.is_applied(pn)) {
+                return Err(anyhow!("patch `{patchname}` is not applied"));
+            }
+            let applied: Vec<PatchName> = trans
+                .applied()
+                .iter()
+                .filter(|pn| !patches.contains(pn))
+                .cloned()
+                .collect();
+            let unapplied: Vec<PatchName> = patches
+                .iter()
+                .chain(trans.unapplied().iter())
+                .cloned()
+                .collect();
+            trans.reorder_patches(Some(&applied), Some(&unapplied), None)
+        }
+
+        "goto" => {
+            let patchname = resolve(trans, "patch")?.remove(0);
+            if let Some(pos) = trans.applied().iter().position(|pn| pn == &patchname) {
+                let applied = trans.applied()[0..=pos].to_vec();
+                let mut unapplied = trans.applied()[pos + 1..].to_vec();
+                unapplied.extend(trans.unapplied().iter().cloned());
+                trans.reorder_patches(Some(&applied), Some(&unapplied), None)
+            } else if let Some(pos) = trans.unapplied().iter().position(|pn| pn == &patchname) {
+                let to_apply = trans.unapplied()[0..=pos].to_vec();
+                trans.push_patches(&to_apply, false)
+            } else {
+                Err(anyhow!("patch `{patchname}` is hidden"))
+            }
+        }
+
+        "float" | "sink" => {
+            let patches = resolve(trans, "patches")?;
+            if let Some(patchname) = patches.iter().find(|pn| trans.is_hidden(pn)) {
+                return Err(anyhow!("patch `{patchname}` is hidden"));
+            }
+            let others = trans
+                .applied()
+                .iter()
+                .filter(|pn| !patches.contains(pn))
+                .cloned();
+            let applied: Vec<PatchName> = if op.name == "float" {
+                others.chain(patches.iter().cloned()).collect()
+            } else {
+                patches.iter().cloned().chain(others).collect()
+            };
+            let unapplied: Vec<PatchName> = trans
+                .unapplied()
+                .iter()
+                .filter(|pn| !patches.contains(pn))
+                .cloned()
+                .collect();
+            trans.reorder_patches(Some(&applied), Some(&unapplied), None)
+        }
+
+        "hide" => {
+            let patches = resolve(trans, "patches")?;
+            if let Some(patchname) = patches.iter().find(|pn| trans.is_hidden(pn)) {
+                return Err(anyhow!("patch `{patchname}` is already hidden"));
+            }
+            trans.hide_patches(&patches)
+        }
+
+        "unhide" => {
+            let patches = resolve(trans, "patches")?;
+            if let Some(patchname) = patches.iter().find(|pn| !trans.is_hidden(pn)) {
+                return Err(anyhow!("patch `{patchname}` is not hidden"));
+            }
+            trans.unhide_patches(&patches)
+        }
+
+        "delete" => {
+            let patches = resolve(trans, "patches")?;
+            let incidental = trans.delete_patches(|pn| patches.contains(pn))?;
+            trans.push_patches(&incidental, false)
+        }
+
+        "rename" => {
+            let old_patchname = resolve(trans, "old")?.remove(0);
+            let new_patchname = op
+                .matches
+                .get_one::<PatchName>("new")
+                .expect("required argument");
+            if let Some(colliding_patchname) = trans.collides(new_patchname) {
+                return Err(anyhow!("patch `{colliding_patchname}` already exists"));
+            }
+            trans.rename_patch(&old_patchname, new_patchname)
+        }
+
+        name => panic!("unhandled batch operation `{name}`"),
+    }
+}
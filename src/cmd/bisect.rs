@@ -0,0 +1,399 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg bisect` implementation.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Arg, ArgMatches};
+
+use super::exec::goto;
+use crate::{
+    ext::RepositoryExtended,
+    patch::{PatchLocator, PatchName},
+    print_info_message,
+    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "bisect",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Find the first bad patch by binary search")
+        .long_about(
+            "Find the applied patch that introduced a regression by binary search \
+             over the applied patches.\n\
+             \n\
+             A bisect session is started with 'stg bisect start', which takes a bad \
+             patch and, optionally, a good patch. The bad patch defaults to the \
+             topmost patch. When no good patch is given, the stack base is assumed to \
+             be good. The stack is then moved, as with 'stg goto', to a patch midway \
+             between the good and bad patches. Once the topmost patch is tested, it is \
+             marked with 'stg bisect good' or 'stg bisect bad' and the stack is moved \
+             to the next patch to test. Patches that cannot be tested may be skipped \
+             with 'stg bisect skip'.\n\
+             \n\
+             Alternatively, 'stg bisect run' runs a shell command to test each patch. \
+             The command's exit code determines whether the topmost patch is good (0), \
+             should be skipped (125), or is bad (1 through 127, except 125). Any other \
+             exit code aborts the run.\n\
+             \n\
+             Once the first bad patch is found, its name is reported and it is made \
+             the topmost patch. Use 'stg bisect reset' to end the session and return \
+             to the original topmost patch.",
+        )
+        .disable_help_subcommand(true)
+        .subcommand_required(true)
+        .subcommand(
+            clap::Command::new("start")
+                .about("Start bisecting the applied patches")
+                .arg(patch_arg("bad").help("Bad patch, defaults to the topmost patch"))
+                .arg(patch_arg("good").help("Good patch, defaults to the stack base")),
+        )
+        .subcommand(
+            clap::Command::new("good")
+                .about("Mark a patch as good")
+                .arg(patch_arg("patch").help("Patch to mark, defaults to the topmost patch")),
+        )
+        .subcommand(
+            clap::Command::new("bad")
+                .about("Mark a patch as bad")
+                .arg(patch_arg("patch").help("Patch to mark, defaults to the topmost patch")),
+        )
+        .subcommand(
+            clap::Command::new("skip")
+                .about("Skip testing a patch")
+                .arg(patch_arg("patch").help("Patch to skip, defaults to the topmost patch")),
+        )
+        .subcommand(
+            clap::Command::new("run")
+                .about("Bisect automatically by running a command")
+                .override_usage(super::make_usage("stg bisect run", &["[--] <command>..."]))
+                .arg(
+                    Arg::new("command")
+                        .help("Shell command to test each patch")
+                        .required(true)
+                        .num_args(1..)
+                        .trailing_var_arg(true)
+                        .allow_hyphen_values(true)
+                        .value_name("command"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("reset")
+                .about("End the bisect session and return to the original topmost patch"),
+        )
+}
+
+fn patch_arg(id: &'static str) -> Arg {
+    Arg::new(id)
+        .value_name(id)
+        .allow_hyphen_values(true)
+        .value_parser(clap::value_parser!(PatchLocator))
+}
+
+/// Bisect session state, persisted between invocations.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BisectState {
+    branch: String,
+    orig_top: PatchName,
+    patches: Vec<PatchName>,
+    good: Option<PatchName>,
+    bad: PatchName,
+    #[serde(default)]
+    skipped: Vec<PatchName>,
+}
+
+/// Outcome of the bisection given the patches marked so far.
+enum Step {
+    Test {
+        patchname: PatchName,
+        remaining: usize,
+    },
+    Found(PatchName),
+    Ambiguous(Vec<PatchName>),
+}
+
+impl BisectState {
+    fn path(repo: &gix::Repository) -> PathBuf {
+        repo.git_dir().join("STGIT_BISECT")
+    }
+
+    fn load(repo: &gix::Repository, stack: &Stack) -> Result<Self> {
+        let path = Self::path(repo);
+        if !path.exists() {
+            return Err(anyhow!(
+                "no bisect in progress; use `stg bisect start` first"
+            ));
+        }
+        let content =
+            std::fs::read(&path).with_context(|| format!("reading `{}`", path.display()))?;
+        let state: Self = serde_json::from_slice(&content)
+            .with_context(|| format!("parsing `{}`", path.display()))?;
+        if state.branch != stack.get_branch_name() {
+            return Err(anyhow!(
+                "bisect in progress on branch `{}`, not `{}`",
+                state.branch,
+                stack.get_branch_name(),
+            ));
+        }
+        Ok(state)
+    }
+
+    fn save(&self, repo: &gix::Repository) -> Result<()> {
+        let path = Self::path(repo);
+        std::fs::write(&path, serde_json::to_vec(self)?)
+            .with_context(|| format!("writing `{}`", path.display()))
+    }
+
+    fn position(&self, patchname: &PatchName) -> Result<usize> {
+        self.patches
+            .iter()
+            .position(|pn| pn == patchname)
+            .ok_or_else(|| anyhow!("patch `{patchname}` is not being bisected"))
+    }
+
+    fn mark_good(&mut self, patchname: &PatchName) -> Result<()> {
+        let pos = self.position(patchname)?;
+        if pos >= self.position(&self.bad)? {
+            return Err(anyhow!(
+                "good patch `{patchname}` is not below bad patch `{}`",
+                self.bad
+            ));
+        }
+        let good_pos = self
+            .good
+            .as_ref()
+            .map(|good| self.position(good))
+            .transpose()?;
+        if good_pos.map_or(true, |good_pos| good_pos < pos) {
+            self.good = Some(patchname.clone());
+        }
+        Ok(())
+    }
+
+    fn mark_bad(&mut self, patchname: &PatchName) -> Result<()> {
+        let pos = self.position(patchname)?;
+        if let Some(good) = self.good.as_ref() {
+            if pos <= self.position(good)? {
+                return Err(anyhow!(
+                    "bad patch `{patchname}` is not above good patch `{good}`"
+                ));
+            }
+        }
+        if pos < self.position(&self.bad)? {
+            self.bad = patchname.clone();
+        }
+        Ok(())
+    }
+
+    fn mark_skip(&mut self, patchname: &PatchName) -> Result<()> {
+        self.position(patchname)?;
+        if !self.skipped.contains(patchname) {
+            self.skipped.push(patchname.clone());
+        }
+        Ok(())
+    }
+
+    /// Determine the next patch to test or the first bad patch.
+    fn next_step(&self) -> Result<Step> {
+        let lo = if let Some(good) = self.good.as_ref() {
+            self.position(good)? + 1
+        } else {
+            0
+        };
+        let hi = self.position(&self.bad)?;
+        let candidates: Vec<usize> = (lo..hi)
+            .filter(|&i| !self.skipped.contains(&self.patches[i]))
+            .collect();
+        if lo == hi {
+            Ok(Step::Found(self.bad.clone()))
+        } else if candidates.is_empty() {
+            Ok(Step::Ambiguous(self.patches[lo..=hi].to_vec()))
+        } else {
+            let mid = lo + (hi - lo - 1) / 2;
+            let i = *candidates
+                .iter()
+                .min_by_key(|&&i| i.abs_diff(mid))
+                .expect("candidates is not empty");
+            Ok(Step::Test {
+                patchname: self.patches[i].clone(),
+                remaining: hi - lo,
+            })
+        }
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let (subname, submatches) = matches.subcommand().expect("subcommand is required");
+
+    match subname {
+        "start" => start(&repo, stack, matches, submatches),
+        "good" | "bad" | "skip" => {
+            let mut state = BisectState::load(&repo, &stack)?;
+            let patchname = get_patch_or_top(&stack, submatches, "patch")?;
+            match subname {
+                "good" => state.mark_good(&patchname)?,
+                "bad" => state.mark_bad(&patchname)?,
+                _ => state.mark_skip(&patchname)?,
+            }
+            state.save(&repo)?;
+            advance(&state, stack, matches)?;
+            Ok(())
+        }
+        "run" => {
+            let state = BisectState::load(&repo, &stack)?;
+            let command = submatches
+                .get_many::<String>("command")
+                .expect("required argument")
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ");
+            bisect_run(&repo, state, stack, &command, matches)
+        }
+        "reset" => {
+            let state = BisectState::load(&repo, &stack)?;
+            if stack.has_patch(&state.orig_top) {
+                goto(stack, &state.orig_top, matches, "bisect reset")?;
+            }
+            std::fs::remove_file(BisectState::path(&repo))?;
+            Ok(())
+        }
+        s => panic!("unhandled bisect subcommand {s}"),
+    }
+}
+
+fn start(
+    repo: &gix::Repository,
+    stack: Stack,
+    matches: &ArgMatches,
+    submatches: &ArgMatches,
+) -> Result<()> {
+    if BisectState::path(repo).exists() {
+        return Err(anyhow!(
+            "bisect already in progress; use `stg bisect reset` first"
+        ));
+    }
+    repo.stupid()
+        .statuses(None)?
+        .check_index_and_worktree_clean()?;
+    stack.check_head_top_mismatch()?;
+    stack.check_no_pending_operation()?;
+
+    let Some(orig_top) = stack.applied().last().cloned() else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+    let bad = get_patch_or_top(&stack, submatches, "bad")?;
+    let mut state = BisectState {
+        branch: stack.get_branch_name().to_string(),
+        orig_top,
+        patches: stack.applied().to_vec(),
+        good: None,
+        bad: bad.clone(),
+        skipped: Vec::new(),
+    };
+    state
+        .position(&bad)
+        .map_err(|_| anyhow!("bad patch `{bad}` is not applied"))?;
+    if let Some(loc) = submatches.get_one::<PatchLocator>("good") {
+        let good = loc.resolve_name(&stack)?;
+        state
+            .position(&good)
+            .map_err(|_| anyhow!("good patch `{good}` is not applied"))?;
+        state.mark_good(&good)?;
+    }
+    state.save(repo)?;
+    advance(&state, stack, matches)?;
+    Ok(())
+}
+
+/// Get the patch given by the argument `id`, or the topmost patch.
+fn get_patch_or_top(stack: &Stack, matches: &ArgMatches, id: &str) -> Result<PatchName> {
+    if let Some(loc) = matches.get_one::<PatchLocator>(id) {
+        Ok(loc.resolve_name(stack)?)
+    } else if let Some(top) = stack.applied().last() {
+        Ok(top.clone())
+    } else {
+        Err(super::Error::NoAppliedPatches.into())
+    }
+}
+
+/// Move the stack to the next patch to test, or report the first bad patch.
+///
+/// Returns the next patch to test, if any.
+fn advance<'repo>(
+    state: &BisectState,
+    stack: Stack<'repo>,
+    matches: &ArgMatches,
+) -> Result<(Stack<'repo>, Option<PatchName>)> {
+    match state.next_step()? {
+        Step::Test {
+            patchname,
+            remaining,
+        } => {
+            let stack = if stack.applied().last() == Some(&patchname) {
+                stack
+            } else {
+                goto(stack, &patchname, matches, "bisect")?
+            };
+            let plural = if remaining == 1 { "" } else { "es" };
+            print_info_message(
+                matches,
+                &format!("Bisecting: {remaining} patch{plural} left to test; now at `{patchname}`"),
+            );
+            Ok((stack, Some(patchname)))
+        }
+        Step::Found(patchname) => {
+            let stack = if stack.applied().last() == Some(&patchname) {
+                stack
+            } else {
+                goto(stack, &patchname, matches, "bisect")?
+            };
+            println!("`{patchname}` is the first bad patch");
+            Ok((stack, None))
+        }
+        Step::Ambiguous(patchnames) => {
+            println!("The first bad patch could be any of:");
+            for patchname in patchnames {
+                println!("{patchname}");
+            }
+            Ok((stack, None))
+        }
+    }
+}
+
+fn bisect_run(
+    repo: &gix::Repository,
+    mut state: BisectState,
+    stack: Stack,
+    command: &str,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let stupid = repo.stupid();
+    let (mut stack, mut next) = advance(&state, stack, matches)?;
+    while let Some(patchname) = next {
+        let status = stupid.user_exec_status(command)?;
+        match status.code() {
+            Some(0) => state.mark_good(&patchname)?,
+            Some(125) => state.mark_skip(&patchname)?,
+            Some(1..=127) => state.mark_bad(&patchname)?,
+            Some(code) => {
+                return Err(anyhow!(
+                    "`{command}` exited with code {code}; bisect run aborted"
+                ))
+            }
+            None => return Err(anyhow!("`{command}` failed; bisect run aborted")),
+        }
+        state.save(repo)?;
+        (stack, next) = advance(&state, stack, matches)?;
+    }
+    Ok(())
+}
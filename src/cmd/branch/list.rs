@@ -9,7 +9,9 @@ use bstr::ByteSlice;
 use termcolor::WriteColor;
 
 use crate::{
+    argset,
     ext::RepositoryExtended,
+    json,
     stack::{InitializationPolicy, Stack},
     wrap::Branch,
 };
@@ -25,6 +27,7 @@ pub(super) fn command() -> clap::Command {
              StGit stacks are prefixed with 's'. Protected branches are prefixed with \
              'p'.",
         )
+        .arg(argset::format_arg())
 }
 
 pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Result<()> {
@@ -48,6 +51,43 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
 
     let config = repo.config_snapshot();
 
+    let get_description = |branchname| {
+        config
+            .string_by("branch", Some(branchname), "description")
+            .unwrap_or_default()
+    };
+
+    if json::is_requested(matches) {
+        #[derive(serde::Serialize)]
+        struct BranchInfo {
+            name: String,
+            current: bool,
+            stgit: bool,
+            protected: bool,
+            description: String,
+        }
+        #[derive(serde::Serialize)]
+        struct Output {
+            branches: Vec<BranchInfo>,
+        }
+        let mut branches = Vec::new();
+        for branchname in &branchnames {
+            let stack =
+                Stack::from_branch_name(repo, branchname, InitializationPolicy::RequireInitialized)
+                    .ok();
+            branches.push(BranchInfo {
+                name: branchname.to_string(),
+                current: Some(branchname) == current_branchname.as_ref(),
+                stgit: stack.is_some(),
+                protected: stack.is_some_and(|stack| stack.is_protected(&config)),
+                description: get_description(branchname.into())
+                    .to_str_lossy()
+                    .to_string(),
+            });
+        }
+        return json::print(&Output { branches });
+    }
+
     let mut stdout = crate::color::get_color_stdout(matches);
     let mut color_spec = termcolor::ColorSpec::new();
 
@@ -100,9 +140,7 @@ pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Re
         color_spec.clear();
         stdout.set_color(&color_spec)?;
 
-        let description = config
-            .string_by("branch", Some(branchname.into()), "description")
-            .unwrap_or_default();
+        let description = get_description(branchname.into());
         if description.is_empty() {
             writeln!(stdout)?;
         } else {
@@ -138,7 +138,7 @@ fn switch(
     stupid.checkout(target_branchname.as_ref())
 }
 
-fn set_description(
+pub(super) fn set_description(
     repo: &gix::Repository,
     branchname: &PartialRefName,
     description: &str,
@@ -0,0 +1,232 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg continue` implementation.
+
+use anyhow::{anyhow, Result};
+use clap::ArgMatches;
+
+use crate::{
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::PatchName,
+    print_info_message,
+    stack::{
+        InitializationPolicy, PendingOperation, PendingStep, Stack, StackAccess, StackStateAccess,
+        StackTransaction,
+    },
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "continue",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Resume an operation interrupted by conflicts")
+        .long_about(
+            "Resume a 'stg push', 'stg rebase', or 'stg pull' operation that was \
+             interrupted by merge conflicts, or a 'stg rebase --interactive' operation \
+             interrupted by a 'break' or failing 'exec' instruction.\n\
+             \n\
+             Once the conflicts are resolved and the resolutions are added to the \
+             index (e.g. with 'stg add'), any changes in the index and worktree are \
+             refreshed into the topmost patch and the remaining steps of the \
+             interrupted operation are performed. If the operation stashed changes with \
+             '--autostash', the stash is applied once all patches are pushed.\n\
+             \n\
+             See also 'stg skip' and 'stg abort'.",
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let stupid = repo.stupid();
+    let pending = get_pending_operation(&stack)?;
+
+    repo.check_repository_state()?;
+    let statuses = stupid.statuses(None)?;
+    statuses.check_conflicts()?;
+    stack.check_head_top_mismatch()?;
+
+    let refresh_paths = super::refresh::determine_refresh_paths(&stupid, &statuses, None, true)?;
+    let tree_id = super::refresh::write_tree(&stack, &refresh_paths, false)?;
+
+    let completed = perform_steps(
+        stack,
+        &pending.remaining,
+        &StepSettings::resuming(&pending),
+        matches,
+        "continue",
+        |trans| {
+            if trans.applied().is_empty() {
+                Ok(())
+            } else {
+                trans.refresh_top_from_tree(tree_id)
+            }
+        },
+    )?;
+
+    if completed {
+        restore_autostash(&repo, &pending)
+    } else {
+        Ok(())
+    }
+}
+
+/// Get the stack's pending operation, checking that it may be resumed.
+pub(super) fn get_pending_operation(stack: &Stack) -> Result<PendingOperation> {
+    let pending = stack
+        .pending_operation()
+        .cloned()
+        .ok_or_else(|| anyhow!("no interrupted operation is pending"))?;
+    if stack.base().id != pending.base {
+        return Err(anyhow!(
+            "the stack base changed since `stg {}` was interrupted; \
+             use `stg abort` to restore the stack",
+            pending.command
+        ));
+    }
+    Ok(pending)
+}
+
+/// Settings for the transactions performing the steps of a resumable operation.
+pub(super) struct StepSettings {
+    pub(super) command: String,
+    pub(super) orig_state: gix::ObjectId,
+    pub(super) autostash: bool,
+    pub(super) allow_push_conflicts: Option<bool>,
+    pub(super) committer_date_is_author_date: bool,
+    pub(super) check_merged: bool,
+}
+
+impl StepSettings {
+    /// Settings for resuming the given pending operation.
+    pub(super) fn resuming(pending: &PendingOperation) -> Self {
+        Self {
+            command: pending.command.clone(),
+            orig_state: pending.orig_state,
+            autostash: pending.autostash,
+            allow_push_conflicts: None,
+            committer_date_is_author_date: false,
+            check_merged: false,
+        }
+    }
+}
+
+/// Perform the steps of a resumable operation.
+///
+/// Consecutive patches are pushed in a single transaction, along with the actions of
+/// `prelude` in the first transaction. The operation is interrupted and recorded as
+/// pending when pushing a patch results in conflicts, when an `exec` command fails,
+/// or at a `break` step.
+///
+/// Returns `true` if all steps were performed or `false` if a `break` step was
+/// encountered.
+pub(super) fn perform_steps<F>(
+    stack: Stack,
+    steps: &[PendingStep],
+    settings: &StepSettings,
+    matches: &ArgMatches,
+    reflog_msg: &str,
+    prelude: F,
+) -> Result<bool>
+where
+    F: FnOnce(&mut StackTransaction) -> Result<()>,
+{
+    let mut stack = stack;
+    let mut prelude = Some(prelude);
+    let mut index = 0;
+
+    loop {
+        let end = steps[index..]
+            .iter()
+            .position(|step| !matches!(step, PendingStep::Push(_)))
+            .map_or(steps.len(), |pos| index + pos);
+
+        if prelude.is_some() || index < end {
+            let prelude = prelude.take();
+            let mut builder = stack
+                .setup_transaction()
+                .use_index_and_worktree(true)
+                .committer_date_is_author_date(settings.committer_date_is_author_date)
+                .resumable(&settings.command, settings.orig_state, settings.autostash)
+                .remaining_steps(steps[end..].to_vec())
+                .with_output_stream(get_color_stdout(matches));
+            if let Some(allow) = settings.allow_push_conflicts {
+                builder = builder.allow_push_conflicts(allow);
+            }
+            stack = builder
+                .transact(|trans| {
+                    if let Some(prelude) = prelude {
+                        prelude(trans)?;
+                    }
+                    let to_push: Vec<PatchName> = steps[index..end]
+                        .iter()
+                        .filter_map(|step| match step {
+                            PendingStep::Push(patchname)
+                                if trans.has_patch(patchname) && !trans.is_applied(patchname) =>
+                            {
+                                Some(patchname.clone())
+                            }
+                            _ => None,
+                        })
+                        .collect();
+                    trans.push_patches(&to_push, settings.check_merged)
+                })
+                .execute(reflog_msg)?;
+        }
+
+        let Some(step) = steps.get(end) else {
+            return Ok(true);
+        };
+        index = end + 1;
+
+        let result = match step {
+            PendingStep::Exec(command) => stack.repo.stupid().user_exec(command),
+            PendingStep::Break => Ok(()),
+            PendingStep::Push(_) => panic!("push steps are performed above"),
+        };
+
+        if result.is_err() || matches!(step, PendingStep::Break) {
+            let top = stack.applied().last().cloned();
+            stack
+                .setup_transaction()
+                .resumable(&settings.command, settings.orig_state, settings.autostash)
+                .remaining_steps(steps[index..].to_vec())
+                .with_output_stream(get_color_stdout(matches))
+                .transact(|trans| {
+                    trans.interrupt();
+                    Ok(())
+                })
+                .execute(reflog_msg)?;
+
+            let stopped_at = top.map_or_else(String::new, |pn| format!(" at `{pn}`"));
+            return if let Err(e) = result {
+                Err(anyhow!(
+                    "{e:#}; stopped{stopped_at}, \
+                     use `stg continue` to resume or `stg abort` to abort"
+                ))
+            } else {
+                print_info_message(
+                    matches,
+                    &format!("Stopped{stopped_at}; use `stg continue` to resume"),
+                );
+                Ok(false)
+            };
+        }
+    }
+}
+
+/// Apply changes stashed by the pending operation, if any.
+pub(super) fn restore_autostash(repo: &gix::Repository, pending: &PendingOperation) -> Result<()> {
+    if !pending.autostash || repo.stupid().stash_pop()? {
+        Ok(())
+    } else {
+        Err(super::Error::CausedConflicts("stash pop resulted in conflicts".to_string()).into())
+    }
+}
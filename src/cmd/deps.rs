@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg deps` implementation.
+
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::{PatchLocator, PatchName},
+    print_warning_message,
+    stack::{DependencyGraph, InitializationPolicy, Stack, StackStateAccess},
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "deps",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Show or declare patch dependencies")
+        .long_about(
+            "Show or declare the dependencies of a patch.\n\
+             \n\
+             A patch depends on another patch when it may only be applied on top of \
+             that other patch. Without any options, the patches that the given patch \
+             depends on are printed in stack order. If no patch is specified, the \
+             topmost patch is used.\n\
+             \n\
+             Dependencies may be declared explicitly with '--add' and removed with \
+             '--remove' or '--clear'. Declared dependencies are recorded in the stack \
+             state. In addition, dependencies between applied patches are computed from \
+             their overlapping changes: a patch depends on a lower patch when any of \
+             its hunks modify, or are adjacent to, lines last modified by the lower \
+             patch.\n\
+             \n\
+             Commands that reorder or push patches, such as 'stg float', 'stg sink', \
+             and 'stg push', honor patch dependencies according to their '--deps' \
+             option.",
+        )
+        .override_usage(super::make_usage(
+            "stg deps",
+            &[
+                "[OPTIONS] [patch]",
+                "[OPTIONS] (--add <dependency>)... [patch]",
+                "[OPTIONS] (--remove <dependency>)... [patch]",
+                "[OPTIONS] --clear [patch]",
+            ],
+        ))
+        .arg(argset::branch_arg())
+        .arg(
+            Arg::new("patch")
+                .help("Patch to show or declare dependencies of")
+                .value_name("patch")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(
+            Arg::new("add")
+                .long("add")
+                .short('a')
+                .help("Declare that the patch depends on <dependency>")
+                .value_name("dependency")
+                .allow_hyphen_values(true)
+                .action(clap::ArgAction::Append)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(
+            Arg::new("remove")
+                .long("remove")
+                .short('r')
+                .help("Remove declared dependency on <dependency>")
+                .value_name("dependency")
+                .allow_hyphen_values(true)
+                .action(clap::ArgAction::Append)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(
+            Arg::new("clear")
+                .long("clear")
+                .help("Remove all declared dependencies of the patch")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["add", "remove"]),
+        )
+        .arg(
+            Arg::new("declared")
+                .long("declared")
+                .help("Only show declared dependencies")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["add", "remove", "clear"]),
+        )
+        .arg(
+            Arg::new("dependents")
+                .long("dependents")
+                .help("Show patches that depend on the patch instead")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["add", "remove", "clear"]),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::from_branch_locator(
+        &repo,
+        matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::AllowUninitialized,
+    )?;
+
+    let patchname = if let Some(loc) = matches.get_one::<PatchLocator>("patch") {
+        loc.resolve_name(&stack)?
+    } else if let Some(top_patchname) = stack.applied().last() {
+        top_patchname.clone()
+    } else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+
+    let resolve_all = |id: &str| -> Result<Vec<PatchName>> {
+        matches
+            .get_many::<PatchLocator>(id)
+            .map(|locs| {
+                locs.map(|loc| loc.resolve_name(&stack))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()
+            .map(Option::unwrap_or_default)
+            .map_err(Into::into)
+    };
+    let to_add = resolve_all("add")?;
+    let to_remove = resolve_all("remove")?;
+
+    if to_add.is_empty() && to_remove.is_empty() && !matches.get_flag("clear") {
+        let graph = DependencyGraph::new(&repo, &stack, !matches.get_flag("declared"))?;
+        let related = if matches.get_flag("dependents") {
+            graph.direct_dependents(&patchname)
+        } else {
+            graph.direct_dependencies(&patchname)
+        };
+        for pn in stack.all_patches().filter(|pn| related.contains(pn)) {
+            println!("{pn}");
+        }
+        return Ok(());
+    }
+
+    let mut dependencies: BTreeSet<PatchName> = if matches.get_flag("clear") {
+        BTreeSet::new()
+    } else {
+        stack
+            .declared_dependencies()
+            .get(&patchname)
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    let declared_graph = DependencyGraph::new(&repo, &stack, false)?;
+    for dependency in to_add {
+        if dependency == patchname {
+            return Err(anyhow!("patch `{patchname}` cannot depend on itself"));
+        } else if declared_graph
+            .all_dependencies(&dependency)
+            .contains(&patchname)
+        {
+            return Err(anyhow!(
+                "`{dependency}` already depends on `{patchname}`; \
+                 a dependency cycle is not allowed"
+            ));
+        }
+        if stack.is_applied(&patchname)
+            && !stack
+                .applied()
+                .iter()
+                .take_while(|pn| *pn != &patchname)
+                .any(|pn| pn == &dependency)
+        {
+            print_warning_message(
+                matches,
+                &format!("dependency `{dependency}` is not applied below `{patchname}`"),
+            );
+        }
+        dependencies.insert(dependency);
+    }
+
+    for dependency in &to_remove {
+        if !dependencies.remove(dependency) {
+            return Err(anyhow!(
+                "patch `{patchname}` does not declare a dependency on `{dependency}`"
+            ));
+        }
+    }
+
+    stack
+        .setup_transaction()
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            trans.set_dependencies(&patchname, dependencies);
+            Ok(())
+        })
+        .execute(&format!("deps {patchname}"))?;
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg exec` implementation.
+
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+use termcolor::WriteColor;
+
+use crate::{
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::{patchrange, PatchName, PatchRange, RangeConstraint},
+    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "exec",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Run a command on each applied patch")
+        .long_about(
+            "Run a shell command with each applied patch in turn as the topmost patch, \
+             from the bottom of the stack to the top. This may be used to check that \
+             each patch in a series builds and passes tests on its own.\n\
+             \n\
+             Before running the command for a patch, the stack is moved to the patch \
+             as with 'stg goto'. The command is run from the top-level directory of \
+             the work tree. A table indicating whether the command passed or failed \
+             for each patch is shown once all patches are visited.\n\
+             \n\
+             By default, the walk stops at the first patch for which the command fails, \
+             leaving that patch as the topmost patch so that the failure may be \
+             investigated. The remaining patches may be pushed again with 'stg push'. \
+             With '--keep-going', the command is run for all patches and the stack is \
+             restored to its original topmost patch.",
+        )
+        .override_usage(super::make_usage(
+            "stg exec",
+            &["[--range <patch-range>] [--keep-going] [--] <command>..."],
+        ))
+        .arg(
+            Arg::new("command")
+                .help("Shell command to run for each patch")
+                .required(true)
+                .num_args(1..)
+                .trailing_var_arg(true)
+                .allow_hyphen_values(true)
+                .value_name("command"),
+        )
+        .arg(
+            Arg::new("range")
+                .long("range")
+                .short('r')
+                .help("Only run the command for the applied patches in <patch-range>")
+                .value_name("patch-range")
+                .num_args(1)
+                .action(clap::ArgAction::Append)
+                .value_parser(clap::value_parser!(PatchRange)),
+        )
+        .arg(
+            Arg::new("keep-going")
+                .long("keep-going")
+                .short('k')
+                .help("Continue with the remaining patches after the command fails")
+                .action(clap::ArgAction::SetTrue),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let stupid = repo.stupid();
+
+    let command = matches
+        .get_many::<String>("command")
+        .expect("required argument")
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let keep_going = matches.get_flag("keep-going");
+
+    stupid.statuses(None)?.check_index_and_worktree_clean()?;
+    stack.check_head_top_mismatch()?;
+    stack.check_no_pending_operation()?;
+
+    let orig_applied = stack.applied().to_vec();
+    let Some(orig_top) = orig_applied.last().cloned() else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+
+    let patches: Vec<PatchName> = if let Some(range_specs) = matches.get_many::<PatchRange>("range")
+    {
+        let in_range = patchrange::resolve_names(&stack, range_specs, RangeConstraint::Applied)?;
+        orig_applied
+            .iter()
+            .filter(|pn| in_range.contains(pn))
+            .cloned()
+            .collect()
+    } else {
+        orig_applied.clone()
+    };
+
+    let mut stack = stack;
+    let mut results: Vec<(PatchName, Result<()>)> = Vec::with_capacity(patches.len());
+    for patchname in patches {
+        stack = match goto(stack, &patchname, matches, "exec") {
+            Ok(stack) => stack,
+            Err(e) => {
+                print_results(&mut get_color_stdout(matches), &results)?;
+                return Err(e);
+            }
+        };
+        let result = stupid.user_exec(&command);
+        let failed = result.is_err();
+        results.push((patchname, result));
+        if failed && !keep_going {
+            break;
+        }
+    }
+
+    let num_failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    let restored = if num_failed == 0 || keep_going {
+        goto(stack, &orig_top, matches, "exec").map(|_| ())
+    } else {
+        Ok(())
+    };
+
+    print_results(&mut get_color_stdout(matches), &results)?;
+    restored?;
+
+    if num_failed == 0 {
+        Ok(())
+    } else {
+        let plural = if num_failed == 1 { "" } else { "es" };
+        Err(anyhow!("`{command}` failed for {num_failed} patch{plural}"))
+    }
+}
+
+/// Make `patchname` the topmost patch by pushing or popping patches.
+pub(super) fn goto<'repo>(
+    stack: Stack<'repo>,
+    patchname: &PatchName,
+    matches: &ArgMatches,
+    reflog_msg: &str,
+) -> Result<Stack<'repo>> {
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            if let Some(pos) = trans.applied().iter().position(|pn| pn == patchname) {
+                let applied = trans.applied()[0..=pos].to_vec();
+                let mut unapplied = trans.applied()[pos + 1..].to_vec();
+                unapplied.extend(trans.unapplied().iter().cloned());
+                trans.reorder_patches(Some(&applied), Some(&unapplied), None)
+            } else if let Some(pos) = trans.unapplied().iter().position(|pn| pn == patchname) {
+                let to_apply: Vec<PatchName> = trans.unapplied()[0..=pos].to_vec();
+                trans.push_patches(&to_apply, false)
+            } else {
+                Err(anyhow!(
+                    "patch `{patchname}` is neither applied nor unapplied"
+                ))
+            }
+        })
+        .execute(reflog_msg)
+}
+
+fn print_results(
+    stdout: &mut termcolor::StandardStream,
+    results: &[(PatchName, Result<()>)],
+) -> Result<()> {
+    let name_width = results
+        .iter()
+        .map(|(patchname, _)| patchname.len())
+        .max()
+        .unwrap_or_default();
+    let mut color_spec = termcolor::ColorSpec::new();
+    for (patchname, result) in results {
+        let (status, color) = if result.is_ok() {
+            ("pass", termcolor::Color::Green)
+        } else {
+            ("FAIL", termcolor::Color::Red)
+        };
+        stdout.set_color(color_spec.set_fg(Some(color)))?;
+        write!(stdout, "{status}")?;
+        stdout.reset()?;
+        if let Err(e) = result {
+            writeln!(stdout, " {patchname:name_width$} {e:#}")?;
+        } else {
+            writeln!(stdout, " {patchname}")?;
+        }
+    }
+    Ok(())
+}
@@ -4,22 +4,23 @@
 
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     ffi::OsString,
     io::Write,
     path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Result};
-use bstr::BStr;
+use anyhow::{anyhow, Context, Result};
+use bstr::{BStr, ByteSlice};
 use clap::Arg;
 
 use crate::{
     argset,
     branchloc::BranchLocator,
     ext::{CommitExtended, RepositoryExtended},
-    patch::{patchrange, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    json::PatchStatus,
+    patch::{patchrange, PatchName, PatchRange, RangeConstraint},
+    stack::{InitializationPolicy, PatchMeta, Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -38,7 +39,8 @@ fn make() -> clap::Command {
              All applied patches are exported by default.\n\
              \n\
              Patches are exported to 'patches-<branch>' by default. The '--dir' option \
-             may be used to specify a different output directory.\n\
+             may be used to specify a different output directory. With '--quilt', the \
+             output directory is suitable for use as quilt's patches directory.\n\
              \n\
              The patch file output may be customized via a template file found at \
              \"$GIT_DIR/patchexport.tmpl\", \"~/.stgit/templates/patchexport.tmpl\", \
@@ -118,6 +120,46 @@ fn make() -> clap::Command {
                 .conflicts_with("dir")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("quilt")
+                .long("quilt")
+                .help("Export patches in a form quilt can apply")
+                .long_help(
+                    "Export patches in a form quilt can apply. The patches are \
+                     written with \"a/\" and \"b/\" path prefixes and without \
+                     rename detection, such that the output directory may be used as \
+                     quilt's patches directory and the whole series applied with \
+                     'quilt push -a'. Patches with binary changes cannot be exported \
+                     in this mode.",
+                )
+                .conflicts_with("stdout")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("bundle")
+                .long("bundle")
+                .help("Export the complete stack to a bundle archive")
+                .long_help(
+                    "Export the complete stack to a bundle archive <file>. The bundle \
+                     is a tar archive containing every applied, unapplied, and hidden \
+                     patch along with its author, committer, message, and state, as \
+                     well as the stack's base commit id. The stack may be recreated \
+                     from the bundle with 'stg import --bundle'.",
+                )
+                .value_name("file")
+                .value_hint(clap::ValueHint::FilePath)
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with_all([
+                    "patchranges",
+                    "dir",
+                    "patch",
+                    "extension",
+                    "numbered",
+                    "template",
+                    "stdout",
+                    "quilt",
+                ]),
+        )
         .arg(argset::diff_opts_arg())
 }
 
@@ -141,6 +183,10 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
         );
     }
 
+    if let Some(bundle_path) = matches.get_one::<PathBuf>("bundle") {
+        return export_bundle(&stack, bundle_path);
+    }
+
     let patches = if let Some(range_specs) = matches.get_many::<PatchRange>("patchranges") {
         patchrange::resolve_names(
             &stack,
@@ -176,7 +222,18 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
     let numbered_flag = matches.get_flag("numbered");
     let num_width = std::cmp::max(patches.len().to_string().len(), 2);
 
-    let diff_opts = argset::get_diff_opts(matches, &repo.config_snapshot(), false, true);
+    let quilt_flag = matches.get_flag("quilt");
+    let diff_opts = if quilt_flag {
+        let mut diff_opts = argset::get_diff_opts(matches, &repo.config_snapshot(), false, false);
+        diff_opts.extend(
+            ["--no-renames", "--src-prefix=a/", "--dst-prefix=b/"]
+                .into_iter()
+                .map(String::from),
+        );
+        diff_opts
+    } else {
+        argset::get_diff_opts(matches, &repo.config_snapshot(), false, true)
+    };
 
     let template = if let Some(template_file) = matches.get_one::<PathBuf>("template") {
         Cow::Owned(std::fs::read_to_string(template_file)?)
@@ -256,6 +313,12 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
             diff_opts.iter(),
         )?;
 
+        if quilt_flag && diff.lines().any(|line| line.starts_with(b"Binary files ")) {
+            return Err(anyhow!(
+                "patch `{patchname}` has binary changes which cannot be exported for quilt"
+            ));
+        }
+
         if need_diffstat {
             replacements.insert(
                 "diffstat",
@@ -303,3 +366,246 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
 
     Ok(())
 }
+
+/// Path of the manifest within a stack bundle archive.
+pub(super) const BUNDLE_MANIFEST_PATH: &str = "stack.json";
+
+/// Version of the stack bundle format.
+pub(super) const BUNDLE_VERSION: u32 = 1;
+
+/// Manifest describing the stack recorded in a bundle archive.
+///
+/// Each patch's diff is stored in the archive at the path given by
+/// [`bundle_diff_path()`].
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct BundleManifest {
+    pub(super) version: u32,
+    pub(super) base: String,
+    pub(super) patches: Vec<BundlePatch>,
+}
+
+/// Patch recorded in a stack bundle.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct BundlePatch {
+    pub(super) name: String,
+    pub(super) status: PatchStatus,
+    /// Earlier patch in the bundle the patch's ancestors, or its diff if it has no
+    /// ancestors, apply to. The base if none.
+    pub(super) parent: Option<String>,
+    /// Commits between `parent` and the patch, oldest first.
+    ///
+    /// Only recorded for patches whose parent commit is neither the base nor an
+    /// earlier patch, e.g. an unapplied patch whose parent was since refreshed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) ancestors: Vec<BundleAncestor>,
+    pub(super) author: BundleSignature,
+    pub(super) committer: BundleSignature,
+    pub(super) message: String,
+    #[serde(default, skip_serializing_if = "PatchMeta::is_empty")]
+    pub(super) meta: PatchMeta,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(super) dependencies: BTreeSet<String>,
+}
+
+/// Commit recorded in a stack bundle as the ancestor of a patch.
+///
+/// Each ancestor's diff is stored in the archive at the path given by
+/// [`bundle_ancestor_diff_path()`].
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct BundleAncestor {
+    pub(super) author: BundleSignature,
+    pub(super) committer: BundleSignature,
+    pub(super) message: String,
+}
+
+impl BundleAncestor {
+    fn new(commit: &gix::Commit<'_>) -> Result<Self> {
+        let author = commit.author_strict()?;
+        let committer = commit.committer()?;
+        Ok(Self {
+            author: BundleSignature::new(author.name.as_ref(), author.email.as_ref(), author.time),
+            committer: BundleSignature::new(committer.name, committer.email, committer.time),
+            message: commit.message_ex().decode()?.to_string(),
+        })
+    }
+}
+
+/// Author or committer signature recorded in a stack bundle.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(super) struct BundleSignature {
+    pub(super) name: String,
+    pub(super) email: String,
+    /// Time in git's raw format, i.e. "<seconds> <offset>".
+    pub(super) date: String,
+}
+
+impl BundleSignature {
+    fn new(name: &BStr, email: &BStr, time: gix::date::Time) -> Self {
+        Self {
+            name: name.to_str_lossy().to_string(),
+            email: email.to_str_lossy().to_string(),
+            date: time.format(gix::date::time::format::RAW),
+        }
+    }
+
+    pub(super) fn to_signature(&self) -> Result<gix::actor::Signature> {
+        let time = gix::date::parse(&self.date, None)
+            .with_context(|| format!("parsing bundle date `{}`", self.date))?;
+        Ok(gix::actor::Signature {
+            name: self.name.as_str().into(),
+            email: self.email.as_str().into(),
+            time,
+        })
+    }
+}
+
+/// Get the path of a patch's diff within a stack bundle archive.
+pub(super) fn bundle_diff_path(patchname: &str) -> String {
+    format!("patches/{patchname}.diff")
+}
+
+/// Get the path of the diff of a patch's `index`th ancestor within a stack bundle
+/// archive.
+pub(super) fn bundle_ancestor_diff_path(patchname: &str, index: usize) -> String {
+    format!("ancestors/{patchname}/{index}.diff")
+}
+
+fn export_bundle(stack: &Stack, bundle_path: &Path) -> Result<()> {
+    let stupid = stack.repo.stupid();
+    let base_id = stack.base().id;
+    let patches: Vec<(&PatchName, PatchStatus)> = stack
+        .applied()
+        .iter()
+        .map(|pn| (pn, PatchStatus::Applied))
+        .chain(
+            stack
+                .unapplied()
+                .iter()
+                .map(|pn| (pn, PatchStatus::Unapplied)),
+        )
+        .chain(stack.hidden().iter().map(|pn| (pn, PatchStatus::Hidden)))
+        .collect();
+
+    let bundle_file =
+        std::fs::File::create(bundle_path).with_context(|| format!("creating {bundle_path:?}"))?;
+    let mut builder = tar::Builder::new(bundle_file);
+    let mut bundle_patches = Vec::with_capacity(patches.len());
+
+    for (i, &(patchname, status)) in patches.iter().enumerate() {
+        let patch_commit = stack.get_patch_commit(patchname);
+        let parent_commit = patch_commit.get_parent_commit()?;
+
+        // A patch whose parent is neither the base nor an earlier patch, e.g. an
+        // unapplied patch whose parent was since refreshed, records the commits
+        // leading to its parent from the base or an earlier patch. Should those
+        // commits not descend from the base, they are recorded on top of the base.
+        let earlier_patch = |commit_id: gix::ObjectId| {
+            patches[..i]
+                .iter()
+                .find(|(pn, _)| stack.get_patch_commit_id(pn) == commit_id)
+                .map(|&(pn, _)| pn)
+        };
+        let mut ancestor_commits = Vec::new();
+        let mut merge_bases: Option<Vec<gix::ObjectId>> = None;
+        let mut commit = parent_commit;
+        let parent = loop {
+            if commit.id == base_id {
+                break None;
+            } else if let Some(parent_name) = earlier_patch(commit.id) {
+                break Some(parent_name);
+            }
+            let merge_bases = if let Some(merge_bases) = merge_bases.as_ref() {
+                merge_bases
+            } else {
+                merge_bases.insert(stupid.merge_bases(commit.id, base_id)?)
+            };
+            if merge_bases.contains(&commit.id) || commit.parent_ids().next().is_none() {
+                break None;
+            }
+            let next_commit = commit.get_parent_commit()?;
+            ancestor_commits.push(commit);
+            commit = next_commit;
+        };
+        ancestor_commits.reverse();
+
+        let mut parent_tree_id = if let Some(parent_name) = parent {
+            stack.get_patch_commit(parent_name).tree_id()?.detach()
+        } else {
+            stack.base().tree_id()?.detach()
+        };
+        let mut ancestors = Vec::with_capacity(ancestor_commits.len());
+        for (index, ancestor_commit) in ancestor_commits.iter().enumerate() {
+            let tree_id = ancestor_commit.tree_id()?.detach();
+            let diff = stupid.diff_tree_patch(
+                parent_tree_id,
+                tree_id,
+                <Option<Vec<OsString>>>::None,
+                false,
+                ["--binary", "--full-index", "--no-renames"],
+            )?;
+            append_bundle_file(
+                &mut builder,
+                &bundle_ancestor_diff_path(patchname.as_ref(), index),
+                &diff,
+            )?;
+            ancestors.push(BundleAncestor::new(ancestor_commit)?);
+            parent_tree_id = tree_id;
+        }
+
+        let diff = stupid.diff_tree_patch(
+            parent_tree_id,
+            patch_commit.tree_id()?.detach(),
+            <Option<Vec<OsString>>>::None,
+            false,
+            ["--binary", "--full-index", "--no-renames"],
+        )?;
+        append_bundle_file(&mut builder, &bundle_diff_path(patchname.as_ref()), &diff)?;
+
+        let author = patch_commit.author_strict()?;
+        let committer = patch_commit.committer()?;
+        bundle_patches.push(BundlePatch {
+            name: patchname.to_string(),
+            status,
+            parent: parent.map(PatchName::to_string),
+            ancestors,
+            author: BundleSignature::new(author.name.as_ref(), author.email.as_ref(), author.time),
+            committer: BundleSignature::new(committer.name, committer.email, committer.time),
+            message: patch_commit.message_ex().decode()?.to_string(),
+            meta: stack.get_patch_meta(patchname).clone(),
+            dependencies: stack
+                .declared_dependencies()
+                .get(patchname)
+                .map(|deps| deps.iter().map(PatchName::to_string).collect())
+                .unwrap_or_default(),
+        });
+    }
+
+    let manifest = BundleManifest {
+        version: BUNDLE_VERSION,
+        base: base_id.to_string(),
+        patches: bundle_patches,
+    };
+    append_bundle_file(
+        &mut builder,
+        BUNDLE_MANIFEST_PATH,
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    builder
+        .into_inner()
+        .and_then(|mut file| file.flush())
+        .with_context(|| format!("writing {bundle_path:?}"))
+}
+
+fn append_bundle_file(
+    builder: &mut tar::Builder<std::fs::File>,
+    path: &str,
+    content: &[u8],
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, path, content)
+        .with_context(|| format!("adding {path} to bundle"))
+}
@@ -9,7 +9,9 @@ use bstr::ByteSlice;
 use clap::{Arg, ArgMatches};
 
 use crate::{
+    argset,
     ext::{CommitExtended, RepositoryExtended},
+    json,
     patch::SingleRevisionSpec,
     stack::Stack,
     stupid::Stupid,
@@ -55,6 +57,7 @@ fn make() -> clap::Command {
                 .action(clap::ArgAction::SetTrue)
                 .conflicts_with("stat"),
         )
+        .arg(argset::format_arg().conflicts_with_all(["stat", "bare"]))
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
@@ -69,6 +72,33 @@ fn run(matches: &ArgMatches) -> Result<()> {
         Rc::new(repo.head_commit()?)
     };
     let parent = commit.get_parent_commit()?;
+
+    if json::is_requested(matches) {
+        #[derive(serde::Serialize)]
+        struct File {
+            status: String,
+            path: String,
+        }
+        #[derive(serde::Serialize)]
+        struct Output {
+            commit_id: String,
+            files: Vec<File>,
+        }
+        let files = repo
+            .stupid()
+            .diff_tree_name_status(parent.tree_id()?.detach(), commit.tree_id()?.detach())?
+            .into_iter()
+            .map(|(status, path)| File {
+                status: status.to_str_lossy().to_string(),
+                path: path.to_str_lossy().to_string(),
+            })
+            .collect();
+        return json::print(&Output {
+            commit_id: commit.id.to_string(),
+            files,
+        });
+    }
+
     let mut output = repo.stupid().diff_tree_files_status(
         parent.tree_id()?.detach(),
         commit.tree_id()?.detach(),
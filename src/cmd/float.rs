@@ -15,7 +15,7 @@ use crate::{
     color::get_color_stdout,
     ext::RepositoryExtended,
     patch::{patchrange, PatchName, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stack::{DependencyGraph, DependencyPolicy, InitializationPolicy, Stack, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -69,6 +69,7 @@ fn make() -> clap::Command {
         )
         .arg(argset::keep_arg())
         .arg(argset::committer_date_is_author_date_arg())
+        .arg(argset::deps_arg())
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
@@ -85,7 +86,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
     statuses.check_conflicts()?;
     stack.check_head_top_mismatch()?;
 
-    let patches: Vec<PatchName> = if let Some(series_path) = opt_series {
+    let mut patches: Vec<PatchName> = if let Some(series_path) = opt_series {
         parse_series(series_path, &stack)?
     } else {
         let range_specs = matches
@@ -98,6 +99,29 @@ fn run(matches: &ArgMatches) -> Result<()> {
         return Err(anyhow!("no patches to float"));
     }
 
+    let dependency_graph = if noapply_flag {
+        None
+    } else {
+        let policy = argset::resolve_dependency_policy(&repo.config_snapshot(), matches)?;
+        let graph = DependencyGraph::for_policy(&repo, &stack, policy)?;
+        if let Some(graph) = graph.as_ref().filter(|_| policy == DependencyPolicy::Pull) {
+            let below: Vec<PatchName> = stack
+                .applied()
+                .iter()
+                .filter(|pn| !patches.contains(pn))
+                .cloned()
+                .collect();
+            let above: Vec<PatchName> = stack
+                .unapplied()
+                .iter()
+                .filter(|pn| !patches.contains(pn))
+                .cloned()
+                .collect();
+            patches = graph.pull_in(&patches, &below, &above);
+        }
+        graph
+    };
+
     if !keep_flag && (!noapply_flag || patches.iter().any(|pn| stack.is_applied(pn))) {
         statuses.check_index_and_worktree_clean()?;
     }
@@ -137,6 +161,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
         .use_index_and_worktree(true)
         .committer_date_is_author_date(matches.get_flag("committer-date-is-author-date"))
         .with_output_stream(get_color_stdout(matches))
+        .dependency_graph(dependency_graph)
         .transact(|trans| trans.reorder_patches(Some(&applied), Some(&unapplied), None))
         .execute("float")?;
 
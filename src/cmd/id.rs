@@ -9,6 +9,7 @@ use crate::{
     argset,
     branchloc::BranchLocator,
     ext::RepositoryExtended,
+    json,
     patch::SingleRevisionSpec,
     stack::{InitializationPolicy, Stack, StackAccess},
 };
@@ -40,6 +41,7 @@ fn make() -> clap::Command {
                 .value_parser(clap::value_parser!(SingleRevisionSpec))
                 .help("StGit revision"),
         )
+        .arg(argset::format_arg())
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
@@ -56,6 +58,16 @@ fn run(matches: &ArgMatches) -> Result<()> {
         .transpose()?
         .unwrap_or_else(|| stack.get_branch_head().id);
 
-    println!("{oid}");
-    Ok(())
+    if json::is_requested(matches) {
+        #[derive(serde::Serialize)]
+        struct Output {
+            id: String,
+        }
+        json::print(&Output {
+            id: oid.to_string(),
+        })
+    } else {
+        println!("{oid}");
+        Ok(())
+    }
 }
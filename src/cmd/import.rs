@@ -3,21 +3,31 @@
 //! `stg import` implementation.
 
 use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
     io::Read,
     path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr,
 };
 
 use anyhow::{anyhow, Context, Result};
 use bstr::{BStr, BString, ByteSlice, ByteVec};
 use clap::{Arg, ArgGroup};
 
+use super::export::{
+    bundle_ancestor_diff_path, bundle_diff_path, BundleManifest, BundlePatch, BUNDLE_MANIFEST_PATH,
+    BUNDLE_VERSION,
+};
 use crate::{
     color::get_color_stdout,
     ext::{RepositoryExtended, TimeExtended},
-    patch::{patchedit, PatchName},
-    print_info_message,
+    json::PatchStatus,
+    mail::{self, SeriesTag, ThreadHeaders},
+    patch::{patchedit, PatchName, PatchRange},
+    print_info_message, print_warning_message,
     stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
-    stupid::Stupid,
+    stupid::{Stupid, StupidContext},
+    wrap::{Message, PartialRefName},
 };
 
 pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
@@ -41,10 +51,25 @@ fn make() -> clap::Command {
              patch's author details.\n\
              \n\
              Patches may also be imported from a mail file (-m/--mail), an mbox \
-             (-M/--mbox), or a series (-S/--series). Furthermore, the -u/--url option \
+             (-M/--mbox), a series (-S/--series), or a stack bundle (--bundle). \
+             Furthermore, the -u/--url option \
              allows the patches source to be fetched from a url instead of from a \
              local file.\n\
              \n\
+             When importing a quilt series, a \"-p<n>\" strip level following a \
+             patch name in the series file is honored, and patches guarded with \
+             \"#+<guard>\" or \"#-<guard>\" are selected according to the '--guard' \
+             options. If quilt's \".pc\" directory is found next to the series file \
+             or in its parent directory, patches that quilt does not record as \
+             applied are imported as unapplied patches.\n\
+             \n\
+             When importing an mbox containing a patch series with subjects such \
+             as \"[PATCH v2 3/5]\", the patches are imported in series order. If \
+             the mbox contains multiple versions of the series, only the latest \
+             version is imported. The cover letter and replies to the patches are \
+             not imported as patches, and it is an error for patches of the series \
+             to be missing unless '--allow-incomplete' is specified.\n\
+             \n\
              If a patch does not apply cleanly import is aborted unless '--reject' \
              is specified, in which case it will apply to the work tree the parts \
              of the patch that are  applicable, leave the rejected hunks in \
@@ -60,6 +85,8 @@ fn make() -> clap::Command {
                     "[OPTIONS] -m [<mail-path>|<Maildir-path>]",
                     "[OPTIONS] -M [<mbox-path>]",
                     "[OPTIONS] -S [<series-path>]",
+                    "[OPTIONS] --bundle [<bundle-path>]",
+                    "[OPTIONS] --from-repo <path> [--branch <branch>] [<patch>]",
                     "[OPTIONS] -u <diff-url>",
                     "[OPTIONS] -u -m <mail-url>",
                     "[OPTIONS] -u -M <mbox-url>",
@@ -71,6 +98,8 @@ fn make() -> clap::Command {
                     "[OPTIONS] -m [<mail-path>|<Maildir-path>]",
                     "[OPTIONS] -M [<mbox-path>]",
                     "[OPTIONS] -S [<series-path>]",
+                    "[OPTIONS] --bundle [<bundle-path>]",
+                    "[OPTIONS] --from-repo <path> [--branch <branch>] [<patch>]",
                 ]
             },
         ))
@@ -80,7 +109,9 @@ fn make() -> clap::Command {
                 .long_help(
                     "Source of patches to import. May be a path to a local file or a \
                      URL if the '--url' option is provided. The default is to read \
-                     from stdin if no source argument is provided.",
+                     from stdin if no source argument is provided. With '--from-repo', \
+                     the source is a patch name or range in the other repository's \
+                     stack.",
                 )
                 .value_parser(clap::value_parser!(PathBuf))
                 .value_hint(clap::ValueHint::AnyPath),
@@ -108,7 +139,38 @@ fn make() -> clap::Command {
                 .long_help("Import patch series from a series file are tar archive.")
                 .action(clap::ArgAction::SetTrue),
         )
-        .group(ArgGroup::new("whence").args(["mail", "mbox", "series"]));
+        .arg(
+            Arg::new("bundle")
+                .long("bundle")
+                .help("Import a complete stack from a bundle")
+                .long_help(
+                    "Recreate a stack from a bundle archive created with 'stg export \
+                     --bundle'. All patches of the bundle are imported with their \
+                     applied, unapplied, or hidden state preserved. The stack must not \
+                     have any patches. The stack's base is moved to the bundle's base \
+                     commit, which must be present in the repository, unless another \
+                     base is given with '--base'.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .group(ArgGroup::new("whence").args(["mail", "mbox", "series", "bundle"]))
+        .arg(
+            Arg::new("from-repo")
+                .long("from-repo")
+                .help("Import patches from the stack of the repository at <path>")
+                .long_help(
+                    "Import patches from the stack of another local repository at \
+                     <path>. The source argument is then a patch name or range in that \
+                     stack, defaulting to all of its applied patches. The patches' \
+                     commits are copied as they are, preserving their authorship, \
+                     committer, message, and patch metadata.",
+                )
+                .value_name("path")
+                .value_hint(clap::ValueHint::DirPath)
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with("whence"),
+        )
+        .arg(super::pick::from_branch_arg());
 
     let app = if cfg!(feature = "import-url") {
         app.arg(
@@ -117,7 +179,8 @@ fn make() -> clap::Command {
                 .short('u')
                 .help("Retrieve source from a url instead of local file")
                 .action(clap::ArgAction::SetTrue)
-                .requires("source"),
+                .requires("source")
+                .conflicts_with("from-repo"),
         )
     } else {
         app
@@ -185,6 +248,21 @@ fn make() -> clap::Command {
                 .help("Ignore the applied patches in the series")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("guard")
+                .long("guard")
+                .help("Select guarded patches from the series")
+                .long_help(
+                    "Select patches guarded by <guard> when importing a quilt series. \
+                    Patches in the series file may be annotated with \"#+<guard>\" \
+                    to only be imported when the guard is selected, or with \
+                    \"#-<guard>\" to be skipped when the guard is selected. This \
+                    option may be repeated to select multiple guards.",
+                )
+                .value_name("guard")
+                .action(clap::ArgAction::Append)
+                .requires("series"),
+        )
         .arg(
             Arg::new("replace")
                 .long("replace")
@@ -196,6 +274,11 @@ fn make() -> clap::Command {
                 .long("base")
                 .short('b')
                 .help("Use <committish> instead of HEAD for file importing")
+                .long_help(
+                    "Use <committish> instead of HEAD for file importing. When \
+                     importing a bundle, the stack is recreated on <committish> instead \
+                     of the bundle's base commit.",
+                )
                 .value_name("committish"),
         )
         .arg(
@@ -221,6 +304,50 @@ fn make() -> clap::Command {
                     the \"stgit.import.message-id\" configuration option.",
                 )
                 .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("cover-description")
+                .long("cover-description")
+                .help("Use the series' cover letter as the branch description")
+                .long_help(
+                    "Use the cover letter, i.e. the \"0/<n>\" message, of a patch \
+                    series imported with '--mbox' as the branch description. By \
+                    default the cover letter is skipped.",
+                )
+                .action(clap::ArgAction::SetTrue)
+                .requires("mbox"),
+        )
+        .arg(
+            Arg::new("allow-incomplete")
+                .long("allow-incomplete")
+                .help("Import a patch series even if some of its patches are missing")
+                .action(clap::ArgAction::SetTrue)
+                .requires("mbox"),
+        )
+        .arg(
+            Arg::new("strip-trailers")
+                .long("strip-trailers")
+                .help("Remove trailers from the imported patch descriptions")
+                .long_help(
+                    "Remove the trailers, e.g. \"Signed-off-by\", at the end of the \
+                    imported patch descriptions. This option is applicable when \
+                    importing with '--mail' or '--mbox'.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("collect-trailers")
+                .long("collect-trailers")
+                .help("Add review trailers from replies to the patches they reply to")
+                .long_help(
+                    "Add the \"Reviewed-by\", \"Acked-by\", and \"Tested-by\" \
+                    trailers found in replies to the imported patches. A reply's \
+                    trailers are added to the patch it replies to, or to all patches \
+                    of the series if it replies to the cover letter. Replies are not \
+                    imported as patches. This option is applicable when importing with \
+                    '--mail' or '--mbox'.",
+                )
+                .action(clap::ArgAction::SetTrue),
         );
     patchedit::add_args(app, false, false)
 }
@@ -230,6 +357,20 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
     let stack = Stack::current(&repo, InitializationPolicy::AutoInitialize)?;
     let stupid = repo.stupid();
 
+    if let Some(from_repo) = matches.get_one::<PathBuf>("from-repo") {
+        let range = if let Some(source) = matches.get_one::<PathBuf>("source") {
+            source
+                .to_str()
+                .ok_or_else(|| anyhow!("patch range `{}` is not UTF-8", source.display()))?
+        } else {
+            ".."
+        };
+        let range = PatchRange::from_str(range)?;
+        stupid.statuses(None)?.check_index_and_worktree_clean()?;
+        stack.check_head_top_mismatch()?;
+        return super::pick::pick_from_repo(stack, matches, from_repo, &[range], true);
+    }
+
     let source_path = if cfg!(feature = "import-url") && matches.get_flag("url") {
         None
     } else if let Some(path) = matches.get_one::<PathBuf>("source") {
@@ -246,6 +387,8 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
 
     if cfg!(feature = "import-url") && matches.get_flag("url") {
         import_url(stack, matches)
+    } else if matches.get_flag("bundle") {
+        import_bundle(stack, matches, source_path.as_deref())
     } else if matches.get_flag("series") {
         import_series(stack, matches, source_path.as_deref())
     } else if matches.get_flag("mail") || matches.get_flag("mbox") {
@@ -305,7 +448,9 @@ fn import_url(stack: Stack, matches: &clap::ArgMatches) -> Result<()> {
         e @ Err(_) => e?,
     }
 
-    if matches.get_flag("series") {
+    if matches.get_flag("bundle") {
+        import_bundle(stack, matches, Some(download_path.as_path()))
+    } else if matches.get_flag("series") {
         import_series(stack, matches, Some(download_path.as_path()))
     } else if matches.get_flag("mail") || matches.get_flag("mbox") {
         import_mail(stack, matches, Some(download_path.as_path()))
@@ -367,21 +512,33 @@ fn import_series(
         buf
     };
 
+    let guards: Vec<&str> = matches
+        .get_many::<String>("guard")
+        .map(|guards| guards.map(String::as_str).collect())
+        .unwrap_or_default();
+    let quilt_applied = source_path.map(read_quilt_applied).transpose()?.flatten();
+
     let mut stack = stack;
+    let mut imported: Vec<(&[u8], PatchName)> = Vec::new();
 
     for line in series.lines() {
-        let line = line
-            .find_char('#')
-            .map_or(line, |pos| &line[..pos])
-            .trim_with(|c| c.is_ascii_whitespace());
-        if line.is_empty() {
+        let Some(entry) = SeriesEntry::parse(line)? else {
+            continue;
+        };
+
+        if !entry.is_selected(&guards) {
+            print_info_message(
+                matches,
+                &format!(
+                    "Skipping patch `{}` excluded by guards",
+                    entry.patchname.to_str_lossy()
+                ),
+            );
             continue;
         }
 
-        let mut fields = line.fields_with(|c| c.is_ascii_whitespace());
-        let raw_patchname = fields
-            .next()
-            .expect("non-empty line must have first field")
+        let raw_patchname = entry
+            .patchname
             .to_os_str()
             .context("converting patch name from series to file name")?;
         let patch_path = source_path.map_or_else(
@@ -389,29 +546,334 @@ fn import_series(
             |p| p.with_file_name(raw_patchname),
         );
 
-        let strip_level = if let Some(extra) = fields.next() {
-            if extra.starts_with_str("-p") {
-                if extra == b"-p0" {
-                    Some(0)
-                } else {
-                    return Err(anyhow!(
-                        "patch `{}` has unsupported strip level \"{}\"",
-                        raw_patchname.to_string_lossy(),
-                        extra.to_str_lossy()
-                    ));
-                }
-            } else {
-                None
+        let old_top = stack.applied().last().cloned();
+        stack = import_file(
+            stack,
+            matches,
+            Some(patch_path.as_path()),
+            entry.strip_level,
+        )?;
+        if let Some(new_top) = stack.applied().last() {
+            if old_top.as_ref() != Some(new_top) {
+                imported.push((entry.patchname, new_top.clone()));
             }
-        } else {
-            None
+        }
+    }
+
+    // Patches quilt does not record as applied are popped, starting from the first
+    // such patch in the series.
+    if let Some(quilt_applied) = quilt_applied {
+        let to_pop: Vec<PatchName> = imported
+            .iter()
+            .skip_while(|(raw_patchname, _)| {
+                quilt_applied
+                    .iter()
+                    .any(|applied| applied.as_slice() == *raw_patchname)
+            })
+            .map(|(_, patchname)| patchname.clone())
+            .collect();
+        if !to_pop.is_empty() {
+            stack
+                .setup_transaction()
+                .use_index_and_worktree(true)
+                .with_output_stream(get_color_stdout(matches))
+                .transact(|trans| {
+                    let popped_extra = trans.pop_patches(|pn| to_pop.contains(pn))?;
+                    assert!(popped_extra.is_empty());
+                    Ok(())
+                })
+                .execute("import: pop unapplied quilt patches")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Entry from a quilt series file.
+struct SeriesEntry<'a> {
+    patchname: &'a [u8],
+    strip_level: Option<usize>,
+    /// Guards as (positive, guard name) pairs.
+    guards: Vec<(bool, &'a [u8])>,
+}
+
+impl<'a> SeriesEntry<'a> {
+    /// Parse a line from a series file.
+    ///
+    /// Returns `None` for blank and comment lines. Besides the patch name, an entry
+    /// may have a "-p<n>" strip level and "#+<guard>" or "#-<guard>" guards. Any other
+    /// field starting with '#' begins a comment.
+    fn parse(line: &'a [u8]) -> Result<Option<Self>> {
+        let mut fields = line.fields_with(|c| c.is_ascii_whitespace());
+        let Some(patchname) = fields.next() else {
+            return Ok(None);
         };
+        let patchname = patchname
+            .find_byte(b'#')
+            .map_or(patchname, |pos| &patchname[..pos]);
+        if patchname.is_empty() {
+            return Ok(None);
+        }
 
-        stack = import_file(stack, matches, Some(patch_path.as_path()), strip_level)?;
+        let mut strip_level = None;
+        let mut guards = Vec::new();
+        for field in fields {
+            if let Some(guard) = field.strip_prefix(b"#+") {
+                guards.push((true, guard));
+            } else if let Some(guard) = field.strip_prefix(b"#-") {
+                guards.push((false, guard));
+            } else if field.starts_with(b"#") {
+                break;
+            } else if let Some(level) = field.strip_prefix(b"-p") {
+                strip_level = Some(
+                    level
+                        .to_str()
+                        .ok()
+                        .filter(|level| level.bytes().all(|c| c.is_ascii_digit()))
+                        .and_then(|level| level.parse::<usize>().ok())
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "patch `{}` has invalid strip level \"{}\"",
+                                patchname.to_str_lossy(),
+                                field.to_str_lossy()
+                            )
+                        })?,
+                );
+            }
+        }
+
+        Ok(Some(Self {
+            patchname,
+            strip_level,
+            guards,
+        }))
+    }
+
+    /// Determine whether the entry is selected given the active guards.
+    ///
+    /// As with quilt, an entry is excluded if any of its negative guards is active,
+    /// and an entry with positive guards requires at least one of them to be active.
+    fn is_selected(&self, active: &[&str]) -> bool {
+        let is_active = |guard: &[u8]| active.iter().any(|a| a.as_bytes() == guard);
+        let mut has_positive = false;
+        let mut positive_active = false;
+        for (positive, guard) in &self.guards {
+            if *positive {
+                has_positive = true;
+                positive_active |= is_active(guard);
+            } else if is_active(guard) {
+                return false;
+            }
+        }
+        !has_positive || positive_active
+    }
+}
+
+/// Read the list of applied patches from quilt's ".pc" directory.
+///
+/// The ".pc" directory is looked for alongside the series file and in its parent
+/// directory, which is where quilt keeps it when the series file is in "patches/".
+fn read_quilt_applied(series_path: &Path) -> Result<Option<Vec<BString>>> {
+    let Some(series_dir) = series_path.parent() else {
+        return Ok(None);
+    };
+    for dir in [Some(series_dir), series_dir.parent()]
+        .into_iter()
+        .flatten()
+    {
+        let pc_dir = dir.join(".pc");
+        if pc_dir.is_dir() {
+            let applied_path = pc_dir.join("applied-patches");
+            let applied = if applied_path.is_file() {
+                std::fs::read(&applied_path)
+                    .with_context(|| format!("reading {applied_path:?}"))?
+                    .lines()
+                    .map(|line| line.trim_with(|c| c.is_ascii_whitespace()))
+                    .filter(|line| !line.is_empty())
+                    .map(BString::from)
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            return Ok(Some(applied));
+        }
+    }
+    Ok(None)
+}
+
+fn import_bundle(
+    stack: Stack,
+    matches: &clap::ArgMatches,
+    source_path: Option<&Path>,
+) -> Result<()> {
+    let repo = stack.repo;
+    if stack.all_patches().next().is_some() {
+        return Err(anyhow!(
+            "branch `{}` already has patches; a bundle may only be imported into an \
+             empty stack",
+            stack.get_branch_name()
+        ));
+    }
+
+    let reader: Box<dyn Read> = if let Some(source_path) = source_path {
+        Box::new(
+            std::fs::File::open(source_path).with_context(|| format!("opening {source_path:?}"))?,
+        )
+    } else {
+        Box::new(std::io::stdin())
+    };
+    let mut archive = tar::Archive::new(reader);
+    let mut manifest: Option<BundleManifest> = None;
+    let mut diffs: HashMap<PathBuf, Vec<u8>> = HashMap::new();
+    for entry in archive.entries().context("reading bundle")? {
+        let mut entry = entry.context("reading bundle")?;
+        let path = entry.path()?.into_owned();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        if path == Path::new(BUNDLE_MANIFEST_PATH) {
+            manifest = Some(serde_json::from_slice(&content).context("parsing bundle manifest")?);
+        } else {
+            diffs.insert(path, content);
+        }
+    }
+    let manifest =
+        manifest.ok_or_else(|| anyhow!("bundle manifest `{BUNDLE_MANIFEST_PATH}` not found"))?;
+    if manifest.version != BUNDLE_VERSION {
+        return Err(anyhow!(
+            "unsupported bundle version {}; expected {BUNDLE_VERSION}",
+            manifest.version
+        ));
     }
+
+    let base_commit = if let Some(base_spec) = matches.get_one::<String>("base") {
+        repo.rev_parse_single_ex(base_spec)?
+            .object()?
+            .try_into_commit()?
+    } else {
+        gix::ObjectId::from_hex(manifest.base.as_bytes())
+            .ok()
+            .and_then(|base_id| repo.find_commit(base_id).ok())
+            .ok_or_else(|| {
+                anyhow!(
+                    "bundle base commit `{}` is not present in the repository; \
+                     use `--base` to import onto another commit",
+                    manifest.base
+                )
+            })?
+    };
+
+    let stupid = repo.stupid();
+    let mut commit_ids: HashMap<&str, gix::ObjectId> = HashMap::new();
+    let mut patches: Vec<(PatchName, &BundlePatch)> = Vec::with_capacity(manifest.patches.len());
+    for bundle_patch in &manifest.patches {
+        let patchname = PatchName::from_str(&bundle_patch.name)?;
+        if patches.iter().any(|(pn, _)| pn == &patchname) {
+            return Err(anyhow!("bundle has duplicate patch `{patchname}`"));
+        }
+        let mut parent_id = if let Some(parent) = bundle_patch.parent.as_deref() {
+            *commit_ids.get(parent).ok_or_else(|| {
+                anyhow!("parent `{parent}` of bundle patch `{patchname}` is not an earlier patch")
+            })?
+        } else {
+            base_commit.id
+        };
+        let get_diff = |path: &str| {
+            diffs
+                .get(Path::new(path))
+                .ok_or_else(|| anyhow!("bundle has no diff for patch `{patchname}`"))
+        };
+        for (index, ancestor) in bundle_patch.ancestors.iter().enumerate() {
+            let diff = get_diff(&bundle_ancestor_diff_path(patchname.as_ref(), index))?;
+            let tree_id = apply_bundle_diff(repo, &stupid, parent_id, diff)
+                .with_context(|| format!("applying ancestor of bundle patch `{patchname}`"))?;
+            parent_id = repo.commit_ex(
+                &ancestor.author.to_signature()?,
+                &ancestor.committer.to_signature()?,
+                &Message::from(ancestor.message.as_str()),
+                tree_id,
+                [parent_id],
+            )?;
+        }
+        let diff = get_diff(&bundle_diff_path(patchname.as_ref()))?;
+        let tree_id = apply_bundle_diff(repo, &stupid, parent_id, diff)
+            .with_context(|| format!("applying bundle patch `{patchname}`"))?;
+        let commit_id = repo.commit_ex(
+            &bundle_patch.author.to_signature()?,
+            &bundle_patch.committer.to_signature()?,
+            &Message::from(bundle_patch.message.as_str()),
+            tree_id,
+            [parent_id],
+        )?;
+        commit_ids.insert(bundle_patch.name.as_str(), commit_id);
+        patches.push((patchname, bundle_patch));
+    }
+
+    let with_status = |status: PatchStatus| -> Vec<PatchName> {
+        patches
+            .iter()
+            .filter(|(_, bundle_patch)| bundle_patch.status == status)
+            .map(|(pn, _)| pn.clone())
+            .collect()
+    };
+    let applied = with_status(PatchStatus::Applied);
+    let unapplied = with_status(PatchStatus::Unapplied);
+    let hidden = with_status(PatchStatus::Hidden);
+    let dependencies = patches
+        .iter()
+        .map(|(_, bundle_patch)| {
+            bundle_patch
+                .dependencies
+                .iter()
+                .map(|name| Ok(PatchName::from_str(name)?))
+                .collect::<Result<BTreeSet<_>>>()
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let move_base = base_commit.id != stack.base().id;
+
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            if move_base {
+                let base_commit = trans.repo().find_commit(base_commit.id)?;
+                trans.set_base(Rc::new(base_commit));
+            }
+            for (i, (patchname, bundle_patch)) in patches.iter().enumerate() {
+                trans.new_unapplied(patchname, commit_ids[bundle_patch.name.as_str()], i)?;
+                if !bundle_patch.meta.is_empty() {
+                    trans.set_patch_meta(patchname, bundle_patch.meta.clone());
+                }
+                trans.set_dependencies(patchname, dependencies[i].clone());
+            }
+            trans.reorder_patches(Some(&applied), Some(&unapplied), Some(&hidden))
+        })
+        .execute("import --bundle")?;
+
     Ok(())
 }
 
+/// Apply a diff from a stack bundle to the tree of `parent_id`.
+///
+/// Returns the resulting tree.
+fn apply_bundle_diff(
+    repo: &gix::Repository,
+    stupid: &StupidContext,
+    parent_id: gix::ObjectId,
+    diff: &[u8],
+) -> Result<gix::ObjectId> {
+    let parent_tree_id = repo.find_commit(parent_id)?.tree_id()?.detach();
+    if diff.is_empty() {
+        Ok(parent_tree_id)
+    } else {
+        stupid.with_temp_index(|stupid_temp| {
+            stupid_temp.read_tree(parent_tree_id)?;
+            stupid_temp.apply_to_index(diff.as_bstr())?;
+            stupid_temp.write_tree()
+        })
+    }
+}
+
 fn find_series_path(base: &Path) -> Result<PathBuf> {
     for entry in base.read_dir()? {
         let entry = entry?;
@@ -431,19 +893,155 @@ fn use_message_id(matches: &clap::ArgMatches, config: &gix::config::Snapshot) ->
     matches.get_flag("message-id") || config.boolean("stgit.import.message-id").unwrap_or(false)
 }
 
+/// An email message split from a mail or mbox source.
+struct Mail {
+    /// Subject of the email before any cleanup.
+    raw_subject: String,
+    tag: Option<SeriesTag>,
+    thread: ThreadHeaders,
+    headers: Headers,
+    message: BString,
+    diff: BString,
+}
+
+impl Mail {
+    fn describe(&self) -> &str {
+        self.headers.subject.as_deref().unwrap_or_default()
+    }
+}
+
 fn import_mail(stack: Stack, matches: &clap::ArgMatches, source_path: Option<&Path>) -> Result<()> {
     let out_dir = tempfile::tempdir()?;
     let missing_from_ok = matches.get_flag("mail");
     let keep_cr = matches.get_flag("keep-cr");
+    let strip_trailers = matches.get_flag("strip-trailers");
+    let collect_trailers = matches.get_flag("collect-trailers");
     let message_id = use_message_id(matches, &stack.repo.config_snapshot());
     let stupid = stack.repo.stupid();
     let num_patches = stupid.mailsplit(source_path, out_dir.path(), keep_cr, missing_from_ok)?;
-    let mut stack = stack;
+
+    let mut mails = Vec::with_capacity(num_patches);
     for i in 1..=num_patches {
         let patch_path = out_dir.path().join(format!("{i:04}"));
+        let thread = ThreadHeaders::parse(&std::fs::read(&patch_path)?);
         let patch_file = std::fs::File::open(patch_path)?;
-        let (mailinfo, message, diff) = stupid.mailinfo(Some(patch_file), message_id)?;
-        let headers = Headers::parse_mailinfo(mailinfo.as_bstr()).unwrap_or_default();
+        // The Message-ID trailer is added after stripping the other trailers.
+        let (mailinfo, message, diff) =
+            stupid.mailinfo(Some(patch_file), true, message_id && !strip_trailers)?;
+        let mut headers = Headers::parse_mailinfo(mailinfo.as_bstr()).unwrap_or_default();
+        let raw_subject = headers.subject.take().unwrap_or_default();
+        headers.subject = Some(mail::cleanup_subject(&raw_subject).to_string());
+        mails.push(Mail {
+            tag: SeriesTag::parse(&raw_subject),
+            raw_subject,
+            thread,
+            headers,
+            message,
+            diff,
+        });
+    }
+
+    let is_series = matches.get_flag("mbox")
+        && mails
+            .iter()
+            .filter_map(|mail| mail.tag)
+            .any(|tag| tag.total > 1 || tag.version > 1 || tag.is_cover_letter());
+
+    let (patches, cover, replies) = if is_series {
+        select_series(&stack, matches, mails)?
+    } else if collect_trailers {
+        let (patches, replies) = mails
+            .into_iter()
+            .partition(|mail| !mail::is_reply(&mail.raw_subject));
+        (patches, None, replies)
+    } else {
+        (mails, None, Vec::new())
+    };
+
+    let mut collected: Vec<Vec<(String, String)>> = vec![Vec::new(); patches.len()];
+    if collect_trailers {
+        let mut targets: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (pos, patch) in patches.iter().enumerate() {
+            if let Some(id) = patch.thread.message_id.as_deref() {
+                targets.entry(id).or_default().push(pos);
+            }
+        }
+        if let Some(id) = cover
+            .as_ref()
+            .and_then(|cover| cover.thread.message_id.as_deref())
+        {
+            targets.insert(id, (0..patches.len()).collect());
+        }
+        for reply in &replies {
+            let Some(positions) = reply.thread.ancestors().find_map(|id| targets.get(id)) else {
+                continue;
+            };
+            let trailers = mail::review_trailers(reply.message.as_ref());
+            for &pos in positions {
+                for trailer in &trailers {
+                    if !collected[pos].contains(trailer) {
+                        collected[pos].push(trailer.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut stack = stack;
+    for (patch, collected) in patches.into_iter().zip(collected) {
+        let Mail {
+            thread,
+            mut headers,
+            message,
+            diff,
+            ..
+        } = patch;
+
+        let message = if strip_trailers || !collected.is_empty() {
+            let body = message.to_str_lossy();
+            let subject = headers.subject.take().unwrap_or_default();
+            let message = if body.trim().is_empty() {
+                format!("{subject}\n")
+            } else {
+                format!("{subject}\n\n{}\n", body.trim_end())
+            };
+            // The subject is the first paragraph, thus a body consisting only of
+            // trailers is still recognized as a separate trailer block.
+            let message = if strip_trailers {
+                format!("{}\n", mail::strip_trailers(&message).trim_end())
+            } else {
+                message
+            };
+
+            let mut trailers: Vec<(String, String)> = collected
+                .into_iter()
+                .filter(|(key, value)| {
+                    let trailer = format!("{key}: {value}");
+                    !message.lines().any(|line| line.trim() == trailer)
+                })
+                .collect();
+            if strip_trailers && message_id {
+                if let Some(id) = thread.message_id {
+                    trailers.push((String::from("Message-ID"), format!("<{id}>")));
+                }
+            }
+
+            if trailers.is_empty() {
+                BString::from(message)
+            } else {
+                BString::from(
+                    stupid.interpret_trailers(
+                        message.as_bytes(),
+                        trailers
+                            .iter()
+                            .map(|(key, value)| (key.as_str(), value.as_str())),
+                    )?,
+                )
+            }
+        } else {
+            message
+        };
+
         stack = create_patch(
             stack,
             matches,
@@ -457,6 +1055,118 @@ fn import_mail(stack: Stack, matches: &clap::ArgMatches, source_path: Option<&Pa
     Ok(())
 }
 
+/// Select the patches of the latest version of a patch series from the mails of an
+/// mbox, in series order.
+///
+/// The series' cover letter and the mails not belonging to the series, e.g. replies,
+/// are returned separately. The cover letter is skipped or, with `--cover-description`,
+/// used as the branch description.
+fn select_series(
+    stack: &Stack,
+    matches: &clap::ArgMatches,
+    mails: Vec<Mail>,
+) -> Result<(Vec<Mail>, Option<Mail>, Vec<Mail>)> {
+    let version = mails
+        .iter()
+        .filter_map(|mail| mail.tag.map(|tag| tag.version))
+        .max()
+        .expect("series has tagged mails");
+    let total = mails
+        .iter()
+        .filter_map(|mail| mail.tag.filter(|tag| tag.version == version))
+        .map(|tag| tag.total)
+        .max()
+        .unwrap_or_default();
+
+    let mut series = BTreeMap::new();
+    let mut others = Vec::new();
+    let mut has_old_versions = false;
+    for mail in mails {
+        match mail.tag {
+            Some(tag) if tag.version == version => {
+                // A resent patch replaces the earlier one.
+                let subject = mail.describe().to_string();
+                if let Some(replaced) = series.insert(tag.index, mail) {
+                    print_warning_message(
+                        matches,
+                        &format!(
+                            "Patch {}/{} `{}` is replaced by later `{subject}`",
+                            tag.index,
+                            tag.total,
+                            replaced.describe(),
+                        ),
+                    );
+                }
+            }
+            Some(_) => {
+                has_old_versions = true;
+                others.push(mail);
+            }
+            None => {
+                if !mail::is_reply(&mail.raw_subject) {
+                    print_warning_message(
+                        matches,
+                        &format!(
+                            "Skipping `{}`: not tagged as part of the patch series",
+                            mail.describe()
+                        ),
+                    );
+                }
+                others.push(mail);
+            }
+        }
+    }
+
+    if has_old_versions {
+        print_info_message(
+            matches,
+            &format!("Importing version {version} of the patch series"),
+        );
+    }
+
+    let missing: Vec<String> = (1..=total)
+        .filter(|index| !series.contains_key(index))
+        .map(|index| format!("{index}/{total}"))
+        .collect();
+    if !missing.is_empty() {
+        let missing = missing.join(", ");
+        if matches.get_flag("allow-incomplete") {
+            print_warning_message(
+                matches,
+                &format!("Patch series is missing patches {missing}"),
+            );
+        } else {
+            return Err(anyhow!(
+                "patch series is missing patches {missing}; \
+                 use `--allow-incomplete` to import it anyway"
+            ));
+        }
+    }
+
+    let cover = series.remove(&0);
+    if let Some(cover) = cover.as_ref() {
+        if matches.get_flag("cover-description") {
+            let subject = cover.describe();
+            let body = cover.message.to_str_lossy();
+            let body = body.trim();
+            let description = if body.is_empty() {
+                subject.to_string()
+            } else {
+                format!("{subject}\n\n{body}")
+            };
+            let branchname = PartialRefName::from_str(stack.get_branch_name())?;
+            super::branch::set_description(stack.repo, &branchname, &description)?;
+        } else {
+            print_info_message(
+                matches,
+                &format!("Skipping cover letter `{}`", cover.describe()),
+            );
+        }
+    }
+
+    Ok((series.into_values().collect(), cover, others))
+}
+
 fn read_gz(source_file: std::fs::File, content: &mut Vec<u8>) -> Result<()> {
     flate2::read::GzDecoder::new(source_file).read_to_end(content)?;
     Ok(())
@@ -712,7 +1422,7 @@ fn split_patch(content: Vec<u8>) -> Result<(BString, BString)> {
 mod test {
     use bstr::B;
 
-    use super::{split_patch, stripname};
+    use super::{split_patch, stripname, SeriesEntry};
 
     #[test]
     fn patch_without_message() {
@@ -816,6 +1526,32 @@ mod test {
         let name = String::from("01-patch-name.patch.diff");
         assert_eq!(stripname(&name), "patch-name.patch");
     }
+
+    #[test]
+    fn series_entry_with_strip_level_and_guards() {
+        let entry = SeriesEntry::parse(b"fix.diff -p2 #+foo #-bar # comment #+baz")
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.patchname, b"fix.diff");
+        assert_eq!(entry.strip_level, Some(2));
+        assert_eq!(entry.guards, [(true, &b"foo"[..]), (false, &b"bar"[..])]);
+        assert!(!entry.is_selected(&[]));
+        assert!(entry.is_selected(&["foo"]));
+        assert!(!entry.is_selected(&["foo", "bar"]));
+        assert!(!entry.is_selected(&["baz"]));
+    }
+
+    #[test]
+    fn series_entry_comments_and_invalid_strip_level() {
+        assert!(SeriesEntry::parse(b"  # comment").unwrap().is_none());
+        assert!(SeriesEntry::parse(b"\t").unwrap().is_none());
+        let entry = SeriesEntry::parse(b"fix.diff#comment").unwrap().unwrap();
+        assert_eq!(entry.patchname, b"fix.diff");
+        assert!(entry.is_selected(&["foo"]));
+        assert!(SeriesEntry::parse(b"fix.diff -p").is_err());
+        assert!(SeriesEntry::parse(b"fix.diff -pab").is_err());
+        assert!(SeriesEntry::parse(b"fix.diff -p+1").is_err());
+    }
 }
 
 #[derive(Default, Debug)]
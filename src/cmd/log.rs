@@ -3,12 +3,14 @@
 //! `stg log` implementation.
 
 use anyhow::{anyhow, Result};
+use bstr::ByteSlice;
 use clap::{Arg, ArgMatches};
 
 use crate::{
     argset,
     branchloc::BranchLocator,
-    ext::RepositoryExtended,
+    ext::{CommitExtended, RepositoryExtended},
+    json,
     patch::{patchrange, PatchRange, RangeConstraint},
     stack::{InitializationPolicy, Stack, StackAccess},
     stupid::Stupid,
@@ -34,11 +36,18 @@ fn make() -> clap::Command {
              \n\
              The '--clear' option may be used to delete the stack's change history. \
              Undo and redo are unavailable on a stack without change history. Clearing \
-             the stack state history cannot be undone.",
+             the stack state history cannot be undone.\n\
+             \n\
+             The '--prune' option deletes only the older part of the stack's change \
+             history according to the retention policy set with the \
+             'stgit.log.maxEntries' and 'stgit.log.maxAge' configuration variables. \
+             States beyond either limit are deleted, while the current state is always \
+             retained. Pruning the stack state history cannot be undone, although the \
+             retained states may still be returned to with 'stg undo'.",
         )
         .override_usage(super::make_usage(
             "stg log",
-            &["[OPTIONS] [--] [patch]...", "--clear"],
+            &["[OPTIONS] [--] [patch]...", "--clear", "--prune"],
         ))
         .arg(
             Arg::new("patchranges-all")
@@ -87,6 +96,27 @@ fn make() -> clap::Command {
                 .action(clap::ArgAction::SetTrue)
                 .conflicts_with_all(["patchranges-all", "diff", "number", "full", "graphical"]),
         )
+        .arg(
+            Arg::new("prune")
+                .long("prune")
+                .help("Prune the stack history according to the retention policy")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "patchranges-all",
+                    "diff",
+                    "number",
+                    "full",
+                    "graphical",
+                    "clear",
+                ]),
+        )
+        .arg(argset::format_arg().conflicts_with_all([
+            "diff",
+            "full",
+            "graphical",
+            "clear",
+            "prune",
+        ]))
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
@@ -99,6 +129,8 @@ fn run(matches: &ArgMatches) -> Result<()> {
 
     if matches.get_flag("clear") {
         stack.clear_state_log("clear log")
+    } else if matches.get_flag("prune") {
+        prune(matches, stack)
     } else {
         let pathspecs: Option<Vec<String>> =
             if let Some(range_specs) = matches.get_many::<PatchRange>("patchranges-all") {
@@ -125,6 +157,35 @@ fn run(matches: &ArgMatches) -> Result<()> {
 
         if matches.get_flag("graphical") {
             stupid.gitk(simplified_parent_id, pathspecs)
+        } else if json::is_requested(matches) {
+            #[derive(serde::Serialize)]
+            struct Entry {
+                id: String,
+                message: String,
+                date: String,
+            }
+            #[derive(serde::Serialize)]
+            struct Output<'a> {
+                branch: &'a str,
+                entries: Vec<Entry>,
+            }
+            let num_commits = matches.get_one::<usize>("number").copied();
+            let mut entries = Vec::new();
+            for commit_id in stupid.rev_list_from(simplified_parent_id, pathspecs, num_commits)? {
+                let commit = repo.find_commit(commit_id)?;
+                entries.push(Entry {
+                    id: commit_id.to_string(),
+                    message: commit.message_ex().decode()?.trim_end().to_string(),
+                    date: commit
+                        .author_strict()?
+                        .time
+                        .format(gix::date::time::format::ISO8601_STRICT),
+                });
+            }
+            json::print(&Output {
+                branch: stack.get_branch_name(),
+                entries,
+            })
         } else {
             let num_commits = matches.get_one::<usize>("number").copied();
             stupid.log(
@@ -138,3 +199,39 @@ fn run(matches: &ArgMatches) -> Result<()> {
         }
     }
 }
+
+fn prune(matches: &ArgMatches, mut stack: Stack<'_>) -> Result<()> {
+    stack.check_no_pending_operation()?;
+
+    let config = stack.repo.config_snapshot();
+    let max_entries = match config.try_integer("stgit.log.maxEntries") {
+        Some(Ok(n)) if n >= 1 => Some(usize::try_from(n)?),
+        None => None,
+        _ => return Err(anyhow!("`stgit.log.maxEntries` must be a positive integer")),
+    };
+    let cutoff = if let Some(max_age) = config.string("stgit.log.maxAge") {
+        let max_age = max_age.to_str_lossy();
+        let max_age = max_age.trim();
+        let time = gix::date::parse(
+            &format!("{max_age} ago"),
+            Some(std::time::SystemTime::now()),
+        )
+        .map_err(|_| anyhow!("invalid `stgit.log.maxAge` value `{max_age}`"))?;
+        Some(time.seconds)
+    } else {
+        None
+    };
+    if max_entries.is_none() && cutoff.is_none() {
+        return Err(anyhow!(
+            "no stack log retention policy; set `stgit.log.maxEntries` or `stgit.log.maxAge`"
+        ));
+    }
+
+    let num_pruned = stack.prune_state_log(max_entries, cutoff, "prune log")?;
+    let plural = if num_pruned == 1 { "" } else { "s" };
+    crate::print_info_message(
+        matches,
+        &format!("Pruned {num_pruned} stack state{plural} from the log"),
+    );
+    Ok(())
+}
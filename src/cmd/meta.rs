@@ -0,0 +1,194 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg meta` implementation.
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+
+use crate::{
+    argset::{self, get_one_str},
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::{PatchLocator, PatchName},
+    stack::{InitializationPolicy, Stack, StackStateAccess},
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "meta",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Get, set, or list patch metadata")
+        .long_about(
+            "Get, set, or list patch metadata.\n\
+             \n\
+             Each patch may have arbitrary metadata associated with it in the form of \
+             key-value pairs. This metadata may be used to record, for example, \
+             free-form notes, a review status, labels such as \"wip\" or \"ready\", or \
+             an upstream URL for the patch.\n\
+             \n\
+             Patch metadata is recorded in the stack state and is thus subject to \
+             `stg undo` and `stg redo`. Metadata follows its patch when the patch is \
+             renamed or modified and is discarded when the patch is deleted or \
+             committed.\n\
+             \n\
+             Keys must start with an alphanumeric character and may only contain \
+             alphanumeric characters, '-', '_', and '.'.\n\
+             \n\
+             Use `stg series --meta=<key>` to display metadata values alongside the \
+             patch series.",
+        )
+        .disable_help_subcommand(true)
+        .subcommand_required(true)
+        .arg(argset::branch_arg())
+        .subcommand(
+            clap::Command::new("get")
+                .about("Print the value of a patch's metadata key")
+                .arg(patch_arg())
+                .arg(key_arg()),
+        )
+        .subcommand(
+            clap::Command::new("set")
+                .about("Set or unset a patch's metadata key")
+                .override_usage(super::make_usage(
+                    "stg meta set",
+                    &["<patch> <key> <value>", "--unset <patch> <key>"],
+                ))
+                .arg(patch_arg())
+                .arg(key_arg())
+                .arg(
+                    Arg::new("value")
+                        .help("Value to set")
+                        .required_unless_present("unset")
+                        .allow_hyphen_values(true),
+                )
+                .arg(
+                    Arg::new("unset")
+                        .long("unset")
+                        .help("Remove the key from the patch's metadata")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("value"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("list")
+                .about("List patch metadata")
+                .long_about(
+                    "List the metadata of the given patch as \"<key>=<value>\" lines. \
+                     When no patch is given, the metadata of all patches is listed as \
+                     \"<patch>:<key>=<value>\" lines.",
+                )
+                .arg(
+                    Arg::new("patch")
+                        .help("Patch to list metadata of")
+                        .value_name("patch")
+                        .allow_hyphen_values(true)
+                        .value_parser(clap::value_parser!(PatchLocator)),
+                ),
+        )
+}
+
+fn patch_arg() -> Arg {
+    Arg::new("patch")
+        .help("Patch name")
+        .value_name("patch")
+        .required(true)
+        .allow_hyphen_values(true)
+        .value_parser(clap::value_parser!(PatchLocator))
+}
+
+fn key_arg() -> Arg {
+    Arg::new("key")
+        .help("Metadata key")
+        .value_name("key")
+        .required(true)
+        .value_parser(parse_key)
+}
+
+/// Validate metadata key.
+fn parse_key(s: &str) -> Result<String> {
+    let mut chars = s.chars();
+    if chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        Ok(s.to_string())
+    } else {
+        Err(anyhow!("invalid metadata key `{s}`"))
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::from_branch_locator(
+        &repo,
+        matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::AllowUninitialized,
+    )?;
+
+    let (subname, submatches) = matches.subcommand().expect("subcommand is required");
+
+    let opt_patchname = submatches
+        .get_one::<PatchLocator>("patch")
+        .map(|loc| loc.resolve_name(&stack))
+        .transpose()?;
+
+    match subname {
+        "get" => {
+            let patchname = opt_patchname.expect("required argument");
+            let key = get_one_str(submatches, "key").expect("required argument");
+            let value = stack
+                .get_patch_meta(&patchname)
+                .get(key)
+                .ok_or_else(|| anyhow!("patch `{patchname}` has no `{key}` metadata"))?;
+            println!("{value}");
+            Ok(())
+        }
+        "set" => {
+            let patchname = opt_patchname.expect("required argument");
+            let key = get_one_str(submatches, "key").expect("required argument");
+            let mut meta = stack.get_patch_meta(&patchname).clone();
+            if submatches.get_flag("unset") {
+                if meta.remove(key).is_none() {
+                    return Err(anyhow!("patch `{patchname}` has no `{key}` metadata"));
+                }
+            } else {
+                let value = get_one_str(submatches, "value").expect("required unless unset");
+                meta.insert(key.to_string(), value.to_string());
+            }
+            stack
+                .setup_transaction()
+                .with_output_stream(get_color_stdout(matches))
+                .transact(|trans| {
+                    trans.set_patch_meta(&patchname, meta);
+                    Ok(())
+                })
+                .execute(&format!("meta {patchname}"))?;
+            Ok(())
+        }
+        "list" => {
+            let print_patch_meta = |patchname: &PatchName, prefix: bool| {
+                for (key, value) in stack.get_patch_meta(patchname) {
+                    if prefix {
+                        println!("{patchname}:{key}={value}");
+                    } else {
+                        println!("{key}={value}");
+                    }
+                }
+            };
+            if let Some(patchname) = opt_patchname.as_ref() {
+                print_patch_meta(patchname, false);
+            } else {
+                for patchname in stack.all_patches() {
+                    print_patch_meta(patchname, true);
+                }
+            }
+            Ok(())
+        }
+        s => panic!("unhandled meta subcommand {s}"),
+    }
+}
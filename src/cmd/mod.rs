@@ -7,14 +7,21 @@
 
 use clap::builder::StyledStr;
 
+pub(crate) mod abort;
+pub(crate) mod absorb;
+pub(crate) mod batch;
+pub(crate) mod bisect;
 pub(crate) mod branch;
 pub(crate) mod clean;
 pub(crate) mod commit;
 pub(crate) mod completion;
+pub(crate) mod r#continue;
 pub(crate) mod delete;
+pub(crate) mod deps;
 pub(crate) mod diff;
 pub(crate) mod edit;
 pub(crate) mod email;
+pub(crate) mod exec;
 pub(crate) mod export;
 pub(crate) mod files;
 pub(crate) mod float;
@@ -25,27 +32,37 @@ pub(crate) mod id;
 pub(crate) mod import;
 pub(crate) mod init;
 pub(crate) mod log;
+pub(crate) mod meta;
+pub(crate) mod move_changes;
 pub(crate) mod new;
 pub(crate) mod next;
+pub(crate) mod patch_history;
 pub(crate) mod patches;
 pub(crate) mod pick;
 pub(crate) mod pop;
 pub(crate) mod prev;
+pub(crate) mod publish_each;
 pub(crate) mod pull;
 pub(crate) mod push;
+pub(crate) mod range_diff;
 pub(crate) mod rebase;
 pub(crate) mod redo;
 pub(crate) mod refresh;
 pub(crate) mod rename;
 pub(crate) mod repair;
 pub(crate) mod reset;
+pub(crate) mod revert_patch;
 pub(crate) mod series;
 pub(crate) mod show;
 pub(crate) mod sink;
+pub(crate) mod skip;
 pub(crate) mod spill;
+pub(crate) mod split;
 pub(crate) mod squash;
+pub(crate) mod stack;
 pub(crate) mod sync;
 pub(crate) mod top;
+pub(crate) mod trailers;
 pub(crate) mod uncommit;
 pub(crate) mod undo;
 pub(crate) mod unhide;
@@ -81,14 +98,21 @@ pub(crate) struct StGitCommand {
 /// This is used in [`crate::main`] for command line argument parsing and eventual
 /// dispatch of a subcommand.
 pub(crate) const STGIT_COMMANDS: &[StGitCommand] = &[
+    abort::STGIT_COMMAND,
+    absorb::STGIT_COMMAND,
+    batch::STGIT_COMMAND,
+    bisect::STGIT_COMMAND,
     branch::STGIT_COMMAND,
     clean::STGIT_COMMAND,
     commit::STGIT_COMMAND,
     completion::STGIT_COMMAND,
+    r#continue::STGIT_COMMAND,
     delete::STGIT_COMMAND,
+    deps::STGIT_COMMAND,
     diff::STGIT_COMMAND,
     edit::STGIT_COMMAND,
     email::STGIT_COMMAND,
+    exec::STGIT_COMMAND,
     export::STGIT_COMMAND,
     files::STGIT_COMMAND,
     float::STGIT_COMMAND,
@@ -99,27 +123,37 @@ pub(crate) const STGIT_COMMANDS: &[StGitCommand] = &[
     import::STGIT_COMMAND,
     init::STGIT_COMMAND,
     log::STGIT_COMMAND,
+    meta::STGIT_COMMAND,
+    move_changes::STGIT_COMMAND,
     new::STGIT_COMMAND,
     next::STGIT_COMMAND,
+    patch_history::STGIT_COMMAND,
     patches::STGIT_COMMAND,
     pick::STGIT_COMMAND,
     pop::STGIT_COMMAND,
     prev::STGIT_COMMAND,
+    publish_each::STGIT_COMMAND,
     pull::STGIT_COMMAND,
     push::STGIT_COMMAND,
+    range_diff::STGIT_COMMAND,
     rebase::STGIT_COMMAND,
     redo::STGIT_COMMAND,
     refresh::STGIT_COMMAND,
     rename::STGIT_COMMAND,
     repair::STGIT_COMMAND,
     reset::STGIT_COMMAND,
+    revert_patch::STGIT_COMMAND,
     series::STGIT_COMMAND,
     show::STGIT_COMMAND,
     sink::STGIT_COMMAND,
+    skip::STGIT_COMMAND,
     spill::STGIT_COMMAND,
+    split::STGIT_COMMAND,
     squash::STGIT_COMMAND,
+    stack::STGIT_COMMAND,
     sync::STGIT_COMMAND,
     top::STGIT_COMMAND,
+    trailers::STGIT_COMMAND,
     uncommit::STGIT_COMMAND,
     undo::STGIT_COMMAND,
     unhide::STGIT_COMMAND,
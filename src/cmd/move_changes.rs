@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg move-changes` implementation.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgGroup, ArgMatches, ValueHint};
+
+use crate::{
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::{LocationConstraint, PatchLocator, PatchName},
+    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "move-changes",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Move changes from one patch to another")
+        .long_about(
+            "Move some of a patch's changes to another patch.\n\
+             \n\
+             The changes to move are selected either by path, with the <path> \
+             arguments, or by interactively choosing the hunks of the '--from' patch \
+             with '--interactive'. The selected changes are removed from the '--from' \
+             patch and added to the '--to' patch. The two patches may be anywhere in \
+             the stack, applied or unapplied.\n\
+             \n\
+             The applied patches from the lower of the two patches and up are popped \
+             and pushed back with the moved changes. The stack is updated in a single \
+             operation that may be undone with 'stg undo'. If pushing a patch results \
+             in conflicts, the operation stops with the conflicting patch as the \
+             topmost patch. Once the conflicts are resolved, the remaining patches may \
+             be pushed with 'stg continue'.",
+        )
+        .override_usage(super::make_usage(
+            "stg move-changes",
+            &[
+                "[--from <patch>] --to <patch> -- <path>...",
+                "[--from <patch>] --to <patch> --interactive",
+            ],
+        ))
+        .arg(
+            Arg::new("from")
+                .long("from")
+                .short('f')
+                .help("Move changes from <patch>, defaults to the topmost patch")
+                .value_name("patch")
+                .num_args(1)
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(
+            Arg::new("to")
+                .long("to")
+                .short('t')
+                .help("Move changes to <patch>")
+                .value_name("patch")
+                .num_args(1)
+                .required(true)
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(
+            Arg::new("pathspecs")
+                .help("Move the changes to files matching path(s)")
+                .value_name("path")
+                .last(true)
+                .num_args(1..)
+                .value_parser(clap::value_parser!(PathBuf))
+                .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            Arg::new("interactive")
+                .long("interactive")
+                .short('i')
+                .help("Interactively select the hunks to move")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .group(
+            ArgGroup::new("selection")
+                .args(["pathspecs", "interactive"])
+                .required(true),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let stupid = repo.stupid();
+
+    repo.check_repository_state()?;
+    stupid.statuses(None)?.check_conflicts()?;
+    stack.check_head_top_mismatch()?;
+
+    let from_patchname = if let Some(patch_loc) = matches.get_one::<PatchLocator>("from") {
+        patch_loc
+            .resolve_name(&stack)?
+            .constrain(&stack, LocationConstraint::Visible)?
+    } else if let Some(top_patchname) = stack.applied().last() {
+        top_patchname.clone()
+    } else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+    let to_patchname = matches
+        .get_one::<PatchLocator>("to")
+        .expect("required argument")
+        .resolve_name(&stack)?
+        .constrain(&stack, LocationConstraint::Visible)?;
+    if from_patchname == to_patchname {
+        return Err(anyhow!(
+            "cannot move changes from `{from_patchname}` to itself"
+        ));
+    }
+
+    let from_commit = stack.get_patch_commit(&from_patchname);
+    let from_parent_tree_id = from_commit.get_parent_commit()?.tree_id()?.detach();
+    let from_tree_id = from_commit.tree_id()?.detach();
+
+    // The tree of the `--from` patch without the selected changes.
+    let remaining_tree_id = super::split::select_changes(
+        &stupid,
+        matches,
+        &from_patchname,
+        from_parent_tree_id,
+        from_tree_id,
+        false,
+        "Move",
+    )?;
+    if remaining_tree_id == from_tree_id {
+        return Err(anyhow!("no changes selected to move"));
+    }
+
+    let visible: Vec<&PatchName> = stack.applied().iter().chain(stack.unapplied()).collect();
+    let position = |patchname: &PatchName| {
+        visible
+            .iter()
+            .position(|pn| *pn == patchname)
+            .expect("patch is visible")
+    };
+    let moving_up = position(&from_patchname) < position(&to_patchname);
+    let committer = repo.get_committer()?;
+
+    // The lower of the two patches gets a new tree with the moved changes added or
+    // removed. The upper patch's diff is adjusted such that, once pushed onto the
+    // updated lower patch, it ends up with the same tree as before.
+    let (lower_patchname, lower_commit_id, upper_patchname, upper_commit_id) = if moving_up {
+        let lower_commit_id = repo.commit_ex(
+            &from_commit.author_strict()?,
+            committer,
+            &from_commit.message_ex(),
+            remaining_tree_id,
+            [from_commit.get_parent_commit()?.id],
+        )?;
+
+        // The upper patch is given a parent without the moved changes such that its
+        // diff includes them. When the changes cannot be removed from below the upper
+        // patch, the patches in between are bound to conflict anyway and the upper
+        // patch is instead based on the updated lower patch.
+        let to_commit = stack.get_patch_commit(&to_patchname);
+        let to_parent = to_commit.get_parent_commit()?;
+        let new_parent_id = if let Some(new_parent_tree_id) = super::absorb::apply_treediff_onto(
+            &stupid,
+            to_parent.tree_id()?.detach(),
+            from_tree_id,
+            remaining_tree_id,
+        )? {
+            repo.commit_ex(
+                &to_parent.author_strict()?,
+                committer,
+                &to_parent.message_ex(),
+                new_parent_tree_id,
+                [to_parent.id],
+            )?
+        } else {
+            lower_commit_id
+        };
+        let upper_commit_id = repo.commit_ex(
+            &to_commit.author_strict()?,
+            committer,
+            &to_commit.message_ex(),
+            to_commit.tree_id()?.detach(),
+            [new_parent_id],
+        )?;
+        (
+            &from_patchname,
+            lower_commit_id,
+            &to_patchname,
+            upper_commit_id,
+        )
+    } else {
+        let to_commit = stack.get_patch_commit(&to_patchname);
+        let new_to_tree_id = super::absorb::apply_treediff_onto(
+            &stupid,
+            to_commit.tree_id()?.detach(),
+            remaining_tree_id,
+            from_tree_id,
+        )?
+        .ok_or_else(|| anyhow!("selected changes do not apply cleanly to `{to_patchname}`"))?;
+        let lower_commit_id = repo.commit_ex(
+            &to_commit.author_strict()?,
+            committer,
+            &to_commit.message_ex(),
+            new_to_tree_id,
+            [to_commit.get_parent_commit()?.id],
+        )?;
+        let upper_commit_id = repo.commit_ex(
+            &from_commit.author_strict()?,
+            committer,
+            &from_commit.message_ex(),
+            remaining_tree_id,
+            [from_commit.get_parent_commit()?.id],
+        )?;
+        (
+            &to_patchname,
+            lower_commit_id,
+            &from_patchname,
+            upper_commit_id,
+        )
+    };
+    let orig_state = stack.state_commit_id()?;
+
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .with_output_stream(get_color_stdout(matches))
+        .resumable_unless_pending("move-changes", orig_state)
+        .transact(|trans| {
+            let to_push =
+                if let Some(pos) = trans.applied().iter().position(|pn| pn == lower_patchname) {
+                    let to_pop = trans.applied()[pos..].to_vec();
+                    let popped_extra = trans.pop_patches(|pn| to_pop.contains(pn))?;
+                    assert!(popped_extra.is_empty());
+                    to_pop
+                } else {
+                    Vec::new()
+                };
+            trans.update_patch(lower_patchname, lower_commit_id)?;
+            trans.update_patch(upper_patchname, upper_commit_id)?;
+            trans.push_patches(&to_push, false)
+        })
+        .execute(&format!("move-changes {from_patchname} to {to_patchname}"))?;
+
+    Ok(())
+}
@@ -11,6 +11,7 @@ use super::refresh;
 use crate::{
     color::get_color_stdout,
     ext::{RepositoryExtended, SignatureExtended},
+    hook::StackHook,
     patch::{patchedit, PatchName},
     stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
@@ -221,6 +222,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
     stack
         .setup_transaction()
         .with_output_stream(get_color_stdout(matches))
+        .run_hook(StackHook::PostNew)
         .transact(|trans| trans.new_applied(&patchname, commit_id))
         .execute(&format!("new: {patchname}"))?;
     Ok(())
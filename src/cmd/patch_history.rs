@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg patch-history` implementation.
+
+use std::{collections::HashSet, io::Write, rc::Rc};
+
+use anyhow::{anyhow, Result};
+use bstr::ByteSlice;
+use clap::{Arg, ArgMatches};
+use termcolor::WriteColor;
+
+use crate::{
+    argset,
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::{PatchLocator, PatchName},
+    stack::{InitializationPolicy, Stack, StackAccess, StackState, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "patch-history",
+    category: super::CommandCategory::PatchInspection,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Show the previous versions of a patch")
+        .long_about(
+            "List each distinct commit a patch has had, as recorded in the stack log, \
+             from newest to oldest.\n\
+             \n\
+             Each version is shown as '<patch>@{<n>}' along with its abbreviated \
+             commit id, the time the version was recorded in the stack log, and the \
+             StGit command that produced it. Version '@{0}' is the patch's current \
+             commit. The history extends back to when the patch was created or last \
+             renamed.\n\
+             \n\
+             With '--diff', the changes between two versions of the patch are shown \
+             instead, as produced by git-range-diff(1). A previous version of the \
+             patch may be restored with 'stg revert-patch'.",
+        )
+        .arg(
+            Arg::new("patch")
+                .help("Patch to show the history of, defaults to the topmost patch")
+                .value_name("patch")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(
+            Arg::new("diff")
+                .long("diff")
+                .short('d')
+                .help("Show the interdiff between versions <n> and <m>")
+                .value_name("n..m")
+                .num_args(1)
+                .value_parser(parse_version_range),
+        )
+        .arg(argset::diff_opts_arg())
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+
+    let patchname = if let Some(patch_loc) = matches.get_one::<PatchLocator>("patch") {
+        patch_loc.resolve_name(&stack)?
+    } else if let Some(top_patchname) = stack.applied().last() {
+        top_patchname.clone()
+    } else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+
+    let versions = patch_versions(&stack, &patchname)?;
+    let mut stdout = get_color_stdout(matches);
+
+    if let Some(&(old, new)) = matches.get_one::<(usize, usize)>("diff") {
+        let get_version = |n: usize| {
+            versions
+                .get(n)
+                .ok_or_else(|| anyhow!("`{patchname}@{{{n}}}` does not exist"))
+        };
+        let old_commit = &get_version(old)?.commit;
+        let new_commit = &get_version(new)?.commit;
+        let diff_opts = argset::get_diff_opts(matches, &repo.config_snapshot(), false, false);
+        let output = repo.stupid().range_diff(
+            old_commit.id,
+            new_commit.id,
+            crate::color::use_color(matches),
+            &diff_opts,
+        )?;
+        stdout.write_all(&output)?;
+        return Ok(());
+    }
+
+    let mut color_spec = termcolor::ColorSpec::new();
+    for (n, version) in versions.iter().enumerate() {
+        stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Yellow)))?;
+        write!(stdout, "{patchname}@{{{n}}}")?;
+        stdout.set_color(color_spec.set_fg(None))?;
+        writeln!(
+            stdout,
+            " {} {} {}",
+            version.commit.id().shorten_or_id(),
+            version.time.format(gix::date::time::format::ISO8601),
+            version.command,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// A distinct commit that a patch had at some point in the stack log.
+pub(super) struct PatchVersion<'repo> {
+    /// The patch's commit.
+    pub(super) commit: Rc<gix::Commit<'repo>>,
+
+    /// Time at which the commit became the patch's commit.
+    pub(super) time: gix::date::Time,
+
+    /// Summary of the stack log message of the operation that produced the commit.
+    pub(super) command: String,
+}
+
+/// Find the distinct versions of a patch by walking the stack log.
+///
+/// The versions are ordered from newest to oldest such that the patch's current commit
+/// is the first version. A commit that the patch returned to, e.g. with `stg undo`, is
+/// only listed at its most recent position.
+pub(super) fn patch_versions<'repo>(
+    stack: &Stack<'repo>,
+    patchname: &PatchName,
+) -> Result<Vec<PatchVersion<'repo>>> {
+    let mut versions: Vec<PatchVersion<'repo>> = Vec::new();
+    let mut seen: HashSet<gix::ObjectId> = HashSet::new();
+    let mut last_id: Option<gix::ObjectId> = None;
+    let mut state_commit = Rc::new(
+        stack
+            .repo
+            .find_reference(stack.get_stack_refname())?
+            .peel_to_commit()?,
+    );
+
+    loop {
+        let state = StackState::from_commit(stack.repo, &state_commit)?;
+        if !state.has_patch(patchname) {
+            break;
+        }
+        let commit = state.get_patch_commit(patchname);
+        let time = state_commit.time()?;
+        let command = state_commit
+            .message_raw()?
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_str_lossy()
+            .to_string();
+
+        if last_id == Some(commit.id) {
+            // An older state with the same commit; the version originated earlier.
+            let version = versions.last_mut().expect("last version is recorded");
+            version.time = time;
+            version.command = command;
+        } else if seen.insert(commit.id) {
+            last_id = Some(commit.id);
+            versions.push(PatchVersion {
+                commit: commit.clone(),
+                time,
+                command,
+            });
+        } else {
+            last_id = None;
+        }
+
+        if let Some(prev) = state.prev {
+            state_commit = prev;
+        } else {
+            break;
+        }
+    }
+
+    Ok(versions)
+}
+
+fn parse_version_range(s: &str) -> Result<(usize, usize)> {
+    s.split_once("..")
+        .and_then(|(old, new)| Some((old.parse().ok()?, new.parse().ok()?)))
+        .ok_or_else(|| anyhow!("expected version range of the form `<n>..<m>`"))
+}
@@ -15,6 +15,7 @@ use crate::{
     argset,
     branchloc::BranchLocator,
     ext::{CommitExtended, RepositoryExtended},
+    json,
     stack::{Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
 };
@@ -48,9 +49,11 @@ fn make() -> clap::Command {
                 .long("diff")
                 .short('d')
                 .help("Show the diff for the given paths")
-                .action(clap::ArgAction::SetTrue),
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("format"),
         )
         .arg(argset::diff_opts_arg())
+        .arg(argset::format_arg())
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
@@ -98,7 +101,23 @@ fn run(matches: &ArgMatches) -> Result<()> {
 
     let revs = stupid.rev_list(stack.base().id, stack.top().id, Some(&pathspecs))?;
 
-    if diff_flag {
+    if json::is_requested(matches) {
+        #[derive(serde::Serialize)]
+        struct Output<'a> {
+            branch: &'a str,
+            patches: Vec<json::PatchInfo<'a>>,
+        }
+        let patches = stack
+            .applied()
+            .iter()
+            .filter(|pn| revs.contains(&stack.get_patch_commit_id(pn)))
+            .map(|pn| json::PatchInfo::new(&stack, pn))
+            .collect::<Result<Vec<_>>>()?;
+        json::print(&Output {
+            branch: stack.get_branch_name(),
+            patches,
+        })?;
+    } else if diff_flag {
         // TODO: pager?
         let stdout = std::io::stdout();
         let mut stdout = stdout.lock();
@@ -18,9 +18,10 @@ use crate::{
     color::get_color_stdout,
     ext::{CommitExtended, RepositoryExtended},
     patch::{
-        revspec, PatchName, RangeConstraint, RangeRevisionSpec, SingleRevisionSpec, StGitRevision,
+        patchrange, revspec, PatchName, PatchRange, RangeConstraint, RangeRevisionSpec,
+        SingleRevisionSpec, StGitRevision,
     },
-    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    stack::{InitializationPolicy, Stack, StackAccess, StackState, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -47,12 +48,20 @@ fn make() -> clap::Command {
              option is a format string as may be supplied to the '--pretty' option of \
              'git show'. The default is \"format:%B%n(imported from commit %H)\", \
              which appends the commit hash of the picked commit to the patch's commit \
-             message.",
+             message.\n\
+             \n\
+             With the '--from-repo' option, patches are picked from the stack of \
+             another local repository. The sources are then patch names or ranges in \
+             that stack, which defaults to the stack of the repository's current \
+             branch unless '--branch' is given. The picked patches' commits are copied \
+             into the current repository as they are, preserving their authorship, \
+             committer, message, and patch metadata.",
         )
         .override_usage(super::make_usage(
             "stg pick",
             &[
                 "[OPTIONS] <source>...",
+                "[OPTIONS] --from-repo <path> [--branch <branch>] <patch>...",
                 "[OPTIONS] [--name NAME] [--parent COMMITTISH] <source>",
                 "[OPTIONS] --fold [--file PATH]... <source>...",
                 "[OPTIONS] --update <source>...",
@@ -74,6 +83,24 @@ fn make() -> clap::Command {
                 .value_name("branch")
                 .value_parser(clap::value_parser!(BranchLocator)),
         )
+        .arg(
+            Arg::new("from-repo")
+                .long("from-repo")
+                .help("Pick patches from the stack of the repository at <path>")
+                .value_name("path")
+                .value_hint(clap::ValueHint::DirPath)
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with_all([
+                    "ref-branch",
+                    "revert",
+                    "expose",
+                    "parent",
+                    "committer-date-is-author-date",
+                    "fold",
+                    "update",
+                ]),
+        )
+        .arg(from_branch_arg())
         .arg(
             Arg::new("revert")
                 .long("revert")
@@ -161,6 +188,25 @@ fn run(matches: &clap::ArgMatches) -> Result<()> {
         stack.check_head_top_mismatch()?;
     }
 
+    if let Some(from_repo) = matches.get_one::<PathBuf>("from-repo") {
+        let ranges = matches
+            .get_raw("stgit-revision")
+            .expect("required argument")
+            .map(|raw| {
+                raw.to_str()
+                    .ok_or_else(|| anyhow!("patch range `{}` is not UTF-8", raw.to_string_lossy()))
+                    .and_then(|s| Ok(PatchRange::from_str(s)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        return pick_from_repo(
+            stack,
+            matches,
+            from_repo,
+            &ranges,
+            !matches.get_flag("noapply"),
+        );
+    }
+
     let picks = revspec::resolve(
         &repo,
         Some(&ref_stack),
@@ -360,3 +406,116 @@ fn pick_picks(
         .execute("pick")?;
     Ok(())
 }
+
+/// Make the `--branch` argument selecting the stack of the `--from-repo` repository.
+pub(super) fn from_branch_arg() -> Arg {
+    Arg::new("from-branch")
+        .long("branch")
+        .help("Use the stack of <branch> in the '--from-repo' repository")
+        .long_help(
+            "Use the stack of <branch> in the repository given with '--from-repo'. \
+             The default is the stack of that repository's current branch.",
+        )
+        .value_name("branch")
+        .requires("from-repo")
+}
+
+/// Copy patches from the stack of another local repository.
+///
+/// The other stack's state, including all of its patch commits, is fetched into the
+/// current repository such that the patches' commits can be used as-is. The copied
+/// patches are added to the top of the stack, and pushed if `apply` is true.
+pub(super) fn pick_from_repo(
+    stack: Stack,
+    matches: &clap::ArgMatches,
+    from_repo: &Path,
+    ranges: &[PatchRange],
+    apply: bool,
+) -> Result<()> {
+    let repo = stack.repo;
+    let from_repo_str = from_repo
+        .to_str()
+        .ok_or_else(|| anyhow!("repository path `{}` is not UTF-8", from_repo.display()))?;
+    let other_repo = gix::open(from_repo)
+        .with_context(|| format!("opening repository `{}`", from_repo.display()))?;
+    let branch_name = if let Some(branch_name) = matches.get_one::<String>("from-branch") {
+        branch_name.clone()
+    } else {
+        other_repo
+            .head_name()?
+            .and_then(|name| {
+                name.as_bstr()
+                    .strip_prefix(b"refs/heads/")
+                    .and_then(|name| name.to_str().ok())
+                    .map(String::from)
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "repository `{}` has a detached HEAD; use `--branch` to select a stack",
+                    from_repo.display()
+                )
+            })?
+    };
+    let stack_refname = format!("refs/stacks/{branch_name}");
+    let state_id = other_repo
+        .try_find_reference(stack_refname.as_str())?
+        .ok_or_else(|| {
+            anyhow!(
+                "branch `{branch_name}` of `{}` is not initialized with StGit",
+                from_repo.display()
+            )
+        })?
+        .peel_to_id_in_place()?
+        .detach();
+    drop(other_repo);
+
+    repo.stupid()
+        .fetch(from_repo_str, [stack_refname.as_str()])?;
+    let other_state = StackState::from_commit(repo, &repo.find_commit(state_id)?)?;
+    let patchnames = patchrange::resolve_names(
+        &other_state,
+        ranges,
+        RangeConstraint::VisibleWithAppliedBoundary,
+    )?;
+
+    if patchnames.len() > 1 && matches.contains_id("name") {
+        return Err(anyhow!("--name can only be specified with one patch"));
+    }
+
+    let mut new_patches: Vec<PatchName> = Vec::with_capacity(patchnames.len());
+    for patchname in &patchnames {
+        let disallow: Vec<&PatchName> = stack.all_patches().chain(new_patches.iter()).collect();
+        let new_patchname = matches
+            .get_one::<PatchName>("name")
+            .unwrap_or(patchname)
+            .clone()
+            .uniquify(&[], &disallow);
+        new_patches.push(new_patchname);
+    }
+
+    stack
+        .setup_transaction()
+        .with_output_stream(get_color_stdout(matches))
+        .use_index_and_worktree(true)
+        .transact(|trans| {
+            for (i, (patchname, new_patchname)) in
+                patchnames.iter().zip(new_patches.iter()).enumerate()
+            {
+                trans.new_unapplied(
+                    new_patchname,
+                    other_state.get_patch_commit_id(patchname),
+                    i,
+                )?;
+                let meta = other_state.get_patch_meta(patchname);
+                if !meta.is_empty() {
+                    trans.set_patch_meta(new_patchname, meta.clone());
+                }
+            }
+            if apply {
+                trans.push_patches(&new_patches, false)?;
+            }
+            Ok(())
+        })
+        .execute(&format!("pick --from-repo {}", from_repo.display()))?;
+    Ok(())
+}
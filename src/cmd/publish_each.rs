@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg publish-each` implementation.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+
+use crate::{
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::PatchName,
+    print_info_message,
+    stack::{InitializationPolicy, PublishedPatch, Stack, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "publish-each",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Push each applied patch to its own remote branch")
+        .long_about(
+            "Push each applied patch to its own branch in a remote repository, e.g. \
+             for reviewing stacked patches one at a time.\n\
+             \n\
+             Each applied patch's commit is pushed to 'refs/heads/<prefix>/<patch>' \
+             in the remote repository. When <prefix> starts with 'refs/', the patch \
+             commits are instead pushed to '<prefix>/<patch>', e.g. \
+             'refs/for/main/<patch>'.\n\
+             \n\
+             The published patches are recorded in the stack's metadata. Subsequent \
+             runs only force-update the remote branches of patches whose commits \
+             changed since they were last published. Remote branches of patches \
+             that were deleted or renamed since being published are deleted. The \
+             remote branches of unapplied and hidden patches are left as-is.",
+        )
+        .arg(
+            Arg::new("remote")
+                .long("remote")
+                .short('r')
+                .help("Push to <remote>")
+                .value_name("remote")
+                .num_args(1)
+                .required(true),
+        )
+        .arg(
+            Arg::new("prefix")
+                .long("prefix")
+                .short('p')
+                .help("Push patches to branches prefixed with <prefix>")
+                .value_name("prefix")
+                .num_args(1)
+                .required(true),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .short('n')
+                .help("Show which branches would be updated without pushing")
+                .action(clap::ArgAction::SetTrue),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let remote = matches
+        .get_one::<String>("remote")
+        .expect("required argument");
+    let prefix = matches
+        .get_one::<String>("prefix")
+        .expect("required argument")
+        .trim_end_matches('/');
+    if prefix.is_empty() {
+        return Err(anyhow!("branch prefix must not be empty"));
+    }
+    let dry_run = matches.get_flag("dry-run");
+
+    let previous = stack
+        .published_patches()
+        .get(remote)
+        .cloned()
+        .unwrap_or_default();
+    let mut published: BTreeMap<PatchName, PublishedPatch> = BTreeMap::new();
+    let mut refspecs: Vec<String> = Vec::new();
+
+    for patchname in stack.applied() {
+        let refname = if prefix.starts_with("refs/") {
+            format!("{prefix}/{patchname}")
+        } else {
+            format!("refs/heads/{prefix}/{patchname}")
+        };
+        gix::refs::FullName::try_from(refname.as_str())
+            .map_err(|_| anyhow!("invalid remote reference name `{refname}`"))?;
+        let commit_id = stack.get_patch_commit(patchname).id;
+        let patch = PublishedPatch { refname, commit_id };
+        if previous.get(patchname) != Some(&patch) {
+            print_info_message(
+                matches,
+                &format!("Publishing `{patchname}` to `{}`", patch.refname),
+            );
+            refspecs.push(format!("{commit_id}:{}", patch.refname));
+        }
+        published.insert(patchname.clone(), patch);
+    }
+
+    for (patchname, patch) in &previous {
+        if published
+            .values()
+            .any(|published_patch| published_patch.refname == patch.refname)
+        {
+            continue;
+        }
+        if stack.has_patch(patchname) && !published.contains_key(patchname) {
+            // Unapplied and hidden patches remain published as-is.
+            published.insert(patchname.clone(), patch.clone());
+        } else {
+            print_info_message(matches, &format!("Deleting `{}`", patch.refname));
+            refspecs.push(format!(":{}", patch.refname));
+        }
+    }
+
+    if refspecs.is_empty() {
+        print_info_message(
+            matches,
+            &format!("All applied patches are already published to `{remote}`"),
+        );
+        return Ok(());
+    } else if dry_run {
+        return Ok(());
+    }
+
+    repo.stupid().push_force(remote, &refspecs)?;
+
+    stack
+        .setup_transaction()
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            trans.set_published_patches(remote, published);
+            Ok(())
+        })
+        .execute(&format!("publish-each {remote}"))?;
+
+    Ok(())
+}
@@ -38,7 +38,9 @@ fn make() -> clap::Command {
              stack, pulling the changes from the remote repository, updating the stack \
              base to the new remote HEAD, and finally pushing all formerly applied \
              patches back onto the stack. Merge conflicts may occur during the final \
-             push step. Those conflicts need to be resolved manually.\n\
+             push step. Those conflicts need to be resolved manually, after which the \
+             pull may be resumed with 'stg continue'. See also 'stg skip' and \
+             'stg abort'.\n\
              \n\
              See git-fetch(1) for the format of remote repository argument.
              ",
@@ -147,8 +149,10 @@ fn run(matches: &ArgMatches) -> Result<()> {
 
     stupid.statuses(None)?.check_index_and_worktree_clean()?;
     stack.check_head_top_mismatch()?;
+    stack.check_no_pending_operation()?;
 
     let applied = stack.applied().to_vec();
+    let orig_state = stack.state_commit_id()?;
 
     stack
         .setup_transaction()
@@ -256,6 +260,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
             .setup_transaction()
             .use_index_and_worktree(true)
             .allow_push_conflicts(allow_push_conflicts)
+            .resumable("pull", orig_state, false)
             .with_output_stream(get_color_stdout(matches))
             .transact(|trans| trans.push_patches(&applied, check_merged))
             .execute("pull (reapply)")?;
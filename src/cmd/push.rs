@@ -10,7 +10,7 @@ use crate::{
     color::get_color_stdout,
     ext::RepositoryExtended,
     patch::{patchrange, PatchName, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stack::{DependencyGraph, DependencyPolicy, InitializationPolicy, Stack, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -33,8 +33,9 @@ fn make() -> clap::Command {
              order pushes may result in merge conflicts. If there are conflicts \
              while pushing a patch, the conflicts are written to the work tree \
              and the push command halts. Conflicts may then be resolved using \
-             the normal Git methods, or alternatively the push may be undone \
-             using 'stg undo'.",
+             the normal Git methods and the push resumed using 'stg continue'. \
+             Alternatively, the conflicting patch may be skipped using 'stg skip' or \
+             the push may be aborted using 'stg abort'.",
         )
         .override_usage(super::make_usage(
             "stg push",
@@ -114,6 +115,7 @@ fn make() -> clap::Command {
         .arg(argset::merged_arg())
         .arg(argset::committer_date_is_author_date_arg())
         .arg(argset::push_conflicts_arg())
+        .arg(argset::deps_arg())
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
@@ -187,12 +189,33 @@ fn run(matches: &ArgMatches) -> Result<()> {
         patches.reverse();
     }
 
+    let dependency_graph = if settree_flag || noapply_flag {
+        None
+    } else {
+        let policy = argset::resolve_dependency_policy(&repo.config_snapshot(), matches)?;
+        let graph = DependencyGraph::for_policy(&repo, &stack, policy)?;
+        if let Some(graph) = graph.as_ref().filter(|_| policy == DependencyPolicy::Pull) {
+            let above: Vec<PatchName> = stack
+                .unapplied()
+                .iter()
+                .filter(|pn| !patches.contains(pn))
+                .cloned()
+                .collect();
+            patches = graph.pull_in(&patches, &[], &above);
+        }
+        graph
+    };
+
+    let orig_state = stack.state_commit_id()?;
+
     stack
         .setup_transaction()
         .use_index_and_worktree(true)
         .allow_push_conflicts(allow_push_conflicts)
         .committer_date_is_author_date(matches.get_flag("committer-date-is-author-date"))
         .with_output_stream(get_color_stdout(matches))
+        .dependency_graph(dependency_graph)
+        .resumable_unless_pending("push", orig_state)
         .transact(|trans| {
             if settree_flag {
                 for (i, patchname) in patches.iter().enumerate() {
@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg range-diff` implementation.
+
+use std::{collections::HashMap, io::Write, rc::Rc};
+
+use anyhow::{anyhow, Result};
+use bstr::ByteSlice;
+use clap::{Arg, ArgMatches};
+use termcolor::WriteColor;
+
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::PatchName,
+    stack::{InitializationPolicy, Stack, StackState, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "range-diff",
+    category: super::CommandCategory::StackInspection,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Show per-patch differences between two stack states")
+        .long_about(
+            "Compare the patches of two stack states from the stack log and show how \
+             each patch changed between them.\n\
+             \n\
+             Stack states are specified with commit ids from the stack log, which may \
+             be viewed with 'stg log'. When no states are given, the state prior to \
+             the last stack operation (i.e. the state 'stg undo' would restore) is \
+             compared with the current state. When only <state-a> is given, it is \
+             compared with the current state.\n\
+             \n\
+             Patches are matched-up by name. Patches renamed between the two states, \
+             as recorded in the stack log, are matched-up by their old and new names. \
+             Each patch is shown with one of the following prefixes:\n\
+             \n\
+             = the patch is unchanged\n\
+             ! the patch changed, followed by the range-diff of the two versions\n\
+             < the patch only exists in <state-a>\n\
+             > the patch only exists in <state-b>\n\
+             \n\
+             The interdiff of modified patches is produced by git-range-diff(1).",
+        )
+        .arg(
+            Arg::new("state-a")
+                .help("Stack state committish to compare from")
+                .value_name("state-a"),
+        )
+        .arg(
+            Arg::new("state-b")
+                .help("Stack state committish to compare to")
+                .value_name("state-b"),
+        )
+        .arg(argset::branch_arg())
+        .arg(argset::diff_opts_arg())
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::from_branch_locator(
+        &repo,
+        matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::RequireInitialized,
+    )?;
+
+    let get_state_commit = |committish: &str| -> Result<Rc<gix::Commit<'_>>> {
+        let commit = repo
+            .rev_parse_single_ex(committish)?
+            .object()?
+            .peel_tags_to_end()?
+            .try_into_commit()
+            .map_err(|_| anyhow!("target `{committish}` is not a commit"))?;
+        let commit = Rc::new(commit);
+        StackState::from_commit(&repo, &commit)
+            .map_err(|e| anyhow!("`{committish}` is not a stack state: {e:#}"))?;
+        Ok(commit)
+    };
+
+    let state_a_commit = if let Some(committish) = argset::get_one_str(matches, "state-a") {
+        get_state_commit(committish)?
+    } else {
+        super::undo::find_undo_state_commit(&stack, 1)?
+    };
+    let state_b_commit = if let Some(committish) = argset::get_one_str(matches, "state-b") {
+        get_state_commit(committish)?
+    } else {
+        super::undo::find_undo_state_commit(&stack, 0)?
+    };
+    let state_a = StackState::from_commit(&repo, &state_a_commit)?;
+    let state_b = StackState::from_commit(&repo, &state_b_commit)?;
+    let renames = recorded_renames(&repo, &state_a_commit, &state_b_commit)?;
+
+    let stupid = repo.stupid();
+    let use_color = crate::color::use_color(matches);
+    let diff_opts = argset::get_diff_opts(matches, &repo.config_snapshot(), false, false);
+    let mut stdout = get_color_stdout(matches);
+
+    let mut removed: Vec<&PatchName> = state_a
+        .all_patches()
+        .filter(|pn| !state_b.has_patch(pn))
+        .collect();
+
+    for patchname in state_b.all_patches() {
+        let new_commit = state_b.get_patch_commit(patchname);
+        let old_patchname = if state_a.has_patch(patchname) {
+            Some(patchname)
+        } else {
+            renames.get(patchname).and_then(|old_patchname| {
+                removed
+                    .iter()
+                    .position(|pn| pn == &old_patchname)
+                    .map(|pos| removed.remove(pos))
+            })
+        };
+
+        if let Some(old_patchname) = old_patchname {
+            let old_commit = state_a.get_patch_commit(old_patchname);
+            let interdiff = if old_commit.id == new_commit.id {
+                None
+            } else {
+                let output =
+                    stupid.range_diff(old_commit.id, new_commit.id, use_color, &diff_opts)?;
+                // The first line summarizing the pairing is replaced by our own.
+                let interdiff = output
+                    .find_byte(b'\n')
+                    .map_or(Default::default(), |pos| output[pos + 1..].to_owned());
+                (!interdiff.is_empty()).then_some(interdiff)
+            };
+            let sigil = if interdiff.is_some() { '!' } else { '=' };
+            if old_patchname == patchname {
+                write_sigil_line(&mut stdout, sigil, patchname.as_ref())?;
+            } else {
+                write_sigil_line(
+                    &mut stdout,
+                    sigil,
+                    &format!("{old_patchname} -> {patchname} (renamed)"),
+                )?;
+            }
+            if let Some(interdiff) = interdiff {
+                stdout.write_all(&interdiff)?;
+            }
+        } else {
+            write_sigil_line(&mut stdout, '>', patchname.as_ref())?;
+        }
+    }
+
+    for patchname in removed {
+        write_sigil_line(&mut stdout, '<', patchname.as_ref())?;
+    }
+
+    Ok(())
+}
+
+/// Get the patch renames recorded in the stack log between two stack states.
+///
+/// The returned map is keyed by the patch names of `state_b` and maps to the patch's
+/// name in `state_a`. A patch is renamed when it appears under a new name with the
+/// same commit from one stack state to the next. Renames can only be determined when
+/// one state is in the history of the other.
+fn recorded_renames<'repo>(
+    repo: &'repo gix::Repository,
+    state_a: &Rc<gix::Commit<'repo>>,
+    state_b: &Rc<gix::Commit<'repo>>,
+) -> Result<HashMap<PatchName, PatchName>> {
+    if let Some(renames) = renames_along_history(repo, state_a, state_b)? {
+        Ok(renames)
+    } else if let Some(renames) = renames_along_history(repo, state_b, state_a)? {
+        Ok(renames
+            .into_iter()
+            .map(|(new_name, old_name)| (old_name, new_name))
+            .collect())
+    } else {
+        Ok(HashMap::new())
+    }
+}
+
+/// Follow the patch renames in the stack log from the `older` state to the `newer`.
+///
+/// Returns `None` if `older` is not in the history of `newer`.
+fn renames_along_history<'repo>(
+    repo: &'repo gix::Repository,
+    older: &Rc<gix::Commit<'repo>>,
+    newer: &Rc<gix::Commit<'repo>>,
+) -> Result<Option<HashMap<PatchName, PatchName>>> {
+    let mut states: Vec<StackState<'repo>> = Vec::new();
+    let mut state_commit = newer.clone();
+    loop {
+        let state = StackState::from_commit(repo, &state_commit)?;
+        let prev = state.prev.clone();
+        states.push(state);
+        if state_commit.id == older.id {
+            break;
+        } else if let Some(prev) = prev {
+            state_commit = prev;
+        } else {
+            return Ok(None);
+        }
+    }
+
+    // Map each patch's current name to its name in the older state.
+    let mut original_names: HashMap<PatchName, PatchName> = states
+        .last()
+        .expect("older state is recorded")
+        .all_patches()
+        .map(|pn| (pn.clone(), pn.clone()))
+        .collect();
+    for pair in states.windows(2).rev() {
+        let (state, prev_state) = (&pair[0], &pair[1]);
+        for patchname in state.all_patches().filter(|pn| !prev_state.has_patch(pn)) {
+            let commit_id = state.get_patch_commit(patchname).id;
+            if let Some(old_patchname) = prev_state
+                .all_patches()
+                .find(|pn| !state.has_patch(pn) && prev_state.get_patch_commit(pn).id == commit_id)
+            {
+                if let Some(original_name) = original_names.remove(old_patchname) {
+                    original_names.insert(patchname.clone(), original_name);
+                }
+            }
+        }
+    }
+
+    Ok(Some(
+        original_names
+            .into_iter()
+            .filter(|(name, original_name)| name != original_name)
+            .collect(),
+    ))
+}
+
+fn write_sigil_line(stdout: &mut termcolor::StandardStream, sigil: char, text: &str) -> Result<()> {
+    let color = match sigil {
+        '!' => Some(termcolor::Color::Yellow),
+        '<' => Some(termcolor::Color::Red),
+        '>' => Some(termcolor::Color::Green),
+        _ => None,
+    };
+    let mut color_spec = termcolor::ColorSpec::new();
+    stdout.set_color(color_spec.set_fg(color))?;
+    write!(stdout, "{sigil}")?;
+    stdout.set_color(color_spec.set_fg(None))?;
+    writeln!(stdout, " {text}")?;
+    Ok(())
+}
@@ -8,13 +8,15 @@ use anyhow::{anyhow, Result};
 use bstr::ByteSlice;
 use clap::{Arg, ArgMatches};
 
+use super::r#continue::{perform_steps, StepSettings};
 use crate::{
     argset,
     color::get_color_stdout,
     ext::RepositoryExtended,
+    hook::StackHook,
     patch::{patchedit, PatchName, SingleRevisionSpec},
     print_info_message,
-    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    stack::{InitializationPolicy, PendingStep, Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -34,16 +36,17 @@ fn make() -> clap::Command {
             \n\
             Merge conflicts may arise when patches are being pushed-back onto the \
             stack. If this occurs, resolve the conflicts and then continue the rebase \
-            with the following sequence:\n\
+            with:\n\
             \n    \
-            stg add --update\n    \
-            stg refresh\n    \
-            stg goto top-patch\n\
+            stg continue\n\
             \n\
-            Or to skip the conflicting patch:\n\
+            Or to skip the conflicting patch, leaving it unapplied:\n\
             \n    \
-            stg undo --hard\n    \
-            stg push next-patch..top-patch\n\
+            stg skip\n\
+            \n\
+            Or to restore the stack to its state from before the rebase:\n\
+            \n    \
+            stg abort\n\
             ",
         )
         .arg(
@@ -93,6 +96,7 @@ fn make() -> clap::Command {
 fn run(matches: &ArgMatches) -> Result<()> {
     let repo = gix::Repository::open()?;
     let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    stack.check_no_pending_operation()?;
     let config = repo.config_snapshot();
     let stupid = repo.stupid();
     let branch_name = stack.get_branch_name().to_string();
@@ -187,16 +191,24 @@ fn run(matches: &ArgMatches) -> Result<()> {
     };
 
     let applied = stack.applied().to_vec();
+    let orig_state = stack.state_commit_id()?;
 
-    stack
+    let pop_result = stack
         .setup_transaction()
         .use_index_and_worktree(true)
         .with_output_stream(get_color_stdout(matches))
+        .run_hook(StackHook::PreRebase(target_commit.id))
         .transact(|trans| {
             trans.pop_patches(|pn| applied.contains(pn))?;
             Ok(())
         })
-        .execute("rebase (pop)")?;
+        .execute("rebase (pop)");
+    if let Err(e) = pop_result {
+        if using_stash {
+            stupid.stash_pop()?;
+        }
+        return Err(e);
+    }
 
     let rebase_cmd = config
         .string_by(
@@ -224,16 +236,22 @@ fn run(matches: &ArgMatches) -> Result<()> {
         stack.log_external_mods(Some("rebase"))?
     };
 
-    if matches.get_flag("interactive") {
+    let completed = if matches.get_flag("interactive") {
         interactive_pushback(
             stack,
             &repo,
             &config,
             matches,
             &applied,
-            allow_push_conflicts,
-            committer_date_is_author_date,
-        )?;
+            &StepSettings {
+                command: "rebase".to_string(),
+                orig_state,
+                autostash: using_stash,
+                allow_push_conflicts: Some(allow_push_conflicts),
+                committer_date_is_author_date,
+                check_merged: matches.get_flag("merged"),
+            },
+        )?
     } else if !matches.get_flag("nopush") {
         stack.check_head_top_mismatch()?;
         let check_merged = matches.get_flag("merged");
@@ -242,12 +260,16 @@ fn run(matches: &ArgMatches) -> Result<()> {
             .use_index_and_worktree(true)
             .allow_push_conflicts(allow_push_conflicts)
             .committer_date_is_author_date(committer_date_is_author_date)
+            .resumable("rebase", orig_state, using_stash)
             .with_output_stream(get_color_stdout(matches))
             .transact(|trans| trans.push_patches(&applied, check_merged))
             .execute("rebase (reapply)")?;
-    }
+        true
+    } else {
+        true
+    };
 
-    if using_stash {
+    if using_stash && completed {
         if stupid.stash_pop()? {
             Ok(())
         } else {
@@ -325,14 +347,19 @@ const INTERACTIVE_HELP_LINES: &str = "\
 #
 #   k, keep <patch> = do not modify this patch
 #   e, edit <patch> = interactively edit this patch
+#   r, reword <patch> = interactively edit this patch's message
+#   rename <patch> <new-name> = rename this patch
 #   s, squash <patch> = squash patch into the previous patch
 #   f, fixup <patch> = like \"squash\", but discard this patch's commit message
 #   h, hide <patch> = hide patch
 #   d, delete <patch> = delete patch
+#   x, exec <command> = run command (the rest of the line) using the shell
+#   b, break = stop here (resume with 'stg continue')
 #
 # These lines can be reordered; they are executed from top to bottom.
 #
 # Patches above the APPLY_LINE are applied; other patches are kept unapplied.
+# The exec and break commands must be above the APPLY_LINE.
 ";
 
 #[derive(Debug, Clone)]
@@ -340,12 +367,17 @@ struct Instruction {
     patchname: PatchName,
     action: Action,
     apply: bool,
+
+    /// Exec and break steps to perform after this patch is pushed.
+    followed_by: Vec<PendingStep>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Action {
     Keep,
     Edit,
+    Reword,
+    Rename(PatchName),
     Squash,
     Fixup,
     Hide,
@@ -358,13 +390,12 @@ fn interactive_pushback(
     config: &gix::config::Snapshot,
     matches: &ArgMatches,
     previously_applied: &[PatchName],
-    allow_push_conflicts: bool,
-    committer_date_is_author_date: bool,
-) -> Result<()> {
+    settings: &StepSettings,
+) -> Result<bool> {
     let mut stack = stack;
 
     if stack.all_patches().next().is_none() {
-        return Ok(());
+        return Ok(true);
     }
 
     let filename = ".stgit-rebase-interactive.txt";
@@ -377,7 +408,7 @@ fn interactive_pushback(
     let buf = buf
         .to_str()
         .map_err(|_| anyhow!("`{filename}` is not valid UTF-8"))?;
-    let mut instructions = parse_instructions(buf)?;
+    let (mut leading_steps, mut instructions) = parse_instructions(buf)?;
 
     validate_instructions(&stack, &instructions)?;
 
@@ -387,7 +418,7 @@ fn interactive_pushback(
         let instruction = instructions[index].clone();
         let patchname = &instruction.patchname;
 
-        match instruction.action {
+        match &instruction.action {
             Action::Keep => {
                 index += 1;
             }
@@ -415,6 +446,12 @@ fn interactive_pushback(
                         Ok(())
                     })
                     .execute("delete")?;
+                adopt_steps(
+                    &mut instructions,
+                    &mut leading_steps,
+                    index,
+                    &delete_instructions,
+                );
             }
 
             Action::Hide => {
@@ -435,18 +472,29 @@ fn interactive_pushback(
                     .with_output_stream(get_color_stdout(matches))
                     .transact(|trans| trans.hide_patches(&to_hide))
                     .execute("hide")?;
+                adopt_steps(
+                    &mut instructions,
+                    &mut leading_steps,
+                    index,
+                    &hide_instructions,
+                );
             }
 
-            Action::Edit => {
+            Action::Edit | Action::Reword => {
                 let dummy_edit_command = clap::Command::new("dummy-edit");
                 let dummy_edit_command = patchedit::add_args(dummy_edit_command, false, false);
+                let edit_args: &[&str] = if instruction.action == Action::Edit {
+                    &["dummy-edit", "--edit", "--diff"]
+                } else {
+                    &["dummy-edit", "--edit"]
+                };
                 let edit_matches = dummy_edit_command
-                    .try_get_matches_from(["dummy-edit", "--edit", "--diff"])
+                    .try_get_matches_from(edit_args)
                     .expect("dummy command has valid arguments");
                 match patchedit::EditBuilder::default()
                     .original_patchname(Some(patchname))
                     .existing_patch_commit(stack.get_patch_commit(patchname))
-                    .allow_diff_edit(true)
+                    .allow_diff_edit(instruction.action == Action::Edit)
                     .edit(&stack, repo, &edit_matches)?
                 {
                     patchedit::EditOutcome::TemplateSaved(_) => panic!("template save not enabled"),
@@ -457,7 +505,9 @@ fn interactive_pushback(
                         if new_patchname.is_some() || new_commit_id.is_some() {
                             stack = stack
                                 .setup_transaction()
-                                .committer_date_is_author_date(committer_date_is_author_date)
+                                .committer_date_is_author_date(
+                                    settings.committer_date_is_author_date,
+                                )
                                 .with_output_stream(get_color_stdout(matches))
                                 .transact(|trans| {
                                     let patchname =
@@ -475,17 +525,27 @@ fn interactive_pushback(
                                 .execute(&format!("edit: {patchname}"))?;
                         }
 
-                        instructions[index] = Instruction {
-                            action: Action::Keep,
-                            patchname: new_patchname.unwrap_or_else(|| patchname.clone()),
-                            apply: instruction.apply,
-                        };
+                        instructions[index].action = Action::Keep;
+                        if let Some(new_patchname) = new_patchname {
+                            instructions[index].patchname = new_patchname;
+                        }
 
                         index += 1;
                     }
                 }
             }
 
+            Action::Rename(new_patchname) => {
+                stack = stack
+                    .setup_transaction()
+                    .with_output_stream(get_color_stdout(matches))
+                    .transact(|trans| trans.rename_patch(patchname, new_patchname))
+                    .execute(&format!("rename {patchname} {new_patchname}"))?;
+                instructions[index].action = Action::Keep;
+                instructions[index].patchname = new_patchname.clone();
+                index += 1;
+            }
+
             Action::Squash | Action::Fixup => {
                 let action_str = match instruction.action {
                     Action::Squash => "squash",
@@ -493,14 +553,20 @@ fn interactive_pushback(
                     _ => panic!("only squash and fixup expected"),
                 };
 
+                let mut squash_steps: Vec<PendingStep> = Vec::new();
                 let squash_patchnames: Vec<PatchName> = if index > 0 {
                     let mut patchnames: Vec<PatchName> =
                         vec![instructions[index - 1].patchname.clone()];
                     while index < instructions.len()
                         && instructions[index].action == instruction.action
                     {
-                        let Instruction { patchname, .. } = instructions.remove(index);
+                        let Instruction {
+                            patchname,
+                            followed_by,
+                            ..
+                        } = instructions.remove(index);
                         patchnames.push(patchname);
+                        squash_steps.extend(followed_by);
                     }
                     patchnames
                 } else {
@@ -543,11 +609,10 @@ fn interactive_pushback(
                             Some(target_patchname),
                             false,
                         )?;
-                        instructions[index - 1] = Instruction {
-                            action: Action::Keep,
-                            patchname: new_patchname,
-                            apply: instruction.apply,
-                        };
+                        let target = &mut instructions[index - 1];
+                        target.action = Action::Keep;
+                        target.patchname = new_patchname;
+                        target.followed_by.append(&mut squash_steps);
                         Ok(())
                     })
                     .execute("squash")?;
@@ -555,29 +620,56 @@ fn interactive_pushback(
         }
     }
 
-    let to_push: Vec<PatchName> = instructions
+    // The unapplied patches are ordered as listed in the instructions, followed by
+    // any patches missing from the instructions.
+    let mut unapplied: Vec<PatchName> = instructions
         .iter()
-        .filter_map(|inst| {
-            if inst.apply {
-                Some(inst.patchname.clone())
-            } else {
-                None
-            }
-        })
+        .filter(|inst| stack.is_unapplied(&inst.patchname))
+        .map(|inst| inst.patchname.clone())
         .collect();
-    let check_merged = matches.get_flag("merged");
+    unapplied.extend(
+        stack
+            .unapplied()
+            .iter()
+            .filter(|pn| !unapplied.contains(pn))
+            .cloned()
+            .collect::<Vec<_>>(),
+    );
+
+    let mut steps = leading_steps;
+    for instruction in instructions {
+        if instruction.apply {
+            steps.push(PendingStep::Push(instruction.patchname));
+            steps.extend(instruction.followed_by);
+        }
+    }
 
     stack.check_head_top_mismatch()?;
-    stack
-        .setup_transaction()
-        .use_index_and_worktree(true)
-        .allow_push_conflicts(allow_push_conflicts)
-        .committer_date_is_author_date(committer_date_is_author_date)
-        .with_output_stream(get_color_stdout(matches))
-        .transact(|trans| trans.push_patches(&to_push, check_merged))
-        .execute("rebase (reapply)")?;
+    perform_steps(
+        stack,
+        &steps,
+        settings,
+        matches,
+        "rebase (reapply)",
+        |trans| trans.reorder_patches(None, Some(&unapplied), None),
+    )
+}
 
-    Ok(())
+/// Move exec and break steps of removed instructions to the preceding instruction.
+fn adopt_steps(
+    instructions: &mut [Instruction],
+    leading_steps: &mut Vec<PendingStep>,
+    index: usize,
+    removed: &[Instruction],
+) {
+    let steps = removed
+        .iter()
+        .flat_map(|inst| inst.followed_by.iter().cloned());
+    if let Some(preceding) = index.checked_sub(1).map(|i| &mut instructions[i]) {
+        preceding.followed_by.extend(steps);
+    } else {
+        leading_steps.extend(steps);
+    }
 }
 
 fn make_instructions_template(stack: &Stack, previously_applied: &[PatchName]) -> String {
@@ -606,8 +698,13 @@ fn make_instructions_template(stack: &Stack, previously_applied: &[PatchName]) -
     template
 }
 
-fn parse_instructions(buf: &str) -> Result<Vec<Instruction>> {
-    let mut instructions = Vec::new();
+/// Parse instructions from the edited instructions buffer.
+///
+/// Exec and break steps are attached to the preceding patch instruction. Any such
+/// steps preceding the first patch instruction are returned separately.
+fn parse_instructions(buf: &str) -> Result<(Vec<PendingStep>, Vec<Instruction>)> {
+    let mut leading_steps = Vec::new();
+    let mut instructions: Vec<Instruction> = Vec::new();
     let mut apply = true;
 
     for line in buf.lines() {
@@ -621,6 +718,31 @@ fn parse_instructions(buf: &str) -> Result<Vec<Instruction>> {
             }
         }
 
+        // The entire remainder of an exec line is the command, including any '#'.
+        let (action_str, remainder) = line
+            .split_once(|c: char| c.is_ascii_whitespace())
+            .map_or((line, ""), |(action_str, remainder)| {
+                (action_str, remainder.trim())
+            });
+        let step = match action_str {
+            "x" | "exec" if remainder.is_empty() => {
+                return Err(anyhow!("bad instruction line: `{line}`"));
+            }
+            "x" | "exec" => Some(PendingStep::Exec(remainder.to_string())),
+            "b" | "break" => Some(PendingStep::Break),
+            _ => None,
+        };
+        if let Some(step) = step {
+            if !apply {
+                return Err(anyhow!("`{action_str}` must be above the APPLY_LINE"));
+            } else if let Some(instruction) = instructions.last_mut() {
+                instruction.followed_by.push(step);
+            } else {
+                leading_steps.push(step);
+            }
+            continue;
+        }
+
         let instruction_str = if let Some((instruction_str, _comment)) = line.split_once('#') {
             instruction_str
         } else {
@@ -635,13 +757,27 @@ fn parse_instructions(buf: &str) -> Result<Vec<Instruction>> {
         if let Some((action_str, patchname_str)) =
             instruction_str.split_once(|c: char| c.is_ascii_whitespace())
         {
-            let action = match action_str {
-                "k" | "keep" => Action::Keep,
-                "e" | "edit" => Action::Edit,
-                "s" | "squash" => Action::Squash,
-                "f" | "fix" | "fixup" => Action::Fixup,
-                "h" | "hide" => Action::Hide,
-                "d" | "delete" => Action::Delete,
+            let patchname_str = patchname_str.trim();
+            let (patchname_str, action) = match action_str {
+                "k" | "keep" => (patchname_str, Action::Keep),
+                "e" | "edit" => (patchname_str, Action::Edit),
+                "r" | "reword" => (patchname_str, Action::Reword),
+                "rename" => {
+                    if let Some((old_str, new_str)) =
+                        patchname_str.split_once(|c: char| c.is_ascii_whitespace())
+                    {
+                        (
+                            old_str,
+                            Action::Rename(PatchName::from_str(new_str.trim())?),
+                        )
+                    } else {
+                        return Err(anyhow!("bad instruction line: `{line}`"));
+                    }
+                }
+                "s" | "squash" => (patchname_str, Action::Squash),
+                "f" | "fix" | "fixup" => (patchname_str, Action::Fixup),
+                "h" | "hide" => (patchname_str, Action::Hide),
+                "d" | "delete" => (patchname_str, Action::Delete),
                 _ => return Err(anyhow!("unknown instruction action `{action_str}`")),
             };
 
@@ -651,12 +787,13 @@ fn parse_instructions(buf: &str) -> Result<Vec<Instruction>> {
                 patchname,
                 action,
                 apply,
+                followed_by: Vec::new(),
             });
         } else {
             return Err(anyhow!("bad instruction line: `{line}`"));
         }
     }
-    Ok(instructions)
+    Ok((leading_steps, instructions))
 }
 
 fn validate_instructions(stack: &Stack, instructions: &[Instruction]) -> Result<()> {
@@ -671,5 +808,13 @@ fn validate_instructions(stack: &Stack, instructions: &[Instruction]) -> Result<
             seen_patchnames.push(patchname);
         }
     }
+    for instruction in instructions {
+        if let Action::Rename(new_patchname) = &instruction.action {
+            if stack.has_patch(new_patchname) || seen_patchnames.contains(&new_patchname) {
+                return Err(anyhow!("cannot rename to existing patch `{new_patchname}`"));
+            }
+            seen_patchnames.push(new_patchname);
+        }
+    }
     Ok(())
 }
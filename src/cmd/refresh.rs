@@ -15,7 +15,7 @@ use crate::{
     argset,
     color::get_color_stdout,
     ext::{CommitExtended, RepositoryExtended, SignatureExtended},
-    hook::run_pre_commit_hook,
+    hook::{run_pre_commit_hook, StackHook},
     patch::{patchedit, LocationConstraint, PatchLocator, PatchName},
     stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
     stupid::{Status, StatusOptions, Statuses, Stupid, StupidContext},
@@ -209,6 +209,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
         .use_index_and_worktree(true)
         .with_output_stream(get_color_stdout(matches))
         .allow_push_conflicts(allow_push_conflicts)
+        .run_hook(StackHook::PostRefresh)
         .transact(|trans| {
             if let Some(pos) = trans.applied().iter().position(|pn| pn == &patchname) {
                 // Absorb temp patch into already applied patch
@@ -337,7 +338,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
-fn determine_refresh_paths(
+pub(super) fn determine_refresh_paths(
     stupid: &StupidContext,
     statuses: &Statuses,
     patch_commit: Option<&Rc<gix::Commit>>,
@@ -398,7 +399,7 @@ fn determine_refresh_paths(
     Ok(refresh_paths)
 }
 
-fn write_tree(
+pub(super) fn write_tree(
     stack: &Stack,
     refresh_paths: &IndexSet<PathBuf>,
     is_path_limiting: bool,
@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg revert-patch` implementation.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+
+use crate::{
+    color::get_color_stdout,
+    ext::RepositoryExtended,
+    patch::PatchLocator,
+    print_info_message,
+    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "revert-patch",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Restore a previous version of a patch")
+        .long_about(
+            "Restore a patch to one of its previous versions, as listed by 'stg \
+             patch-history'.\n\
+             \n\
+             The version to restore is specified as '<patch>@{<n>}', where '@{0}' is \
+             the patch's current version, '@{1}' the version before it, and so on. \
+             The patch's commit, including its message and author, is replaced with \
+             the commit of the given version. Only the patch itself is reverted; the \
+             other patches of the stack are left as they are.\n\
+             \n\
+             When the patch is applied, it and the patches above it are popped and \
+             pushed back with the restored version. If pushing a patch results in \
+             conflicts, the operation stops with the conflicting patch as the topmost \
+             patch. Once the conflicts are resolved, the remaining patches may be \
+             pushed with 'stg continue'.",
+        )
+        .arg(
+            Arg::new("version")
+                .help("Patch version to restore, as <patch>@{<n>}")
+                .value_name("patch@{n}")
+                .required(true)
+                .allow_hyphen_values(true)
+                .value_parser(parse_patch_version),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let stupid = repo.stupid();
+
+    repo.check_repository_state()?;
+    stupid.statuses(None)?.check_conflicts()?;
+    stack.check_head_top_mismatch()?;
+
+    let (patch_loc, n) = matches
+        .get_one::<(PatchLocator, usize)>("version")
+        .expect("required argument");
+    let patchname = patch_loc.resolve_name(&stack)?;
+    let versions = super::patch_history::patch_versions(&stack, &patchname)?;
+    let commit_id = versions
+        .get(*n)
+        .ok_or_else(|| anyhow!("`{patchname}@{{{n}}}` does not exist"))?
+        .commit
+        .id;
+    if commit_id == stack.get_patch_commit_id(&patchname) {
+        print_info_message(
+            matches,
+            &format!("`{patchname}` is already at version @{{{n}}}"),
+        );
+        return Ok(());
+    }
+
+    let orig_state = stack.state_commit_id()?;
+
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .with_output_stream(get_color_stdout(matches))
+        .resumable_unless_pending("revert-patch", orig_state)
+        .transact(|trans| {
+            let to_push = if let Some(pos) = trans.applied().iter().position(|pn| pn == &patchname)
+            {
+                let to_pop = trans.applied()[pos..].to_vec();
+                let popped_extra = trans.pop_patches(|pn| to_pop.contains(pn))?;
+                assert!(popped_extra.is_empty());
+                to_pop
+            } else {
+                Vec::new()
+            };
+            trans.update_patch(&patchname, commit_id)?;
+            trans.push_patches(&to_push, false)
+        })
+        .execute(&format!("revert-patch {patchname}@{{{n}}}"))?;
+
+    Ok(())
+}
+
+fn parse_patch_version(s: &str) -> Result<(PatchLocator, usize)> {
+    let (patch, version) = s
+        .strip_suffix('}')
+        .and_then(|s| s.rsplit_once("@{"))
+        .ok_or_else(|| anyhow!("expected patch version of the form `<patch>@{{<n>}}`"))?;
+    let n = version
+        .parse()
+        .map_err(|_| anyhow!("invalid patch version `{version}`"))?;
+    Ok((PatchLocator::from_str(patch)?, n))
+}
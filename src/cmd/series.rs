@@ -13,6 +13,7 @@ use crate::{
     argset,
     branchloc::BranchLocator,
     ext::{CommitExtended, RepositoryExtended},
+    json,
     patch::{patchrange, PatchName, PatchRange, RangeConstraint},
     stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
 };
@@ -134,7 +135,15 @@ fn make() -> clap::Command {
                 .short('c')
                 .help("Display the number of selected patches and exit")
                 .action(clap::ArgAction::SetTrue)
-                .conflicts_with_all(["description", "author", "empty", "show-branch", "no-prefix"]),
+                .conflicts_with_all([
+                    "description",
+                    "author",
+                    "meta",
+                    "empty",
+                    "show-branch",
+                    "no-prefix",
+                    "format",
+                ]),
         )
         .arg(
             Arg::new("commit-id")
@@ -179,6 +188,20 @@ fn make() -> clap::Command {
                 .action(clap::ArgAction::SetTrue)
                 .overrides_with("description"),
         )
+        .arg(
+            Arg::new("meta")
+                .long("meta")
+                .short('M')
+                .help("Display patch metadata <key> for each patch")
+                .long_help(
+                    "Display the value of patch metadata <key> for each patch. This \
+                     option may be repeated to display multiple metadata columns. Only \
+                     the first line of multi-line values is displayed. See `stg meta`.",
+                )
+                .value_name("key")
+                .action(clap::ArgAction::Append)
+                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+        )
         .arg(
             Arg::new("empty")
                 .long("empty")
@@ -289,6 +312,7 @@ fn make() -> clap::Command {
                 .action(clap::ArgAction::SetTrue)
                 .overrides_with("show-branch"),
         )
+        .arg(argset::format_arg())
 }
 
 #[derive(Clone)]
@@ -478,6 +502,22 @@ fn run(matches: &ArgMatches) -> Result<()> {
         return Ok(());
     }
 
+    if json::is_requested(matches) {
+        #[derive(serde::Serialize)]
+        struct Output<'a> {
+            branch: &'a str,
+            patches: Vec<json::PatchInfo<'a>>,
+        }
+        let patches = patches
+            .iter()
+            .map(|Entry { patchname, .. }| json::PatchInfo::new(&stack, patchname))
+            .collect::<Result<Vec<_>>>()?;
+        return json::print(&Output {
+            branch: stack.get_branch_name(),
+            patches,
+        });
+    }
+
     let opt_commit_id = matches.get_one::<CommitIdLength>("commit-id");
     let description_flag = matches.get_flag("description");
     let author_flag = matches.get_flag("author");
@@ -489,17 +529,31 @@ fn run(matches: &ArgMatches) -> Result<()> {
         ""
     };
 
-    let patchname_width = if opt_commit_id.is_some() || description_flag || author_flag {
-        patches
-            .iter()
-            .map(|Entry { patchname, .. }| patchname.len())
-            .max()
-            .unwrap_or(0)
-    } else {
-        0
+    let meta_keys: Vec<&String> = matches
+        .get_many::<String>("meta")
+        .map(Iterator::collect)
+        .unwrap_or_default();
+
+    let meta_value = |patchname: &PatchName, key: &str| -> &str {
+        stack
+            .get_patch_meta(patchname)
+            .get(key)
+            .and_then(|value| value.lines().next())
+            .unwrap_or_default()
     };
 
-    let author_width: usize = if author_flag && description_flag {
+    let patchname_width =
+        if opt_commit_id.is_some() || description_flag || author_flag || !meta_keys.is_empty() {
+            patches
+                .iter()
+                .map(|Entry { patchname, .. }| patchname.len())
+                .max()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+
+    let author_width: usize = if author_flag && (description_flag || !meta_keys.is_empty()) {
         patches
             .iter()
             .map(|Entry { commit_id, .. }| -> usize {
@@ -520,6 +574,22 @@ fn run(matches: &ArgMatches) -> Result<()> {
         0
     };
 
+    let meta_widths: Vec<usize> = meta_keys
+        .iter()
+        .enumerate()
+        .map(|(i, key)| {
+            if i + 1 < meta_keys.len() || description_flag {
+                patches
+                    .iter()
+                    .map(|Entry { patchname, .. }| meta_value(patchname, key).chars().count())
+                    .max()
+                    .unwrap_or(0)
+            } else {
+                0
+            }
+        })
+        .collect();
+
     let no_prefix_flag = matches.get_flag("no-prefix");
     let empty_flag = matches.get_flag("empty");
     let indices_flag = matches.get_flag("indices");
@@ -629,6 +699,13 @@ fn run(matches: &ArgMatches) -> Result<()> {
                 write!(stdout, "{name:author_width$}")?;
             }
         }
+        for (key, width) in meta_keys.iter().zip(&meta_widths) {
+            stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Black)))?;
+            write!(stdout, " # ")?;
+            stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Cyan)))?;
+            let value = meta_value(&patchname, key);
+            write!(stdout, "{value:width$}")?;
+        }
         if description_flag {
             stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Black)))?;
             write!(stdout, " #")?;
@@ -5,13 +5,15 @@
 use std::path::PathBuf;
 
 use anyhow::Result;
+use bstr::ByteSlice;
 use clap::{Arg, ArgMatches};
 
 use crate::{
     argset,
     branchloc::BranchLocator,
-    ext::RepositoryExtended,
-    patch::{RangeConstraint, RangeRevisionSpec},
+    ext::{CommitExtended, RepositoryExtended},
+    json,
+    patch::{PatchName, RangeConstraint, RangeRevisionSpec},
     stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
     stupid::Stupid,
 };
@@ -84,6 +86,7 @@ fn make() -> clap::Command {
                 .action(clap::ArgAction::SetTrue),
         )
         .arg(argset::diff_opts_arg())
+        .arg(argset::format_arg().conflicts_with("stat"))
         .next_help_heading("Selection Options")
         .arg(
             Arg::new("applied")
@@ -154,6 +157,52 @@ fn run(matches: &ArgMatches) -> Result<()> {
         oids.push(stack.get_branch_head().id);
     }
 
+    if json::is_requested(matches) {
+        #[derive(serde::Serialize)]
+        struct Commit<'a> {
+            commit_id: String,
+            patch: Option<json::PatchInfo<'a>>,
+            author: json::Person,
+            message: String,
+            diff: String,
+        }
+        #[derive(serde::Serialize)]
+        struct Output<'a> {
+            commits: Vec<Commit<'a>>,
+        }
+        let stupid = repo.stupid();
+        let patchnames: Vec<&PatchName> = stack.all_patches().collect();
+        let mut commits = Vec::with_capacity(oids.len());
+        for oid in oids {
+            let commit = repo.find_commit(oid)?;
+            let parent_tree_id = if let Some(parent_id) = commit.parent_ids().next() {
+                repo.find_commit(parent_id)?.tree_id()?.detach()
+            } else {
+                gix::ObjectId::empty_tree(repo.object_hash())
+            };
+            let diff = stupid.diff_tree_patch(
+                parent_tree_id,
+                commit.tree_id()?.detach(),
+                matches.get_many::<PathBuf>("pathspecs"),
+                false,
+                argset::get_diff_opts(matches, &repo.config_snapshot(), false, false),
+            )?;
+            let patch = patchnames
+                .iter()
+                .find(|pn| stack.get_patch_commit_id(pn) == oid)
+                .map(|pn| json::PatchInfo::new(&stack, pn))
+                .transpose()?;
+            commits.push(Commit {
+                commit_id: oid.to_string(),
+                patch,
+                author: json::Person::from(&commit.author_strict()?),
+                message: commit.message_ex().decode()?.to_string(),
+                diff: diff.to_str_lossy().to_string(),
+            });
+        }
+        return json::print(&Output { commits });
+    }
+
     repo.stupid().show(
         oids,
         matches.get_many::<PathBuf>("pathspecs"),
@@ -10,7 +10,7 @@ use crate::{
     color::get_color_stdout,
     ext::RepositoryExtended,
     patch::{patchrange, LocationConstraint, PatchLocator, PatchName, PatchRange, RangeConstraint},
-    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stack::{DependencyGraph, DependencyPolicy, InitializationPolicy, Stack, StackStateAccess},
     stupid::Stupid,
 };
 
@@ -96,6 +96,7 @@ fn make() -> clap::Command {
         )
         .arg(argset::keep_arg())
         .arg(argset::committer_date_is_author_date_arg())
+        .arg(argset::deps_arg())
 }
 
 fn run(matches: &ArgMatches) -> Result<()> {
@@ -134,7 +135,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
             _ => e.into(),
         })?;
 
-    let patches: Vec<PatchName> =
+    let mut patches: Vec<PatchName> =
         if let Some(range_specs) = matches.get_many::<PatchRange>("patchranges") {
             patchrange::resolve_names(&stack, range_specs, RangeConstraint::All)?
         } else if let Some(patchname) = stack.applied().last() {
@@ -151,6 +152,46 @@ fn run(matches: &ArgMatches) -> Result<()> {
         }
     }
 
+    let target_position = |remaining_applied: &[PatchName]| -> usize {
+        if let Some(target_patch) = &opt_target {
+            remaining_applied
+                .iter()
+                .position(|pn| pn == target_patch)
+                .expect("already validated that target is applied")
+                + if is_above { 1 } else { 0 }
+        } else {
+            0
+        }
+    };
+
+    let policy = argset::resolve_dependency_policy(&repo.config_snapshot(), matches)?;
+    let dependency_graph = DependencyGraph::for_policy(&repo, &stack, policy)?;
+    if let Some(graph) = dependency_graph
+        .as_ref()
+        .filter(|_| policy == DependencyPolicy::Pull)
+    {
+        let remaining_applied: Vec<PatchName> = stack
+            .applied()
+            .iter()
+            .filter(|pn| !patches.contains(pn))
+            .cloned()
+            .collect();
+        let (below, above_applied) =
+            remaining_applied.split_at(target_position(&remaining_applied));
+        let above: Vec<PatchName> = above_applied
+            .iter()
+            .chain(stack.unapplied().iter().filter(|pn| !patches.contains(pn)))
+            .cloned()
+            .collect();
+        patches = graph.pull_in(&patches, below, &above);
+        if let Some(target_patch) = opt_target.as_ref().filter(|pn| patches.contains(pn)) {
+            return Err(anyhow!(
+                "target patch `{target_patch}` depends on or is a dependent of a patch to \
+                 sink",
+            ));
+        }
+    }
+
     let mut remaining_unapplied: Vec<PatchName> = stack
         .unapplied()
         .iter()
@@ -165,17 +206,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
         .cloned()
         .collect();
 
-    let target_pos = if let Some(target_patch) = &opt_target {
-        remaining_applied
-            .iter()
-            .position(|pn| pn == target_patch)
-            .expect("already validated that target is applied")
-            + if is_above { 1 } else { 0 }
-    } else {
-        0
-    };
-
-    let mut patches = patches;
+    let target_pos = target_position(&remaining_applied);
 
     let (applied, unapplied) = if nopush_flag {
         let mut applied: Vec<PatchName> = Vec::with_capacity(target_pos + patches.len());
@@ -200,6 +231,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
         .use_index_and_worktree(true)
         .committer_date_is_author_date(matches.get_flag("committer-date-is-author-date"))
         .with_output_stream(get_color_stdout(matches))
+        .dependency_graph(dependency_graph)
         .transact(|trans| trans.reorder_patches(Some(&applied), Some(&unapplied), None))
         .execute("sink")?;
 
@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg skip` implementation.
+
+use anyhow::{anyhow, Result};
+use clap::ArgMatches;
+
+use super::r#continue::{get_pending_operation, perform_steps, restore_autostash, StepSettings};
+use crate::{
+    ext::RepositoryExtended,
+    stack::{InitializationPolicy, Stack, StackAccess, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "skip",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Skip the conflicting patch and resume the interrupted operation")
+        .long_about(
+            "Resume a 'stg push', 'stg rebase', or 'stg pull' operation that was \
+             interrupted by merge conflicts, skipping the conflicting patch.\n\
+             \n\
+             Any changes in the index and worktree, including conflicts, are \
+             discarded. The conflicting patch is popped and restored to its state \
+             from before the push, leaving it unapplied. The remaining patches of the \
+             interrupted operation are then pushed.\n\
+             \n\
+             When the operation was interrupted by a 'break' or failing 'exec' \
+             instruction of 'stg rebase --interactive', no patch is popped.\n\
+             \n\
+             See also 'stg continue' and 'stg abort'.",
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
+    let stupid = repo.stupid();
+    let pending = get_pending_operation(&stack)?;
+
+    repo.check_repository_state()?;
+    stack.check_head_top_mismatch()?;
+    if let Some((patchname, _)) = pending.conflict.as_ref() {
+        if stack.applied().last() != Some(patchname) {
+            return Err(anyhow!(
+                "conflicting patch `{patchname}` is not the topmost patch"
+            ));
+        }
+    }
+
+    // Conflicts must be cleared before any further patches may be pushed.
+    stupid.read_tree_checkout_hard(stack.get_branch_head().tree_id()?.detach())?;
+
+    let completed = perform_steps(
+        stack,
+        &pending.remaining,
+        &StepSettings::resuming(&pending),
+        matches,
+        "skip",
+        |trans| {
+            if let Some((patchname, commit_id)) = pending.conflict.as_ref() {
+                trans.pop_patches(|pn| pn == patchname)?;
+                trans.update_patch(patchname, *commit_id)?;
+            }
+            Ok(())
+        },
+    )?;
+
+    if completed {
+        restore_autostash(&repo, &pending)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,452 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg split` implementation.
+
+use std::{io::Write, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use bstr::{BString, ByteSlice};
+use clap::{Arg, ArgGroup, ArgMatches, ValueHint};
+
+use crate::{
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::{patchedit, LocationConstraint, PatchLocator, PatchName},
+    stack::{InitializationPolicy, Stack, StackStateAccess},
+    stupid::{FileDiff, Stupid, StupidContext},
+    wrap::Message,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "split",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    let command = clap::Command::new(STGIT_COMMAND.name)
+        .about("Split a patch into two patches")
+        .long_about(
+            "Split some of a patch's changes off into a new patch.\n\
+             \n\
+             The changes to split off are selected either by path, with the \
+             <path> arguments, or by interactively choosing the patch's hunks with \
+             '--interactive'. The selected changes are moved to a new patch that is \
+             placed above the original patch or, with '--below', below the original \
+             patch. The original patch retains the changes that were not selected.\n\
+             \n\
+             The new patch is named and described using the patch edit options. \
+             Unless a message is provided, an editor is launched with the original \
+             patch's description to describe the new patch. When no name is given \
+             with '--name', the new patch's name is generated from its message.\n\
+             \n\
+             The original patch may also be renamed with '--original-name' and \
+             redescribed with '--original-message'. Otherwise it keeps its name and \
+             description.\n\
+             \n\
+             The patch to split may be applied or unapplied. When splitting an \
+             applied patch, the patches above it are pushed back onto the split \
+             patches.",
+        )
+        .override_usage(super::make_usage(
+            "stg split",
+            &[
+                "[OPTIONS] [<patch>] -- <path>...",
+                "[OPTIONS] --interactive [<patch>]",
+            ],
+        ))
+        .arg(
+            Arg::new("patch")
+                .help("Patch to split, defaults to the topmost patch")
+                .value_name("patch")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchLocator)),
+        )
+        .arg(
+            Arg::new("pathspecs")
+                .help("Split off the changes to files matching path(s)")
+                .value_name("path")
+                .last(true)
+                .num_args(1..)
+                .value_parser(clap::value_parser!(PathBuf))
+                .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            Arg::new("interactive")
+                .long("interactive")
+                .short('i')
+                .help("Interactively select the hunks to split off")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .group(
+            ArgGroup::new("selection")
+                .args(["pathspecs", "interactive"])
+                .required(true),
+        )
+        .arg(
+            Arg::new("below")
+                .long("below")
+                .short('b')
+                .help("Place the new patch below the original patch")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("name")
+                .long("name")
+                .short('n')
+                .help("Use <name> for the new patch")
+                .value_name("name")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchName)),
+        )
+        .arg(
+            Arg::new("original-name")
+                .long("original-name")
+                .help("Rename the original patch to <name>")
+                .value_name("name")
+                .allow_hyphen_values(true)
+                .value_parser(clap::value_parser!(PatchName)),
+        )
+        .arg(
+            Arg::new("original-message")
+                .long("original-message")
+                .help("Use <message> as the original patch's description")
+                .value_name("message")
+                .allow_hyphen_values(true)
+                .value_parser(clap::builder::NonEmptyStringValueParser::new()),
+        );
+    patchedit::add_args(command, true, false)
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    let stack = Stack::current(&repo, InitializationPolicy::AllowUninitialized)?;
+    let stupid = repo.stupid();
+
+    repo.check_repository_state()?;
+    stupid.statuses(None)?.check_conflicts()?;
+    stack.check_head_top_mismatch()?;
+
+    let patchname = if let Some(patch_loc) = matches.get_one::<PatchLocator>("patch") {
+        patch_loc
+            .resolve_name(&stack)?
+            .constrain(&stack, LocationConstraint::Visible)?
+    } else if let Some(top_patchname) = stack.applied().last() {
+        top_patchname.clone()
+    } else {
+        return Err(super::Error::NoAppliedPatches.into());
+    };
+
+    let opt_new_patchname = matches.get_one::<PatchName>("name").cloned();
+    if let Some(new_patchname) = opt_new_patchname.as_ref() {
+        if let Some(colliding_patchname) = stack.collides(new_patchname) {
+            return Err(anyhow!("patch `{colliding_patchname}` already exists"));
+        }
+    }
+    let original_patchname = matches
+        .get_one::<PatchName>("original-name")
+        .cloned()
+        .unwrap_or_else(|| patchname.clone());
+    if let Some(colliding_patchname) = stack.collides(&original_patchname) {
+        if colliding_patchname != &patchname {
+            return Err(anyhow!("patch `{colliding_patchname}` already exists"));
+        }
+    }
+    if opt_new_patchname.as_ref() == Some(&original_patchname) {
+        return Err(anyhow!(
+            "the new and original patches cannot both be named `{original_patchname}`"
+        ));
+    }
+
+    let below = matches.get_flag("below");
+    let patch_commit = stack.get_patch_commit(&patchname);
+    let parent_commit = patch_commit.get_parent_commit()?;
+    let parent_tree_id = parent_commit.tree_id()?.detach();
+    let tree_id = patch_commit.tree_id()?.detach();
+
+    // The tree between the lower and upper of the two resulting patches.
+    let middle_tree_id = select_changes(
+        &stupid,
+        matches,
+        &patchname,
+        parent_tree_id,
+        tree_id,
+        below,
+        "Split off",
+    )?;
+
+    let (nothing_selected, all_selected) = if below {
+        (middle_tree_id == parent_tree_id, middle_tree_id == tree_id)
+    } else {
+        (middle_tree_id == tree_id, middle_tree_id == parent_tree_id)
+    };
+    if nothing_selected {
+        return Err(anyhow!("no changes selected to split off"));
+    } else if all_selected {
+        return Err(anyhow!(
+            "all changes selected; nothing would remain in `{patchname}`"
+        ));
+    }
+
+    let author = patch_commit.author_strict()?;
+    let message = patch_commit.message_ex();
+    let original_message = matches.get_one::<String>("original-message").map_or_else(
+        || patch_commit.message_ex(),
+        |message| Message::from(patchedit::prettify(message)),
+    );
+    let committer = repo.get_committer()?;
+
+    // When the new patch is placed above, the original patch keeps the lower tree.
+    let lower_original_commit_id = if below {
+        None
+    } else {
+        Some(repo.commit_ex(
+            &author,
+            committer,
+            &original_message,
+            middle_tree_id,
+            [parent_commit.id],
+        )?)
+    };
+
+    let (new_patchname, new_commit_id) = match patchedit::EditBuilder::default()
+        .allow_diff_edit(false)
+        .allow_implicit_edit(true)
+        .allow_template_save(false)
+        .original_patchname(opt_new_patchname.as_ref())
+        .default_author(author.clone())
+        .default_message(message.decode()?.to_string())
+        .override_tree_id(if below { middle_tree_id } else { tree_id })
+        .override_parent_id(lower_original_commit_id.unwrap_or(parent_commit.id))
+        .edit(&stack, &repo, matches)?
+    {
+        patchedit::EditOutcome::TemplateSaved(_) => panic!("not allowed for split"),
+        patchedit::EditOutcome::Edited {
+            new_patchname,
+            new_commit_id,
+        } => (
+            new_patchname
+                .or(opt_new_patchname)
+                .expect("either have original or new patchname"),
+            new_commit_id.expect("must have new commit id because no original patch commit"),
+        ),
+    };
+    if new_patchname == original_patchname {
+        return Err(anyhow!(
+            "the new and original patches cannot both be named `{original_patchname}`"
+        ));
+    }
+
+    let original_commit_id = if let Some(commit_id) = lower_original_commit_id {
+        commit_id
+    } else {
+        repo.commit_ex(
+            &author,
+            committer,
+            &original_message,
+            tree_id,
+            [new_commit_id],
+        )?
+    };
+
+    let split_patchnames = if below {
+        [new_patchname.clone(), original_patchname.clone()]
+    } else {
+        [original_patchname.clone(), new_patchname.clone()]
+    };
+
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            let to_push = if let Some(pos) = trans.applied().iter().position(|pn| pn == &patchname)
+            {
+                let to_pop = trans.applied()[pos..].to_vec();
+                let popped_extra = trans.pop_patches(|pn| to_pop.contains(pn))?;
+                assert!(popped_extra.is_empty());
+                let mut to_push = split_patchnames.to_vec();
+                to_push.extend(to_pop.into_iter().skip(1));
+                to_push
+            } else {
+                Vec::new()
+            };
+            trans.update_patch(&patchname, original_commit_id)?;
+            trans.rename_patch(&patchname, &original_patchname)?;
+            let pos = trans
+                .unapplied()
+                .iter()
+                .position(|pn| pn == &original_patchname)
+                .expect("split patch is unapplied");
+            let new_pos = if below { pos } else { pos + 1 };
+            trans.new_unapplied(&new_patchname, new_commit_id, new_pos)?;
+            trans.push_patches(&to_push, false)
+        })
+        .execute(&format!("split {patchname}: {new_patchname}"))?;
+
+    Ok(())
+}
+
+/// Select some of a patch's changes, either by the "pathspecs" argument or
+/// interactively with the "interactive" flag.
+///
+/// Returns the tree resulting from applying either the selected changes or, when
+/// `keep_selected` is false, the unselected changes to the patch's parent tree.
+pub(super) fn select_changes(
+    stupid: &StupidContext,
+    matches: &ArgMatches,
+    patchname: &PatchName,
+    parent_tree_id: gix::ObjectId,
+    tree_id: gix::ObjectId,
+    keep_selected: bool,
+    action: &str,
+) -> Result<gix::ObjectId> {
+    if let Some(pathspecs) = matches.get_many::<PathBuf>("pathspecs") {
+        let (from_tree_id, to_tree_id) = if keep_selected {
+            (parent_tree_id, tree_id)
+        } else {
+            (tree_id, parent_tree_id)
+        };
+        stupid.with_temp_index(|stupid_temp| {
+            stupid_temp.read_tree(from_tree_id)?;
+            if !stupid_temp.apply_pathlimited_treediff_to_index(
+                from_tree_id,
+                to_tree_id,
+                false,
+                pathspecs,
+            )? {
+                return Err(anyhow!("failed to select changes of `{patchname}`"));
+            }
+            stupid_temp.write_tree()
+        })
+    } else {
+        let file_diffs = stupid.diff_tree_file_diffs(parent_tree_id, tree_id, 3)?;
+        let selection = select_hunks(&file_diffs, action)?;
+        let (paths, diff) = selection.compose(&file_diffs, keep_selected);
+        stupid.with_temp_index(|stupid_temp| {
+            stupid_temp.read_tree(parent_tree_id)?;
+            if !paths.is_empty()
+                && !stupid_temp.apply_pathlimited_treediff_to_index(
+                    parent_tree_id,
+                    tree_id,
+                    false,
+                    paths.iter().map(|path| {
+                        path.to_os_str()
+                            .expect("paths on Windows must be utf8")
+                            .to_os_string()
+                    }),
+                )?
+            {
+                return Err(anyhow!("failed to select changes of `{patchname}`"));
+            }
+            if !diff.is_empty() {
+                stupid_temp.apply_to_index(diff.as_bstr())?;
+            }
+            stupid_temp.write_tree()
+        })
+    }
+}
+
+/// Hunks, or whole files, selected interactively.
+struct Selection {
+    /// Per-file selection of whole file changes, used for files without hunks.
+    files: Vec<bool>,
+
+    /// Per-file, per-hunk selection.
+    hunks: Vec<Vec<bool>>,
+}
+
+impl Selection {
+    /// Compose the changes to be applied on top of the patch's parent.
+    ///
+    /// The composed changes are either the selected changes or, when `keep_selected`
+    /// is false, the unselected changes.
+    ///
+    /// Returns the paths of whole files to be taken from the patch along with a diff
+    /// of the hunks to be applied.
+    fn compose(&self, file_diffs: &[FileDiff], keep_selected: bool) -> (Vec<BString>, BString) {
+        let mut paths = Vec::new();
+        let mut diff = BString::from("");
+        for (i, file_diff) in file_diffs.iter().enumerate() {
+            if file_diff.hunks.is_empty() || file_diff.is_binary {
+                if self.files[i] == keep_selected {
+                    paths.push(file_diff.path().to_owned());
+                }
+            } else if self.hunks[i].contains(&keep_selected) {
+                let hunks =
+                    file_diff.hunks.iter().zip(self.hunks[i].iter()).filter_map(
+                        |(hunk, &selected)| (selected == keep_selected).then_some(hunk),
+                    );
+                diff.extend_from_slice(&file_diff.to_diff_with_hunks(hunks));
+            }
+        }
+        (paths, diff)
+    }
+}
+
+/// Interactively select hunks, prompting with `action`, e.g. "Split off".
+fn select_hunks(file_diffs: &[FileDiff], action: &str) -> Result<Selection> {
+    let mut selection = Selection {
+        files: vec![false; file_diffs.len()],
+        hunks: file_diffs
+            .iter()
+            .map(|file_diff| vec![false; file_diff.hunks.len()])
+            .collect(),
+    };
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    let verb = action.to_lowercase();
+
+    'files: for (i, file_diff) in file_diffs.iter().enumerate() {
+        stdout.write_all(&file_diff.header)?;
+        let num_units = file_diff.hunks.len().max(1);
+        let mut j = 0;
+        while j < num_units {
+            if let Some(hunk) = file_diff.hunks.get(j) {
+                stdout.write_all(&hunk.text)?;
+                write!(stdout, "{action} this hunk [y,n,a,d,q,?]? ")?;
+            } else {
+                write!(
+                    stdout,
+                    "{action} changes to `{}` [y,n,a,d,q,?]? ",
+                    file_diff.path()
+                )?;
+            }
+            stdout.flush()?;
+
+            let mut answer = String::new();
+            if stdin.read_line(&mut answer)? == 0 {
+                writeln!(stdout)?;
+                break 'files;
+            }
+            let mut select = |selected: bool, rest: bool| {
+                let end = if rest { num_units } else { j + 1 };
+                if file_diff.hunks.is_empty() {
+                    selection.files[i] = selected;
+                } else {
+                    selection.hunks[i][j..end].fill(selected);
+                }
+                j = end;
+            };
+            match answer.trim() {
+                "y" => select(true, false),
+                "n" => select(false, false),
+                "a" => select(true, true),
+                "d" => select(false, true),
+                "q" => break 'files,
+                _ => writeln!(
+                    stdout,
+                    "y - {verb} this hunk\n\
+                     n - do not {verb} this hunk\n\
+                     a - {verb} this hunk and all later hunks in the file\n\
+                     d - do not {verb} this hunk or any later hunks in the file\n\
+                     q - quit; do not {verb} this hunk or any remaining hunks\n\
+                     ? - print help"
+                )?,
+            }
+        }
+    }
+
+    Ok(selection)
+}
@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg stack fetch` implementation.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use clap::Arg;
+
+use super::Relation;
+use crate::{
+    ext::RepositoryExtended,
+    print_info_message,
+    stack::{
+        state_refname_from_branch_name, InitializationPolicy, Stack, StackAccess, StackState,
+        StackStateAccess,
+    },
+    stupid::Stupid,
+    wrap::PartialRefName,
+};
+
+pub(super) fn command() -> clap::Command {
+    clap::Command::new("fetch")
+        .about("Fetch a stack from a remote repository")
+        .long_about(
+            "Fetch a branch's stack from a remote repository.\n\
+             \n\
+             The remote repository's 'refs/stacks/<branch>' reference is fetched and \
+             the local stack is updated to the remote stack's state, including its \
+             unapplied and hidden patches. The branch defaults to the current branch. \
+             If the branch does not exist locally, it is created with the fetched \
+             stack, but is not checked out.\n\
+             \n\
+             The local stack is only updated when the remote stack is a continuation \
+             of the local stack's history. If the local stack is ahead of the remote \
+             stack, nothing is changed. If both stacks have changed, the fetch is \
+             refused unless '--force' is used, in which case the local stack is \
+             replaced with the remote stack. The replaced local state remains \
+             available in the branch's reflog.\n\
+             \n\
+             When the branch is checked out, the worktree and index must be clean and \
+             are updated to the new stack's state.",
+        )
+        .arg(
+            Arg::new("remote")
+                .help("Remote repository to fetch from")
+                .required(true)
+                .value_hint(clap::ValueHint::Other),
+        )
+        .arg(
+            Arg::new("branch")
+                .help("Branch whose stack to fetch, defaults to the current branch")
+                .value_hint(clap::ValueHint::Other),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .short('f')
+                .help("Replace the local stack even if it has diverged")
+                .action(clap::ArgAction::SetTrue),
+        )
+}
+
+pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Result<()> {
+    let remote = matches
+        .get_one::<String>("remote")
+        .expect("required argument");
+    let current_branch_name = repo
+        .get_current_branch()
+        .ok()
+        .and_then(|branch| branch.get_branch_name().ok().map(String::from));
+    let branch_name = if let Some(branch_name) = matches.get_one::<String>("branch") {
+        branch_name.clone()
+    } else if let Some(branch_name) = current_branch_name.clone() {
+        branch_name
+    } else {
+        return Err(anyhow!("no branch specified and HEAD is detached"));
+    };
+    let force = matches.get_flag("force");
+
+    let remote_id = super::fetch_stack_state(repo, remote, &branch_name)?
+        .ok_or_else(|| anyhow!("`{remote}` does not have a stack for branch `{branch_name}`"))?;
+    let remote_state = StackState::from_commit(repo, &repo.find_commit(remote_id)?)
+        .map_err(|e| anyhow!("invalid stack state fetched from `{remote}`: {e}"))?;
+    let remote_head_id = remote_state.head().id;
+    let stack_refname = state_refname_from_branch_name(&branch_name);
+    let branch_refname = gix::refs::FullName::try_from(format!("refs/heads/{branch_name}"))?;
+    let reflog_msg = format!("stack fetch: {remote}");
+
+    if repo.try_find_reference(&branch_refname)?.is_none() {
+        repo.edit_reference(gix::refs::transaction::RefEdit {
+            change: gix::refs::transaction::Change::Update {
+                log: gix::refs::transaction::LogChange {
+                    mode: gix::refs::transaction::RefLog::AndReference,
+                    force_create_reflog: false,
+                    message: format!("branch: Created from {remote}").into(),
+                },
+                expected: gix::refs::transaction::PreviousValue::MustNotExist,
+                new: gix::refs::Target::Object(remote_head_id),
+            },
+            name: branch_refname,
+            deref: false,
+        })?;
+        repo.reference(
+            stack_refname.as_str(),
+            remote_id,
+            gix::refs::transaction::PreviousValue::MustNotExist,
+            reflog_msg.as_str(),
+        )?;
+        // Loading the stack creates the patch references.
+        Stack::from_branch_name(
+            repo,
+            &PartialRefName::from_str(&branch_name)?,
+            InitializationPolicy::RequireInitialized,
+        )?;
+        print_info_message(
+            matches,
+            &format!("Created branch `{branch_name}` with stack from `{remote}`"),
+        );
+        return Ok(());
+    }
+
+    let partial_branch_name = PartialRefName::from_str(&branch_name)?;
+    let stack = Stack::from_branch_name(
+        repo,
+        &partial_branch_name,
+        InitializationPolicy::RequireInitialized,
+    )?;
+    let local_id = stack.state_commit_id()?;
+
+    match super::relate(repo, local_id, remote_id)? {
+        Relation::Same => {
+            print_info_message(
+                matches,
+                &format!("Stack `{branch_name}` is already up to date with `{remote}`"),
+            );
+            return Ok(());
+        }
+        Relation::LocalAhead => {
+            print_info_message(
+                matches,
+                &format!("Stack `{branch_name}` is ahead of `{remote}`"),
+            );
+            return Ok(());
+        }
+        Relation::RemoteAhead => {}
+        Relation::Diverged(base_id) => {
+            if !force {
+                return Err(super::diverged_error(
+                    repo,
+                    remote,
+                    &branch_name,
+                    base_id,
+                    local_id,
+                    remote_id,
+                )?);
+            }
+        }
+    }
+
+    stack.check_no_pending_operation()?;
+    stack.check_head_top_mismatch()?;
+    let branch_head = stack.get_branch_head();
+    if current_branch_name.as_deref() == Some(branch_name.as_str()) {
+        repo.check_repository_state()?;
+        let stupid = repo.stupid();
+        let statuses = stupid.statuses(None)?;
+        statuses.check_conflicts()?;
+        statuses.check_index_and_worktree_clean()?;
+        stupid.read_tree_checkout(
+            branch_head.tree_id()?.detach(),
+            remote_state.head().tree_id()?.detach(),
+        )?;
+    }
+
+    repo.edit_reference(gix::refs::transaction::RefEdit {
+        change: gix::refs::transaction::Change::Update {
+            log: gix::refs::transaction::LogChange {
+                mode: gix::refs::transaction::RefLog::AndReference,
+                force_create_reflog: false,
+                message: reflog_msg.as_str().into(),
+            },
+            expected: gix::refs::transaction::PreviousValue::MustExistAndMatch(
+                gix::refs::Target::Object(branch_head.id),
+            ),
+            new: gix::refs::Target::Object(remote_head_id),
+        },
+        name: branch_refname,
+        deref: false,
+    })?;
+    repo.reference(
+        stack_refname.as_str(),
+        remote_id,
+        gix::refs::transaction::PreviousValue::MustExistAndMatch(gix::refs::Target::Object(
+            local_id,
+        )),
+        reflog_msg.as_str(),
+    )?;
+
+    // Reloading the stack brings the patch references in line with the new state.
+    let stack = Stack::from_branch_name(
+        repo,
+        &partial_branch_name,
+        InitializationPolicy::RequireInitialized,
+    )?;
+    print_info_message(
+        matches,
+        &format!(
+            "Updated stack `{branch_name}` from `{remote}`: {} applied, {} unapplied, {} hidden",
+            stack.applied().len(),
+            stack.unapplied().len(),
+            stack.hidden().len(),
+        ),
+    );
+    Ok(())
+}
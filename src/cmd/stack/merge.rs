@@ -0,0 +1,451 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg stack merge` implementation.
+
+use std::{collections::BTreeMap, rc::Rc};
+
+use anyhow::{anyhow, Result};
+use clap::Arg;
+
+use super::Relation;
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    patch::PatchName,
+    print_info_message,
+    stack::{InitializationPolicy, PatchMeta, Stack, StackAccess, StackState, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) fn command() -> clap::Command {
+    clap::Command::new("merge")
+        .about("Merge another stack state into a stack")
+        .long_about(
+            "Merge another state of the branch's stack into the stack.\n\
+             \n\
+             The other state is typically the state of a shared stack as fetched \
+             from a remote repository, e.g. 'refs/remote-stacks/<remote>/<branch>', \
+             but may be any stack state commit. The two states are merged patch by \
+             patch relative to their most recent common state:\n\
+             \n\
+             - Patches changed, created, or deleted on only one side are taken from \
+             that side.\n\
+             - Patches changed on both sides are merged by applying both sides' \
+             changes to the patch. A patch's message and author are taken from the \
+             side that changed them, preferring the local side if both did.\n\
+             - Patches whose changes cannot be merged, that were changed on one side \
+             but deleted on the other, or that were created differently on both \
+             sides are conflicts. The merge is refused unless the conflicts are \
+             resolved with '--ours' or '--theirs'.\n\
+             \n\
+             The order of the patches is taken from the side that reordered patches, \
+             preferring the local side, with patches created on the other side placed \
+             after the same patches as on that side. Likewise, whether a patch is \
+             applied, unapplied, or hidden is taken from the side that changed it. \
+             If only the other side moved the stack base, the patches are pushed \
+             onto the other side's base.\n\
+             \n\
+             The resulting stack state records both the previous local state and the \
+             other state as its parents such that the other state is not merged \
+             again, and such that the merged stack may subsequently be pushed with \
+             'stg stack push'. If pushing a patch onto its new parent results in \
+             conflicts, the merge stops with the conflicting patch as the topmost \
+             patch and may be resumed with 'stg continue'.",
+        )
+        .arg(
+            Arg::new("state")
+                .help("Stack state commit to merge")
+                .required(true)
+                .value_hint(clap::ValueHint::Other),
+        )
+        .arg(argset::branch_arg())
+        .arg(
+            Arg::new("ours")
+                .long("ours")
+                .help("Resolve conflicting patches with the local side")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("theirs")
+                .long("theirs")
+                .help("Resolve conflicting patches with the other side")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("ours"),
+        )
+}
+
+pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Result<()> {
+    let spec = matches
+        .get_one::<String>("state")
+        .expect("required argument");
+    let stack = Stack::from_branch_locator(
+        repo,
+        matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::RequireInitialized,
+    )?;
+    let branch_name = stack.get_branch_name().to_string();
+
+    let other_id = repo.rev_parse_single_ex(spec)?.detach();
+    let other = repo
+        .find_commit(other_id)
+        .map_err(anyhow::Error::from)
+        .and_then(|commit| StackState::from_commit(repo, &commit))
+        .map_err(|_| anyhow!("`{spec}` is not a stack state"))?;
+
+    repo.check_repository_state()?;
+    repo.stupid().statuses(None)?.check_conflicts()?;
+    stack.check_head_top_mismatch()?;
+    stack.check_no_pending_operation()?;
+
+    let local_id = stack.state_commit_id()?;
+    let base_id = match super::relate(repo, local_id, other_id)? {
+        Relation::Same | Relation::LocalAhead => {
+            print_info_message(
+                matches,
+                &format!("Stack `{branch_name}` already contains `{spec}`"),
+            );
+            return Ok(());
+        }
+        Relation::RemoteAhead => Some(local_id),
+        Relation::Diverged(base_id) => base_id,
+    };
+    let base = if let Some(base_id) = base_id {
+        Some(StackState::from_commit(repo, &repo.find_commit(base_id)?)?)
+    } else {
+        None
+    };
+    let local = StackState::from_commit(repo, &repo.find_commit(local_id)?)?;
+
+    let resolution = if matches.get_flag("ours") {
+        Some(Side::Ours)
+    } else if matches.get_flag("theirs") {
+        Some(Side::Theirs)
+    } else {
+        None
+    };
+    let merged = merge_states(repo, base.as_ref(), &local, &other, resolution)?;
+
+    let mut all_unapplied = merged.applied.clone();
+    all_unapplied.extend(merged.unapplied.iter().cloned());
+
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .resumable("stack merge", local_id, false)
+        .merged_state(other_id)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            let applied = trans.applied().to_vec();
+            trans.pop_patches(|pn| applied.contains(pn))?;
+            if let Some(new_base_id) = merged.base {
+                let new_base = trans.repo().find_commit(new_base_id)?;
+                trans.set_base(Rc::new(new_base));
+            }
+            trans.delete_patches(|pn| !merged.patches.contains_key(pn))?;
+            for (patchname, (commit_id, meta)) in &merged.patches {
+                if !trans.has_patch(patchname) {
+                    trans.new_unapplied(patchname, *commit_id, 0)?;
+                } else if trans.get_patch_commit_id(patchname) != *commit_id {
+                    trans.update_patch(patchname, *commit_id)?;
+                }
+                if trans.get_patch_meta(patchname) != meta {
+                    trans.set_patch_meta(patchname, meta.clone());
+                }
+            }
+            trans.reorder_patches(None, Some(&all_unapplied), Some(&merged.hidden))?;
+            trans.reorder_patches(Some(&merged.applied), None, None)
+        })
+        .execute(&format!("stack merge {spec}"))?;
+
+    Ok(())
+}
+
+/// A side of a merge.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Ours,
+    Theirs,
+}
+
+/// Where a patch is in a stack.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Applied,
+    Unapplied,
+    Hidden,
+}
+
+/// The result of merging two stack states.
+struct MergedState {
+    /// The merged patches' commits and metadata.
+    patches: BTreeMap<PatchName, (gix::ObjectId, PatchMeta)>,
+
+    applied: Vec<PatchName>,
+    unapplied: Vec<PatchName>,
+    hidden: Vec<PatchName>,
+
+    /// New stack base, if the base is to be moved.
+    base: Option<gix::ObjectId>,
+}
+
+/// Merge the `theirs` stack state into the `ours` stack state.
+///
+/// The states are merged patch by patch relative to their common `base` state. When
+/// the states have no common state, all patches are considered to be created on both
+/// sides.
+fn merge_states(
+    repo: &gix::Repository,
+    base: Option<&StackState>,
+    ours: &StackState,
+    theirs: &StackState,
+    resolution: Option<Side>,
+) -> Result<MergedState> {
+    let mut patches = BTreeMap::new();
+    let mut conflicts: Vec<&PatchName> = Vec::new();
+
+    for patchname in ours
+        .all_patches()
+        .chain(theirs.all_patches().filter(|pn| !ours.has_patch(pn)))
+    {
+        let base_commit = base.and_then(|base| patch_commit(base, patchname));
+        let ours_commit = patch_commit(ours, patchname);
+        let theirs_commit = patch_commit(theirs, patchname);
+
+        let fingerprint = |commit: Option<&Rc<gix::Commit>>| {
+            commit
+                .map(|commit| super::patch_fingerprint(repo, commit))
+                .transpose()
+        };
+
+        let side = if ours_commit.map(|c| c.id) == theirs_commit.map(|c| c.id) {
+            Some(Side::Ours)
+        } else {
+            let base_fingerprint = fingerprint(base_commit)?;
+            let ours_fingerprint = fingerprint(ours_commit)?;
+            if ours_fingerprint == base_fingerprint {
+                Some(Side::Theirs)
+            } else {
+                let theirs_fingerprint = fingerprint(theirs_commit)?;
+                if theirs_fingerprint == base_fingerprint || theirs_fingerprint == ours_fingerprint
+                {
+                    Some(Side::Ours)
+                } else {
+                    None
+                }
+            }
+        };
+
+        let merged = match side {
+            Some(Side::Ours) => ours_commit.map(|commit| (commit.id, ours)),
+            Some(Side::Theirs) => theirs_commit.map(|commit| (commit.id, theirs)),
+            None => {
+                let merged_id = if let (Some(base_commit), Some(ours_commit), Some(theirs_commit)) =
+                    (base_commit, ours_commit, theirs_commit)
+                {
+                    merge_patch(repo, base_commit, ours_commit, theirs_commit)?
+                } else {
+                    None
+                };
+                if let Some(merged_id) = merged_id {
+                    Some((merged_id, ours))
+                } else {
+                    match resolution {
+                        Some(Side::Ours) => ours_commit.map(|commit| (commit.id, ours)),
+                        Some(Side::Theirs) => theirs_commit.map(|commit| (commit.id, theirs)),
+                        None => {
+                            conflicts.push(patchname);
+                            None
+                        }
+                    }
+                }
+            }
+        };
+
+        if let Some((commit_id, state)) = merged {
+            let meta = state.get_patch_meta(patchname).clone();
+            patches.insert(patchname.clone(), (commit_id, meta));
+        }
+    }
+
+    if !conflicts.is_empty() {
+        let names: Vec<String> = conflicts.iter().map(|pn| format!("`{pn}`")).collect();
+        return Err(anyhow!(
+            "patches changed on both sides cannot be merged: {}; \
+             use `--ours` or `--theirs` to resolve",
+            names.join(", ")
+        ));
+    }
+
+    let base_sequence: Vec<&PatchName> = base.map_or_else(Vec::new, |base| sequence(base));
+    let ours_sequence = sequence(ours);
+    let theirs_sequence = sequence(theirs);
+    let ours_reordered = relative_order(&ours_sequence, &base_sequence)
+        != relative_order(&base_sequence, &ours_sequence);
+    let (primary, secondary) = if ours_reordered || base.is_none() {
+        (&ours_sequence, &theirs_sequence)
+    } else {
+        (&theirs_sequence, &ours_sequence)
+    };
+    let mut order: Vec<&PatchName> = primary
+        .iter()
+        .copied()
+        .filter(|pn| patches.contains_key(*pn))
+        .collect();
+    for (i, patchname) in secondary.iter().enumerate() {
+        if patches.contains_key(*patchname) && !order.contains(patchname) {
+            let pos = secondary[..i]
+                .iter()
+                .rev()
+                .find_map(|prev| order.iter().position(|pn| pn == prev))
+                .map_or(0, |pos| pos + 1);
+            order.insert(pos, patchname);
+        }
+    }
+
+    let mut applied = Vec::new();
+    let mut unapplied = Vec::new();
+    let mut hidden = Vec::new();
+    for patchname in order {
+        let base_status = base.and_then(|base| status(base, patchname));
+        let ours_status = status(ours, patchname);
+        let theirs_status = status(theirs, patchname);
+        let merged_status = if ours_status == base_status {
+            theirs_status.or(ours_status)
+        } else {
+            ours_status.or(theirs_status)
+        };
+        match merged_status.expect("merged patch exists on one side") {
+            Status::Applied => applied.push(patchname.clone()),
+            Status::Unapplied => unapplied.push(patchname.clone()),
+            Status::Hidden => hidden.push(patchname.clone()),
+        }
+    }
+
+    let ours_base = state_base(ours)?;
+    let theirs_base = state_base(theirs)?;
+    let base_base = base.map(state_base).transpose()?;
+    let new_base =
+        (base_base == Some(ours_base) && theirs_base != ours_base).then_some(theirs_base);
+
+    Ok(MergedState {
+        patches,
+        applied,
+        unapplied,
+        hidden,
+        base: new_base,
+    })
+}
+
+/// Merge the changes made to a patch on both sides.
+///
+/// The base and other side's versions of the patch are first rebased onto the parent
+/// of our version of the patch. The changes from the rebased base version to the
+/// rebased other version are then applied to our version. Returns `None` if any of
+/// these steps results in conflicts.
+fn merge_patch(
+    repo: &gix::Repository,
+    base: &gix::Commit,
+    ours: &gix::Commit,
+    theirs: &gix::Commit,
+) -> Result<Option<gix::ObjectId>> {
+    let parent = ours.get_parent_commit()?;
+    let parent_tree_id = parent.tree_id()?.detach();
+    let ours_tree_id = ours.tree_id()?.detach();
+
+    let tree_id = repo.stupid().with_temp_index(|stupid_temp| {
+        let mut rebased_tree_ids = Vec::with_capacity(2);
+        for commit in [base, theirs] {
+            let commit_parent_tree_id = commit.get_parent_commit()?.tree_id()?.detach();
+            let commit_tree_id = commit.tree_id()?.detach();
+            stupid_temp.read_tree(parent_tree_id)?;
+            if commit_parent_tree_id != commit_tree_id
+                && !stupid_temp.apply_treediff_to_index(
+                    commit_parent_tree_id,
+                    commit_tree_id,
+                    true,
+                )?
+            {
+                return Ok(None);
+            }
+            rebased_tree_ids.push(stupid_temp.write_tree()?);
+        }
+        let (base_tree_id, theirs_tree_id) = (rebased_tree_ids[0], rebased_tree_ids[1]);
+
+        stupid_temp.read_tree(ours_tree_id)?;
+        if base_tree_id != theirs_tree_id
+            && !stupid_temp.apply_treediff_to_index(base_tree_id, theirs_tree_id, true)?
+        {
+            return Ok(None);
+        }
+        stupid_temp.write_tree().map(Some)
+    })?;
+
+    let Some(tree_id) = tree_id else {
+        return Ok(None);
+    };
+
+    let base_ref = base.decode()?;
+    let ours_ref = ours.decode()?;
+    let message_source =
+        if ours_ref.message == base_ref.message && ours_ref.author == base_ref.author {
+            theirs
+        } else {
+            ours
+        };
+    let commit_id = repo.commit_ex(
+        &message_source.author_strict()?,
+        repo.get_committer()?,
+        &message_source.message_ex(),
+        tree_id,
+        [parent.id],
+    )?;
+    Ok(Some(commit_id))
+}
+
+/// Get a patch's commit, if the patch exists in the state.
+fn patch_commit<'a, 'repo>(
+    state: &'a StackState<'repo>,
+    patchname: &PatchName,
+) -> Option<&'a Rc<gix::Commit<'repo>>> {
+    state
+        .has_patch(patchname)
+        .then(|| state.get_patch_commit(patchname))
+}
+
+/// Get whether a patch is applied, unapplied, or hidden in the state.
+fn status(state: &StackState, patchname: &PatchName) -> Option<Status> {
+    if state.is_applied(patchname) {
+        Some(Status::Applied)
+    } else if state.is_unapplied(patchname) {
+        Some(Status::Unapplied)
+    } else if state.is_hidden(patchname) {
+        Some(Status::Hidden)
+    } else {
+        None
+    }
+}
+
+/// Get the state's patches in stack order.
+fn sequence<'a>(state: &'a StackState) -> Vec<&'a PatchName> {
+    state.all_patches().collect()
+}
+
+/// Get the patches of `sequence` that are also in `other`, in `sequence`'s order.
+fn relative_order<'a>(sequence: &[&'a PatchName], other: &[&PatchName]) -> Vec<&'a PatchName> {
+    sequence
+        .iter()
+        .copied()
+        .filter(|pn| other.contains(pn))
+        .collect()
+}
+
+/// Get the stack base of the state.
+fn state_base(state: &StackState) -> Result<gix::ObjectId> {
+    if let Some(patchname) = state.applied().first() {
+        Ok(state.get_patch_commit(patchname).get_parent_commit()?.id)
+    } else {
+        Ok(state.head().id)
+    }
+}
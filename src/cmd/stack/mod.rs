@@ -0,0 +1,268 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg stack` implementation.
+
+mod fetch;
+mod merge;
+mod push;
+
+use std::collections::{HashSet, VecDeque};
+
+use anyhow::{anyhow, Result};
+use bstr::{BString, ByteSlice};
+
+use crate::{
+    ext::{CommitExtended, RepositoryExtended},
+    patch::PatchName,
+    stack::{state_refname_from_branch_name, StackState, StackStateAccess},
+    stupid::Stupid,
+};
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "stack",
+    category: super::CommandCategory::StackManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Share stacks with remote repositories")
+        .long_about(
+            "Share StGit stacks with remote repositories.\n\
+             \n\
+             A branch's stack is recorded in the 'refs/stacks/<branch>' reference, \
+             with each patch's commit referenced by 'refs/patches/<branch>/<patch>'. \
+             The `push` subcommand sends these references to a remote repository and \
+             the `fetch` subcommand retrieves them, reconstructing the stack, \
+             including its unapplied and hidden patches, in the local repository.\n\
+             \n\
+             The last known state of each remote stack is tracked by the \
+             'refs/remote-stacks/<remote>/<branch>' reference. When both the local \
+             and remote stacks have changed since they last had a common state, the \
+             stacks have diverged and neither pushing nor fetching is performed \
+             unless '--force' is used. The patches changed on both sides are \
+             reported. Diverged stacks may be reconciled with the `merge` \
+             subcommand, e.g. by merging 'refs/remote-stacks/<remote>/<branch>' \
+             after a refused fetch, and then pushing the merged stack.",
+        )
+        .subcommand_required(true)
+        .subcommand(fetch::command())
+        .subcommand(merge::command())
+        .subcommand(push::command())
+}
+
+fn run(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    match matches.subcommand() {
+        Some(("fetch", sub_matches)) => fetch::dispatch(&repo, sub_matches),
+        Some(("merge", sub_matches)) => merge::dispatch(&repo, sub_matches),
+        Some(("push", sub_matches)) => push::dispatch(&repo, sub_matches),
+        _ => panic!("valid subcommand is expected"),
+    }
+}
+
+/// How a local stack state relates to a remote stack state.
+enum Relation {
+    /// Both stacks are at the same state.
+    Same,
+
+    /// The remote state is in the local state's history.
+    LocalAhead,
+
+    /// The local state is in the remote state's history.
+    RemoteAhead,
+
+    /// Both stacks changed since their common state, if any.
+    Diverged(Option<gix::ObjectId>),
+}
+
+/// Get the name of the reference tracking a remote repository's stack.
+fn tracking_refname(remote: &str, branch_name: &str) -> Result<gix::refs::FullName> {
+    gix::refs::FullName::try_from(format!("refs/remote-stacks/{remote}/{branch_name}"))
+        .map_err(|_| anyhow!("invalid remote name `{remote}`"))
+}
+
+/// Fetch the remote stack state of a branch into its tracking reference.
+///
+/// Returns the id of the remote state commit, or `None` if the remote repository
+/// does not have a stack for the branch.
+fn fetch_stack_state(
+    repo: &gix::Repository,
+    remote: &str,
+    branch_name: &str,
+) -> Result<Option<gix::ObjectId>> {
+    if repo.try_find_remote(remote).is_none() {
+        return Err(anyhow!("remote `{remote}` does not exist"));
+    }
+    let stack_refname = state_refname_from_branch_name(branch_name);
+    let tracking_refname = tracking_refname(remote, branch_name)?;
+    let stupid = repo.stupid();
+    if let Some(remote_id) = stupid.ls_remote(remote, &stack_refname)? {
+        stupid.fetch(
+            remote,
+            [format!("+{stack_refname}:{}", tracking_refname.as_bstr())],
+        )?;
+        Ok(Some(remote_id))
+    } else {
+        if let Some(reference) = repo.try_find_reference(&tracking_refname)? {
+            reference.delete()?;
+        }
+        Ok(None)
+    }
+}
+
+/// Update the tracking reference of a remote stack to the given state.
+fn update_tracking_ref(
+    repo: &gix::Repository,
+    remote: &str,
+    branch_name: &str,
+    state_id: gix::ObjectId,
+    reflog_msg: &str,
+) -> Result<()> {
+    repo.reference(
+        tracking_refname(remote, branch_name)?,
+        state_id,
+        gix::refs::transaction::PreviousValue::Any,
+        reflog_msg,
+    )?;
+    Ok(())
+}
+
+/// Get the ids of the stack state commits in the history of a state, newest first.
+///
+/// The `prev` and `merged` states are followed. The other parents of state commits are
+/// patch commits which do not contribute to the stack's history.
+fn state_history(repo: &gix::Repository, state_id: gix::ObjectId) -> Result<Vec<gix::ObjectId>> {
+    let mut history = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([state_id]);
+    while let Some(id) = queue.pop_front() {
+        if !seen.insert(id) {
+            continue;
+        }
+        history.push(id);
+        let state = StackState::from_commit(repo, &repo.find_commit(id)?)?;
+        queue.extend(
+            state
+                .prev
+                .iter()
+                .chain(state.merged.iter())
+                .map(|commit| commit.id),
+        );
+    }
+    Ok(history)
+}
+
+/// Determine how a local stack state relates to a remote stack state.
+fn relate(
+    repo: &gix::Repository,
+    local_id: gix::ObjectId,
+    remote_id: gix::ObjectId,
+) -> Result<Relation> {
+    if local_id == remote_id {
+        return Ok(Relation::Same);
+    }
+    let local_history = state_history(repo, local_id)?;
+    if local_history.contains(&remote_id) {
+        return Ok(Relation::LocalAhead);
+    }
+    let remote_history = state_history(repo, remote_id)?;
+    if remote_history.contains(&local_id) {
+        return Ok(Relation::RemoteAhead);
+    }
+    let local_history: HashSet<gix::ObjectId> = local_history.into_iter().collect();
+    let base_id = remote_history
+        .into_iter()
+        .find(|id| local_history.contains(id));
+    Ok(Relation::Diverged(base_id))
+}
+
+/// Build the error reported when a local and remote stack have diverged.
+///
+/// The patches that were changed differently on both sides since the common state
+/// are listed in the error message.
+fn diverged_error(
+    repo: &gix::Repository,
+    remote: &str,
+    branch_name: &str,
+    base_id: Option<gix::ObjectId>,
+    local_id: gix::ObjectId,
+    remote_id: gix::ObjectId,
+) -> Result<anyhow::Error> {
+    let load = |id| -> Result<StackState> { StackState::from_commit(repo, &repo.find_commit(id)?) };
+    let base = base_id.map(load).transpose()?;
+    let local = load(local_id)?;
+    let remote_state = load(remote_id)?;
+
+    let fingerprint = |state: &StackState, patchname: &PatchName| {
+        if state.has_patch(patchname) {
+            patch_fingerprint(repo, state.get_patch_commit(patchname)).map(Some)
+        } else {
+            Ok(None)
+        }
+    };
+
+    let mut conflicts: Vec<&PatchName> = Vec::new();
+    for patchname in local
+        .all_patches()
+        .chain(remote_state.all_patches().filter(|pn| !local.has_patch(pn)))
+    {
+        let local_commit_id = local
+            .has_patch(patchname)
+            .then(|| local.get_patch_commit_id(patchname));
+        let remote_commit_id = remote_state
+            .has_patch(patchname)
+            .then(|| remote_state.get_patch_commit_id(patchname));
+        if local_commit_id == remote_commit_id {
+            continue;
+        }
+        let base_fingerprint = if let Some(base) = base.as_ref() {
+            fingerprint(base, patchname)?
+        } else {
+            None
+        };
+        let local_fingerprint = fingerprint(&local, patchname)?;
+        let remote_fingerprint = fingerprint(&remote_state, patchname)?;
+        if local_fingerprint != base_fingerprint
+            && remote_fingerprint != base_fingerprint
+            && local_fingerprint != remote_fingerprint
+        {
+            conflicts.push(patchname);
+        }
+    }
+
+    let mut message =
+        format!("local and `{remote}` stacks of branch `{branch_name}` have diverged");
+    if !conflicts.is_empty() {
+        let names: Vec<String> = conflicts.iter().map(|pn| format!("`{pn}`")).collect();
+        message.push_str(&format!(
+            "; patches changed on both sides: {}",
+            names.join(", ")
+        ));
+    }
+    Ok(anyhow!(message))
+}
+
+/// Get a fingerprint of a patch that does not depend on the patch's parent commit.
+///
+/// The fingerprint is comprised of the patch's message and its diff. Similar to `git
+/// patch-id`, blob ids and hunk line numbers are disregarded such that a patch that was
+/// only rebased, e.g. because a patch below it was modified, keeps its fingerprint.
+fn patch_fingerprint(repo: &gix::Repository, commit: &gix::Commit) -> Result<(BString, BString)> {
+    let parent_tree_id = commit.get_parent_commit()?.tree_id()?.detach();
+    let diff = repo.stupid().diff_tree_patch(
+        parent_tree_id,
+        commit.tree_id()?.detach(),
+        None::<Vec<&str>>,
+        false,
+        std::iter::empty::<&str>(),
+    )?;
+    let diff: BString = diff
+        .lines_with_terminator()
+        .filter(|line| !line.starts_with(b"index ") && !line.starts_with(b"@@ "))
+        .flat_map(|line| line.iter().copied())
+        .collect::<Vec<u8>>()
+        .into();
+    Ok((commit.message_raw()?.to_owned(), diff))
+}
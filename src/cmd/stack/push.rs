@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg stack push` implementation.
+
+use anyhow::{anyhow, Result};
+use clap::Arg;
+
+use super::Relation;
+use crate::{
+    argset,
+    branchloc::BranchLocator,
+    print_info_message,
+    stack::{InitializationPolicy, Stack, StackAccess},
+    stupid::Stupid,
+};
+
+pub(super) fn command() -> clap::Command {
+    clap::Command::new("push")
+        .about("Push a stack to a remote repository")
+        .long_about(
+            "Push the branch's stack to a remote repository.\n\
+             \n\
+             The 'refs/stacks/<branch>' reference and all 'refs/patches/<branch>/*' \
+             references are pushed atomically. Patch references on the remote that \
+             no longer exist locally are deleted.\n\
+             \n\
+             The push is refused if the remote stack has changes that are not \
+             present in the local stack. Use 'stg stack fetch' to integrate the \
+             remote changes first, or '--force' to overwrite the remote stack.",
+        )
+        .arg(
+            Arg::new("remote")
+                .help("Remote repository to push to")
+                .required(true)
+                .value_hint(clap::ValueHint::Other),
+        )
+        .arg(argset::branch_arg())
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .short('f')
+                .help("Overwrite the remote stack even if it has diverged")
+                .action(clap::ArgAction::SetTrue),
+        )
+}
+
+pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Result<()> {
+    let remote = matches
+        .get_one::<String>("remote")
+        .expect("required argument");
+    let stack = Stack::from_branch_locator(
+        repo,
+        matches.get_one::<BranchLocator>("branch"),
+        InitializationPolicy::RequireInitialized,
+    )?;
+    let branch_name = stack.get_branch_name();
+    let force = matches.get_flag("force");
+    let local_id = stack.state_commit_id()?;
+
+    if let Some(remote_id) = super::fetch_stack_state(repo, remote, branch_name)? {
+        match super::relate(repo, local_id, remote_id)? {
+            Relation::Same => {
+                print_info_message(
+                    matches,
+                    &format!("Stack `{branch_name}` is already up to date on `{remote}`"),
+                );
+                return Ok(());
+            }
+            Relation::LocalAhead => {}
+            Relation::RemoteAhead if !force => {
+                return Err(anyhow!(
+                    "`{remote}` has changes to stack `{branch_name}` that are not present \
+                     locally; use `stg stack fetch` to integrate them"
+                ));
+            }
+            Relation::Diverged(base_id) if !force => {
+                return Err(super::diverged_error(
+                    repo,
+                    remote,
+                    branch_name,
+                    base_id,
+                    local_id,
+                    remote_id,
+                )?);
+            }
+            Relation::RemoteAhead | Relation::Diverged(_) => {}
+        }
+    }
+
+    let stack_refname = stack.get_stack_refname();
+    let patch_refspec = stack.patch_revspec("*");
+    repo.stupid().push_atomic(
+        remote,
+        [
+            format!(
+                "{}{stack_refname}:{stack_refname}",
+                if force { "+" } else { "" }
+            ),
+            format!("+{patch_refspec}:{patch_refspec}"),
+        ],
+        true,
+    )?;
+    super::update_tracking_ref(
+        repo,
+        remote,
+        branch_name,
+        local_id,
+        &format!("stack push: {remote}"),
+    )?;
+
+    print_info_message(
+        matches,
+        &format!("Pushed stack `{branch_name}` to `{remote}`"),
+    );
+    Ok(())
+}
@@ -0,0 +1,209 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg trailers apply` implementation.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::Result;
+use clap::Arg;
+
+use crate::{
+    color::get_color_stdout,
+    ext::{CommitExtended, RepositoryExtended},
+    mail::{self, ThreadHeaders},
+    patch::{patchedit, PatchName},
+    print_info_message,
+    stack::{InitializationPolicy, Stack, StackStateAccess, StackTransaction},
+    stupid::Stupid,
+    wrap::Message,
+};
+
+pub(super) fn command() -> clap::Command {
+    clap::Command::new("apply")
+        .about("Apply review trailers from email replies to patches")
+        .long_about(
+            "Apply the \"Reviewed-by\", \"Acked-by\", and \"Tested-by\" trailers given \
+             in email replies to the patches being replied to.\n\
+             \n\
+             The replies are read from an mbox file. Each reply is matched to a patch \
+             using the reply's \"In-Reply-To\" and \"References\" headers and the \
+             \"Message-ID\" trailer recorded in the patch's description, e.g. by \
+             'stg import --message-id'. A reply to the cover letter of a patch series \
+             applies to all patches of the series when the mbox also contains the \
+             patches themselves. Quoted lines of the replies are ignored, as are \
+             trailers already present in a patch's description.\n\
+             \n\
+             All patches are updated in a single transaction.",
+        )
+        .arg(
+            Arg::new("mbox")
+                .long("mbox")
+                .short('M')
+                .help("Read email replies from mbox <file>")
+                .value_name("file")
+                .value_parser(clap::value_parser!(PathBuf))
+                .value_hint(clap::ValueHint::FilePath)
+                .required(true),
+        )
+}
+
+pub(super) fn dispatch(repo: &gix::Repository, matches: &clap::ArgMatches) -> Result<()> {
+    let stack = Stack::current(repo, InitializationPolicy::AllowUninitialized)?;
+    let stupid = repo.stupid();
+    repo.check_repository_state()?;
+    stupid.statuses(None)?.check_conflicts()?;
+    stack.check_head_top_mismatch()?;
+
+    let mut patch_ids: HashMap<String, PatchName> = HashMap::new();
+    for patchname in stack.all_patches() {
+        let message = stack.get_patch_commit(patchname).message_ex();
+        if let Some(id) = mail::recorded_message_id(&message.decode()?) {
+            patch_ids.insert(id, patchname.clone());
+        }
+    }
+
+    let mbox_path = matches
+        .get_one::<PathBuf>("mbox")
+        .expect("required argument");
+    let out_dir = tempfile::tempdir()?;
+    let num_mails = stupid.mailsplit(Some(mbox_path), out_dir.path(), false, false)?;
+    let mut mails = Vec::with_capacity(num_mails);
+    for i in 1..=num_mails {
+        let mail_path = out_dir.path().join(format!("{i:04}"));
+        let thread = ThreadHeaders::parse(&std::fs::read(&mail_path)?);
+        let mail_file = std::fs::File::open(mail_path)?;
+        let (_mailinfo, body, _diff) = stupid.mailinfo(Some(mail_file), false, false)?;
+        mails.push((thread, body));
+    }
+
+    // Messages that patches of the stack reply to, e.g. a cover letter, are mapped to
+    // those patches.
+    let mut thread_patches: HashMap<&str, Vec<&PatchName>> = HashMap::new();
+    for (thread, _) in &mails {
+        let Some(patchname) = thread.message_id.as_ref().and_then(|id| patch_ids.get(id)) else {
+            continue;
+        };
+        for ancestor in thread.ancestors() {
+            let patchnames = thread_patches.entry(ancestor).or_default();
+            if !patchnames.contains(&patchname) {
+                patchnames.push(patchname);
+            }
+        }
+    }
+
+    let mut collected: HashMap<&PatchName, Vec<(String, String)>> = HashMap::new();
+    for (thread, body) in &mails {
+        if thread
+            .message_id
+            .as_ref()
+            .is_some_and(|id| patch_ids.contains_key(id))
+        {
+            continue;
+        }
+        let targets = thread.ancestors().find_map(|id| {
+            patch_ids
+                .get(id)
+                .map(|patchname| vec![patchname])
+                .or_else(|| thread_patches.get(id).cloned())
+        });
+        let Some(targets) = targets else {
+            continue;
+        };
+        let trailers = mail::review_trailers(body.as_ref());
+        for patchname in targets {
+            let patch_trailers = collected.entry(patchname).or_default();
+            for trailer in &trailers {
+                if !patch_trailers.contains(trailer) {
+                    patch_trailers.push(trailer.clone());
+                }
+            }
+        }
+    }
+
+    let mut updates: HashMap<PatchName, Message> = HashMap::new();
+    for patchname in stack.all_patches() {
+        let Some(trailers) = collected.get(patchname) else {
+            continue;
+        };
+        let message = stack.get_patch_commit(patchname).message_ex();
+        let decoded = message.decode()?;
+        let trailers: Vec<(&str, &str)> = trailers
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .filter(|(key, value)| {
+                let trailer = format!("{key}: {value}");
+                !decoded.lines().any(|line| line.trim() == trailer)
+            })
+            .collect();
+        if !trailers.is_empty() {
+            let message = patchedit::append_trailers(repo, &message, trailers)?;
+            updates.insert(patchname.clone(), message);
+        }
+    }
+
+    if updates.is_empty() {
+        print_info_message(matches, "No new trailers to apply");
+        return Ok(());
+    }
+
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            let to_pop = if let Some(pos) = trans
+                .applied()
+                .iter()
+                .position(|pn| updates.contains_key(pn))
+            {
+                trans.applied()[pos..].to_vec()
+            } else {
+                vec![]
+            };
+            let popped_extra = trans.pop_patches(|pn| to_pop.contains(pn))?;
+            assert!(popped_extra.is_empty());
+
+            let committer = trans.repo().get_committer()?.to_owned();
+            let rewrite = |trans: &mut StackTransaction,
+                           patchname: &PatchName,
+                           parent_id: gix::ObjectId|
+             -> Result<()> {
+                if let Some(message) = updates.get(patchname) {
+                    let commit = trans.get_patch_commit(patchname);
+                    let commit_id = trans.repo().commit_ex(
+                        &commit.author_strict()?,
+                        &committer,
+                        message,
+                        commit.tree_id()?.detach(),
+                        [parent_id],
+                    )?;
+                    trans.update_patch(patchname, commit_id)?;
+                }
+                Ok(())
+            };
+
+            let unapplied: Vec<PatchName> = trans
+                .unapplied()
+                .iter()
+                .chain(trans.hidden())
+                .filter(|pn| !to_pop.contains(pn))
+                .cloned()
+                .collect();
+            for patchname in &unapplied {
+                let parent_id = trans.get_patch_commit(patchname).parent_ids().next();
+                if let Some(parent_id) = parent_id {
+                    rewrite(trans, patchname, parent_id.detach())?;
+                }
+            }
+
+            for patchname in &to_pop {
+                let parent_id = trans.top().id;
+                rewrite(trans, patchname, parent_id)?;
+                trans.push_patches(&[patchname], false)?;
+            }
+            Ok(())
+        })
+        .execute("trailers apply")?;
+
+    Ok(())
+}
@@ -0,0 +1,38 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! `stg trailers` implementation.
+
+mod apply;
+
+use anyhow::Result;
+
+use crate::ext::RepositoryExtended;
+
+pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
+    name: "trailers",
+    category: super::CommandCategory::PatchManipulation,
+    make,
+    run,
+};
+
+fn make() -> clap::Command {
+    clap::Command::new(STGIT_COMMAND.name)
+        .about("Manage trailers of patch descriptions")
+        .long_about(
+            "Manage the trailers, such as \"Reviewed-by\" or \"Acked-by\", at the end \
+             of patch descriptions.\n\
+             \n\
+             The `apply` subcommand adds the review trailers given in email replies to \
+             the patches being replied to.",
+        )
+        .subcommand_required(true)
+        .subcommand(apply::command())
+}
+
+fn run(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = gix::Repository::open()?;
+    match matches.subcommand() {
+        Some(("apply", sub_matches)) => apply::dispatch(&repo, sub_matches),
+        _ => panic!("valid subcommand is expected"),
+    }
+}
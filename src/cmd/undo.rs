@@ -2,16 +2,18 @@
 
 //! `stg undo` implementation.
 
-use std::rc::Rc;
+use std::{io::Write, rc::Rc};
 
 use anyhow::{anyhow, Result};
 use bstr::{BStr, ByteSlice};
-use clap::Arg;
+use clap::{Arg, ArgGroup};
+use termcolor::WriteColor;
 
 use crate::{
     color::get_color_stdout,
     ext::RepositoryExtended,
-    stack::{InitializationPolicy, Stack, StackAccess, StackState},
+    patch::PatchName,
+    stack::{InitializationPolicy, Stack, StackAccess, StackState, StackStateAccess},
 };
 
 pub(super) const STGIT_COMMAND: super::StGitCommand = super::StGitCommand {
@@ -26,8 +28,29 @@ fn make() -> clap::Command {
         .about("Undo the last command")
         .long_about(
             "Reset the patch stack to the state before the last operation. \
-             Consecutive undos will go back to yet older stack states.",
+             Consecutive undos will go back to yet older stack states.\n\
+             \n\
+             The operations that may be undone are shown with '--list', most recent \
+             first, along with a summary of the patches each operation changed. \
+             Operations that were already undone are not listed. The stack may be \
+             reset to the state before any listed operation with '--to <entry>'.\n\
+             \n\
+             With '--selective', only the listed operation's changes to patches are \
+             undone, leaving the effects of later operations in place. This is only \
+             possible when none of the patches changed by the operation were changed \
+             again by later operations. Patches created by the operation are deleted, \
+             patches modified by the operation are restored to their prior commit, \
+             and patches deleted by the operation are restored as unapplied patches. \
+             Changes to the order of patches are not undone selectively.",
         )
+        .override_usage(super::make_usage(
+            "stg undo",
+            &[
+                "[--hard] [--number <n>]",
+                "[--hard] --to <entry> [--selective]",
+                "--list",
+            ],
+        ))
         .arg(
             Arg::new("number")
                 .long("number")
@@ -46,10 +69,40 @@ fn make() -> clap::Command {
                         })
                 }),
         )
+        .arg(
+            Arg::new("to")
+                .long("to")
+                .short('t')
+                .help("Undo back to and including entry <entry> of the list")
+                .value_name("entry")
+                .value_parser(|s: &str| {
+                    s.parse::<isize>()
+                        .ok()
+                        .filter(|&n| n >= 1)
+                        .ok_or_else(|| format!("'{s}' is not a valid entry number"))
+                }),
+        )
+        .arg(
+            Arg::new("selective")
+                .long("selective")
+                .short('s')
+                .help("Only undo the changes made by the given entry")
+                .requires("to")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("list")
+                .long("list")
+                .short('l')
+                .help("List the operations that may be undone")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .group(ArgGroup::new("mode").args(["number", "to", "list"]))
         .arg(
             Arg::new("hard")
                 .long("hard")
                 .help("Discard changes in the index and worktree")
+                .conflicts_with("list")
                 .action(clap::ArgAction::SetTrue),
         )
 }
@@ -57,7 +110,20 @@ fn make() -> clap::Command {
 fn run(matches: &clap::ArgMatches) -> Result<()> {
     let repo = gix::Repository::open()?;
     let stack = Stack::current(&repo, InitializationPolicy::RequireInitialized)?;
-    let undo_steps = matches.get_one::<isize>("number").copied().unwrap_or(1);
+
+    if matches.get_flag("list") {
+        return list_entries(&stack, matches);
+    }
+
+    let undo_steps = matches
+        .get_one::<isize>("to")
+        .or_else(|| matches.get_one::<isize>("number"))
+        .copied()
+        .unwrap_or(1);
+
+    if matches.get_flag("selective") {
+        return undo_selectively(stack, undo_steps, matches);
+    }
 
     stack
         .setup_transaction()
@@ -78,6 +144,17 @@ pub(super) fn find_undo_state<'repo>(
     stack: &Stack<'repo>,
     undo_steps: isize,
 ) -> Result<StackState<'repo>> {
+    let state_commit = find_undo_state_commit(stack, undo_steps)?;
+    StackState::from_commit(stack.repo, &state_commit)
+}
+
+/// Find the commit of the stack state `undo_steps` back in the stack log.
+///
+/// Like [`find_undo_state()`], but yields the state's commit.
+pub(super) fn find_undo_state_commit<'repo>(
+    stack: &Stack<'repo>,
+    undo_steps: isize,
+) -> Result<Rc<gix::Commit<'repo>>> {
     let mut undo_steps = undo_steps;
     let mut state_commit = Rc::new(
         stack
@@ -86,10 +163,10 @@ pub(super) fn find_undo_state<'repo>(
             .peel_to_commit()?,
     );
     loop {
-        let state = StackState::from_commit(stack.repo, &state_commit)?;
         if undo_steps == 0 {
-            break Ok(state);
+            break Ok(state_commit);
         }
+        let state = StackState::from_commit(stack.repo, &state_commit)?;
         let msg = state_commit.message_raw()?;
         let urstate = parse_undo_redo_message(msg);
         if undo_steps > 0 {
@@ -112,6 +189,206 @@ pub(super) fn find_undo_state<'repo>(
     }
 }
 
+/// An operation from the stack log that may be undone.
+struct UndoEntry<'repo> {
+    /// First line of the operation's stack log message.
+    message: String,
+
+    /// Stack state prior to the operation.
+    before: StackState<'repo>,
+
+    /// Stack state resulting from the operation.
+    after: StackState<'repo>,
+}
+
+/// Find the operations that may be undone, most recent first.
+///
+/// The operations are numbered the same way as by [`find_undo_state()`], i.e. undoing
+/// `n` steps resets the stack to the state before the `n`th entry. Operations that were
+/// undone, as well as the undo operations themselves, are skipped.
+fn find_undo_entries<'repo>(
+    stack: &Stack<'repo>,
+    max_entries: Option<usize>,
+) -> Result<Vec<UndoEntry<'repo>>> {
+    let mut entries = Vec::new();
+    let mut skip = 0;
+    let mut state_commit = Rc::new(
+        stack
+            .repo
+            .find_reference(stack.get_stack_refname())?
+            .peel_to_commit()?,
+    );
+    while max_entries.map_or(true, |max| entries.len() < max) {
+        let state = StackState::from_commit(stack.repo, &state_commit)?;
+        let Some(prev) = state.prev.clone() else {
+            break;
+        };
+        let msg = state_commit.message_raw()?;
+        if let Some(URState::Undo(n)) = parse_undo_redo_message(msg) {
+            skip += n;
+        } else if skip > 0 {
+            skip -= 1;
+        } else {
+            entries.push(UndoEntry {
+                message: msg
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_str_lossy()
+                    .to_string(),
+                before: StackState::from_commit(stack.repo, &prev)?,
+                after: state,
+            });
+        }
+        state_commit = prev;
+    }
+    Ok(entries)
+}
+
+/// Get the names of patches created, deleted, or modified between two stack states.
+fn changed_patches<'a>(
+    before: &'a StackState<'_>,
+    after: &'a StackState<'_>,
+) -> Vec<&'a PatchName> {
+    let mut changed: Vec<&PatchName> = after
+        .all_patches()
+        .filter(|pn| {
+            !before.has_patch(pn)
+                || before.get_patch_commit_id(pn) != after.get_patch_commit_id(pn)
+                || before.get_patch_meta(pn) != after.get_patch_meta(pn)
+        })
+        .collect();
+    changed.extend(before.all_patches().filter(|pn| !after.has_patch(pn)));
+    changed
+}
+
+/// Summarize the patch changes between two stack states.
+fn summarize_changes(before: &StackState<'_>, after: &StackState<'_>) -> String {
+    let mut created = Vec::new();
+    let mut modified = Vec::new();
+    let mut pushed = Vec::new();
+    let mut popped = Vec::new();
+    let mut hidden = Vec::new();
+    let mut unhidden = Vec::new();
+
+    for pn in after.all_patches() {
+        if !before.has_patch(pn) {
+            created.push(pn);
+            continue;
+        }
+        if before.get_patch_commit_id(pn) != after.get_patch_commit_id(pn)
+            || before.get_patch_meta(pn) != after.get_patch_meta(pn)
+        {
+            modified.push(pn);
+        }
+        if after.is_applied(pn) && !before.is_applied(pn) {
+            pushed.push(pn);
+        } else if after.is_unapplied(pn) && before.is_applied(pn) {
+            popped.push(pn);
+        } else if after.is_hidden(pn) && !before.is_hidden(pn) {
+            hidden.push(pn);
+        } else if after.is_unapplied(pn) && before.is_hidden(pn) {
+            unhidden.push(pn);
+        }
+    }
+    let deleted: Vec<&PatchName> = before
+        .all_patches()
+        .filter(|pn| !after.has_patch(pn))
+        .collect();
+
+    let mut summary: Vec<String> = [
+        ("created", created),
+        ("modified", modified),
+        ("deleted", deleted),
+        ("pushed", pushed),
+        ("popped", popped),
+        ("hidden", hidden),
+        ("unhidden", unhidden),
+    ]
+    .into_iter()
+    .filter(|(_, patchnames)| !patchnames.is_empty())
+    .map(|(action, patchnames)| {
+        let patchnames: Vec<&str> = patchnames.iter().map(|pn| pn.as_ref()).collect();
+        format!("{action}: {}", patchnames.join(", "))
+    })
+    .collect();
+
+    if summary.is_empty() {
+        let order =
+            |state: &StackState<'_>| -> Vec<PatchName> { state.all_patches().cloned().collect() };
+        if order(before) != order(after) {
+            summary.push("reordered".to_string());
+        }
+    }
+
+    summary.join("; ")
+}
+
+fn list_entries(stack: &Stack<'_>, matches: &clap::ArgMatches) -> Result<()> {
+    let entries = find_undo_entries(stack, None)?;
+    let width = entries.len().to_string().len();
+    let mut stdout = get_color_stdout(matches);
+    let mut color_spec = termcolor::ColorSpec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        stdout.set_color(color_spec.set_fg(Some(termcolor::Color::Yellow)))?;
+        write!(stdout, "{:>width$}", i + 1)?;
+        stdout.set_color(color_spec.set_fg(None))?;
+        writeln!(stdout, " {}", entry.message)?;
+        let summary = summarize_changes(&entry.before, &entry.after);
+        if !summary.is_empty() {
+            writeln!(stdout, "{:width$}   {summary}", "")?;
+        }
+    }
+    Ok(())
+}
+
+fn undo_selectively(stack: Stack<'_>, entry_num: isize, matches: &clap::ArgMatches) -> Result<()> {
+    let num_entries = usize::try_from(entry_num).expect("entry number is positive");
+    let mut message = String::new();
+    stack
+        .setup_transaction()
+        .use_index_and_worktree(true)
+        .discard_changes(matches.get_flag("hard"))
+        .with_output_stream(get_color_stdout(matches))
+        .transact(|trans| {
+            let mut entries = find_undo_entries(trans.stack(), Some(num_entries))?;
+            if entries.len() < num_entries {
+                return Err(anyhow!("not enough undo information available"));
+            }
+            let entry = entries.pop().expect("entry exists");
+            message = entry.message;
+
+            let patchnames: Vec<PatchName> = changed_patches(&entry.before, &entry.after)
+                .into_iter()
+                .cloned()
+                .collect();
+            if patchnames.is_empty() {
+                return Err(anyhow!(
+                    "`{message}` did not create, modify, or delete any patches"
+                ));
+            }
+            for pn in &patchnames {
+                let unchanged = if entry.after.has_patch(pn) {
+                    trans.has_patch(pn)
+                        && trans.get_patch_commit_id(pn) == entry.after.get_patch_commit_id(pn)
+                } else {
+                    !trans.has_patch(pn)
+                };
+                if !unchanged {
+                    return Err(anyhow!(
+                        "cannot selectively undo `{message}`: \
+                         patch `{pn}` was changed by a later operation"
+                    ));
+                }
+            }
+
+            trans.reset_to_state_partially(&entry.before, &patchnames)
+        })
+        .execute(&format!("undo --selective: {message}"))?;
+
+    Ok(())
+}
+
 #[derive(Debug)]
 enum URState {
     Undo(isize),
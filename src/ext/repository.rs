@@ -3,7 +3,7 @@
 use std::borrow::Cow;
 
 use anyhow::{anyhow, Result};
-use bstr::BStr;
+use bstr::{BStr, BString};
 
 use crate::{
     stupid::Stupid,
@@ -76,6 +76,23 @@ pub(crate) trait RepositoryExtended {
         options: &CommitOptions<'_>,
     ) -> Result<gix::ObjectId>;
 
+    /// Perform an in-process three-way merge of trees.
+    ///
+    /// The changes from `base_tree_id` to `theirs_tree_id` are merged into
+    /// `ours_tree_id`. The merged tree is written to the object database and its id
+    /// returned.
+    ///
+    /// Files modified on both sides are merged in-process as long as their changes are
+    /// separated by unchanged lines. `None` is returned for any other merge, which
+    /// should then be performed with git, as well as for merges resulting in
+    /// conflicts.
+    fn merge_trees_clean(
+        &self,
+        base_tree_id: gix::ObjectId,
+        ours_tree_id: gix::ObjectId,
+        theirs_tree_id: gix::ObjectId,
+    ) -> Result<Option<gix::ObjectId>>;
+
     /// [`gix::Repository::rev_parse_single()`] with StGit-specific error mapping.
     fn rev_parse_single_ex(&self, spec: &str) -> Result<gix::Id<'_>>;
 }
@@ -266,6 +283,89 @@ impl RepositoryExtended for gix::Repository {
         }
     }
 
+    fn merge_trees_clean(
+        &self,
+        base_tree_id: gix::ObjectId,
+        ours_tree_id: gix::ObjectId,
+        theirs_tree_id: gix::ObjectId,
+    ) -> Result<Option<gix::ObjectId>> {
+        use std::collections::BTreeMap;
+
+        use gix::{merge::tree::TreatAsUnresolved, object::tree::diff::ChangeDetached};
+
+        let base_tree = self.find_tree(base_tree_id)?;
+        let diff_options = gix::diff::Options::default().with_rewrites(None);
+
+        let changes = |tree_id| -> Result<BTreeMap<BString, ChangeDetached>> {
+            Ok(self
+                .diff_tree_to_tree(&base_tree, &self.find_tree(tree_id)?, diff_options)?
+                .into_iter()
+                .filter(|change| !change.entry_mode().is_tree())
+                .map(|change| (change.location().to_owned(), change))
+                .collect())
+        };
+
+        // The content merge performed by gitoxide resolves changes that are adjacent
+        // to each other, whereas git considers them to conflict. Such merges, as well
+        // as any merge of files added, deleted, or changing mode on both sides, are
+        // left to git.
+        let theirs_changes = changes(theirs_tree_id)?;
+        for (path, ours_change) in changes(ours_tree_id)? {
+            let Some(theirs_change) = theirs_changes.get(&path) else {
+                continue;
+            };
+            let (
+                ChangeDetached::Modification {
+                    previous_entry_mode,
+                    previous_id: base_id,
+                    entry_mode: ours_mode,
+                    id: ours_id,
+                    ..
+                },
+                ChangeDetached::Modification {
+                    entry_mode: theirs_mode,
+                    id: theirs_id,
+                    ..
+                },
+            ) = (ours_change, theirs_change)
+            else {
+                return Ok(None);
+            };
+            if ours_id == *theirs_id {
+                continue;
+            }
+            if !previous_entry_mode.is_blob()
+                || ours_mode != previous_entry_mode
+                || *theirs_mode != previous_entry_mode
+                || changes_touch(
+                    &self.find_blob(base_id)?.data,
+                    &self.find_blob(ours_id)?.data,
+                    &self.find_blob(*theirs_id)?.data,
+                )
+            {
+                return Ok(None);
+            }
+        }
+
+        let how = TreatAsUnresolved::git();
+        let options = self
+            .tree_merge_options()?
+            .with_rewrites(None)
+            .with_fail_on_conflict(Some(how));
+        let mut outcome = self.merge_trees(
+            base_tree_id,
+            ours_tree_id,
+            theirs_tree_id,
+            Default::default(),
+            options,
+        )?;
+        if outcome.failed_on_first_unresolved_conflict || outcome.has_unresolved_conflicts(how) {
+            Ok(None)
+        } else {
+            Ok(Some(outcome.tree.write()?.detach()))
+        }
+    }
+
     fn rev_parse_single_ex(&self, spec: &str) -> Result<gix::Id<'_>> {
         use gix::{
             refs::file::find::existing::Error as FindError,
@@ -331,3 +431,99 @@ impl RepositoryExtended for gix::Repository {
             })
     }
 }
+
+/// Determine whether the changes from `base` to `ours` and from `base` to `theirs`
+/// overlap or are adjacent to each other.
+fn changes_touch(base: &[u8], ours: &[u8], theirs: &[u8]) -> bool {
+    use std::ops::Range;
+
+    use gix::diff::blob::{diff, intern::InternedInput, sources::byte_lines, Algorithm};
+
+    let changed_ranges = |new: &[u8]| {
+        let input = InternedInput::new(byte_lines(base), byte_lines(new));
+        let mut ranges: Vec<Range<u32>> = Vec::new();
+        diff(Algorithm::Myers, &input, |before: Range<u32>, _| {
+            ranges.push(before);
+        });
+        ranges
+    };
+    let theirs_ranges = changed_ranges(theirs);
+    changed_ranges(ours).iter().any(|ours_range| {
+        theirs_ranges.iter().any(|theirs_range| {
+            ours_range.start <= theirs_range.end && theirs_range.start <= ours_range.end
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::RepositoryExtended;
+
+    fn write_tree(repo: &gix::Repository, files: &[(&str, &str)]) -> gix::ObjectId {
+        let mut entries: Vec<gix::objs::tree::Entry> = files
+            .iter()
+            .map(|(name, content)| gix::objs::tree::Entry {
+                mode: gix::objs::tree::EntryKind::Blob.into(),
+                filename: (*name).into(),
+                oid: repo.write_blob(content.as_bytes()).unwrap().detach(),
+            })
+            .collect();
+        entries.sort();
+        repo.write_object(gix::objs::Tree { entries })
+            .unwrap()
+            .detach()
+    }
+
+    fn read_file(repo: &gix::Repository, tree_id: gix::ObjectId, name: &str) -> String {
+        let tree = repo.find_tree(tree_id).unwrap();
+        let entry = tree.find_entry(name).unwrap();
+        let blob = repo.find_blob(entry.object_id()).unwrap();
+        String::from_utf8(blob.data.clone()).unwrap()
+    }
+
+    #[test]
+    fn merge_trees_clean() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = gix::init_bare(dir.path()).unwrap();
+        let lines = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let base = write_tree(&repo, &[("a", lines), ("b", "b\n")]);
+
+        // Changes to different files.
+        let ours = write_tree(
+            &repo,
+            &[("a", "1\n2\n3\n4\n5\n6\n7\n8\n9\nours\n"), ("b", "b\n")],
+        );
+        let theirs = write_tree(&repo, &[("a", lines), ("b", "theirs\n")]);
+        let merged = repo.merge_trees_clean(base, ours, theirs).unwrap().unwrap();
+        assert_eq!(
+            read_file(&repo, merged, "a"),
+            "1\n2\n3\n4\n5\n6\n7\n8\n9\nours\n"
+        );
+        assert_eq!(read_file(&repo, merged, "b"), "theirs\n");
+
+        // Non-overlapping changes to the same file.
+        let theirs = write_tree(
+            &repo,
+            &[("a", "theirs\n1\n2\n3\n4\n5\n6\n7\n8\n9\n"), ("b", "b\n")],
+        );
+        let merged = repo.merge_trees_clean(base, ours, theirs).unwrap().unwrap();
+        assert_eq!(
+            read_file(&repo, merged, "a"),
+            "theirs\n1\n2\n3\n4\n5\n6\n7\n8\n9\nours\n"
+        );
+
+        // Overlapping changes to the same file.
+        let theirs = write_tree(
+            &repo,
+            &[("a", "1\n2\n3\n4\n5\n6\n7\n8\n9\ntheirs\n"), ("b", "b\n")],
+        );
+        assert_eq!(repo.merge_trees_clean(base, ours, theirs).unwrap(), None);
+
+        // Adjacent changes to the same file conflict with git.
+        let theirs = write_tree(
+            &repo,
+            &[("a", "1\n2\n3\n4\n5\n6\n7\n8\nnine\n"), ("b", "b\n")],
+        );
+        assert_eq!(repo.merge_trees_clean(base, ours, theirs).unwrap(), None);
+    }
+}
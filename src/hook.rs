@@ -11,7 +11,47 @@ use std::{
 use anyhow::{anyhow, Context, Result};
 use bstr::BString;
 
-use crate::wrap::Message;
+use crate::{patch::PatchName, wrap::Message};
+
+/// StGit-specific hooks run around the execution of stack transactions.
+#[derive(Clone, Debug)]
+pub(crate) enum StackHook {
+    /// `stg-pre-push`, run before the patches pushed by a stack transaction are
+    /// recorded.
+    PrePush,
+
+    /// `stg-post-push`, run after the patches pushed by a stack transaction are
+    /// recorded.
+    PostPush,
+
+    /// `stg-post-refresh`, run after a patch is refreshed by `stg refresh`.
+    PostRefresh,
+
+    /// `stg-pre-rebase`, run before `stg rebase` pops the patches to be rebased onto
+    /// the given commit.
+    PreRebase(gix::ObjectId),
+
+    /// `stg-post-new`, run after `stg new` creates a patch.
+    PostNew,
+}
+
+impl StackHook {
+    /// Name of the hook script.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            StackHook::PrePush => "stg-pre-push",
+            StackHook::PostPush => "stg-post-push",
+            StackHook::PostRefresh => "stg-post-refresh",
+            StackHook::PreRebase(_) => "stg-pre-rebase",
+            StackHook::PostNew => "stg-post-new",
+        }
+    }
+
+    /// Whether the hook runs before the transaction is recorded and may thus veto it.
+    pub(crate) fn is_pre(&self) -> bool {
+        matches!(self, StackHook::PrePush | StackHook::PreRebase(_))
+    }
+}
 
 /// Find path to hook script given a hook name.
 ///
@@ -152,6 +192,73 @@ pub(crate) fn run_commit_msg_hook<'repo>(
     }
 }
 
+/// Run a StGit hook script.
+///
+/// Each of the patches affected by the stack transaction is written to the hook's stdin
+/// as a line of the form `<old-id> <new-id> <patchname>`. The null id is used as the
+/// old id of newly created patches.
+///
+/// Returns `Ok(true)` if the hook ran and completed successfully, `Err()` if the hook
+/// ran but failed, and `Ok(false)` if the hook did not run due to the script not
+/// existing, not being a file, or not being executable.
+pub(crate) fn run_stack_hook(
+    repo: &gix::Repository,
+    hook: &StackHook,
+    patches: &[(PatchName, Option<gix::ObjectId>, gix::ObjectId)],
+) -> Result<bool> {
+    let hook_name = hook.name();
+    let hook_path = if let Some(hook_path) = get_hook_path(repo, hook_name)? {
+        hook_path
+    } else {
+        return Ok(false);
+    };
+
+    let work_dir = repo.work_dir().expect("not a bare repo");
+
+    let mut hook_command = std::process::Command::from(
+        gix::command::prepare(hook_path)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::inherit()),
+    );
+    hook_command.current_dir(work_dir);
+    if let StackHook::PreRebase(target_id) = hook {
+        hook_command.arg(target_id.to_string());
+    }
+
+    let mut child = hook_command
+        .spawn()
+        .with_context(|| format!("`{hook_name}` hook"))?;
+
+    let null_id = gix::ObjectId::null(repo.object_hash());
+    let mut input = Vec::new();
+    for (patchname, old_id, new_id) in patches {
+        let old_id = old_id.unwrap_or(null_id);
+        writeln!(input, "{old_id} {new_id} {patchname}")?;
+    }
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // The hook is free to not read its input.
+    match stdin.write_all(&input) {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+            return Err(anyhow::Error::from(e).context(format!("`{hook_name}` hook")));
+        }
+        _ => {}
+    }
+    drop(stdin);
+
+    let status = child
+        .wait()
+        .with_context(|| format!("`{hook_name}` hook"))?;
+
+    if status.success() {
+        Ok(true)
+    } else {
+        Err(anyhow!(
+            "`{hook_name}` hook returned {}",
+            status.code().unwrap_or(-1)
+        ))
+    }
+}
+
 /// Temporary commit message file for commit-msg hook.
 ///
 /// The temporary file is created relative to the work dir using the StGit process id to
@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Machine-readable JSON output for inspection commands.
+//!
+//! Commands supporting the `--format=json` option emit a single JSON object to
+//! stdout. The types in this module define the parts of the output schema that are
+//! shared between commands. Fields may be added in the future, but existing fields
+//! will not be renamed or removed.
+
+use std::io::Write;
+
+use anyhow::Result;
+use bstr::ByteSlice;
+
+use crate::{
+    argset::get_one_str,
+    ext::CommitExtended,
+    patch::PatchName,
+    stack::{PatchMeta, StackStateAccess},
+};
+
+/// Determine whether JSON output was requested with `--format=json`.
+pub(crate) fn is_requested(matches: &clap::ArgMatches) -> bool {
+    get_one_str(matches, "format") == Some("json")
+}
+
+/// Write value as pretty-printed JSON to stdout.
+pub(crate) fn print<T: serde::Serialize>(value: &T) -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+/// Location of a patch in the stack.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PatchStatus {
+    Applied,
+    Unapplied,
+    Hidden,
+}
+
+/// Name and email of a commit's author or committer.
+#[derive(serde::Serialize, Debug)]
+pub(crate) struct Person {
+    pub(crate) name: String,
+    pub(crate) email: String,
+}
+
+impl From<&gix::actor::Signature> for Person {
+    fn from(signature: &gix::actor::Signature) -> Self {
+        Self {
+            name: signature.name.to_str_lossy().to_string(),
+            email: signature.email.to_str_lossy().to_string(),
+        }
+    }
+}
+
+/// Description of a single patch.
+#[derive(serde::Serialize, Debug)]
+pub(crate) struct PatchInfo<'a> {
+    /// Patch name.
+    pub(crate) name: &'a PatchName,
+
+    /// Full commit id of the patch.
+    pub(crate) commit_id: String,
+
+    /// Whether the patch is applied, unapplied, or hidden.
+    pub(crate) status: PatchStatus,
+
+    /// Whether the patch is the topmost applied patch.
+    pub(crate) top: bool,
+
+    /// Whether the patch does not change the tree of its parent.
+    pub(crate) empty: bool,
+
+    /// Patch author.
+    pub(crate) author: Person,
+
+    /// First line of the patch's commit message.
+    pub(crate) description: String,
+
+    /// Patch metadata, see `stg meta`.
+    pub(crate) meta: &'a PatchMeta,
+}
+
+impl<'a> PatchInfo<'a> {
+    /// Gather information about a patch from the stack.
+    pub(crate) fn new<'repo>(
+        stack: &'a impl StackStateAccess<'repo>,
+        patchname: &'a PatchName,
+    ) -> Result<Self>
+    where
+        'repo: 'a,
+    {
+        let commit = stack.get_patch_commit(patchname);
+        let status = if stack.is_applied(patchname) {
+            PatchStatus::Applied
+        } else if stack.is_unapplied(patchname) {
+            PatchStatus::Unapplied
+        } else {
+            PatchStatus::Hidden
+        };
+        let author = commit.author_strict()?;
+        let description = commit
+            .message_ex()
+            .decode()?
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        Ok(Self {
+            name: patchname,
+            commit_id: commit.id.to_string(),
+            status,
+            top: stack.applied().last() == Some(patchname),
+            empty: commit.is_no_change()?,
+            author: Person::from(&author),
+            description,
+            meta: stack.get_patch_meta(patchname),
+        })
+    }
+}
@@ -0,0 +1,335 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Support for patch series sent as email.
+//!
+//! Patch series are typically sent as a thread of emails, each with a subject prefix
+//! such as "[PATCH v2 3/5]" identifying the series version and the patch's position
+//! in the series. Replies to the patches may carry review trailers, e.g.
+//! "Reviewed-by", which belong to the patch being replied to.
+
+use bstr::ByteSlice;
+
+/// Trailers collected from replies to patches.
+pub(crate) const REVIEW_TRAILERS: &[&str] = &["Reviewed-by", "Acked-by", "Tested-by"];
+
+/// Threading-related headers of an email message.
+#[derive(Default, Debug)]
+pub(crate) struct ThreadHeaders {
+    pub(crate) message_id: Option<String>,
+    pub(crate) in_reply_to: Option<String>,
+    pub(crate) references: Vec<String>,
+}
+
+impl ThreadHeaders {
+    /// Parse the threading headers from a raw email message.
+    ///
+    /// Only the header section of the message, up to the first blank line, is
+    /// considered. Folded header lines are unfolded.
+    pub(crate) fn parse(mail: &[u8]) -> Self {
+        let mut headers = Self::default();
+        let mut unfolded: Vec<String> = Vec::new();
+        for line in mail.lines() {
+            if line.is_empty() {
+                break;
+            }
+            let line = line.to_str_lossy();
+            if line.starts_with([' ', '\t']) {
+                if let Some(last) = unfolded.last_mut() {
+                    last.push(' ');
+                    last.push_str(line.trim());
+                }
+            } else {
+                unfolded.push(line.into_owned());
+            }
+        }
+
+        for line in &unfolded {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("message-id") {
+                headers.message_id = message_ids(value).next();
+            } else if name.eq_ignore_ascii_case("in-reply-to") {
+                headers.in_reply_to = message_ids(value).next();
+            } else if name.eq_ignore_ascii_case("references") {
+                headers.references = message_ids(value).collect();
+            }
+        }
+        headers
+    }
+
+    /// Get the ids of the messages this message replies to, nearest first.
+    pub(crate) fn ancestors(&self) -> impl Iterator<Item = &str> {
+        self.in_reply_to
+            .iter()
+            .chain(self.references.iter().rev())
+            .map(String::as_str)
+    }
+}
+
+/// Get the message id recorded in a "Message-ID" trailer of a patch description, e.g.
+/// by `stg import --message-id`.
+pub(crate) fn recorded_message_id(message: &str) -> Option<String> {
+    message.lines().rev().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim().eq_ignore_ascii_case("message-id") {
+            message_ids(value).next()
+        } else {
+            None
+        }
+    })
+}
+
+/// Iterate over the "<...>" message ids in a header value.
+fn message_ids(value: &str) -> impl Iterator<Item = String> + '_ {
+    value.split('<').skip(1).filter_map(|s| {
+        s.split_once('>')
+            .map(|(id, _)| id.trim())
+            .filter(|id| !id.is_empty())
+            .map(String::from)
+    })
+}
+
+/// Position of a patch within a patch series, from a subject such as
+/// "[PATCH v2 3/5] Subject".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SeriesTag {
+    /// Version of the series, 1 if the subject has no "v<n>" tag.
+    pub(crate) version: usize,
+
+    /// Position of the patch in the series, where 0 is the cover letter.
+    pub(crate) index: usize,
+
+    /// Total number of patches in the series, excluding the cover letter.
+    pub(crate) total: usize,
+}
+
+impl SeriesTag {
+    /// Parse the series tag from an email subject.
+    ///
+    /// The tag is taken from the first bracketed prefix containing "PATCH". A patch
+    /// without an "<m>/<n>" position is taken to be 1/1. Subjects of replies, i.e.
+    /// starting with "Re:", have no series tag.
+    pub(crate) fn parse(subject: &str) -> Option<Self> {
+        if is_reply(subject) {
+            return None;
+        }
+
+        let mut rest = subject.trim_start();
+        while let Some(prefix) = rest.strip_prefix('[') {
+            let (tag, after) = prefix.split_once(']')?;
+            rest = after.trim_start();
+            if !tag.to_ascii_uppercase().contains("PATCH") {
+                continue;
+            }
+
+            let mut series_tag = Self {
+                version: 1,
+                index: 1,
+                total: 1,
+            };
+            for word in tag.split_ascii_whitespace() {
+                if let Some(version) = word
+                    .strip_prefix(['v', 'V'])
+                    .and_then(|v| v.parse::<usize>().ok())
+                {
+                    series_tag.version = version;
+                } else if let Some((index, total)) = word.split_once('/') {
+                    if let (Ok(index), Ok(total)) = (index.parse(), total.parse()) {
+                        series_tag.index = index;
+                        series_tag.total = total;
+                    }
+                }
+            }
+            return Some(series_tag);
+        }
+        None
+    }
+
+    /// Determine whether this is the tag of a cover letter.
+    pub(crate) fn is_cover_letter(&self) -> bool {
+        self.index == 0
+    }
+}
+
+/// Determine whether an email subject is the subject of a reply.
+pub(crate) fn is_reply(subject: &str) -> bool {
+    subject
+        .trim_start()
+        .get(..3)
+        .is_some_and(|re| re.eq_ignore_ascii_case("re:"))
+}
+
+/// Remove leading "Re:" and bracketed prefixes from an email subject.
+///
+/// This matches the subject cleanup performed by `git mailinfo` unless its `-k` option
+/// is used.
+pub(crate) fn cleanup_subject(subject: &str) -> &str {
+    let mut rest = subject;
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ':']);
+        if is_reply(rest) {
+            rest = &rest[3..];
+        } else if let Some((_, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+            rest = after;
+        } else {
+            break;
+        }
+    }
+    rest.trim()
+}
+
+/// Get the review trailers given in the body of a reply.
+///
+/// Quoted lines, i.e. starting with '>', are ignored such that trailers of the message
+/// being replied to are not collected.
+pub(crate) fn review_trailers(body: &[u8]) -> Vec<(String, String)> {
+    let mut trailers = Vec::new();
+    for line in body.lines() {
+        let Ok(line) = line.to_str() else {
+            continue;
+        };
+        let line = line.trim();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        if let Some(key) = REVIEW_TRAILERS
+            .iter()
+            .find(|trailer| trailer.eq_ignore_ascii_case(key.trim()))
+        {
+            let value = value.trim();
+            if !value.is_empty() {
+                let trailer = (key.to_string(), value.to_string());
+                if !trailers.contains(&trailer) {
+                    trailers.push(trailer);
+                }
+            }
+        }
+    }
+    trailers
+}
+
+/// Remove the trailing block of trailers from a message.
+///
+/// The trailers must form a separate final paragraph, i.e. be preceded by a blank
+/// line. A message consisting of a single paragraph is never stripped.
+pub(crate) fn strip_trailers(message: &str) -> &str {
+    let trimmed = message.trim_end();
+    let Some(block_start) = trimmed.rfind("\n\n").map(|pos| pos + 2) else {
+        return message;
+    };
+    let block = &trimmed[block_start..];
+    let is_trailer = |line: &str| {
+        line.split_once(':').is_some_and(|(key, value)| {
+            !key.is_empty()
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && !value.trim().is_empty()
+        })
+    };
+    if !block.is_empty() && block.lines().all(is_trailer) {
+        trimmed[..block_start].trim_end()
+    } else {
+        message
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        cleanup_subject, recorded_message_id, review_trailers, strip_trailers, SeriesTag,
+        ThreadHeaders,
+    };
+
+    #[test]
+    fn series_tags() {
+        assert_eq!(
+            SeriesTag::parse("[PATCH v2 3/5] Fix things"),
+            Some(SeriesTag {
+                version: 2,
+                index: 3,
+                total: 5
+            })
+        );
+        assert_eq!(
+            SeriesTag::parse("[RFC PATCH 0/2] Cover"),
+            Some(SeriesTag {
+                version: 1,
+                index: 0,
+                total: 2
+            })
+        );
+        assert_eq!(
+            SeriesTag::parse("[net-next] [PATCH V3] Single"),
+            Some(SeriesTag {
+                version: 3,
+                index: 1,
+                total: 1
+            })
+        );
+        assert_eq!(SeriesTag::parse("Re: [PATCH 1/2] Reply"), None);
+        assert_eq!(SeriesTag::parse("Plain subject"), None);
+    }
+
+    #[test]
+    fn subject_cleanup() {
+        assert_eq!(cleanup_subject("[PATCH v2 3/5] Fix things"), "Fix things");
+        assert_eq!(cleanup_subject("Re: [RFC] [PATCH] Fix: it"), "Fix: it");
+        assert_eq!(cleanup_subject("Fix [things]"), "Fix [things]");
+    }
+
+    #[test]
+    fn thread_headers() {
+        let headers = ThreadHeaders::parse(
+            b"From: A <a@example.com>\n\
+              Message-ID: <3@example.com>\n\
+              In-Reply-To: <2@example.com>\n\
+              References: <1@example.com>\n\
+              \t<2@example.com>\n\
+              \n\
+              Message-ID: <body@example.com>\n",
+        );
+        assert_eq!(headers.message_id.as_deref(), Some("3@example.com"));
+        assert_eq!(
+            headers.ancestors().collect::<Vec<_>>(),
+            ["2@example.com", "2@example.com", "1@example.com"]
+        );
+        assert_eq!(
+            recorded_message_id("Subject\n\nBody.\n\nMessage-Id: <4@example.com>\n").as_deref(),
+            Some("4@example.com")
+        );
+        assert_eq!(recorded_message_id("Subject\n\nBody.\n"), None);
+    }
+
+    #[test]
+    fn trailers() {
+        let trailers = review_trailers(
+            b"> Signed-off-by: Author <a@example.com>\n\
+              > Reviewed-by: Quoted <q@example.com>\n\
+              \n\
+              Looks good.\n\
+              \n\
+              Reviewed-by: Reviewer <r@example.com>\n\
+              acked-by: Acker <k@example.com>\n",
+        );
+        assert_eq!(
+            trailers,
+            [
+                (
+                    String::from("Reviewed-by"),
+                    String::from("Reviewer <r@example.com>")
+                ),
+                (
+                    String::from("Acked-by"),
+                    String::from("Acker <k@example.com>")
+                ),
+            ]
+        );
+        assert_eq!(
+            strip_trailers("Body.\n\nSigned-off-by: A <a@example.com>\n"),
+            "Body."
+        );
+        assert_eq!(strip_trailers("Body: text\n"), "Body: text\n");
+        assert_eq!(strip_trailers("Body text.\n"), "Body text.\n");
+    }
+}
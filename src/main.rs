@@ -14,6 +14,8 @@ mod cmd;
 mod color;
 mod ext;
 mod hook;
+mod json;
+mod mail;
 mod patch;
 mod signal;
 mod stack;
@@ -490,7 +492,7 @@ pub(crate) fn get_aliases() -> Result<(alias::Aliases, Option<gix::Repository>)>
 /// Print user-facing message to stderr.
 ///
 /// Any parts of `msg` enclosed in backticks (``) are highlighted in yellow.
-fn print_message(
+pub(crate) fn print_message(
     label: &str,
     label_color: termcolor::Color,
     stderr: &mut termcolor::StandardStream,
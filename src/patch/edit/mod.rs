@@ -23,7 +23,9 @@ use anyhow::{anyhow, Result};
 use bstr::{BString, ByteSlice};
 use clap::ArgMatches;
 
-pub(crate) use self::{args::add_args, interactive::call_editor, parse::parse_name_email};
+pub(crate) use self::{
+    args::add_args, interactive::call_editor, parse::parse_name_email, trailers::append_trailers,
+};
 use self::{
     description::{DiffBuffer, EditablePatchDescription, EditedPatchDescription},
     interactive::edit_interactive,
@@ -624,7 +626,8 @@ fn author_from_args(
     }
 }
 
-fn prettify(message: &str) -> String {
+/// Trim trailing whitespace and collapse runs of blank lines in a patch message.
+pub(crate) fn prettify(message: &str) -> String {
     let mut pretty = String::with_capacity(message.len() + 1);
     let mut consecutive_empty = false;
     for line in message.split_inclusive('\n') {
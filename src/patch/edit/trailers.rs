@@ -58,9 +58,9 @@ pub(crate) fn add_trailers<'a, 'b>(
 
         trailers.sort_by_key(|(index, _, _)| *index);
 
-        let message_str = message.decode()?;
-        let message_bytes = repo.stupid().interpret_trailers(
-            message_str.as_bytes(),
+        append_trailers(
+            repo,
+            &message,
             trailers.iter().map(|(_index, trailer, value)| {
                 if value.is_empty() {
                     (*trailer, default_value.as_str())
@@ -68,13 +68,25 @@ pub(crate) fn add_trailers<'a, 'b>(
                     (*trailer, *value)
                 }
             }),
-        )?;
-        let message = String::from_utf8(message_bytes)
-            .map_err(|_| anyhow!("could not decode message after adding trailers"))?;
-        Ok(Message::from(message))
+        )
     }
 }
 
+/// Append the given `(trailer, value)` pairs to the trailers of a commit message.
+pub(crate) fn append_trailers<'a, 'b>(
+    repo: &gix::Repository,
+    message: &Message,
+    trailers: impl IntoIterator<Item = (&'b str, &'b str)>,
+) -> Result<Message<'a>> {
+    let message_str = message.decode()?;
+    let message_bytes = repo
+        .stupid()
+        .interpret_trailers(message_str.as_bytes(), trailers)?;
+    let message = String::from_utf8(message_bytes)
+        .map_err(|_| anyhow!("could not decode message after adding trailers"))?;
+    Ok(Message::from(message))
+}
+
 #[cfg(test)]
 mod test {
     use clap::Arg;
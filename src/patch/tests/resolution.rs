@@ -1,6 +1,10 @@
 // SPDX-License-Identifier: GPL-2.0-only
 
-use std::{collections::BTreeMap, rc::Rc, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+    str::FromStr,
+};
 
 use super::super::*;
 use crate::stack::{PatchState, StackStateAccess};
@@ -11,6 +15,7 @@ struct DummyStack {
     unapplied: Vec<PatchName>,
     hidden: Vec<PatchName>,
     commit_ids: BTreeMap<PatchName, gix::ObjectId>,
+    dependencies: BTreeMap<PatchName, BTreeSet<PatchName>>,
 }
 
 impl<'repo> StackStateAccess<'repo> for DummyStack {
@@ -45,6 +50,10 @@ impl<'repo> StackStateAccess<'repo> for DummyStack {
     fn head(&self) -> &Rc<gix::Commit<'repo>> {
         panic!()
     }
+
+    fn declared_dependencies(&self) -> &BTreeMap<PatchName, BTreeSet<PatchName>> {
+        &self.dependencies
+    }
 }
 
 impl DummyStack {
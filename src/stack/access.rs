@@ -3,11 +3,14 @@
 //! The [`StackStateAccess`] trait allows uniform access to stack information for
 //! stack-like objects.
 
-use std::rc::Rc;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
 
 use super::{
     iter::{AllPatches, BothPatches},
-    state::PatchState,
+    state::{PatchMeta, PatchState},
 };
 use crate::patch::{LocationConstraint, LocationGroup, PatchName};
 
@@ -79,6 +82,11 @@ pub(crate) trait StackStateAccess<'repo> {
     /// [`crate::stack::Stack::branch_head`].
     fn head(&self) -> &Rc<gix::Commit<'repo>>;
 
+    /// Get explicitly declared patch dependencies.
+    ///
+    /// Maps patch names to the set of patch names they depend on.
+    fn declared_dependencies(&self) -> &BTreeMap<PatchName, BTreeSet<PatchName>>;
+
     /// Get the commit for the given patch name.
     fn get_patch_commit(&self, patchname: &PatchName) -> &Rc<gix::Commit<'repo>> {
         &self.get_patch(patchname).commit
@@ -89,6 +97,14 @@ pub(crate) trait StackStateAccess<'repo> {
         self.get_patch_commit(patchname).id
     }
 
+    /// Get the metadata for the given patch name.
+    fn get_patch_meta<'a>(&'a self, patchname: &PatchName) -> &'a PatchMeta
+    where
+        'repo: 'a,
+    {
+        &self.get_patch(patchname).meta
+    }
+
     /// Test whether given patch name is applied.
     fn is_applied(&self, patchname: &PatchName) -> bool {
         self.applied().contains(patchname)
@@ -0,0 +1,370 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Patch dependency tracking.
+//!
+//! A patch depends on another patch when it may only be applied on top of that other
+//! patch. Dependencies are either declared explicitly, in which case they are recorded
+//! in the stack state, or are computed from the overlapping hunks of applied patches.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use bstr::{BStr, BString};
+
+use super::StackStateAccess;
+use crate::{
+    ext::CommitExtended,
+    patch::PatchName,
+    stupid::{FileDiff, Hunk, Stupid},
+};
+
+/// Policy for handling patch dependencies when reordering or pushing patches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DependencyPolicy {
+    /// Refuse operations that would apply a patch without its dependencies.
+    Refuse,
+
+    /// Pull dependencies (and dependents) into the operation as needed.
+    Pull,
+
+    /// Ignore patch dependencies.
+    Ignore,
+}
+
+impl FromStr for DependencyPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "refuse" => Ok(Self::Refuse),
+            "pull" => Ok(Self::Pull),
+            "ignore" => Ok(Self::Ignore),
+            _ => Err(anyhow!(
+                "invalid dependency policy `{s}`; expected `refuse`, `pull`, or `ignore`"
+            )),
+        }
+    }
+}
+
+/// Graph of patch dependencies.
+///
+/// Only patches existing in the stack at the time the graph is created are tracked.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DependencyGraph {
+    depends: BTreeMap<PatchName, BTreeSet<PatchName>>,
+}
+
+impl DependencyGraph {
+    /// Create dependency graph for a stack.
+    ///
+    /// The graph contains the stack's declared dependencies and, if `use_hunks` is true,
+    /// the dependencies computed from the overlapping hunks of the applied patches.
+    pub(crate) fn new<'repo>(
+        repo: &gix::Repository,
+        stack: &impl StackStateAccess<'repo>,
+        use_hunks: bool,
+    ) -> Result<Self> {
+        let mut depends: BTreeMap<PatchName, BTreeSet<PatchName>> = BTreeMap::new();
+        for (patchname, dependencies) in stack.declared_dependencies() {
+            if stack.has_patch(patchname) {
+                let dependencies: BTreeSet<PatchName> = dependencies
+                    .iter()
+                    .filter(|pn| stack.has_patch(pn))
+                    .cloned()
+                    .collect();
+                if !dependencies.is_empty() {
+                    depends.insert(patchname.clone(), dependencies);
+                }
+            }
+        }
+
+        if use_hunks {
+            for (patchname, dependencies) in compute_hunk_dependencies(repo, stack)? {
+                depends.entry(patchname).or_default().extend(dependencies);
+            }
+        }
+
+        Ok(Self { depends })
+    }
+
+    /// Create dependency graph for enforcing the given policy.
+    ///
+    /// No graph is needed when dependencies are ignored. Hunk dependencies are only
+    /// computed when `stgit.deps.hunks` is enabled in the config.
+    pub(crate) fn for_policy<'repo>(
+        repo: &gix::Repository,
+        stack: &impl StackStateAccess<'repo>,
+        policy: DependencyPolicy,
+    ) -> Result<Option<Self>> {
+        if policy == DependencyPolicy::Ignore {
+            Ok(None)
+        } else {
+            let use_hunks = repo
+                .config_snapshot()
+                .boolean("stgit.deps.hunks")
+                .unwrap_or(false);
+            Self::new(repo, stack, use_hunks).map(Some)
+        }
+    }
+
+    /// Get the patches the given patch directly depends on.
+    pub(crate) fn direct_dependencies(&self, patchname: &PatchName) -> Vec<&PatchName> {
+        self.depends
+            .get(patchname)
+            .map(|deps| deps.iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the patches that directly depend on the given patch.
+    pub(crate) fn direct_dependents(&self, patchname: &PatchName) -> Vec<&PatchName> {
+        self.depends
+            .iter()
+            .filter_map(|(pn, deps)| deps.contains(patchname).then_some(pn))
+            .collect()
+    }
+
+    /// Get all patches the given patch depends on, directly or transitively.
+    pub(crate) fn all_dependencies(&self, patchname: &PatchName) -> BTreeSet<&PatchName> {
+        let mut found: BTreeSet<&PatchName> = BTreeSet::new();
+        let mut to_visit = self.direct_dependencies(patchname);
+        while let Some(pn) = to_visit.pop() {
+            if found.insert(pn) {
+                to_visit.extend(self.direct_dependencies(pn));
+            }
+        }
+        found
+    }
+
+    /// Check that all of a patch's dependencies are in the given applied list.
+    pub(crate) fn check_applied(&self, patchname: &PatchName, applied: &[PatchName]) -> Result<()> {
+        if let Some(missing) = self
+            .direct_dependencies(patchname)
+            .into_iter()
+            .find(|dep| !applied.contains(dep))
+        {
+            Err(anyhow!(
+                "patch `{patchname}` depends on `{missing}`, which would not be applied \
+                 below it"
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Extend a list of patches with their dependencies and dependents.
+    ///
+    /// Dependencies of `patches` found in `above` are placed before the patches that
+    /// depend on them and dependents of `patches` found in `below` are placed after
+    /// the patches they depend on. The `above` and `below` slices must be in stack
+    /// order.
+    ///
+    /// This is used to move a group of patches to a new position in the stack: patches
+    /// in `below` are those that would end up below the moved group and patches in
+    /// `above` would end up above the moved group.
+    pub(crate) fn pull_in(
+        &self,
+        patches: &[PatchName],
+        below: &[PatchName],
+        above: &[PatchName],
+    ) -> Vec<PatchName> {
+        let with_dependencies = pull_dependencies(&self.depends, patches, above);
+
+        let mut dependents: BTreeMap<PatchName, BTreeSet<PatchName>> = BTreeMap::new();
+        for (patchname, deps) in &self.depends {
+            for dep in deps {
+                dependents
+                    .entry(dep.clone())
+                    .or_default()
+                    .insert(patchname.clone());
+            }
+        }
+        let reversed: Vec<PatchName> = with_dependencies.into_iter().rev().collect();
+        let below_reversed: Vec<PatchName> = below.iter().rev().cloned().collect();
+        let mut result = pull_dependencies(&dependents, &reversed, &below_reversed);
+        result.reverse();
+        result
+    }
+}
+
+/// Place each patch's dependencies from `candidates` before the patch.
+fn pull_dependencies(
+    depends: &BTreeMap<PatchName, BTreeSet<PatchName>>,
+    patches: &[PatchName],
+    candidates: &[PatchName],
+) -> Vec<PatchName> {
+    fn visit(
+        patchname: &PatchName,
+        depends: &BTreeMap<PatchName, BTreeSet<PatchName>>,
+        eligible: &[&PatchName],
+        visited: &mut BTreeSet<PatchName>,
+        result: &mut Vec<PatchName>,
+    ) {
+        if !visited.insert(patchname.clone()) {
+            return;
+        }
+        if let Some(deps) = depends.get(patchname) {
+            for &dep in eligible.iter().filter(|pn| deps.contains(pn)) {
+                visit(dep, depends, eligible, visited, result);
+            }
+        }
+        result.push(patchname.clone());
+    }
+
+    let eligible: Vec<&PatchName> = patches.iter().chain(candidates.iter()).collect();
+    let mut visited = BTreeSet::new();
+    let mut result = Vec::with_capacity(patches.len());
+    for patchname in patches {
+        visit(patchname, depends, &eligible, &mut visited, &mut result);
+    }
+    result
+}
+
+/// Compute dependencies between applied patches based on overlapping hunks.
+///
+/// A patch depends on a lower patch when any of its hunks modify, or are adjacent to,
+/// lines last modified by the lower patch.
+pub(crate) fn compute_hunk_dependencies<'repo>(
+    repo: &gix::Repository,
+    stack: &impl StackStateAccess<'repo>,
+) -> Result<BTreeMap<PatchName, BTreeSet<PatchName>>> {
+    let stupid = repo.stupid();
+    let mut owners: LineOwners<PatchName> = LineOwners::default();
+    let mut depends: BTreeMap<PatchName, BTreeSet<PatchName>> = BTreeMap::new();
+
+    for patchname in stack.applied() {
+        let commit = stack.get_patch_commit(patchname);
+        let parent_tree_id = commit.get_parent_commit()?.tree_id()?.detach();
+        let tree_id = commit.tree_id()?.detach();
+        let mut dependencies = BTreeSet::new();
+        for file_diff in stupid.diff_tree_file_diffs(parent_tree_id, tree_id, 0)? {
+            dependencies.extend(owners.record(&file_diff, patchname));
+        }
+        dependencies.remove(patchname);
+        if !dependencies.is_empty() {
+            depends.insert(patchname.clone(), dependencies);
+        }
+    }
+
+    Ok(depends)
+}
+
+/// Track which owner last modified each line of a set of files.
+///
+/// File diffs are recorded in order, each attributed to an owner, e.g. a patch. Diffs
+/// must have zero lines of context.
+#[derive(Debug)]
+pub(crate) struct LineOwners<T> {
+    files: BTreeMap<BString, Vec<Option<T>>>,
+}
+
+impl<T> Default for LineOwners<T> {
+    fn default() -> Self {
+        Self {
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Clone + Ord> LineOwners<T> {
+    /// Get owners of the lines touched by a hunk of the given file.
+    ///
+    /// When `adjacent` is true, owners of the lines immediately surrounding the hunk are
+    /// also included.
+    pub(crate) fn hunk_owners(&self, path: &BStr, hunk: &Hunk, adjacent: bool) -> BTreeSet<T> {
+        let Some(lines) = self.files.get(path) else {
+            return BTreeSet::new();
+        };
+
+        let (start, end) = if hunk.old_lines == 0 {
+            // Insertion after line `old_start`; only the surrounding lines are relevant.
+            if adjacent {
+                (hunk.old_start.saturating_sub(1), hunk.old_start + 1)
+            } else {
+                (0, 0)
+            }
+        } else if adjacent {
+            (
+                hunk.old_start.saturating_sub(2),
+                hunk.old_start - 1 + hunk.old_lines + 1,
+            )
+        } else {
+            (hunk.old_start - 1, hunk.old_start - 1 + hunk.old_lines)
+        };
+
+        lines
+            .iter()
+            .take(end)
+            .skip(start)
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Get the sole owner of the lines touched by a hunk of the given file.
+    ///
+    /// For a hunk that only inserts lines, the owned lines immediately surrounding the
+    /// insertion are considered instead. `None` is returned when the lines have
+    /// different owners or when any of the touched lines has no owner.
+    pub(crate) fn sole_hunk_owner(&self, path: &BStr, hunk: &Hunk) -> Option<T> {
+        let lines = self.files.get(path)?;
+        let mut owners: BTreeSet<&T> = BTreeSet::new();
+        if hunk.old_lines == 0 {
+            let start = hunk.old_start.saturating_sub(1);
+            owners.extend(lines.iter().take(hunk.old_start + 1).skip(start).flatten());
+        } else {
+            let start = hunk.old_start - 1;
+            for line in start..start + hunk.old_lines {
+                owners.insert(lines.get(line)?.as_ref()?);
+            }
+        }
+        if owners.len() == 1 {
+            owners.pop_first().cloned()
+        } else {
+            None
+        }
+    }
+
+    /// Record a file diff made by `owner`.
+    ///
+    /// Returns the previous owners of the modified and adjacent lines.
+    pub(crate) fn record(&mut self, file_diff: &FileDiff, owner: &T) -> BTreeSet<T> {
+        let path = file_diff.path();
+        let mut previous: BTreeSet<T> = BTreeSet::new();
+
+        if file_diff.is_binary {
+            if let Some(lines) = self.files.get(path) {
+                previous.extend(lines.iter().flatten().cloned());
+            }
+            self.files
+                .insert(BString::from(path), vec![Some(owner.clone())]);
+            return previous;
+        }
+
+        for hunk in file_diff.hunks.iter().rev() {
+            previous.extend(self.hunk_owners(path, hunk, true));
+            let lines = self.files.entry(BString::from(path)).or_default();
+            let start = if hunk.old_lines == 0 {
+                hunk.old_start
+            } else {
+                hunk.old_start - 1
+            };
+            let end = start + hunk.old_lines;
+            if lines.len() < end {
+                lines.resize(end, None);
+            }
+            lines.splice(
+                start..end,
+                std::iter::repeat(Some(owner.clone())).take(hunk.new_lines),
+            );
+        }
+
+        if file_diff.new_path.is_none() {
+            self.files.remove(path);
+        }
+
+        previous
+    }
+}
@@ -2,6 +2,7 @@
 
 //! The StGit stack data structure.
 mod access;
+mod deps;
 mod iter;
 mod serde;
 #[allow(clippy::module_inception)]
@@ -11,6 +12,9 @@ mod transaction;
 mod upgrade;
 
 pub(crate) use access::{StackAccess, StackStateAccess};
+pub(crate) use deps::{DependencyGraph, DependencyPolicy, LineOwners};
 pub(crate) use stack::{state_refname_from_branch_name, InitializationPolicy, Stack};
-pub(crate) use state::{PatchState, StackState};
+pub(crate) use state::{
+    PatchMeta, PatchState, PendingOperation, PendingStep, PublishedPatch, StackState,
+};
 pub(crate) use transaction::{Error as TransactionError, StackTransaction};
@@ -2,28 +2,40 @@
 
 //! Serialize and deserialize stack state to/from JSON representation.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::{Context, Result};
 
+use super::state::{PatchMeta, PendingOperation, PendingStep, PublishedPatch};
 use crate::patch::PatchName;
 
+/// Current version of the `stack.json` format.
+pub(crate) const FORMAT_VERSION: i64 = 6;
+
 /// Raw state deserialization representation.
 ///
 /// `PatchNames` and `Oids` are checked, but `Oids` are not converted to `Commits`.
 pub(crate) struct RawStackState {
+    pub version: i64,
     pub prev: Option<gix::ObjectId>,
+    pub merged: Option<gix::ObjectId>,
     pub head: gix::ObjectId,
     pub applied: Vec<PatchName>,
     pub unapplied: Vec<PatchName>,
     pub hidden: Vec<PatchName>,
     pub patches: BTreeMap<PatchName, RawPatchState>,
+    pub dependencies: BTreeMap<PatchName, BTreeSet<PatchName>>,
+    pub pending: Option<PendingOperation>,
+    pub published: BTreeMap<String, BTreeMap<PatchName, PublishedPatch>>,
 }
 
 /// Raw patch state representation.
 pub(crate) struct RawPatchState {
     /// The commit id of the patch.
     pub oid: gix::ObjectId,
+
+    /// The patch's metadata.
+    pub meta: PatchMeta,
 }
 
 impl RawStackState {
@@ -45,24 +57,60 @@ impl<'de> serde::Deserialize<'de> for RawStackState {
         struct DeserState {
             pub version: i64,
             pub prev: Option<String>,
+            #[serde(default)]
+            pub merged: Option<String>,
             pub head: String,
             pub applied: Vec<PatchName>,
             pub unapplied: Vec<PatchName>,
             pub hidden: Vec<PatchName>,
             pub patches: BTreeMap<PatchName, DeserPatchState>,
+            #[serde(default)]
+            pub dependencies: BTreeMap<PatchName, BTreeSet<PatchName>>,
+            #[serde(default)]
+            pub pending: Option<DeserPendingOperation>,
+            #[serde(default)]
+            pub published: BTreeMap<String, BTreeMap<PatchName, DeserPublishedPatch>>,
         }
 
         #[derive(serde::Deserialize)]
         struct DeserPatchState {
             pub oid: String,
+            #[serde(default)]
+            pub meta: PatchMeta,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct DeserPendingOperation {
+            pub command: String,
+            pub orig_state: String,
+            pub base: String,
+            #[serde(default)]
+            pub patch: Option<PatchName>,
+            #[serde(default)]
+            pub patch_commit: Option<String>,
+            pub remaining: Vec<PendingStep>,
+            pub autostash: bool,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct DeserPublishedPatch {
+            pub refname: String,
+            pub commit: String,
         }
 
+        let parse_oid = |field: &str, oid_str: &str| -> Result<gix::ObjectId, D::Error> {
+            gix::ObjectId::from_hex(oid_str.as_bytes())
+                .map_err(|_| D::Error::custom(format!("invalid `{field}` oid `{oid_str}`")))
+        };
+
         let ds = DeserState::deserialize(deserializer)?;
 
-        if ds.version != 5 {
+        // Older states remain readable since they may be found in the stack's
+        // history. Versions after 5 only add optional fields.
+        if !(5..=FORMAT_VERSION).contains(&ds.version) {
             return Err(D::Error::invalid_value(
                 ::serde::de::Unexpected::Signed(ds.version),
-                &"5",
+                &format!("5 through {FORMAT_VERSION}").as_str(),
             ));
         }
 
@@ -79,6 +127,12 @@ impl<'de> serde::Deserialize<'de> for RawStackState {
             None => None,
         };
 
+        let merged = ds
+            .merged
+            .as_ref()
+            .map(|oid_str| parse_oid("merged", oid_str))
+            .transpose()?;
+
         let head = gix::ObjectId::from_hex(ds.head.as_bytes())
             .map_err(|_| D::Error::custom(format!("invalid `head` oid '{}'", &ds.head)))?;
 
@@ -90,16 +144,61 @@ impl<'de> serde::Deserialize<'de> for RawStackState {
                     patchname, &raw_patch.oid
                 ))
             })?;
-            patches.insert(patchname, RawPatchState { oid });
+            patches.insert(
+                patchname,
+                RawPatchState {
+                    oid,
+                    meta: raw_patch.meta,
+                },
+            );
+        }
+
+        let pending = if let Some(pending) = ds.pending {
+            Some(PendingOperation {
+                command: pending.command,
+                orig_state: parse_oid("orig_state", &pending.orig_state)?,
+                base: parse_oid("base", &pending.base)?,
+                conflict: if let (Some(patch), Some(patch_commit)) =
+                    (pending.patch, pending.patch_commit)
+                {
+                    Some((patch, parse_oid("patch_commit", &patch_commit)?))
+                } else {
+                    None
+                },
+                remaining: pending.remaining,
+                autostash: pending.autostash,
+            })
+        } else {
+            None
+        };
+
+        let mut published = BTreeMap::new();
+        for (remote, patches) in ds.published {
+            let mut published_patches = BTreeMap::new();
+            for (patchname, patch) in patches {
+                published_patches.insert(
+                    patchname,
+                    PublishedPatch {
+                        refname: patch.refname,
+                        commit_id: parse_oid("published commit", &patch.commit)?,
+                    },
+                );
+            }
+            published.insert(remote, published_patches);
         }
 
         Ok(RawStackState {
+            version: ds.version,
             prev,
+            merged,
             head,
             applied: ds.applied,
             unapplied: ds.unapplied,
             hidden: ds.hidden,
             patches,
+            dependencies: ds.dependencies,
+            pending,
+            published,
         })
     }
 }
@@ -113,19 +212,50 @@ impl serde::Serialize for super::state::StackState<'_> {
         struct SerializableState<'a> {
             pub version: i64,
             pub prev: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub merged: Option<String>,
             pub head: String,
             pub applied: &'a Vec<PatchName>,
             pub unapplied: &'a Vec<PatchName>,
             pub hidden: &'a Vec<PatchName>,
-            pub patches: BTreeMap<&'a PatchName, SerializablePatchState>,
+            pub patches: BTreeMap<&'a PatchName, SerializablePatchState<'a>>,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            pub dependencies: &'a BTreeMap<PatchName, BTreeSet<PatchName>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub pending: Option<SerializablePendingOperation<'a>>,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            pub published:
+                BTreeMap<&'a str, BTreeMap<&'a PatchName, SerializablePublishedPatch<'a>>>,
         }
 
         #[derive(serde::Serialize)]
-        struct SerializablePatchState {
+        struct SerializablePatchState<'a> {
             pub oid: String,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            pub meta: &'a PatchMeta,
+        }
+
+        #[derive(serde::Serialize)]
+        struct SerializablePendingOperation<'a> {
+            pub command: &'a str,
+            pub orig_state: String,
+            pub base: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub patch: Option<&'a PatchName>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub patch_commit: Option<String>,
+            pub remaining: &'a Vec<PendingStep>,
+            pub autostash: bool,
+        }
+
+        #[derive(serde::Serialize)]
+        struct SerializablePublishedPatch<'a> {
+            pub refname: &'a str,
+            pub commit: String,
         }
 
         let prev: Option<String> = self.prev.as_ref().map(|commit| commit.id().to_string());
+        let merged: Option<String> = self.merged.as_ref().map(|commit| commit.id().to_string());
         let head: String = self.head.id().to_string();
         let mut patches: BTreeMap<&PatchName, SerializablePatchState> = BTreeMap::new();
         for (patchname, patch_state) in &self.patches {
@@ -133,18 +263,57 @@ impl serde::Serialize for super::state::StackState<'_> {
                 patchname,
                 SerializablePatchState {
                     oid: patch_state.commit.id().to_string(),
+                    meta: &patch_state.meta,
                 },
             );
         }
 
         let ss = SerializableState {
-            version: 5,
+            version: FORMAT_VERSION,
             prev,
+            merged,
             head,
             applied: &self.applied,
             unapplied: &self.unapplied,
             hidden: &self.hidden,
             patches,
+            dependencies: &self.dependencies,
+            pending: self
+                .pending
+                .as_ref()
+                .map(|pending| SerializablePendingOperation {
+                    command: &pending.command,
+                    orig_state: pending.orig_state.to_string(),
+                    base: pending.base.to_string(),
+                    patch: pending.conflict.as_ref().map(|(patchname, _)| patchname),
+                    patch_commit: pending
+                        .conflict
+                        .as_ref()
+                        .map(|(_, commit_id)| commit_id.to_string()),
+                    remaining: &pending.remaining,
+                    autostash: pending.autostash,
+                }),
+            published: self
+                .published
+                .iter()
+                .map(|(remote, patches)| {
+                    (
+                        remote.as_str(),
+                        patches
+                            .iter()
+                            .map(|(patchname, patch)| {
+                                (
+                                    patchname,
+                                    SerializablePublishedPatch {
+                                        refname: &patch.refname,
+                                        commit: patch.commit_id.to_string(),
+                                    },
+                                )
+                            })
+                            .collect(),
+                    )
+                })
+                .collect(),
         };
 
         ss.serialize(serializer)
@@ -2,14 +2,20 @@
 
 //! High-level StGit stack representation.
 
-use std::{collections::BTreeMap, rc::Rc, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+    str::FromStr,
+};
 
 use anyhow::{anyhow, Context, Result};
 use bstr::ByteSlice;
 
 use super::{
-    state::StackState, transaction::TransactionBuilder, upgrade::stack_upgrade, PatchState,
-    StackAccess, StackStateAccess,
+    state::{PendingOperation, PublishedPatch, StackState},
+    transaction::TransactionBuilder,
+    upgrade::{read_stack_state, stack_upgrade},
+    PatchState, StackAccess, StackStateAccess,
 };
 use crate::{
     branchloc::BranchLocator,
@@ -153,8 +159,8 @@ impl<'repo> Stack<'repo> {
 
         let state_and_base_from_ref =
             |state_ref: gix::Reference<'repo>| -> Result<(StackState<'repo>, Rc<gix::Commit<'repo>>)> {
-                let stack_tree = state_ref.id().object()?.try_into_commit()?.tree()?;
-                let state = StackState::from_tree(repo, stack_tree)?;
+                let state_commit = state_ref.id().object()?.try_into_commit()?;
+                let state = read_stack_state(repo, &branch_name, state_commit)?;
                 let base = if let Some(first_patchname) = state.applied.first() {
                     Rc::new(
                         repo.find_object(
@@ -299,6 +305,40 @@ impl<'repo> Stack<'repo> {
         }
     }
 
+    /// Get the interrupted operation pending resumption, if any.
+    pub(crate) fn pending_operation(&self) -> Option<&PendingOperation> {
+        self.state.pending.as_ref()
+    }
+
+    /// Get the patches published to per-patch references of remotes, by remote.
+    pub(crate) fn published_patches(
+        &self,
+    ) -> &BTreeMap<String, BTreeMap<PatchName, PublishedPatch>> {
+        &self.state.published
+    }
+
+    /// Return an error if an interrupted operation is pending.
+    pub(crate) fn check_no_pending_operation(&self) -> Result<()> {
+        if let Some(pending) = self.pending_operation() {
+            Err(anyhow!(
+                "`stg {}` was interrupted; \
+                 use `stg continue`, `stg skip`, or `stg abort` first",
+                pending.command
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get the id of the stack's current state commit.
+    pub(crate) fn state_commit_id(&self) -> Result<gix::ObjectId> {
+        Ok(self
+            .repo
+            .find_reference(&self.stack_refname)?
+            .peel_to_commit()?
+            .id)
+    }
+
     /// Re-commit stack state with updated branch head.
     pub(crate) fn log_external_mods(self, message: Option<&str>) -> Result<Self> {
         assert!(
@@ -355,11 +395,119 @@ impl<'repo> Stack<'repo> {
     /// Clear the stack state history.
     pub(crate) fn clear_state_log(&mut self, reflog_msg: &str) -> Result<()> {
         self.state.prev = None;
+        self.state.merged = None;
         self.state
             .commit(self.repo, Some(&self.stack_refname), reflog_msg)?;
         Ok(())
     }
 
+    /// Prune old states from the stack state history.
+    ///
+    /// The current state is always retained. Older states are retained while there are
+    /// fewer than `max_entries` retained states and while the states are no older than
+    /// `cutoff`. The retained states are recommitted with the oldest retained state
+    /// having no previous state. The histories of merged states, see `stg stack merge`,
+    /// are pruned likewise. Returns the number of pruned states.
+    pub(crate) fn prune_state_log(
+        &mut self,
+        max_entries: Option<usize>,
+        cutoff: Option<gix::date::SecondsSinceUnixEpoch>,
+        reflog_msg: &str,
+    ) -> Result<usize> {
+        let state_commit = Rc::new(
+            self.repo
+                .find_reference(&self.stack_refname)?
+                .peel_to_commit()?,
+        );
+        let orig_commit_id = state_commit.id;
+        let (top_commit_id, num_pruned) =
+            self.prune_state_history(state_commit, max_entries, cutoff, true)?;
+
+        if num_pruned == 0 {
+            return Ok(0);
+        }
+
+        let top_commit_id = top_commit_id.expect("current state is retained");
+        self.repo.reference(
+            self.stack_refname.as_str(),
+            top_commit_id,
+            gix::refs::transaction::PreviousValue::MustExistAndMatch(gix::refs::Target::Object(
+                orig_commit_id,
+            )),
+            reflog_msg,
+        )?;
+        let top_state = StackState::from_commit(self.repo, &self.repo.find_commit(top_commit_id)?)?;
+        self.state.prev = top_state.prev;
+        self.state.merged = top_state.merged;
+
+        Ok(num_pruned)
+    }
+
+    /// Prune the stack state history starting at `state_commit`.
+    ///
+    /// See [`Stack::prune_state_log()`]. The first state is only retained
+    /// unconditionally if `retain_first` is true. Returns the id of the recommitted
+    /// first state, or `None` if it was pruned, along with the number of pruned states.
+    fn prune_state_history(
+        &self,
+        state_commit: Rc<gix::Commit<'repo>>,
+        max_entries: Option<usize>,
+        cutoff: Option<gix::date::SecondsSinceUnixEpoch>,
+        retain_first: bool,
+    ) -> Result<(Option<gix::ObjectId>, usize)> {
+        let mut retained: Vec<(StackState<'repo>, Rc<gix::Commit<'repo>>)> = Vec::new();
+        let mut num_pruned = 0;
+        let mut state_commit = state_commit;
+        loop {
+            let state = StackState::from_commit(self.repo, &state_commit)?;
+            let prev = state.prev.clone();
+            let retain = (retain_first && retained.is_empty())
+                || (max_entries.map_or(true, |max| retained.len() < max)
+                    && cutoff.map_or(Ok(true), |cutoff| {
+                        state_commit.time().map(|time| time.seconds >= cutoff)
+                    })?);
+            if retain && num_pruned == 0 {
+                retained.push((state, state_commit));
+            } else {
+                num_pruned += 1;
+            }
+            if let Some(prev) = prev {
+                state_commit = prev;
+            } else {
+                break;
+            }
+        }
+
+        // Merged states would otherwise keep their entire history reachable.
+        for (state, _) in &mut retained {
+            if let Some(merged) = state.merged.take() {
+                let (merged_id, merged_pruned) =
+                    self.prune_state_history(merged.clone(), max_entries, cutoff, false)?;
+                num_pruned += merged_pruned;
+                state.merged = if merged_id == Some(merged.id) {
+                    Some(merged)
+                } else {
+                    merged_id
+                        .map(|id| self.repo.find_commit(id).map(Rc::new))
+                        .transpose()?
+                };
+            }
+        }
+
+        if num_pruned == 0 {
+            return Ok((retained.first().map(|(_, commit)| commit.id), 0));
+        }
+
+        let mut prev: Option<Rc<gix::Commit<'repo>>> = None;
+        for (state, orig_commit) in retained.iter_mut().rev() {
+            state.prev = prev.take();
+            let commit_id = state.recommit(self.repo, orig_commit)?;
+            prev = Some(Rc::new(self.repo.find_commit(commit_id)?));
+        }
+
+        Ok((prev.map(|commit| commit.id), num_pruned))
+    }
+
     /// Update the branch and branch head commit.
     pub(super) fn update_head(&mut self, branch: Branch<'repo>, commit: Rc<gix::Commit<'repo>>) {
         self.branch = branch;
@@ -434,6 +582,10 @@ impl<'repo> StackStateAccess<'repo> for Stack<'repo> {
     fn head(&self) -> &Rc<gix::Commit<'repo>> {
         self.state.head()
     }
+
+    fn declared_dependencies(&self) -> &BTreeMap<PatchName, BTreeSet<PatchName>> {
+        self.state.declared_dependencies()
+    }
 }
 
 /// Get reference name for StGit stack state for the given branch name.
@@ -5,7 +5,11 @@
 //! This stack state representation is serialized to/from the `stack.json` blob
 //! in the stack state tree.
 
-use std::{collections::BTreeMap, rc::Rc, str};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+    str,
+};
 
 use anyhow::{anyhow, Result};
 use bstr::{BString, ByteVec};
@@ -28,6 +32,12 @@ pub(crate) struct StackState<'repo> {
     /// cleared (i.e. with `stg log --clear`).
     pub(crate) prev: Option<Rc<gix::Commit<'repo>>>,
 
+    /// Commit of a stack state merged into this state with `stg stack merge`.
+    ///
+    /// The history of the merged state is part of this state's history in addition to
+    /// the history of `prev`.
+    pub(crate) merged: Option<Rc<gix::Commit<'repo>>>,
+
     /// Head commit of the stack.
     ///
     /// Either the topmost patch if patches are applied, or the stack base if no
@@ -45,16 +55,92 @@ pub(crate) struct StackState<'repo> {
 
     /// Mapping of patch names to their state.
     pub(super) patches: BTreeMap<PatchName, PatchState<'repo>>,
+
+    /// Explicitly declared patch dependencies.
+    ///
+    /// Maps a patch name to the names of the patches it depends on. A patch may only
+    /// be applied when all of its dependencies are applied below it.
+    pub(super) dependencies: BTreeMap<PatchName, BTreeSet<PatchName>>,
+
+    /// Operation interrupted by merge conflicts, if any.
+    ///
+    /// The operation may be resumed with `stg continue` or `stg skip`, or rolled back
+    /// with `stg abort`.
+    pub(super) pending: Option<PendingOperation>,
+
+    /// Patches published to per-patch references of remote repositories.
+    ///
+    /// Maps a remote to the names of its published patches along with the remote
+    /// references and commits they were last published as.
+    pub(super) published: BTreeMap<String, BTreeMap<PatchName, PublishedPatch>>,
+}
+
+/// An operation that was interrupted while pushing patches.
+///
+/// Operations are interrupted by merge conflicts, or by `break` and failing `exec`
+/// instructions of an interactive rebase.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PendingOperation {
+    /// Name of the interrupted command, e.g. "rebase".
+    pub(crate) command: String,
+
+    /// Stack state commit from before the operation started.
+    pub(crate) orig_state: gix::ObjectId,
+
+    /// The stack base the patches are being pushed onto.
+    pub(crate) base: gix::ObjectId,
+
+    /// The patch whose push resulted in conflicts along with its commit from before
+    /// it was pushed. This is `None` when the operation was not interrupted by
+    /// conflicts.
+    pub(crate) conflict: Option<(PatchName, gix::ObjectId)>,
+
+    /// Steps that remain to be performed to complete the operation.
+    pub(crate) remaining: Vec<PendingStep>,
+
+    /// Whether changes were stashed before the operation and need to be restored
+    /// once the operation completes.
+    pub(crate) autostash: bool,
+}
+
+/// A patch published to a remote reference with `stg publish-each`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PublishedPatch {
+    /// Full name of the remote reference, e.g. `refs/heads/review/my-patch`.
+    pub(crate) refname: String,
+
+    /// The patch's commit when it was last published.
+    pub(crate) commit_id: gix::ObjectId,
+}
+
+/// A step remaining to be performed by a [`PendingOperation`].
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PendingStep {
+    /// Push the patch.
+    Push(PatchName),
+
+    /// Run the shell command, interrupting the operation if the command fails.
+    Exec(String),
+
+    /// Interrupt the operation.
+    Break,
 }
 
 /// State associated with a patch.
 ///
-/// Currently the only state is a commit object.
+/// Each patch has a commit object and an optional set of metadata.
 #[derive(Clone, Debug)]
 pub(crate) struct PatchState<'repo> {
     pub(crate) commit: Rc<gix::Commit<'repo>>,
+
+    /// Arbitrary key-value metadata, e.g. notes, review status, or labels.
+    pub(crate) meta: PatchMeta,
 }
 
+/// Per-patch metadata mapping keys to values.
+pub(crate) type PatchMeta = BTreeMap<String, String>;
+
 impl<'repo> StackStateAccess<'repo> for StackState<'repo> {
     fn applied(&self) -> &[PatchName] {
         &self.applied
@@ -87,6 +173,10 @@ impl<'repo> StackStateAccess<'repo> for StackState<'repo> {
     fn head(&self) -> &Rc<gix::Commit<'repo>> {
         &self.head
     }
+
+    fn declared_dependencies(&self) -> &BTreeMap<PatchName, BTreeSet<PatchName>> {
+        &self.dependencies
+    }
 }
 
 /// Maximum number of parents a stack state commit is allowed before parent
@@ -98,11 +188,15 @@ impl<'repo> StackState<'repo> {
     pub(super) fn new(head: Rc<gix::Commit<'repo>>) -> Self {
         Self {
             prev: None,
+            merged: None,
             head,
             applied: vec![],
             unapplied: vec![],
             hidden: vec![],
             patches: BTreeMap::new(),
+            dependencies: BTreeMap::new(),
+            pending: None,
+            published: BTreeMap::new(),
         }
     }
 
@@ -116,12 +210,16 @@ impl<'repo> StackState<'repo> {
 
     /// Read and parse stack state from given stack state tree.
     pub(super) fn from_tree(repo: &'repo gix::Repository, tree: gix::Tree<'repo>) -> Result<Self> {
+        Self::from_raw_state(repo, Self::read_raw(tree)?)
+    }
+
+    /// Read and parse raw stack state from given stack state tree.
+    pub(super) fn read_raw(tree: gix::Tree<'_>) -> Result<RawStackState> {
         let mut tree = tree;
         let stack_json = tree.peel_to_entry_by_path("stack.json")?;
         if let Some(stack_json) = stack_json {
             let stack_json_data = stack_json.object()?.try_into_blob()?.take_data();
-            let raw_state = RawStackState::from_stack_json(&stack_json_data)?;
-            Self::from_raw_state(repo, raw_state)
+            RawStackState::from_stack_json(&stack_json_data)
         } else {
             Err(anyhow!("stack metadata not found"))
         }
@@ -143,6 +241,7 @@ impl<'repo> StackState<'repo> {
                 patchname,
                 PatchState {
                     commit: Rc::new(commit),
+                    meta: raw_state.meta,
                 },
             );
         }
@@ -152,11 +251,19 @@ impl<'repo> StackState<'repo> {
             } else {
                 None
             },
+            merged: if let Some(merged_id) = raw_state.merged {
+                Some(Rc::new(repo.find_object(merged_id)?.try_into_commit()?))
+            } else {
+                None
+            },
             head: Rc::new(repo.find_object(raw_state.head)?.try_into_commit()?),
             applied: raw_state.applied,
             unapplied: raw_state.unapplied,
             hidden: raw_state.hidden,
             patches,
+            dependencies: raw_state.dependencies,
+            pending: raw_state.pending,
+            published: raw_state.published,
         })
     }
 
@@ -182,6 +289,7 @@ impl<'repo> StackState<'repo> {
     ) -> Self {
         Self {
             prev: Some(prev_state),
+            merged: None,
             head: new_head,
             ..self
         }
@@ -200,6 +308,43 @@ impl<'repo> StackState<'repo> {
         repo: &'repo gix::Repository,
         update_ref: Option<&str>,
         message: &str,
+    ) -> Result<gix::ObjectId> {
+        self.commit_as(
+            repo,
+            update_ref,
+            &Message::from(message),
+            repo.get_author()?,
+            repo.get_committer()?,
+        )
+    }
+
+    /// Commit stack state to repository reusing the message, author, and committer of
+    /// an existing stack state commit.
+    ///
+    /// This is used to rewrite the stack state history without changing the recorded
+    /// time and description of each state.
+    pub(crate) fn recommit(
+        &self,
+        repo: &'repo gix::Repository,
+        orig_commit: &gix::Commit<'_>,
+    ) -> Result<gix::ObjectId> {
+        let orig_commit_ref = orig_commit.decode()?;
+        self.commit_as(
+            repo,
+            None,
+            &orig_commit.message_ex(),
+            orig_commit_ref.author(),
+            orig_commit_ref.committer(),
+        )
+    }
+
+    fn commit_as(
+        &self,
+        repo: &'repo gix::Repository,
+        update_ref: Option<&str>,
+        message: &Message,
+        author: gix::actor::SignatureRef<'_>,
+        committer: gix::actor::SignatureRef<'_>,
     ) -> Result<gix::ObjectId> {
         let (state_tree_id, prev_state) = if let Some(prev_commit) = self.prev.as_ref() {
             let prev_state = Self::from_tree(repo, prev_commit.tree()?)?;
@@ -209,21 +354,19 @@ impl<'repo> StackState<'repo> {
             (self.make_tree(repo, None)?, None)
         };
         let config = repo.config_snapshot();
-        let committer = repo.get_committer()?;
-        let author = repo.get_author()?;
 
-        let simplified_parents: Vec<gix::ObjectId> = match &self.prev {
-            Some(prev_commit) => {
-                vec![prev_commit
+        let simplified_parents: Vec<gix::ObjectId> = self
+            .prev
+            .iter()
+            .chain(self.merged.iter())
+            .map(|state_commit| {
+                state_commit
                     .parent_ids()
                     .next()
-                    .expect("prev state commit has a parent")
-                    .detach()]
-            }
-            None => vec![],
-        };
-
-        let message = Message::from(message);
+                    .expect("state commit has a parent")
+                    .detach()
+            })
+            .collect();
 
         let commit_opts = CommitOptions {
             commit_encoding: None,
@@ -233,7 +376,7 @@ impl<'repo> StackState<'repo> {
         let simplified_parent_id = repo.commit_with_options(
             author,
             committer,
-            &message,
+            message,
             state_tree_id,
             simplified_parents,
             &commit_opts,
@@ -257,6 +400,10 @@ impl<'repo> StackState<'repo> {
             }
         }
 
+        if let Some(merged_commit) = self.merged.as_ref() {
+            parent_set.insert(merged_commit.id);
+        }
+
         let mut parent_oids: Vec<gix::ObjectId> = parent_set.iter().copied().collect();
 
         while parent_oids.len() > MAX_PARENTS {
@@ -278,7 +425,7 @@ impl<'repo> StackState<'repo> {
         let commit_oid = repo.commit_with_options(
             author,
             committer,
-            &message,
+            message,
             state_tree_id,
             parent_oids,
             &commit_opts,
@@ -3,19 +3,25 @@
 use std::collections::BTreeMap;
 
 use anyhow::Result;
+use indexmap::IndexSet;
 
 use super::{
-    options::{ConflictMode, TransactionOptions},
+    super::deps::DependencyGraph,
+    options::{ConflictMode, Resumable, TransactionOptions},
     ui::TransactionUserInterface,
     ExecuteContext, StackTransaction,
 };
-use crate::stack::{Stack, StackAccess, StackStateAccess};
+use crate::{
+    hook::StackHook,
+    stack::{PendingStep, Stack, StackAccess, StackStateAccess},
+};
 
 /// Builder used to setup a stack transaction.
 pub(crate) struct TransactionBuilder<'repo> {
     stack: Stack<'repo>,
     output: Option<termcolor::StandardStream>,
     options: TransactionOptions,
+    dependency_graph: Option<DependencyGraph>,
 }
 
 /// Builder for setting up transaction state and options.
@@ -27,6 +33,7 @@ impl<'repo> TransactionBuilder<'repo> {
             stack,
             output: None,
             options: TransactionOptions::default(),
+            dependency_graph: None,
         }
     }
 
@@ -117,6 +124,84 @@ impl<'repo> TransactionBuilder<'repo> {
         self
     }
 
+    /// Record the operation in the stack state if pushing a patch results in conflicts,
+    /// such that the operation may be resumed with `stg continue` or `stg skip`, or
+    /// rolled back to `orig_state` with `stg abort`. When the transaction completes
+    /// without conflicts, any previously pending operation is cleared.
+    #[must_use]
+    pub(crate) fn resumable(
+        mut self,
+        command: &str,
+        orig_state: gix::ObjectId,
+        autostash: bool,
+    ) -> Self {
+        self.options.resumable = Some(Resumable {
+            command: command.to_string(),
+            orig_state,
+            autostash,
+            remaining: vec![],
+        });
+        self
+    }
+
+    /// Make the transaction [resumable](TransactionBuilder::resumable()) unless some
+    /// other interrupted operation is already pending, in which case a conflicting
+    /// push is left to be resolved as part of the pending operation.
+    #[must_use]
+    pub(crate) fn resumable_unless_pending(self, command: &str, orig_state: gix::ObjectId) -> Self {
+        if self.stack.pending_operation().is_none() {
+            self.resumable(command, orig_state, false)
+        } else {
+            self
+        }
+    }
+
+    /// Steps of a resumable operation that remain to be performed after this
+    /// transaction. These steps are recorded with the pending operation if the
+    /// transaction is interrupted.
+    ///
+    /// Must be called after [`TransactionBuilder::resumable()`].
+    #[must_use]
+    pub(crate) fn remaining_steps(mut self, steps: Vec<PendingStep>) -> Self {
+        self.options
+            .resumable
+            .as_mut()
+            .expect("transaction must be resumable")
+            .remaining = steps;
+        self
+    }
+
+    /// Enforce patch dependencies from the given graph when pushing patches. By default,
+    /// patch dependencies are not enforced.
+    #[must_use]
+    pub(crate) fn dependency_graph(mut self, graph: Option<DependencyGraph>) -> Self {
+        self.dependency_graph = graph;
+        self
+    }
+
+    /// Run a StGit hook when the transaction is executed.
+    ///
+    /// Pre hooks run before the new stack state is recorded; a failing pre hook causes
+    /// the transaction to be rolled back. Post hooks run after the new stack state is
+    /// recorded; a failing post hook only results in a warning.
+    ///
+    /// The `stg-pre-push` and `stg-post-push` hooks need not be requested; they are
+    /// run by any transaction that pushes patches.
+    #[must_use]
+    pub(crate) fn run_hook(mut self, hook: StackHook) -> Self {
+        self.options.hooks.push(hook);
+        self
+    }
+
+    /// Record the given stack state commit as merged into the new stack state.
+    ///
+    /// The merged state's history becomes part of the new state's history.
+    #[must_use]
+    pub(crate) fn merged_state(mut self, state_commit_id: gix::ObjectId) -> Self {
+        self.options.merged_state = Some(state_commit_id);
+        self
+    }
+
     /// Perform stack transaction operations.
     ///
     /// The closure provided to this method may call various methods on the provided
@@ -137,6 +222,7 @@ impl<'repo> TransactionBuilder<'repo> {
             stack,
             output,
             options,
+            dependency_graph,
         } = self;
 
         let ui =
@@ -150,6 +236,16 @@ impl<'repo> TransactionBuilder<'repo> {
         let applied = stack.applied().to_vec();
         let unapplied = stack.unapplied().to_vec();
         let hidden = stack.hidden().to_vec();
+        let dependencies = stack.declared_dependencies().clone();
+        let published = stack.published_patches().clone();
+        // A resumable transaction either completes the pending operation or records
+        // a new pending operation when interrupted. Other transactions carry the
+        // pending operation forward unless they invalidate it.
+        let pending = if options.resumable.is_some() {
+            None
+        } else {
+            stack.pending_operation().cloned()
+        };
 
         let mut transaction = StackTransaction {
             stack,
@@ -161,11 +257,18 @@ impl<'repo> TransactionBuilder<'repo> {
             updated_patches: BTreeMap::new(),
             updated_head: None,
             updated_base: None,
+            dependencies,
+            dependency_graph,
+            pending,
+            published,
+            pushed: IndexSet::new(),
             current_tree_id,
+            rollback_tree_id: current_tree_id,
             error: None,
         };
 
         transaction.error = f(&mut transaction).err();
+        transaction.discard_stale_pending();
 
         ExecuteContext(transaction)
     }
@@ -30,7 +30,10 @@ mod builder;
 mod options;
 mod ui;
 
-use std::{collections::BTreeMap, rc::Rc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
 
 use anyhow::{anyhow, Result};
 use indexmap::IndexSet;
@@ -40,11 +43,15 @@ use self::{
     options::{ConflictMode, TransactionOptions},
     ui::TransactionUserInterface,
 };
-use super::{state::StackState, StackAccess};
+use super::{deps::DependencyGraph, state::StackState, StackAccess};
 use crate::{
     ext::{CommitExtended, RepositoryExtended},
+    hook::StackHook,
     patch::PatchName,
-    stack::{PatchState, Stack, StackStateAccess},
+    stack::{
+        PatchMeta, PatchState, PendingOperation, PendingStep, PublishedPatch, Stack,
+        StackStateAccess,
+    },
     stupid::{Stupid, StupidContext},
     wrap::Branch,
 };
@@ -58,6 +65,9 @@ pub(crate) enum Error {
     TransactionHalt { msg: String, conflicts: bool },
 }
 
+/// Patch passed to a stack hook: its name, old commit id if any, and new commit id.
+type HookPatch = (PatchName, Option<gix::ObjectId>, gix::ObjectId);
+
 /// Stack transaction state.
 pub(crate) struct StackTransaction<'repo> {
     stack: Stack<'repo>,
@@ -70,8 +80,14 @@ pub(crate) struct StackTransaction<'repo> {
     updated_patches: BTreeMap<PatchName, Option<PatchState<'repo>>>,
     updated_head: Option<Rc<gix::Commit<'repo>>>,
     updated_base: Option<Rc<gix::Commit<'repo>>>,
+    dependencies: BTreeMap<PatchName, BTreeSet<PatchName>>,
+    dependency_graph: Option<DependencyGraph>,
+    pending: Option<PendingOperation>,
+    published: BTreeMap<String, BTreeMap<PatchName, PublishedPatch>>,
+    pushed: IndexSet<PatchName>,
 
     current_tree_id: gix::ObjectId,
+    rollback_tree_id: gix::ObjectId,
     error: Option<anyhow::Error>,
 }
 
@@ -131,6 +147,18 @@ impl<'repo> ExecuteContext<'repo> {
         let trans_head = transaction.head().clone();
         let trans_head_tree_id = trans_head.tree_id()?.detach();
         let trans_top_patchname = transaction.applied().last().cloned();
+        let mut hooks: Vec<(StackHook, Vec<HookPatch>)> = Vec::new();
+        let pushed_patches = transaction.pushed_patches();
+        if !pushed_patches.is_empty() {
+            hooks.push((StackHook::PrePush, pushed_patches.clone()));
+            hooks.push((StackHook::PostPush, pushed_patches));
+        }
+        if !transaction.options.hooks.is_empty() {
+            let changed_patches = transaction.changed_patches();
+            for hook in &transaction.options.hooks {
+                hooks.push((hook.clone(), changed_patches.clone()));
+            }
+        }
 
         let StackTransaction {
             stack,
@@ -140,14 +168,17 @@ impl<'repo> ExecuteContext<'repo> {
             unapplied,
             hidden,
             updated_patches,
+            dependencies,
+            pending,
+            published,
             current_tree_id,
+            rollback_tree_id,
             error,
             ..
         } = transaction;
 
         let repo = stack.repo;
         let stack_top_patchname = stack.applied().last().cloned();
-        let rollback_tree_id = stack.get_branch_head().tree_id()?.detach();
 
         // Only proceed for halt errors
         let has_conflicts = if let Some(err) = &error {
@@ -189,6 +220,11 @@ impl<'repo> ExecuteContext<'repo> {
             )
         };
 
+        for (hook, hook_patches) in hooks.iter().filter(|(hook, _)| hook.is_pre()) {
+            crate::hook::run_stack_hook(repo, hook, hook_patches)
+                .map_err(|e| rollback(current_tree_id, e))?;
+        }
+
         if options.set_head && options.use_index_and_worktree {
             if !options.allow_bad_head {
                 stack.check_head_top_mismatch()?;
@@ -226,10 +262,28 @@ impl<'repo> ExecuteContext<'repo> {
                 }
             }
             state.prev = Some(Rc::new(prev_state_commit));
+            state.merged = if let Some(merged_id) = options.merged_state {
+                Some(Rc::new(repo.find_commit(merged_id)?))
+            } else {
+                None
+            };
             state.head = trans_head.clone();
             state.applied = applied;
             state.unapplied = unapplied;
             state.hidden = hidden;
+            state.dependencies = dependencies
+                .into_iter()
+                .filter_map(|(patchname, deps)| {
+                    let deps: BTreeSet<PatchName> = deps
+                        .into_iter()
+                        .filter(|pn| state.patches.contains_key(pn))
+                        .collect();
+                    (state.patches.contains_key(&patchname) && !deps.is_empty())
+                        .then_some((patchname, deps))
+                })
+                .collect();
+            state.pending = pending;
+            state.published = published;
             let state_commit_id = state.commit(repo, None, state_reflog_msg)?;
 
             // Update various refs as a single transaction. This reference transaction is
@@ -304,6 +358,14 @@ impl<'repo> ExecuteContext<'repo> {
         })
         .map_err(|e| rollback(trans_head_tree_id, e))?;
 
+        // The transaction is already recorded, so a failing post hook only warrants a
+        // warning.
+        for (hook, hook_patches) in hooks.iter().filter(|(hook, _)| !hook.is_pre()) {
+            if let Err(e) = crate::hook::run_stack_hook(repo, hook, hook_patches) {
+                ui.print_warning(&format!("{e:#}"));
+            }
+        }
+
         if let Some(err) = error {
             Err(err)
         } else {
@@ -368,11 +430,17 @@ impl<'repo> StackTransaction<'repo> {
         }
         let StackState {
             prev: _prev,
+            merged: _merged,
             head,
             applied,
             unapplied,
             hidden,
             patches,
+            dependencies,
+            pending,
+            // Published patches reflect the state of remote repositories, which is
+            // unaffected by resetting the stack.
+            published: _,
         } = state;
         self.updated_base = Some(if let Some(pn) = applied.first() {
             Rc::new(patches[pn].commit.get_parent_commit()?)
@@ -386,9 +454,116 @@ impl<'repo> StackTransaction<'repo> {
         self.applied = applied;
         self.unapplied = unapplied;
         self.hidden = hidden;
+        self.dependencies = dependencies;
+        self.pending = pending;
         Ok(())
     }
 
+    /// Discard the pending operation if this transaction abandoned it by other means.
+    ///
+    /// An operation interrupted by conflicts is abandoned when a non-resumable
+    /// transaction pops, deletes, or renames the conflicting patch. Refreshing the
+    /// conflicting patch, e.g. with `stg refresh`, keeps the operation pending such that
+    /// it may still be completed with `stg continue`. An operation interrupted by a
+    /// `break` or `exec` step is abandoned when any of its remaining patches is pushed
+    /// or deleted. A pending operation restored by
+    /// [`StackTransaction::reset_to_state()`] is retained.
+    ///
+    /// Changes stashed by an abandoned operation's `--autostash` are left in the stash
+    /// with a warning.
+    pub(super) fn discard_stale_pending(&mut self) {
+        if self.options.resumable.is_some()
+            || self.pending.as_ref() != self.stack.pending_operation()
+        {
+            return;
+        }
+        let Some(pending) = self.pending.as_ref() else {
+            return;
+        };
+        let is_stale = if let Some((patchname, _)) = pending.conflict.as_ref() {
+            !self.is_applied(patchname)
+        } else {
+            pending.remaining.iter().any(|step| {
+                matches!(step, PendingStep::Push(patchname)
+                    if !self.has_patch(patchname) || self.is_applied(patchname))
+            })
+        };
+        if is_stale {
+            if pending.autostash {
+                self.ui.print_warning(&format!(
+                    "abandoned interrupted `stg {}`; its autostashed changes remain in \
+                     `git stash list`",
+                    pending.command
+                ));
+            }
+            self.pending = None;
+        }
+    }
+
+    /// Record the resumable operation as pending.
+    ///
+    /// The operation's remaining steps are those provided to
+    /// [`TransactionBuilder::remaining_steps()`]. The operation may subsequently be
+    /// resumed with `stg continue`.
+    pub(crate) fn interrupt(&mut self) {
+        let resumable = self
+            .options
+            .resumable
+            .as_ref()
+            .expect("transaction must be resumable");
+        self.pending = Some(PendingOperation {
+            command: resumable.command.clone(),
+            orig_state: resumable.orig_state,
+            base: self.base().id,
+            conflict: None,
+            remaining: resumable.remaining.clone(),
+            autostash: resumable.autostash,
+        });
+    }
+
+    /// Get the patches pushed by the transaction along with their old and new commit
+    /// ids.
+    ///
+    /// Patches popped again after being pushed are not included.
+    fn pushed_patches(&self) -> Vec<HookPatch> {
+        self.pushed
+            .iter()
+            .filter(|patchname| self.applied.contains(patchname))
+            .map(|patchname| {
+                let old_id = self
+                    .stack
+                    .has_patch(patchname)
+                    .then(|| self.stack.get_patch_commit(patchname).id);
+                (
+                    patchname.clone(),
+                    old_id,
+                    self.get_patch_commit(patchname).id,
+                )
+            })
+            .collect()
+    }
+
+    /// Get the patches changed by the transaction along with their old and new commit
+    /// ids.
+    ///
+    /// A patch is changed if its commit changed, including newly created patches, or
+    /// if it was pushed or popped. Deleted patches are not included.
+    fn changed_patches(&self) -> Vec<HookPatch> {
+        self.all_patches()
+            .filter_map(|patchname| {
+                let new_id = self.get_patch_commit(patchname).id;
+                let old_id = self
+                    .stack
+                    .has_patch(patchname)
+                    .then(|| self.stack.get_patch_commit(patchname).id);
+                let was_applied = self.stack.applied().contains(patchname);
+                let is_applied = self.applied.contains(patchname);
+                (old_id != Some(new_id) || was_applied != is_applied)
+                    .then(|| (patchname.clone(), old_id, new_id))
+            })
+            .collect()
+    }
+
     /// Reset stack to previous stack state, but only for the specified patch names.
     pub(crate) fn reset_to_state_partially<P>(
         &mut self,
@@ -417,7 +592,10 @@ impl<'repo> StackTransaction<'repo> {
             .patches
             .iter()
             .filter_map(|(pn, patch_state)| {
-                if self.has_patch(pn) && self.get_patch_commit_id(pn) == patch_state.commit.id {
+                if self.has_patch(pn)
+                    && self.get_patch_commit_id(pn) == patch_state.commit.id
+                    && self.get_patch_meta(pn) == &patch_state.meta
+                {
                     Some(pn)
                 } else {
                     None
@@ -462,6 +640,19 @@ impl<'repo> StackTransaction<'repo> {
         Ok(())
     }
 
+    /// Move the stack base to a different commit.
+    ///
+    /// All patches must be popped before the base is moved. Patches subsequently
+    /// pushed are applied on top of the new base.
+    pub(crate) fn set_base(&mut self, commit: Rc<gix::Commit<'repo>>) {
+        assert!(
+            self.applied.is_empty(),
+            "patches must be popped to move the base"
+        );
+        self.updated_base = Some(commit);
+        self.updated_head = None;
+    }
+
     /// Update a patch with a different commit object.
     ///
     /// Any notes associated with the patch's previous commit are copied to the new
@@ -479,16 +670,105 @@ impl<'repo> StackTransaction<'repo> {
             .stupid()
             .notes_copy(old_commit.id, commit_id)
             .ok();
+        let meta = self.get_patch(patchname).meta.clone();
         self.updated_patches.insert(
             patchname.clone(),
             Some(PatchState {
                 commit: Rc::new(commit),
+                meta,
             }),
         );
         self.ui.print_updated(patchname, self.applied())?;
         Ok(())
     }
 
+    /// Absorb index changes into the topmost applied patch.
+    ///
+    /// The provided tree must be written from the index before any worktree-modifying
+    /// operations of the transaction. The difference between the transaction's current
+    /// tree and the provided tree is applied to the topmost patch. The provided tree
+    /// then becomes the current tree such that subsequent calls only absorb newer
+    /// changes and such that a rollback restores the index with the changes intact.
+    pub(crate) fn refresh_top_from_tree(&mut self, tree_id: gix::ObjectId) -> Result<()> {
+        let patchname = self
+            .applied
+            .last()
+            .cloned()
+            .ok_or_else(|| anyhow!("no patches applied"))?;
+        let base_tree_id = self.current_tree_id;
+        if base_tree_id == tree_id {
+            return Ok(());
+        }
+
+        let repo = self.stack.repo;
+        let patch_commit = self.get_patch_commit(&patchname).clone();
+        let patch_tree_id = patch_commit.tree_id()?.detach();
+        let new_tree_id = if patch_tree_id == base_tree_id {
+            tree_id
+        } else {
+            repo.stupid()
+                .with_temp_index(|stupid_temp| {
+                    stupid_temp.read_tree(patch_tree_id)?;
+                    if stupid_temp.apply_treediff_to_index(base_tree_id, tree_id, true)? {
+                        stupid_temp.write_tree().map(Some)
+                    } else {
+                        Ok(None)
+                    }
+                })?
+                .ok_or_else(|| anyhow!("changes do not apply cleanly to `{patchname}`"))?
+        };
+
+        let commit_id = repo.commit_ex(
+            &patch_commit.author_strict()?,
+            repo.get_committer()?,
+            &patch_commit.message_ex(),
+            new_tree_id,
+            patch_commit.parent_ids().map(|id| id.detach()),
+        )?;
+        self.current_tree_id = tree_id;
+        self.rollback_tree_id = tree_id;
+        self.update_patch(&patchname, commit_id)
+    }
+
+    /// Set a patch's metadata.
+    ///
+    /// Any previous metadata of the patch is replaced.
+    pub(crate) fn set_patch_meta(&mut self, patchname: &PatchName, meta: PatchMeta) {
+        let commit = self.get_patch_commit(patchname).clone();
+        self.updated_patches
+            .insert(patchname.clone(), Some(PatchState { commit, meta }));
+    }
+
+    /// Set the patches published to per-patch references of a remote.
+    ///
+    /// Any previously recorded published patches of the remote are replaced.
+    pub(crate) fn set_published_patches(
+        &mut self,
+        remote: &str,
+        published: BTreeMap<PatchName, PublishedPatch>,
+    ) {
+        if published.is_empty() {
+            self.published.remove(remote);
+        } else {
+            self.published.insert(remote.to_string(), published);
+        }
+    }
+
+    /// Set the declared dependencies of a patch.
+    ///
+    /// Any previously declared dependencies of the patch are replaced.
+    pub(crate) fn set_dependencies(
+        &mut self,
+        patchname: &PatchName,
+        dependencies: BTreeSet<PatchName>,
+    ) {
+        if dependencies.is_empty() {
+            self.dependencies.remove(patchname);
+        } else {
+            self.dependencies.insert(patchname.clone(), dependencies);
+        }
+    }
+
     /// Add new patch to the top of the stack.
     ///
     /// The commit for the new patch must be parented by the former top commit of the
@@ -501,6 +781,7 @@ impl<'repo> StackTransaction<'repo> {
             patchname.clone(),
             Some(PatchState {
                 commit: Rc::new(commit),
+                meta: PatchMeta::new(),
             }),
         );
         self.ui.print_pushed(patchname, PushStatus::New, true)?;
@@ -522,6 +803,7 @@ impl<'repo> StackTransaction<'repo> {
             patchname.clone(),
             Some(PatchState {
                 commit: Rc::new(commit),
+                meta: PatchMeta::new(),
             }),
         );
         self.ui.print_popped(&[patchname.clone()])?;
@@ -578,10 +860,12 @@ impl<'repo> StackTransaction<'repo> {
             repo.stupid()
                 .notes_copy(patch_commit.id, new_commit_id)
                 .ok();
+            let meta = self.get_patch(patchname).meta.clone();
             self.updated_patches.insert(
                 patchname.clone(),
                 Some(PatchState {
                     commit: Rc::new(commit),
+                    meta,
                 }),
             );
 
@@ -603,6 +887,7 @@ impl<'repo> StackTransaction<'repo> {
         }
 
         self.applied.push(patchname.clone());
+        self.pushed.insert(patchname.clone());
 
         self.ui.print_pushed(patchname, push_status, is_last)
     }
@@ -737,6 +1022,7 @@ impl<'repo> StackTransaction<'repo> {
                 patchname.clone(),
                 Some(PatchState {
                     commit: Rc::new(commit),
+                    meta: PatchMeta::new(),
                 }),
             );
             new_applied.push(patchname.clone());
@@ -814,7 +1100,7 @@ impl<'repo> StackTransaction<'repo> {
                 ));
             }
         }
-        if !self.stack.has_patch(old_patchname) {
+        if !self.has_patch(old_patchname) {
             return Err(anyhow!("patch `{old_patchname}` does not exist"));
         }
 
@@ -831,7 +1117,7 @@ impl<'repo> StackTransaction<'repo> {
         if let Some(Some(patch_state)) = self.updated_patches.remove(old_patchname) {
             // The renamed patch may have been previously updated in this transaction.
             // This can happen, for example, for `stg refresh`.
-            self.updated_patches.insert(old_patchname.clone(), None);
+            self.remove_patch_state(old_patchname);
             self.updated_patches
                 .insert(new_patchname.clone(), Some(patch_state));
         } else {
@@ -841,6 +1127,15 @@ impl<'repo> StackTransaction<'repo> {
                 .insert(new_patchname.clone(), Some(patch_state));
         }
 
+        if let Some(deps) = self.dependencies.remove(old_patchname) {
+            self.dependencies.insert(new_patchname.clone(), deps);
+        }
+        for deps in self.dependencies.values_mut() {
+            if deps.remove(old_patchname) {
+                deps.insert(new_patchname.clone());
+            }
+        }
+
         self.ui.print_rename(old_patchname, new_patchname)
     }
 
@@ -876,7 +1171,7 @@ impl<'repo> StackTransaction<'repo> {
         for patchname in all_popped {
             if should_delete(&patchname) {
                 deleted_group.push(patchname.clone());
-                self.updated_patches.insert(patchname, None);
+                self.remove_patch_state(&patchname);
             } else if !deleted_group.is_empty() {
                 self.ui.print_deleted(&deleted_group)?;
                 deleted_group.clear();
@@ -886,7 +1181,7 @@ impl<'repo> StackTransaction<'repo> {
         for patchname in unapplied {
             if should_delete(&patchname) {
                 deleted_group.push(patchname.clone());
-                self.updated_patches.insert(patchname, None);
+                self.remove_patch_state(&patchname);
             } else {
                 self.ui.print_deleted(&deleted_group)?;
                 deleted_group.clear();
@@ -899,7 +1194,7 @@ impl<'repo> StackTransaction<'repo> {
             if should_delete(&self.hidden[i]) {
                 let patchname = self.hidden.remove(i);
                 deleted_group.push(patchname.clone());
-                self.updated_patches.insert(patchname, None);
+                self.remove_patch_state(&patchname);
             } else {
                 i += 1;
                 self.ui.print_deleted(&deleted_group)?;
@@ -914,6 +1209,17 @@ impl<'repo> StackTransaction<'repo> {
         Ok(incidental)
     }
 
+    /// Record that a patch no longer exists.
+    ///
+    /// Patches that were created earlier in the transaction are simply forgotten.
+    fn remove_patch_state(&mut self, patchname: &PatchName) {
+        if self.stack.has_patch(patchname) {
+            self.updated_patches.insert(patchname.clone(), None);
+        } else {
+            self.updated_patches.remove(patchname);
+        }
+    }
+
     /// Pop applied patches, making them unapplied.
     ///
     /// The `should_pop` closure should return true for each patch name to be popped and
@@ -965,6 +1271,9 @@ impl<'repo> StackTransaction<'repo> {
     /// whether the patches' changes have already been merged into the stack's base
     /// tree. Patches that are determined to have already been merged will still be
     /// pushed successfully, but their diff will be empty.
+    ///
+    /// If the transaction was setup with a dependency graph, an error is returned when
+    /// a patch would be pushed without all of its dependencies applied.
     pub(crate) fn push_patches<P>(&mut self, patchnames: &[P], check_merged: bool) -> Result<()>
     where
         P: AsRef<PatchName>,
@@ -985,13 +1294,40 @@ impl<'repo> StackTransaction<'repo> {
                 let already_merged = merged
                     .as_ref()
                     .map_or(false, |merged| merged.contains(&patchname));
-                self.push_patch(
+                if let Some(graph) = self.dependency_graph.as_ref() {
+                    graph.check_applied(patchname, &self.applied)?;
+                }
+                let patch_commit_id = self.get_patch_commit(patchname).id;
+                let result = self.push_patch(
                     patchname,
                     already_merged,
                     is_last,
                     stupid_temp,
                     &mut temp_index_tree_id,
-                )?;
+                );
+                if let Err(e) = result {
+                    if let (
+                        Some(resumable),
+                        Some(Error::TransactionHalt {
+                            conflicts: true, ..
+                        }),
+                    ) = (self.options.resumable.as_ref(), e.downcast_ref::<Error>())
+                    {
+                        self.pending = Some(PendingOperation {
+                            command: resumable.command.clone(),
+                            orig_state: resumable.orig_state,
+                            base: self.base().id,
+                            conflict: Some((patchname.clone(), patch_commit_id)),
+                            remaining: patchnames[i + 1..]
+                                .iter()
+                                .map(|pn| PendingStep::Push(pn.as_ref().clone()))
+                                .chain(resumable.remaining.iter().cloned())
+                                .collect(),
+                            autostash: resumable.autostash,
+                        });
+                    }
+                    return Err(e);
+                }
             }
 
             Ok(())
@@ -1028,7 +1364,15 @@ impl<'repo> StackTransaction<'repo> {
             new_parent_ref.tree()
         } else if new_parent_ref.tree() == patch_commit_ref.tree() {
             patch_commit_ref.tree()
+        } else if let Some(tree_id) = repo.merge_trees_clean(
+            old_parent_ref.tree(),
+            new_parent_ref.tree(),
+            patch_commit_ref.tree(),
+        )? {
+            tree_id
         } else {
+            // The in-process merge could not be performed cleanly. Try again with `git
+            // apply` on the temp index, and then finally resort to a worktree merge.
             let (ours, theirs) = if temp_index_tree_id == &Some(patch_commit_ref.tree()) {
                 (patch_commit_ref.tree(), new_parent_ref.tree())
             } else {
@@ -1137,8 +1481,9 @@ impl<'repo> StackTransaction<'repo> {
                 push_status = PushStatus::Empty;
             }
 
+            let meta = self.get_patch(patchname).meta.clone();
             self.updated_patches
-                .insert(patchname.clone(), Some(PatchState { commit }));
+                .insert(patchname.clone(), Some(PatchState { commit, meta }));
         }
 
         if push_status == PushStatus::Conflict {
@@ -1152,15 +1497,22 @@ impl<'repo> StackTransaction<'repo> {
             self.hidden.remove(pos);
         }
         self.applied.push(patchname.clone());
+        self.pushed.insert(patchname.clone());
 
         self.ui.print_pushed(patchname, push_status, is_last)?;
 
         if push_status == PushStatus::Conflict {
+            let msg = if self.options.resumable.is_some() {
+                "merge conflicts; \
+                 resolve conflicts manually then resume with `stg continue`, \
+                 skip the patch with `stg skip`, or abort with `stg abort`."
+            } else {
+                "merge conflicts; \
+                 resolve conflicts manually then refresh or \
+                 undo the operation with `stg undo --hard`."
+            };
             Err(Error::TransactionHalt {
-                msg: "merge conflicts; \
-                      resolve conflicts manually then refresh or \
-                      undo the operation with `stg undo --hard`."
-                    .to_string(),
+                msg: msg.to_string(),
                 conflicts: true,
             }
             .into())
@@ -1289,4 +1641,8 @@ impl<'repo> StackStateAccess<'repo> for StackTransaction<'repo> {
             self.top()
         }
     }
+
+    fn declared_dependencies(&self) -> &BTreeMap<PatchName, BTreeSet<PatchName>> {
+        &self.dependencies
+    }
 }
@@ -1,5 +1,7 @@
 // SPDX-License-Identifier: GPL-2.0-only
 
+use crate::{hook::StackHook, stack::PendingStep};
+
 /// Options for fine-tuning stack transaction behaviors.
 pub(super) struct TransactionOptions {
     pub(super) conflict_mode: ConflictMode,
@@ -9,6 +11,18 @@ pub(super) struct TransactionOptions {
     pub(super) set_head: bool,
     pub(super) allow_bad_head: bool,
     pub(super) committer_date_is_author_date: bool,
+    pub(super) resumable: Option<Resumable>,
+    pub(super) hooks: Vec<StackHook>,
+    pub(super) merged_state: Option<gix::ObjectId>,
+}
+
+/// Information for recording an interrupted operation such that it may later be
+/// resumed.
+pub(super) struct Resumable {
+    pub(super) command: String,
+    pub(super) orig_state: gix::ObjectId,
+    pub(super) autostash: bool,
+    pub(super) remaining: Vec<PendingStep>,
 }
 
 impl Default for TransactionOptions {
@@ -21,6 +35,9 @@ impl Default for TransactionOptions {
             set_head: true,
             allow_bad_head: false,
             committer_date_is_author_date: false,
+            resumable: None,
+            hooks: Vec::new(),
+            merged_state: None,
         }
     }
 }
@@ -243,6 +243,18 @@ impl TransactionUserInterface {
         Ok(())
     }
 
+    /// Print warning message to stderr, colored only if the output stream is.
+    pub(super) fn print_warning(&self, msg: &str) {
+        use is_terminal::IsTerminal;
+        let choice = if self.output.borrow().supports_color() && std::io::stderr().is_terminal() {
+            termcolor::ColorChoice::Auto
+        } else {
+            termcolor::ColorChoice::Never
+        };
+        let mut stderr = termcolor::StandardStream::stderr(choice);
+        crate::print_message("warning", termcolor::Color::Yellow, &mut stderr, msg);
+    }
+
     pub(super) fn print_updated(&self, patchname: &PatchName, applied: &[PatchName]) -> Result<()> {
         let mut output = self.output.borrow_mut();
         let (is_applied, is_top) = if let Some(pos) = applied.iter().position(|pn| pn == patchname)
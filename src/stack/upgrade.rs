@@ -2,9 +2,12 @@
 
 //! Methods for upgrading old stack state representations to the current version.
 //!
-//! The current stack state format is version 5, introduced in StGit `v1.2`.
+//! The current stack state format is version 6.
 //!
-//! This module is capable of upgrading stack state version 4 to version 5.
+//! This module is capable of upgrading stack state versions 2 through 5 to version 6.
+//! - Stack state version 6 adds optional fields to the version 5 `stack.json` format:
+//!   patch dependencies, per-patch metadata, the pending interrupted operation, the
+//!   branches of `stg publish-each`, and the state merged by `stg stack merge`.
 //! - Stack state version 5 was introduced in StGit `v1.2`.
 //! - Stack state version 4 was introduced in StGit `v1.0`.
 //! - Stack state version 3 was introduced in StGit `v0.20`.
@@ -14,20 +17,21 @@ use std::{
     collections::BTreeMap,
     fs::{remove_dir, remove_dir_all, remove_file, File, OpenOptions},
     io::{BufRead, BufReader},
+    rc::Rc,
     str::FromStr,
 };
 
 use anyhow::{anyhow, Context, Result};
 use bstr::{ByteSlice, ByteVec};
 
-use super::serde::{RawPatchState, RawStackState};
+use super::serde::{RawPatchState, RawStackState, FORMAT_VERSION};
 use crate::{ext::RepositoryExtended, patch::PatchName, stack::state::StackState};
 
 /// Upgrade stack state metadata to most recent version.
 pub(crate) fn stack_upgrade(repo: &gix::Repository, branch_name: &str) -> Result<()> {
     let version = get_format_version(repo, branch_name)?;
     match version {
-        5 => Ok(()),
+        5 => Ok(()), // upgraded by read_stack_state()
         4 => stack_upgrade_from_4(repo, branch_name),
         3 => stack_upgrade_from_3(repo, branch_name),
         2 => stack_upgrade_from_2(repo, branch_name),
@@ -38,6 +42,9 @@ pub(crate) fn stack_upgrade(repo: &gix::Repository, branch_name: &str) -> Result
 }
 
 /// Get current format version
+///
+/// Version 5 is reported for all `stack.json` based formats. Those are upgraded
+/// when the stack state is read, see [`read_stack_state()`].
 fn get_format_version(repo: &gix::Repository, branch_name: &str) -> Result<i64> {
     let refname_v5 = state_refname_from_branch_name_v5(branch_name);
 
@@ -83,7 +90,36 @@ fn get_format_version(repo: &gix::Repository, branch_name: &str) -> Result<i64>
     Ok(-1)
 }
 
-/// Upgrade from 4 to 5
+/// Read stack state from a `stack.json` based state commit, upgrading it to the current
+/// format version if needed.
+///
+/// Version 6 only adds optional fields to the version 5 format, thus a version 5
+/// state is upgraded by simply recording it anew using the current format.
+pub(crate) fn read_stack_state<'repo>(
+    repo: &'repo gix::Repository,
+    branch_name: &str,
+    state_commit: gix::Commit<'repo>,
+) -> Result<StackState<'repo>> {
+    let raw_state = StackState::read_raw(state_commit.tree()?)?;
+    let version = raw_state.version;
+    let state = StackState::from_raw_state(repo, raw_state)?;
+    if version == FORMAT_VERSION {
+        return Ok(state);
+    }
+
+    let refname = state_refname_from_branch_name_v5(branch_name);
+    let head = state.head.clone();
+    let state = state.advance_head(head, Rc::new(state_commit));
+    state.commit(
+        repo,
+        Some(&refname),
+        &format!("stack upgrade to version {FORMAT_VERSION}"),
+    )?;
+    eprintln!("Upgraded {branch_name} to stack format version {FORMAT_VERSION}");
+    Ok(state)
+}
+
+/// Upgrade from 4 to the current version
 fn stack_upgrade_from_4(repo: &gix::Repository, branch_name: &str) -> Result<()> {
     let refname_v4 = state_refname_from_branch_name_v4(branch_name);
 
@@ -137,7 +173,13 @@ fn stack_upgrade_from_4(repo: &gix::Repository, branch_name: &str) -> Result<()>
                                     format!("converting `{oid_str}` for `{patchname}`")
                                 })?;
                             patch_list.push(patchname.clone());
-                            patches.insert(patchname, RawPatchState { oid: commit_id });
+                            patches.insert(
+                                patchname,
+                                RawPatchState {
+                                    oid: commit_id,
+                                    meta: BTreeMap::new(),
+                                },
+                            );
                         }
                     } else {
                         return Err(anyhow!("malformed metadata"));
@@ -175,36 +217,45 @@ fn stack_upgrade_from_4(repo: &gix::Repository, branch_name: &str) -> Result<()>
             }
 
             let raw_stack_state = RawStackState {
+                version: FORMAT_VERSION,
                 prev: None,
+                merged: None,
                 head: head.unwrap(),
                 applied,
                 unapplied,
                 hidden,
                 patches,
+                dependencies: BTreeMap::new(),
+                pending: None,
+                published: BTreeMap::new(),
             };
 
             let state = StackState::from_raw_state(repo, raw_stack_state)?;
-            let new_state_commit_id = state.commit(repo, None, "stack upgrade to version 5")?;
+            let new_state_commit_id = state.commit(
+                repo,
+                None,
+                &format!("stack upgrade to version {FORMAT_VERSION}"),
+            )?;
             let refname = state_refname_from_branch_name_v5(branch_name);
             repo.reference(
                 refname.as_str(),
                 new_state_commit_id,
                 gix::refs::transaction::PreviousValue::MustNotExist,
-                "stack upgrade to version 5",
+                format!("stack upgrade to version {FORMAT_VERSION}"),
             )
             .with_context(|| format!("creating `{refname}`"))?;
 
             stack_ref_v4
                 .delete()
                 .with_context(|| format!("deleting old `{refname_v4}` ref"))?;
-            eprintln!("Upgraded {branch_name} to stack format version 5");
+            eprintln!("Upgraded {branch_name} to stack format version {FORMAT_VERSION}");
         };
     }
 
     Ok(())
 }
 
-/// Upgrade from 3 to 5
+/// Upgrade from 3 to the current version
 fn stack_upgrade_from_3(repo: &gix::Repository, branch_name: &str) -> Result<()> {
     let branch_dir = repo.git_dir().join("patches").join(branch_name);
     let applied_file = branch_dir.join("applied");
@@ -253,28 +304,43 @@ fn stack_upgrade_from_3(repo: &gix::Repository, branch_name: &str) -> Result<()>
                     .with_context(|| format!("converting `{}` to patchname", &pn))?;
                 patch_list.push(patchname.clone());
                 cleanup.push(format!("refs/patches/{branch_name}/{pn}.log"));
-                patches.insert(patchname, RawPatchState { oid: commit_id });
+                patches.insert(
+                    patchname,
+                    RawPatchState {
+                        oid: commit_id,
+                        meta: BTreeMap::new(),
+                    },
+                );
             }
         }
     }
 
     let raw_stack_state = RawStackState {
+        version: FORMAT_VERSION,
         prev: None,
+        merged: None,
         head: head.unwrap(),
         applied,
         unapplied,
         hidden,
         patches,
+        dependencies: BTreeMap::new(),
+        pending: None,
+        published: BTreeMap::new(),
     };
 
     let state = StackState::from_raw_state(repo, raw_stack_state)?;
-    let new_state_commit_id = state.commit(repo, None, "stack upgrade to version 5")?;
+    let new_state_commit_id = state.commit(
+        repo,
+        None,
+        &format!("stack upgrade to version {FORMAT_VERSION}"),
+    )?;
     let refname = state_refname_from_branch_name_v5(branch_name);
     repo.reference(
         refname.as_str(),
         new_state_commit_id,
         gix::refs::transaction::PreviousValue::MustNotExist,
-        "stack upgrade to version 5",
+        format!("stack upgrade to version {FORMAT_VERSION}"),
     )
     .with_context(|| format!("creating `{refname}`"))?;
 
@@ -293,12 +359,12 @@ fn stack_upgrade_from_3(repo: &gix::Repository, branch_name: &str) -> Result<()>
     // .git/patches will be removed after the last stack is converted
     remove_dir(repo.git_dir().join("patches")).ok();
 
-    eprintln!("Upgraded {branch_name} to stack format version 5");
+    eprintln!("Upgraded {branch_name} to stack format version {FORMAT_VERSION}");
 
     Ok(())
 }
 
-/// Upgrade from 2 to 5
+/// Upgrade from 2 to the current version
 fn stack_upgrade_from_2(repo: &gix::Repository, branch_name: &str) -> Result<()> {
     let branch_dir = repo.git_dir().join("patches").join(branch_name);
     let protect_file = branch_dir.join("protected");
@@ -358,7 +424,7 @@ fn state_refname_from_branch_name_v4(branch_name: &str) -> String {
     format!("refs/heads/{branch_name}.stgit")
 }
 
-/// Get stack state version 5 stack state reference.
+/// Get stack state reference for version 5 and later.
 fn state_refname_from_branch_name_v5(branch_name: &str) -> String {
     format!("refs/stacks/{branch_name}")
 }
@@ -9,7 +9,7 @@ use std::{
     ffi::{OsStr, OsString},
     io::Write,
     path::Path,
-    process::{Command, Stdio},
+    process::{Command, ExitStatus, Stdio},
 };
 
 use anyhow::{anyhow, Context, Result};
@@ -17,7 +17,7 @@ use bstr::{BStr, BString, ByteSlice, ByteVec};
 
 use super::{
     command::{git_command_error, StupidCommand, StupidExitStatus, StupidOutput},
-    diff::DiffFiles,
+    diff::{parse_diff, DiffFiles, FileDiff},
     oid::parse_oid,
     status::{StatusOptions, Statuses},
     tempindex::TempIndex,
@@ -121,6 +121,16 @@ impl StupidContext<'_, '_> {
         Ok(())
     }
 
+    /// Apply a diff with zero lines of context to the index.
+    pub(crate) fn apply_unidiff_zero_to_index(&self, diff: &BStr) -> Result<()> {
+        self.git_in_work_root()?
+            .args(["apply", "--cached", "--unidiff-zero"])
+            .stdout(Stdio::null())
+            .in_and_out(diff)?
+            .require_success("apply --unidiff-zero")?;
+        Ok(())
+    }
+
     /// Apply a patch (diff) to both the index and the working tree.
     ///
     /// Returns `None` if the patch applies cleanly or output of the `git apply`
@@ -582,6 +592,31 @@ impl StupidContext<'_, '_> {
         Ok(BString::from(output.stdout))
     }
 
+    /// Get status letters and paths of files differing between two trees.
+    ///
+    /// Uses `git diff-tree -r --name-status -z`. Renames are not detected.
+    pub(crate) fn diff_tree_name_status(
+        &self,
+        tree1: gix::ObjectId,
+        tree2: gix::ObjectId,
+    ) -> Result<Vec<(BString, BString)>> {
+        let output = self
+            .git()
+            .args(["diff-tree", "-r", "--name-status", "--no-renames", "-z"])
+            .args([tree1.to_string(), tree2.to_string()])
+            .output_git()?
+            .require_success("diff-tree")?;
+        let mut fields = output.stdout.split_str(b"\0");
+        let mut entries = Vec::new();
+        while let Some(status) = fields.next().filter(|status| !status.is_empty()) {
+            let path = fields
+                .next()
+                .ok_or_else(|| anyhow!("unexpected diff-tree output"))?;
+            entries.push((BString::from(status), BString::from(path)));
+        }
+        Ok(entries)
+    }
+
     /// Generate diff between two trees using `git diff-tree -p`.
     pub(crate) fn diff_tree_patch<SpecIter, SpecArg, OptIter, OptArg>(
         &self,
@@ -614,6 +649,29 @@ impl StupidContext<'_, '_> {
         Ok(BString::from(output.stdout))
     }
 
+    /// Get per-file diffs between two trees using `git diff-tree -p`.
+    ///
+    /// Renames are not detected, thus each [`FileDiff`] pertains to a single path. The
+    /// number of context lines surrounding each hunk is determined by `context_lines`.
+    pub(crate) fn diff_tree_file_diffs(
+        &self,
+        tree1: gix::ObjectId,
+        tree2: gix::ObjectId,
+        context_lines: usize,
+    ) -> Result<Vec<FileDiff>> {
+        if tree1 == tree2 {
+            return Ok(Vec::new());
+        }
+        let output = self
+            .git()
+            .args(["diff-tree", "-p", "-r", "--no-renames", "--no-color"])
+            .arg(format!("--unified={context_lines}"))
+            .args([tree1.to_string(), tree2.to_string()])
+            .output_git()?
+            .require_success("diff-tree")?;
+        parse_diff(&output.stdout)
+    }
+
     /// Get unmerged path list using `git diff --name-only --diff-filter=U`.
     ///
     /// The returned unmerged paths are relative to the work tree root regardless of the
@@ -634,6 +692,22 @@ impl StupidContext<'_, '_> {
         Ok(paths)
     }
 
+    /// Fetch refspecs from a remote repository using `git fetch`.
+    pub(crate) fn fetch<SpecIter, SpecArg>(&self, remote: &str, refspecs: SpecIter) -> Result<()>
+    where
+        SpecIter: IntoIterator<Item = SpecArg>,
+        SpecArg: AsRef<OsStr>,
+    {
+        self.git()
+            .args(["fetch", "--quiet", "--no-tags", "--no-write-fetch-head"])
+            .arg(remote)
+            .args(refspecs)
+            .stdout(Stdio::null())
+            .output_git()?
+            .require_success("fetch")?;
+        Ok(())
+    }
+
     /// Run `git format-patch` with arbitrary arguments.
     pub(crate) fn format_patch<OptIter, OptArg>(&self, args: OptIter) -> Result<()>
     where
@@ -746,13 +820,38 @@ impl StupidContext<'_, '_> {
         Ok(())
     }
 
+    /// Get the id a reference points to in a remote repository using `git ls-remote`.
+    ///
+    /// Returns `None` if the remote repository does not have the reference.
+    pub(crate) fn ls_remote(&self, remote: &str, refname: &str) -> Result<Option<gix::ObjectId>> {
+        let output = self
+            .git()
+            .args(["ls-remote", "--quiet", "--refs"])
+            .arg(remote)
+            .arg(refname)
+            .output_git()?
+            .require_success("ls-remote")?;
+        for line in output.stdout.lines() {
+            if let Some((oid, name)) = line.split_once_str(b"\t") {
+                if name == refname.as_bytes() {
+                    return parse_oid(oid).map(Some);
+                }
+            }
+        }
+        Ok(None)
+    }
+
     pub(crate) fn mailinfo(
         &self,
         input: Option<std::fs::File>,
+        keep_subject: bool,
         copy_message_id: bool,
     ) -> Result<(BString, BString, BString)> {
         let mut command = self.git();
         command.args(["mailinfo", "--scissors", "--encoding=UTF-8"]);
+        if keep_subject {
+            command.arg("-k");
+        }
         if copy_message_id {
             command.arg("--message-id");
         }
@@ -903,6 +1002,89 @@ impl StupidContext<'_, '_> {
         Ok(())
     }
 
+    /// Push refspecs to a remote repository atomically using `git push --atomic`.
+    ///
+    /// With `prune`, remote references matching the refspecs' patterns that do not
+    /// exist locally are deleted.
+    pub(crate) fn push_atomic<SpecIter, SpecArg>(
+        &self,
+        remote: &str,
+        refspecs: SpecIter,
+        prune: bool,
+    ) -> Result<()>
+    where
+        SpecIter: IntoIterator<Item = SpecArg>,
+        SpecArg: AsRef<OsStr>,
+    {
+        let mut command = self.git();
+        command.args(["push", "--atomic", "--quiet"]);
+        if prune {
+            command.arg("--prune");
+        }
+        command
+            .arg(remote)
+            .args(refspecs)
+            .stdout(Stdio::null())
+            .output_git()?
+            .require_success("push")?;
+        Ok(())
+    }
+
+    /// Force push refspecs to a remote repository using `git push`.
+    pub(crate) fn push_force<SpecIter, SpecArg>(
+        &self,
+        remote: &str,
+        refspecs: SpecIter,
+    ) -> Result<()>
+    where
+        SpecIter: IntoIterator<Item = SpecArg>,
+        SpecArg: AsRef<OsStr>,
+    {
+        self.git()
+            .args(["push", "--force", "--quiet"])
+            .arg(remote)
+            .args(refspecs)
+            .stdout(Stdio::null())
+            .output_git()?
+            .require_success("push")?;
+        Ok(())
+    }
+
+    /// Compare two single-commit ranges using `git range-diff`.
+    ///
+    /// The two commits are always paired-up, regardless of how different their diffs
+    /// are. The output is returned verbatim, including the leading line summarizing the
+    /// pairing.
+    pub(crate) fn range_diff<OptIter, OptArg>(
+        &self,
+        old_commit_id: gix::ObjectId,
+        new_commit_id: gix::ObjectId,
+        use_color: bool,
+        diff_opts: OptIter,
+    ) -> Result<BString>
+    where
+        OptIter: IntoIterator<Item = OptArg>,
+        OptArg: AsRef<OsStr>,
+    {
+        let output = self
+            .git()
+            .arg("range-diff")
+            .arg(if use_color {
+                "--color=always"
+            } else {
+                "--color=never"
+            })
+            // A single pair of commits are being compared and must never be presented
+            // as a removal and creation.
+            .arg("--creation-factor=10000")
+            .args(diff_opts)
+            .arg(format!("{old_commit_id}^!"))
+            .arg(format!("{new_commit_id}^!"))
+            .output_git()?
+            .require_success("range-diff")?;
+        Ok(BString::from(output.stdout))
+    }
+
     /// Read content of a tree into specified index using `git read-tree`.
     pub(crate) fn read_tree(&self, tree_id: gix::ObjectId) -> Result<()> {
         self.git_in_work_root()?
@@ -956,6 +1138,27 @@ impl StupidContext<'_, '_> {
         Ok(())
     }
 
+    /// Reset the index entries of the given paths to their state in a tree-ish.
+    pub(crate) fn reset_paths<SpecIter, SpecArg>(
+        &self,
+        tree_ish: gix::ObjectId,
+        pathspecs: SpecIter,
+    ) -> Result<()>
+    where
+        SpecIter: IntoIterator<Item = SpecArg>,
+        SpecArg: AsRef<OsStr>,
+    {
+        self.git_in_work_root()?
+            .args(["reset", "--quiet"])
+            .arg(tree_ish.to_string())
+            .arg("--")
+            .args(pathspecs)
+            .stdout(Stdio::null())
+            .output_git()?
+            .require_success("reset")?;
+        Ok(())
+    }
+
     /// Get list of revisions using `git rev-list`.
     pub(crate) fn rev_list<SpecIter, SpecArg>(
         &self,
@@ -987,6 +1190,42 @@ impl StupidContext<'_, '_> {
         Ok(oids)
     }
 
+    /// Get ids of commits reachable from commit, optionally limited to those touching
+    /// the given pathspecs and to a maximum number of commits.
+    pub(crate) fn rev_list_from<SpecIter, SpecArg>(
+        &self,
+        commit_id: gix::ObjectId,
+        pathspecs: Option<SpecIter>,
+        num_commits: Option<usize>,
+    ) -> Result<Vec<gix::ObjectId>>
+    where
+        SpecIter: IntoIterator<Item = SpecArg>,
+        SpecArg: AsRef<OsStr>,
+    {
+        let mut command = self.git();
+        command.arg("rev-list");
+        if let Some(n) = num_commits {
+            command.arg(format!("--max-count={n}"));
+        }
+        command.arg(commit_id.to_string());
+
+        command.arg("--");
+        if let Some(pathspecs) = pathspecs {
+            command.args(pathspecs);
+        }
+
+        let output = command.output_git()?.require_success("rev-list")?;
+        let mut oids: Vec<gix::ObjectId> = Vec::new();
+        for line in output
+            .stdout
+            .split_str("\n")
+            .filter(|line| !line.is_empty())
+        {
+            oids.push(parse_oid(line)?);
+        }
+        Ok(oids)
+    }
+
     /// Get `cdup` for current directory from `git rev-parse --show-cdup`.
     pub(crate) fn rev_parse_cdup(&self) -> Result<OsString> {
         let output = self
@@ -1319,6 +1558,35 @@ impl StupidContext<'_, '_> {
         }
     }
 
+    /// Run user-provided shell command from the top-level of the work tree.
+    pub(crate) fn user_exec(&self, user_cmd_str: &str) -> Result<()> {
+        let status = self.user_exec_status(user_cmd_str)?;
+        if status.success() {
+            Ok(())
+        } else if let Some(code) = status.code() {
+            Err(anyhow!("`{user_cmd_str}` exited with code {code}"))
+        } else {
+            Err(anyhow!("`{user_cmd_str}` failed"))
+        }
+    }
+
+    /// Run user-provided shell command and return its exit status.
+    ///
+    /// Unlike [`Self::user_exec()`], a non-zero exit is not treated as an error.
+    pub(crate) fn user_exec_status(&self, user_cmd_str: &str) -> Result<ExitStatus> {
+        let mut command = Command::new("sh");
+        if let Some(work_dir) = self.work_dir {
+            command.current_dir(work_dir);
+        }
+        command
+            .arg("-c")
+            .arg(user_cmd_str)
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .with_context(|| format!("could not execute `{user_cmd_str}`"))
+    }
+
     /// Run user-provided rebase command.
     pub(crate) fn user_rebase(&self, user_cmd_str: &str, target: gix::ObjectId) -> Result<()> {
         let mut args = user_cmd_str.split(|c: char| c.is_ascii_whitespace());
@@ -4,7 +4,8 @@
 
 use std::path::Path;
 
-use bstr::ByteSlice;
+use anyhow::{anyhow, Result};
+use bstr::{BStr, BString, ByteSlice};
 
 /// Diff output containing only names of differing files.
 ///
@@ -52,6 +53,238 @@ impl<'a> Iterator for DiffFilesIter<'a> {
     }
 }
 
+/// Diff of a single file parsed from unified diff output.
+///
+/// E.g. from `git diff-tree -p`
+#[derive(Clone, Debug)]
+pub(crate) struct FileDiff {
+    /// Header lines preceding the first hunk, starting with the `diff --git` line.
+    pub(crate) header: BString,
+
+    /// Path of the file before the change or `None` if the file is added.
+    pub(crate) old_path: Option<BString>,
+
+    /// Path of the file after the change or `None` if the file is deleted.
+    pub(crate) new_path: Option<BString>,
+
+    /// Whether the file's content is binary, in which case there are no hunks.
+    pub(crate) is_binary: bool,
+
+    /// The file's hunks, in order.
+    pub(crate) hunks: Vec<Hunk>,
+}
+
+/// A single hunk from a unified diff.
+#[derive(Clone, Debug)]
+pub(crate) struct Hunk {
+    /// First line of the hunk in the old file. When `old_lines` is zero, this is the
+    /// line after which the hunk's lines are inserted.
+    pub(crate) old_start: usize,
+
+    /// Number of lines from the old file covered by the hunk.
+    pub(crate) old_lines: usize,
+
+    /// First line of the hunk in the new file.
+    #[allow(unused)]
+    pub(crate) new_start: usize,
+
+    /// Number of lines in the new file covered by the hunk.
+    pub(crate) new_lines: usize,
+
+    /// Complete text of the hunk, including the `@@` line.
+    pub(crate) text: BString,
+}
+
+impl FileDiff {
+    /// Get the path of the file, preferring the new path.
+    pub(crate) fn path(&self) -> &BStr {
+        self.new_path
+            .as_ref()
+            .or(self.old_path.as_ref())
+            .expect("file diff has at least one path")
+            .as_bstr()
+    }
+
+    /// Compose a diff for this file containing only the selected hunks.
+    ///
+    /// The hunks' line numbers are not adjusted, thus the resulting diff must be
+    /// applied with `git apply --recount` or with zero context and `--unidiff-zero`.
+    pub(crate) fn to_diff_with_hunks<'a>(
+        &self,
+        hunks: impl IntoIterator<Item = &'a Hunk>,
+    ) -> BString {
+        let mut diff = self.header.clone();
+        for hunk in hunks {
+            diff.extend_from_slice(&hunk.text);
+        }
+        diff
+    }
+
+    /// Compose a diff containing this file's complete set of changes.
+    #[allow(unused)]
+    pub(crate) fn to_diff(&self) -> BString {
+        self.to_diff_with_hunks(&self.hunks)
+    }
+}
+
+/// Parse unified diff output into per-file diffs.
+///
+/// The diff is expected to be produced by `git diff-tree -p` or similar with
+/// `--no-color` and with renames disabled.
+pub(crate) fn parse_diff(diff: &[u8]) -> Result<Vec<FileDiff>> {
+    let mut files: Vec<FileDiff> = Vec::new();
+
+    for line in diff.lines_with_terminator() {
+        if line.starts_with(b"diff --git ") {
+            let (old_path, new_path) = parse_diff_git_line(&line[b"diff --git ".len()..])?;
+            files.push(FileDiff {
+                header: BString::from(line),
+                old_path: Some(old_path),
+                new_path: Some(new_path),
+                is_binary: false,
+                hunks: Vec::new(),
+            });
+            continue;
+        }
+
+        let file = files
+            .last_mut()
+            .ok_or_else(|| anyhow!("diff content before `diff --git` line"))?;
+
+        if let Some(hunk) = file.hunks.last_mut() {
+            if !line.starts_with(b"@@ ") {
+                hunk.text.extend_from_slice(line);
+                continue;
+            }
+        }
+
+        if line.starts_with(b"@@ ") {
+            let (old_start, old_lines, new_start, new_lines) = parse_hunk_header(line)?;
+            file.hunks.push(Hunk {
+                old_start,
+                old_lines,
+                new_start,
+                new_lines,
+                text: BString::from(line),
+            });
+        } else {
+            file.header.extend_from_slice(line);
+            let trimmed = line.trim_end_with(|c| c == '\n');
+            if trimmed.starts_with(b"new file mode ") {
+                file.old_path = None;
+            } else if trimmed.starts_with(b"deleted file mode ") {
+                file.new_path = None;
+            } else if trimmed.starts_with(b"Binary files ") || trimmed == b"GIT binary patch" {
+                file.is_binary = true;
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Parse the paths from a `diff --git a/<old> b/<new>` line.
+fn parse_diff_git_line(paths: &[u8]) -> Result<(BString, BString)> {
+    let paths = paths.trim_end_with(|c| c == '\n');
+    if paths.starts_with(b"\"") {
+        let (old_path, rest) = unquote_path(paths)?;
+        let rest = rest.trim_start();
+        let (new_path, _) = if rest.starts_with(b"\"") {
+            unquote_path(rest)?
+        } else {
+            (BString::from(rest), &b""[..])
+        };
+        Ok((strip_prefix(old_path), strip_prefix(new_path)))
+    } else if paths.ends_with(b"\"") {
+        let start = paths
+            .rfind(b" \"")
+            .ok_or_else(|| anyhow!("malformed diff header `{}`", paths.as_bstr()))?;
+        let (new_path, _) = unquote_path(&paths[start + 1..])?;
+        let old_path = BString::from(&paths[..start]);
+        Ok((strip_prefix(old_path), strip_prefix(new_path)))
+    } else {
+        // Without renames, both paths are the same length: `a/<path> b/<path>`.
+        if paths.len() < 5 || (paths.len() - 5) % 2 != 0 {
+            return Err(anyhow!("malformed diff header `{}`", paths.as_bstr()));
+        }
+        let path_len = (paths.len() - 5) / 2;
+        let old_path = BString::from(&paths[..path_len + 2]);
+        let new_path = BString::from(&paths[path_len + 3..]);
+        Ok((strip_prefix(old_path), strip_prefix(new_path)))
+    }
+}
+
+/// Strip the `a/` or `b/` prefix from a diff path.
+fn strip_prefix(path: BString) -> BString {
+    if path.starts_with(b"a/") || path.starts_with(b"b/") {
+        BString::from(&path[2..])
+    } else {
+        path
+    }
+}
+
+/// Unquote a C-style quoted path, returning the path and the remaining bytes.
+fn unquote_path(quoted: &[u8]) -> Result<(BString, &[u8])> {
+    let mut path = BString::from("");
+    let mut iter = quoted.iter().enumerate().skip(1);
+    while let Some((i, &c)) = iter.next() {
+        match c {
+            b'"' => return Ok((path, &quoted[i + 1..])),
+            b'\\' => {
+                let (_, &escaped) = iter
+                    .next()
+                    .ok_or_else(|| anyhow!("malformed quoted path"))?;
+                match escaped {
+                    b'n' => path.push(b'\n'),
+                    b't' => path.push(b'\t'),
+                    b'"' => path.push(b'"'),
+                    b'\\' => path.push(b'\\'),
+                    b'0'..=b'7' => {
+                        let mut value = u32::from(escaped - b'0');
+                        for _ in 0..2 {
+                            let digit = iter
+                                .next()
+                                .map(|(_, &digit)| digit)
+                                .filter(|digit| (b'0'..=b'7').contains(digit))
+                                .ok_or_else(|| anyhow!("malformed quoted path"))?;
+                            value = value * 8 + u32::from(digit - b'0');
+                        }
+                        path.push(u8::try_from(value)?);
+                    }
+                    other => path.push(other),
+                }
+            }
+            _ => path.push(c),
+        }
+    }
+    Err(anyhow!("unterminated quoted path"))
+}
+
+/// Parse `@@ -<start>[,<lines>] +<start>[,<lines>] @@` hunk header.
+fn parse_hunk_header(line: &[u8]) -> Result<(usize, usize, usize, usize)> {
+    let malformed = || anyhow!("malformed hunk header `{}`", line.trim_end().as_bstr());
+    let line = line.to_str().map_err(|_| malformed())?;
+    let mut parts = line[3..].split(' ');
+    let old = parts
+        .next()
+        .and_then(|s| s.strip_prefix('-'))
+        .ok_or_else(malformed)?;
+    let new = parts
+        .next()
+        .and_then(|s| s.strip_prefix('+'))
+        .ok_or_else(malformed)?;
+    let parse_range = |range: &str| -> Option<(usize, usize)> {
+        if let Some((start, lines)) = range.split_once(',') {
+            Some((start.parse().ok()?, lines.parse().ok()?))
+        } else {
+            Some((range.parse().ok()?, 1))
+        }
+    };
+    let (old_start, old_lines) = parse_range(old).ok_or_else(malformed)?;
+    let (new_start, new_lines) = parse_range(new).ok_or_else(malformed)?;
+    Ok((old_start, old_lines, new_start, new_lines))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,4 +298,65 @@ mod tests {
         assert_eq!(it.next(), Some(Path::new("jkl")));
         assert!(it.next().is_none());
     }
+
+    #[test]
+    fn parse_file_diffs() {
+        let diff = b"diff --git a/foo.txt b/foo.txt\n\
+                     index 1234567..89abcde 100644\n\
+                     --- a/foo.txt\n\
+                     +++ b/foo.txt\n\
+                     @@ -1 +1,2 @@\n\
+                     -a\n\
+                     +b\n\
+                     +c\n\
+                     @@ -10,0 +11 @@ ctx\n\
+                     +d\n\
+                     diff --git a/new file b/new file\n\
+                     new file mode 100644\n\
+                     index 0000000..1234567\n\
+                     --- /dev/null\n\
+                     +++ b/new file\n\
+                     @@ -0,0 +1 @@\n\
+                     +x\n\
+                     diff --git \"a/bin\\303\\244\" \"b/bin\\303\\244\"\n\
+                     index 1234567..89abcde 100644\n\
+                     Binary files \"a/bin\\303\\244\" and \"b/bin\\303\\244\" differ\n";
+        let files = parse_diff(diff).unwrap();
+        assert_eq!(files.len(), 3);
+
+        assert_eq!(files[0].path(), "foo.txt");
+        assert_eq!(files[0].hunks.len(), 2);
+        assert_eq!(
+            (
+                files[0].hunks[0].old_start,
+                files[0].hunks[0].old_lines,
+                files[0].hunks[0].new_start,
+                files[0].hunks[0].new_lines
+            ),
+            (1, 1, 1, 2)
+        );
+        assert_eq!(files[0].hunks[1].old_start, 10);
+        assert_eq!(files[0].hunks[1].old_lines, 0);
+        assert_eq!(files[0].hunks[1].text, "@@ -10,0 +11 @@ ctx\n+d\n");
+        assert!(files[0].header.ends_with(b"+++ b/foo.txt\n"));
+
+        assert!(files[1].old_path.is_none());
+        assert_eq!(files[1].path(), "new file");
+        assert_eq!(files[1].hunks.len(), 1);
+
+        assert!(files[2].is_binary);
+        assert_eq!(files[2].path(), "bin\u{e4}");
+        assert!(files[2].hunks.is_empty());
+    }
+
+    #[test]
+    fn unquote_malformed_octal() {
+        assert_eq!(
+            unquote_path(b"\"a\\303\\244\" rest").unwrap(),
+            (BString::from("a\u{e4}"), &b" rest"[..])
+        );
+        assert!(unquote_path(b"\"a\\3x4\"").is_err());
+        assert!(unquote_path(b"\"a\\39\"").is_err());
+        assert!(unquote_path(b"\"a\\3").is_err());
+    }
 }
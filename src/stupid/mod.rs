@@ -18,6 +18,7 @@ use std::cell::RefCell;
 
 pub(crate) use self::{
     context::StupidContext,
+    diff::{FileDiff, Hunk},
     status::{Status, StatusOptions, Statuses},
 };
 
@@ -136,6 +136,88 @@ test_expect_success 'Log with number' '
     head -n 3 log.txt | tail -n 1 | grep -e "refresh"
 '
 
+test_expect_success 'Prune without retention policy' '
+    command_error stg log --prune 2>err &&
+    grep -e "no stack log retention policy" err &&
+    general_error stg log --prune --clear 2>err &&
+    grep -e "the argument .--prune. cannot be used with .--clear." err
+'
+
+test_expect_success 'Prune with invalid retention policy' '
+    test_config stgit.log.maxEntries 0 &&
+    command_error stg log --prune 2>err &&
+    grep -e "\`stgit.log.maxEntries\` must be a positive integer" err &&
+    test_unconfig stgit.log.maxEntries &&
+    test_config stgit.log.maxAge "a while" &&
+    command_error stg log --prune 2>err &&
+    grep -e "invalid \`stgit.log.maxAge\` value \`a while\`" err
+'
+
+test_expect_success 'Prune by number of entries' '
+    stg pop -n 2 &&
+    stg hide p3 &&
+    test_config stgit.log.maxEntries 3 &&
+    stg log --prune 2>err &&
+    grep -e "Pruned [0-9]* stack states from the log" err &&
+    stg log >log.txt &&
+    test_line_count = 3 log.txt &&
+    head -n 1 log.txt | grep -e "hide" &&
+    stg log --prune 2>err &&
+    grep -e "Pruned 0 stack states from the log" err
+'
+
+test_expect_success 'Pruned log retains unapplied and hidden patches' '
+    git gc --prune=now --quiet &&
+    stg undo &&
+    test "$(echo $(stg series --noprefix --all))" = "p0 p1 p2 p3" &&
+    stg undo &&
+    test "$(echo $(stg series --noprefix --applied))" = "p0 p1 p2 p3" &&
+    command_error stg undo 2>err &&
+    grep -e "not enough undo information available" err &&
+    stg pop -n 2 &&
+    stg hide p3 &&
+    test_config stgit.log.maxEntries 1 &&
+    stg log --prune &&
+    git gc --prune=now --quiet &&
+    stg unhide p3 &&
+    stg push -a &&
+    test "$(cat file3.txt)" = "foo3"
+'
+
+test_expect_success 'Prune by age' '
+    test_config stgit.log.maxAge "1 day" &&
+    stg log --prune &&
+    stg log >log.txt &&
+    test_line_count = 1 log.txt &&
+    head -n 1 log.txt | grep -e "push"
+'
+
+test_expect_success 'Prune the history of merged states' '
+    base_state=$(git rev-parse refs/stacks/master) &&
+    base_head=$(git rev-parse HEAD) &&
+    stg new -m other other &&
+    other_state=$(git rev-parse refs/stacks/master) &&
+    git update-ref refs/stacks/master $base_state &&
+    git reset --hard $base_head &&
+    stg new -m local local &&
+    stg stack merge $other_state &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2 p3 local other" &&
+    git rev-list refs/stacks/master >reachable &&
+    grep $other_state reachable &&
+    grep $base_state reachable &&
+    test_config stgit.log.maxEntries 1 &&
+    stg log --prune &&
+    git rev-list refs/stacks/master >reachable &&
+    ! grep $other_state reachable &&
+    ! grep $base_state reachable &&
+    stg log >log.txt &&
+    test_line_count = 2 log.txt &&
+    head -n 1 log.txt | grep -e "stack merge" &&
+    tail -n 1 log.txt | grep -e "new: other" &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p2 p3 local other" &&
+    stg delete local other
+'
+
 test_expect_success 'Clear the log' '
     stg log --clear &&
     test "$(echo $(stg series --noprefix))" = "p0 p1 p2 p3" &&
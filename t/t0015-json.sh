@@ -0,0 +1,107 @@
+#!/bin/sh
+
+test_description='Test JSON output of inspection commands'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    test_commit_bulk --message="p%s" 3 &&
+    stg uncommit -n 3 &&
+    stg new -m "empty patch" p4 &&
+    stg pop p3 p4 &&
+    stg hide p4 &&
+    stg meta set p1 status ready
+'
+
+test_expect_success 'Invalid format' '
+    general_error stg series --format=xml 2>err &&
+    grep "invalid value .xml. for .--format <format>." err
+'
+
+test_expect_success 'Series as JSON' '
+    stg series --format=json >out &&
+    grep "\"branch\": \"master\"" out &&
+    test "$(grep -c "^      \"name\":" out)" = "3" &&
+    grep -A2 "\"name\": \"p2\"" out | grep "\"status\": \"applied\"" &&
+    grep -A3 "\"name\": \"p2\"" out | grep "\"top\": true" &&
+    grep -A2 "\"name\": \"p3\"" out | grep "\"status\": \"unapplied\"" &&
+    grep "\"commit_id\": \"$(stg id p1)\"" out &&
+    grep "\"description\": \"p1\"" out &&
+    grep "\"status\": \"ready\"" out &&
+    grep "\"email\": \"author@example.com\"" out
+'
+
+test_expect_success 'Series as JSON respects selection options' '
+    stg series --format=json --all >out &&
+    grep -A2 "\"name\": \"p4\"" out | grep "\"status\": \"hidden\"" &&
+    grep -A4 "\"name\": \"p4\"" out | grep "\"empty\": true" &&
+    stg series --format=json --applied >out &&
+    test "$(grep -c "^      \"name\":" out)" = "2" &&
+    stg series --format=json --description --author >out2 &&
+    stg series --format=json >out &&
+    test_cmp out out2
+'
+
+test_expect_success 'Series JSON conflicts with count' '
+    general_error stg series --format=json --count
+'
+
+test_expect_success 'Files as JSON' '
+    stg files --format=json p1 >out &&
+    grep "\"commit_id\": \"$(stg id p1)\"" out &&
+    grep "\"status\": \"A\"" out &&
+    grep "\"path\": \"1.t\"" out &&
+    general_error stg files --format=json --stat p1
+'
+
+test_expect_success 'Id as JSON' '
+    cat >expected <<-EOF &&
+	{
+	  "id": "$(stg id p2)"
+	}
+	EOF
+    stg id --format=json p2 >out &&
+    test_cmp expected out
+'
+
+test_expect_success 'Show as JSON' '
+    stg show --format=json p1 p3 >out &&
+    test "$(grep -c "^      \"commit_id\":" out)" = "2" &&
+    grep -A2 "\"name\": \"p1\"" out | grep "\"status\": \"applied\"" &&
+    grep -A2 "\"name\": \"p3\"" out | grep "\"status\": \"unapplied\"" &&
+    grep "\"message\": \"p1\\\\n\"" out &&
+    grep "\"diff\": \"diff --git a/1.t b/1.t" out &&
+    general_error stg show --format=json --stat p1
+'
+
+test_expect_success 'Show non-patch revision as JSON' '
+    stg show --format=json HEAD~2 >out &&
+    grep "\"patch\": null" out
+'
+
+test_expect_success 'Patches as JSON' '
+    stg patches --format=json 2.t >out &&
+    test "$(grep -c "^      \"name\":" out)" = "1" &&
+    grep "\"name\": \"p2\"" out
+'
+
+test_expect_success 'Branch list as JSON' '
+    stg branch --create other &&
+    git config branch.other.description "other branch" &&
+    stg branch master &&
+    stg branch --list --format=json >out &&
+    grep -A1 "\"name\": \"master\"" out | grep "\"current\": true" &&
+    grep -A2 "\"name\": \"other\"" out | grep "\"stgit\": true" &&
+    grep "\"description\": \"other branch\"" out
+'
+
+test_expect_success 'Log as JSON' '
+    stg log --format=json -n 2 >out &&
+    test "$(grep -c "\"id\":" out)" = "2" &&
+    grep "\"message\": \"meta p1\"" out &&
+    stg log --format=json p3 >out &&
+    ! grep "\"message\": \"meta p1\"" out &&
+    grep "\"message\": \"uncommit\"" out
+'
+
+test_done
@@ -0,0 +1,153 @@
+#!/bin/sh
+
+test_description='Test patch dependencies'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    test_commit_bulk --message="p%s" 5 &&
+    stg uncommit -n 5 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3 p4 p5"
+'
+
+test_expect_success 'No dependencies by default' '
+    stg deps p3 >out &&
+    test_must_be_empty out
+'
+
+test_expect_success 'Patch may not depend on itself' '
+    command_error stg deps --add p3 p3 2>err &&
+    grep "error: patch \`p3\` cannot depend on itself" err
+'
+
+test_expect_success 'Declare dependencies' '
+    stg deps --add p1 --add p2 p4 &&
+    stg deps p4 >out &&
+    test "$(echo $(cat out))" = "p1 p2" &&
+    stg deps --dependents p1 >out &&
+    test "$(echo $(cat out))" = "p4"
+'
+
+test_expect_success 'Dependency cycles are not allowed' '
+    command_error stg deps --add p4 p1 2>err &&
+    grep "error: \`p4\` already depends on \`p1\`" err
+'
+
+test_expect_success 'Remove dependency' '
+    stg deps --remove p2 p4 &&
+    stg deps p4 >out &&
+    test "$(echo $(cat out))" = "p1" &&
+    command_error stg deps --remove p2 p4 2>err &&
+    grep "error: patch \`p4\` does not declare a dependency on \`p2\`" err
+'
+
+test_expect_success 'Float refuses to move dependency above dependent' '
+    command_error stg float p1 2>err &&
+    grep "error: patch \`p4\` depends on \`p1\`, which would not be applied below it" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3 p4 p5"
+'
+
+test_expect_success 'Float ignoring dependencies' '
+    stg float --deps=ignore p1 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2 p3 p4 p5 p1" &&
+    stg undo --hard &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3 p4 p5"
+'
+
+test_expect_success 'Float pulls in dependents' '
+    stg float --deps=pull p1 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2 p3 p5 p1 p4"
+'
+
+test_expect_success 'Sink refuses to move dependent below dependency' '
+    command_error stg sink p4 2>err &&
+    grep "error: patch \`p4\` depends on \`p1\`, which would not be applied below it" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2 p3 p5 p1 p4"
+'
+
+test_expect_success 'Sink pulls in dependencies' '
+    stg sink --deps=pull -t p3 p4 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2 p1 p4 p3 p5"
+'
+
+test_expect_success 'Sink refuses target pulled in as dependency' '
+    stg deps --add p2 p3 &&
+    command_error stg sink --deps=pull -t p2 p3 2>err &&
+    grep "error: target patch \`p2\` depends on or is a dependent of a patch to sink" err &&
+    stg deps --clear p3
+'
+
+test_expect_success 'Dependency policy from config' '
+    test_config stgit.deps.policy pull &&
+    stg float p1 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2 p3 p5 p1 p4"
+'
+
+test_expect_success 'Invalid dependency policy' '
+    general_error stg float --deps=bogus p1 2>err &&
+    grep "invalid value .bogus." err
+'
+
+test_expect_success 'Push refuses patch without its dependencies' '
+    stg pop -a &&
+    command_error stg push p4 2>err &&
+    grep "error: patch \`p4\` depends on \`p1\`, which would not be applied below it" err &&
+    test "$(echo $(stg series --applied --noprefix))" = ""
+'
+
+test_expect_success 'Push pulls in dependencies' '
+    stg push --deps=pull p4 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p4"
+'
+
+test_expect_success 'Rename carries dependencies' '
+    stg rename p1 base &&
+    stg deps p4 >out &&
+    test "$(echo $(cat out))" = "base" &&
+    stg rename p4 top &&
+    stg deps --dependents base >out &&
+    test "$(echo $(cat out))" = "top"
+'
+
+test_expect_success 'Delete drops dependencies' '
+    stg delete base &&
+    stg deps top >out &&
+    test_must_be_empty out
+'
+
+test_expect_success 'Setup patches with overlapping changes' '
+    stg pop -a &&
+    stg delete $(stg series --noprefix) &&
+    test_write_lines 1 2 3 4 5 6 7 8 9 >file &&
+    stg add file &&
+    stg new -m base &&
+    stg refresh &&
+    test_write_lines 1 2 3 4 5 6 7 8 9 a >file &&
+    stg new -m add-a &&
+    stg refresh &&
+    test_write_lines 0 1 2 3 4 5 6 7 8 9 a >file &&
+    stg new -m add-0 &&
+    stg refresh &&
+    test_write_lines 0 1 2 3 4 5 6 7 8 9 a b >file &&
+    stg new -m add-b &&
+    stg refresh
+'
+
+test_expect_success 'Show hunk dependencies' '
+    stg deps add-b >out &&
+    test "$(echo $(cat out))" = "add-a" &&
+    stg deps add-0 >out &&
+    test "$(echo $(cat out))" = "base" &&
+    stg deps --declared add-b >out &&
+    test_must_be_empty out
+'
+
+test_expect_success 'Hunk dependencies are enforced when enabled' '
+    test_config stgit.deps.hunks true &&
+    command_error stg float add-a 2>err &&
+    grep "error: patch \`add-b\` depends on \`add-a\`, which would not be applied below it" err &&
+    stg float --deps=pull add-a &&
+    test "$(echo $(stg series --applied --noprefix))" = "base add-0 add-a add-b"
+'
+
+test_done
@@ -7,7 +7,7 @@ test_description='Test import from quilt series'
 . ./test-lib.sh
 
 cleanup_test () {
-     stg delete ..
+     stg delete --all
      rm -r patches .pc
 }
 
@@ -136,12 +136,13 @@ test_expect_success QUILT 'Import a quilt series with unexpected "-p" in series'
     cleanup_test
 '
 
-test_expect_success QUILT 'Import a quilt series with unexpected "-p1" in series' '
+test_expect_success QUILT 'Import a quilt series with "-p1" in series' '
     test_import_quilt_series_should_fail_p_something_ok 1 &&
     after_test &&
     sed -e "s/^\(.*\)$/\1 -p1/g" patches/series >series_messed &&
     mv series_messed patches/series &&
-    test_expect_code 2 check_test &&
+    check_test &&
+    test "$(echo $(stg series --noprefix))" = "patch-p1.diff patch-p1-s.diff" &&
     cleanup_test
 '
 
@@ -192,7 +193,7 @@ test_expect_success QUILT 'Quilt import filename with spaces' '
     quilt pop -a &&
     stg import -S patches/series &&
     stg series &&
-    stg delete ..
+    stg delete --all
 '
 
 test_expect_success QUILT 'Quilt import series with subdirs' '
@@ -210,7 +211,7 @@ test_expect_success QUILT 'Quilt import series with subdirs' '
     quilt pop -a &&
     stg import -S patches/series &&
     stg series &&
-    stg delete ..
+    stg delete --all
 '
 
 test_done
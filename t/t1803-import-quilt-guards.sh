@@ -0,0 +1,145 @@
+#!/bin/sh
+
+test_description='Test quilt series strip levels, guards, applied state, and export'
+
+. ./test-lib.sh
+
+make_patch () {
+    name=$1 &&
+    prefix=$2 &&
+    shift 2 &&
+    git add -N "$@" &&
+    git diff --src-prefix="$prefix" --dst-prefix="$prefix" >"q/patches/$name" &&
+    git add "$@"
+}
+
+test_expect_success 'Initialize repository and quilt patches' '
+    mkdir -p s q/patches &&
+    echo 0 >t &&
+    echo 0 >s/u &&
+    git add t s/u &&
+    git commit -m initial &&
+    stg init &&
+    echo 1 >t &&
+    make_patch one.diff a/ t &&
+    echo 2 >s/u &&
+    make_patch two.diff "" s/u &&
+    echo 3 >>t &&
+    make_patch three.diff x/y/ t &&
+    echo 4 >v &&
+    make_patch four.diff a/ v &&
+    git reset --hard
+'
+
+test_expect_success 'Import series with strip levels' '
+    cat >q/patches/series <<-\EOF &&
+	one.diff
+	two.diff -p0
+	three.diff -p2 # applies as -p2
+	EOF
+    stg import -S q/patches/series &&
+    test "$(echo $(stg series --noprefix))" = "one.diff two.diff three.diff" &&
+    test_write_lines 1 3 >expected &&
+    test_cmp expected t &&
+    test_write_lines 2 >expected &&
+    test_cmp expected s/u &&
+    stg delete ..
+'
+
+test_expect_success 'Invalid strip level' '
+    cat >q/patches/series <<-\EOF &&
+	one.diff
+	two.diff -pab
+	EOF
+    command_error stg import -S q/patches/series 2>err &&
+    grep "patch \`two.diff\` has invalid strip level \"-pab\"" err &&
+    test "$(stg series --noprefix)" = "one.diff" &&
+    stg delete ..
+'
+
+test_expect_success 'Guard option requires series' '
+    general_error stg import --guard foo q/patches/one.diff
+'
+
+test_expect_success 'Import series with guards' '
+    cat >q/patches/series <<-\EOF &&
+	one.diff #+foo
+	two.diff -p0 #-foo
+	three.diff -p2 #+foo #+bar
+	four.diff #-bar
+	EOF
+    stg import -S q/patches/series 2>err &&
+    grep "Skipping patch \`one.diff\` excluded by guards" err &&
+    test "$(echo $(stg series --noprefix))" = "two.diff four.diff" &&
+    stg delete .. &&
+    stg import -S --guard foo q/patches/series &&
+    test "$(echo $(stg series --noprefix))" = "one.diff three.diff four.diff" &&
+    stg delete .. &&
+    stg import -S --guard foo --guard bar q/patches/series &&
+    test "$(echo $(stg series --noprefix))" = "one.diff three.diff" &&
+    stg delete ..
+'
+
+test_expect_success 'Import applied state from quilt' '
+    cat >q/patches/series <<-\EOF &&
+	one.diff
+	two.diff -p0
+	three.diff -p2
+	EOF
+    mkdir q/.pc &&
+    test_write_lines one.diff two.diff >q/.pc/applied-patches &&
+    stg import -S q/patches/series &&
+    test "$(echo $(stg series --noprefix --applied))" = "one.diff two.diff" &&
+    test "$(echo $(stg series --noprefix --unapplied))" = "three.diff" &&
+    test_write_lines 1 >expected &&
+    test_cmp expected t &&
+    stg delete --all &&
+    rm q/.pc/applied-patches &&
+    stg import -S q/patches/series &&
+    test "$(stg series --applied -c)" = "0" &&
+    test "$(echo $(stg series --noprefix --unapplied))" = "one.diff two.diff three.diff" &&
+    stg delete --all &&
+    rm -r q/.pc
+'
+
+test_expect_success 'Export series for quilt' '
+    stg import -S q/patches/series &&
+    git mv t t-renamed &&
+    stg new -m "rename t" &&
+    stg refresh &&
+    stg export --quilt --dir exported &&
+    grep "^rename-t$" exported/series &&
+    ! grep "^rename from" exported/rename-t &&
+    git worktree add --detach plain "$(stg id {base})" &&
+    (
+        cd plain &&
+        grep -v "^#" ../exported/series |
+        while read patch; do
+            patch -p1 --quiet <"../exported/$patch" || exit 1
+        done
+    ) &&
+    test_cmp t-renamed plain/t-renamed &&
+    test_path_is_missing plain/t &&
+    test_cmp s/u plain/s/u
+'
+
+test_expect_success 'Import series exported for quilt' '
+    stg delete .. &&
+    stg import -S exported/series &&
+    test "$(echo $(stg series --noprefix))" = "one.diff two.diff three.diff rename-t" &&
+    test_write_lines 1 3 >expected &&
+    test_cmp expected t-renamed &&
+    test_path_is_missing t
+'
+
+test_expect_success 'Binary changes cannot be exported for quilt' '
+    printf "\000\001" >bin &&
+    stg add bin &&
+    stg new -m "binary" &&
+    stg refresh &&
+    command_error stg export --quilt --dir exported 2>err &&
+    grep "patch \`binary\` has binary changes" err &&
+    stg export --dir exported-git
+'
+
+test_done
@@ -0,0 +1,135 @@
+#!/bin/sh
+
+test_description='Test stg export --bundle and stg import --bundle'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize StGit stack' '
+    test_commit_bulk --message="base %s" 2 &&
+    stg init &&
+    for i in 1 2 3 4 5; do
+        stg new -m "patch $i" p$i &&
+        echo $i >file$i &&
+        stg add file$i &&
+        stg refresh || return 1
+    done &&
+    printf "\000\001" >bin &&
+    stg add bin &&
+    stg refresh -p p2 &&
+    stg pop p3 p4 p5 &&
+    stg goto p1 &&
+    echo 11 >>file1 &&
+    stg refresh &&
+    stg hide p5 &&
+    stg edit --authname "Other Author" --authemail other@example.com p4 &&
+    stg push p2
+'
+
+test_expect_success 'Bundle option conflicts' '
+    general_error stg export --bundle b.tar --dir out &&
+    general_error stg export --bundle b.tar p1 &&
+    general_error stg import --bundle --series b.tar
+'
+
+test_expect_success 'Export bundle' '
+    stg export --bundle stack.tar &&
+    tar -tf stack.tar >contents &&
+    grep "^stack.json$" contents &&
+    grep "^patches/p1.diff$" contents &&
+    grep "^patches/p5.diff$" contents &&
+    tar -xOf stack.tar stack.json >manifest &&
+    grep "\"base\": \"$(stg id {base})\"" manifest &&
+    grep "\"status\": \"hidden\"" manifest
+'
+
+test_expect_success 'Import bundle into a non-empty stack' '
+    command_error stg import --bundle stack.tar 2>err &&
+    grep "branch \`master\` already has patches" err
+'
+
+test_expect_success 'Import bundle onto a new branch' '
+    git branch other HEAD~3 &&
+    stg branch other &&
+    stg import --bundle stack.tar &&
+    test "$(stg id {base})" = "$(stg id master:{base})" &&
+    test "$(echo $(stg series --noprefix --applied))" = "p1 p2" &&
+    test "$(echo $(stg series --noprefix --unapplied))" = "p3 p4" &&
+    test "$(echo $(stg series --noprefix --hidden))" = "p5" &&
+    for p in p1 p2; do
+        test "$(stg id $p)" = "$(stg id master:$p)" || return 1
+    done &&
+    for p in p3 p4 p5; do
+        git diff-tree -p $(stg id $p)^ $(stg id $p) >actual &&
+        git diff-tree -p $(stg id master:$p)^ $(stg id master:$p) >expected &&
+        test_cmp expected actual || return 1
+    done &&
+    test "$(git log -1 --format=%an $(stg id p4))" = "Other Author" &&
+    test "$(git log -1 --format=%ad $(stg id p4))" = \
+         "$(git log -1 --format=%ad $(stg id master:p4))" &&
+    test_write_lines 1 11 >expected &&
+    test_cmp expected file1 &&
+    test_path_is_file bin &&
+    test_path_is_missing file3
+'
+
+test_expect_success 'Unapplied patches are pushable' '
+    stg push -a &&
+    test "$(echo $(stg series --noprefix --applied))" = "p1 p2 p3 p4" &&
+    test_write_lines 4 >expected &&
+    test_cmp expected file4 &&
+    stg undo
+'
+
+test_expect_success 'Import bundle onto another base' '
+    stg branch --create third master~3 &&
+    stg import --bundle --base HEAD stack.tar &&
+    test "$(stg id {base})" = "$(git rev-parse master~3)" &&
+    test "$(echo $(stg series --noprefix --applied))" = "p1 p2" &&
+    test "$(git log -1 --format=%s $(stg id p2))" = "patch 2"
+'
+
+test_expect_success 'Missing bundle base' '
+    stg branch --create fourth &&
+    mkdir unpacked &&
+    (
+        cd unpacked &&
+        tar -xf ../stack.tar &&
+        sed -e "s/\"base\": \"[0-9a-f]*\"/\"base\": \"$(test_oid zero)\"/" \
+            stack.json >stack.json.new &&
+        mv stack.json.new stack.json &&
+        tar -cf ../missing-base.tar stack.json patches
+    ) &&
+    command_error stg import --bundle missing-base.tar 2>err &&
+    grep "bundle base commit \`$(test_oid zero)\` is not present" err &&
+    test "$(stg series -c)" = "0"
+'
+
+test_expect_success 'Unapplied patch with a refreshed parent' '
+    stg branch --create stale master~3 &&
+    stg new -m "stale 1" s1 &&
+    echo 1 >s &&
+    stg add s &&
+    stg refresh &&
+    stg new -m "stale 2" s2 &&
+    echo 2 >s &&
+    stg refresh &&
+    stg pop &&
+    echo 11 >s &&
+    stg refresh &&
+    stg export --bundle stale.tar &&
+    tar -tf stale.tar >contents &&
+    grep "^ancestors/s2/0.diff$" contents &&
+    stg branch --create stale-import master~3 &&
+    stg import --bundle stale.tar &&
+    test "$(echo $(stg series --noprefix --applied))" = "s1" &&
+    test "$(echo $(stg series --noprefix --unapplied))" = "s2" &&
+    test "$(stg id s1)" = "$(stg id stale:s1)" &&
+    test "$(git rev-parse $(stg id s2)^{tree})" = \
+         "$(git rev-parse $(stg id stale:s2)^{tree})" &&
+    test "$(git rev-parse $(stg id s2)^^{tree})" = \
+         "$(git rev-parse $(stg id stale:s2)^^{tree})" &&
+    test "$(git log -1 --format=%s $(stg id s2)^)" = "stale 1" &&
+    test "$(git rev-parse $(stg id s2)~2)" = "$(stg id {base})"
+'
+
+test_done
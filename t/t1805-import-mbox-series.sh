@@ -0,0 +1,192 @@
+#!/bin/sh
+
+test_description='Test importing patch series from an mbox'
+
+. ./test-lib.sh
+
+message_id () {
+    sed -n -e "s/^Message-I[Dd]: *//p" "$1"
+}
+
+test_expect_success 'Initialize repository and patch series' '
+    test_commit_bulk --message="base %s" 1 &&
+    stg init &&
+    git checkout -b series &&
+    for i in 1 2; do
+        echo $i >file$i &&
+        git add file$i &&
+        git commit -m "patch $i" -m "Description $i." \
+            -m "Signed-off-by: Author <author@example.com>" || return 1
+    done &&
+    git format-patch -q --thread -o v1 HEAD~2 &&
+    git commit --amend -m "patch 2 rev" -m "Description 2." \
+        -m "Signed-off-by: Author <author@example.com>" &&
+    echo 3 >file3 &&
+    git add file3 &&
+    git commit -m "patch 3" -m "Description 3." \
+        -m "Signed-off-by: Author <author@example.com>" &&
+    git format-patch -q --thread --cover-letter -v2 -o v2 HEAD~3 &&
+    git checkout master &&
+    cat v2/v2-0003-*.patch v1/*.patch v2/v2-0001-*.patch v2/v2-0000-*.patch \
+        v2/v2-0002-*.patch >series.mbox
+'
+
+test_expect_success 'Series options require mbox' '
+    general_error stg import --allow-incomplete v1/0001-patch-1.patch &&
+    general_error stg import --cover-description v1/0001-patch-1.patch
+'
+
+test_expect_success 'Import latest version of series in order' '
+    stg import -M series.mbox 2>err &&
+    grep "Importing version 2 of the patch series" err &&
+    grep "Skipping cover letter \`\*\*\* SUBJECT HERE \*\*\*\`" err &&
+    test "$(echo $(stg series --noprefix))" = "patch-1 patch-2-rev patch-3" &&
+    test "$(git log -1 --format=%s $(stg id patch-2-rev))" = "patch 2 rev" &&
+    test_must_fail git config branch.master.description &&
+    stg delete --all
+'
+
+test_expect_success 'Missing patches in series' '
+    cat v2/v2-0001-*.patch v2/v2-0003-*.patch >incomplete.mbox &&
+    command_error stg import -M incomplete.mbox 2>err &&
+    grep "patch series is missing patches 2/3" err &&
+    test "$(stg series -c)" = "0" &&
+    stg import -M --allow-incomplete incomplete.mbox 2>err &&
+    grep "Patch series is missing patches 2/3" err &&
+    test "$(echo $(stg series --noprefix))" = "patch-1 patch-3" &&
+    stg delete --all
+'
+
+test_expect_success 'Use cover letter as branch description' '
+    stg import -M --cover-description series.mbox &&
+    git config branch.master.description >description &&
+    head -n 1 description >actual &&
+    echo "*** SUBJECT HERE ***" >expected &&
+    test_cmp expected actual &&
+    grep "BLURB HERE" description &&
+    stg delete --all &&
+    git config --unset branch.master.description
+'
+
+test_expect_success 'Collect trailers from replies' '
+    cat >replies.mbox <<-EOF &&
+	From nobody Mon Sep 17 00:00:00 2001
+	From: Reviewer <reviewer@example.com>
+	Subject: Re: [PATCH v2 2/3] patch 2 rev
+	Date: Thu, 1 Jan 2026 00:00:00 +0000
+	Message-ID: <reply-1@example.com>
+	In-Reply-To: $(message_id v2/v2-0002-*.patch)
+	References: $(message_id v2/v2-0000-*.patch) $(message_id v2/v2-0002-*.patch)
+
+	> Signed-off-by: Author <author@example.com>
+	> Reviewed-by: Quoted <quoted@example.com>
+
+	Reviewed-by: Reviewer <reviewer@example.com>
+
+	From nobody Mon Sep 17 00:00:00 2001
+	From: Acker <acker@example.com>
+	Subject: Re: [PATCH v2 0/3] *** SUBJECT HERE ***
+	Date: Thu, 1 Jan 2026 00:00:00 +0000
+	Message-ID: <reply-2@example.com>
+	In-Reply-To: $(message_id v2/v2-0000-*.patch)
+
+	For the series:
+
+	Acked-by: Acker <acker@example.com>
+	Signed-off-by: Not Collected <nc@example.com>
+
+	From nobody Mon Sep 17 00:00:00 2001
+	From: Tester <tester@example.com>
+	Subject: Re: [PATCH 2/2] patch 2
+	Date: Thu, 1 Jan 2026 00:00:00 +0000
+	Message-ID: <reply-3@example.com>
+	In-Reply-To: $(message_id v1/0002-patch-2.patch)
+
+	Tested-by: Tester <tester@example.com>
+	EOF
+    cat series.mbox replies.mbox >reviewed.mbox &&
+    stg import -M --collect-trailers reviewed.mbox &&
+    test "$(echo $(stg series --noprefix))" = "patch-1 patch-2-rev patch-3" &&
+    cat >expected <<-\EOF &&
+	patch 2 rev
+
+	Description 2.
+
+	Signed-off-by: Author <author@example.com>
+	Reviewed-by: Reviewer <reviewer@example.com>
+	Acked-by: Acker <acker@example.com>
+
+	EOF
+    git log -1 --format=%B $(stg id patch-2-rev) >actual &&
+    test_cmp expected actual &&
+    for p in patch-1 patch-3; do
+        git log -1 --format=%B $(stg id $p) >msg &&
+        grep "^Acked-by: Acker <acker@example.com>$" msg &&
+        ! grep "Reviewed-by" msg &&
+        ! grep "Tested-by" msg &&
+        ! grep "Not Collected" msg || return 1
+    done &&
+    stg delete --all
+'
+
+test_expect_success 'Strip trailers' '
+    stg import -M --strip-trailers series.mbox &&
+    cat >expected <<-\EOF &&
+	patch 1
+
+	Description 1.
+
+	EOF
+    git log -1 --format=%B $(stg id patch-1) >actual &&
+    test_cmp expected actual &&
+    stg delete --all &&
+    stg import -M --strip-trailers --collect-trailers --message-id reviewed.mbox &&
+    git log -1 --format=%B $(stg id patch-2-rev) >msg &&
+    ! grep "Signed-off-by" msg &&
+    grep "^Reviewed-by: Reviewer <reviewer@example.com>$" msg &&
+    grep "^Message-ID: $(message_id v2/v2-0002-*.patch)$" msg &&
+    stg delete --all
+'
+
+test_expect_success 'Strip trailers from body without description' '
+    git format-patch -q --stdout -1 series~2 >one.patch &&
+    sed -e "/^Description 1\./,/^$/d" one.patch >bare.mbox &&
+    stg import -M --strip-trailers bare.mbox &&
+    printf "patch 1\n\n" >expected &&
+    git log -1 --format=%B $(stg id patch-1) >actual &&
+    test_cmp expected actual &&
+    stg delete --all
+'
+
+test_expect_success 'Warn about untagged and replaced patches in series' '
+    git format-patch -q -k --stdout -1 series~2 >untagged.mbox &&
+    sed -e "s/^Subject: \(.*\) patch 2 rev$/Subject: \1 patch 2 other/" \
+        v2/v2-0002-*.patch >other.mbox &&
+    cat series.mbox untagged.mbox other.mbox >mixed.mbox &&
+    stg import -M mixed.mbox 2>err &&
+    grep "Skipping \`patch 1\`: not tagged as part of the patch series" err &&
+    grep "Patch 2/3 \`patch 2 rev\` is replaced by later \`patch 2 other\`" err &&
+    test "$(echo $(stg series --noprefix))" = "patch-1 patch-2-other patch-3" &&
+    stg delete --all
+'
+
+test_expect_success 'Collect trailers for a single patch' '
+    git format-patch -q --thread -o single -1 series~2 &&
+    cp single/*.patch single.mbox &&
+    cat >>single.mbox <<-EOF &&
+	From nobody Mon Sep 17 00:00:00 2001
+	From: Tester <tester@example.com>
+	Subject: Re: [PATCH] patch 1
+	Date: Thu, 1 Jan 2026 00:00:00 +0000
+	Message-ID: <reply-4@example.com>
+	In-Reply-To: $(message_id single/*.patch)
+
+	Tested-by: Tester <tester@example.com>
+	EOF
+    stg import -M --collect-trailers single.mbox &&
+    test "$(stg series --noprefix)" = "patch-1" &&
+    git log -1 --format=%B $(stg id patch-1) >msg &&
+    grep "^Tested-by: Tester <tester@example.com>$" msg
+'
+
+test_done
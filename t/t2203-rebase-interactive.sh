@@ -286,4 +286,147 @@ test_expect_success 'Patches with multi-line subject' '
     test "$(stg series --applied -c)" = "2"
 '
 
+test_expect_success 'Reword a patch' '
+    stg delete $(stg series --all --noprefix --no-description) &&
+    stg new -m p0 &&
+    echo p0 >p0.txt && stg add p0.txt && stg refresh &&
+    stg new -m p1 &&
+    write_script fake-editor <<-\EOF &&
+	if [ ! -f .fake-editor-has-run-once ]
+	then
+	    printf "keep p0\nreword p1\n" >"$1" &&
+	    touch .fake-editor-has-run-once
+	else
+	    ! grep -e "^diff --git" "$1" &&
+	    sed "s/^p1$/p1 reworded/" "$1" >"$1".tmp && mv "$1".tmp "$1"
+	fi
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    test_when_finished rm -f .fake-editor-has-run-once &&
+    stg rebase --interactive &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1" &&
+    test "$(git log -1 --format=%s)" = "p1 reworded" &&
+    git diff-index --quiet HEAD
+'
+
+test_expect_success 'Rename a patch' '
+    write_script fake-editor <<-\EOF &&
+	printf "rename p0 p0-renamed\nkeep p1\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    stg rebase --interactive &&
+    test "$(echo $(stg series --noprefix))" = "p0-renamed p1"
+'
+
+test_expect_success 'Rename to an existing patch name throws error' '
+    write_script fake-editor <<-\EOF &&
+	printf "rename p0-renamed p1\nkeep p1\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    command_error stg rebase --interactive 2>err &&
+    grep -e "cannot rename to existing patch \`p1\`" err
+'
+
+test_expect_success 'Reorder applied and unapplied patches exactly' '
+    stg delete $(stg series --all --noprefix --no-description) &&
+    stg new -m p0 &&
+    stg new -m p1 &&
+    stg new -m p2 &&
+    stg new -m p3 &&
+    write_script fake-editor <<-\EOF &&
+	printf "keep p2\nkeep p0\n# --- APPLY_LINE ---\nkeep p3\nkeep p1\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    stg rebase --interactive &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2 p0" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p3 p1"
+'
+
+test_expect_success 'Exec runs commands after patches are pushed' '
+    write_script fake-editor <<-\EOF &&
+	printf "keep p0\nexec stg top >>exec.log # not a comment\nkeep p1\nx stg top >>exec.log\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    test_when_finished rm -f exec.log &&
+    stg rebase --interactive &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1" &&
+    test "$(echo $(cat exec.log))" = "p0 p1"
+'
+
+test_expect_success 'Exec after APPLY_LINE throws error' '
+    write_script fake-editor <<-\EOF &&
+	printf "keep p0\n# --- APPLY_LINE ---\nkeep p1\nexec true\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    command_error stg rebase --interactive 2>err &&
+    grep -e "\`exec\` must be above the APPLY_LINE" err
+'
+
+test_expect_success 'Failing exec interrupts the rebase' '
+    write_script fake-editor <<-\EOF &&
+	printf "keep p0\nexec false\nkeep p1\nkeep p2\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    command_error stg rebase --interactive 2>err &&
+    grep -e "\`false\` exited with code 1; stopped at \`p0\`" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0" &&
+    command_error stg rebase --interactive &&
+    stg continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1 p2"
+'
+
+test_expect_success 'Break stops the rebase' '
+    write_script fake-editor <<-\EOF &&
+	printf "keep p1\nbreak\nkeep p0\nexec stg top >exec.log\nkeep p2\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    test_when_finished rm -f exec.log &&
+    stg rebase --interactive 2>err &&
+    grep -e "Stopped at \`p1\`" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1" &&
+    echo change >p1.txt &&
+    stg add p1.txt &&
+    stg continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p0 p2" &&
+    test "$(cat exec.log)" = "p0" &&
+    test "$(stg files p1)" = "A p1.txt" &&
+    git diff-index --quiet HEAD
+'
+
+test_expect_success 'Refresh and new patches at a break keep the rebase pending' '
+    write_script fake-editor <<-\EOF &&
+	printf "keep p1\nbreak\nkeep p0\nkeep p2\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    stg rebase --interactive &&
+    echo refreshed >p1.txt &&
+    stg refresh &&
+    stg new -m extra extra &&
+    stg continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 extra p0 p2" &&
+    test "$(git show $(stg id p1):p1.txt)" = "refreshed" &&
+    stg delete extra
+'
+
+test_expect_success 'Abort after break restores the stack' '
+    write_script fake-editor <<-\EOF &&
+	printf "keep p2\nbreak\nkeep p0\nkeep p1\n" >"$1"
+	EOF
+    test_set_editor "$(pwd)/fake-editor" &&
+    test_when_finished test_set_editor false &&
+    stg rebase --interactive &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2" &&
+    stg abort &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p0 p2"
+'
+
 test_done
@@ -0,0 +1,182 @@
+#!/bin/sh
+
+test_description='Test continue, skip, and abort of interrupted operations'
+
+. ./test-lib.sh
+
+test_expect_success 'Setup master' '
+    printf "a\nb\nc\n" >file1 &&
+    git add file1 &&
+    git commit -m base
+'
+
+test_expect_success 'Setup stack branch' '
+    stg branch --create stack &&
+    stg new p1 -m p1 &&
+    printf "a\nb1\nc\n" >file1 &&
+    stg refresh &&
+    stg new p2 -m p2 &&
+    echo two >file2 &&
+    stg add file2 &&
+    stg refresh &&
+    stg new p3 -m p3 &&
+    echo three >file3 &&
+    stg add file3 &&
+    stg refresh
+'
+
+test_expect_success 'Add conflicting commit to master' '
+    stg branch master &&
+    printf "a\nb2\nc\n" >file1 &&
+    git commit -a -m b2 &&
+    stg branch stack
+'
+
+test_expect_success 'Nothing to continue, skip, or abort' '
+    command_error stg continue 2>err &&
+    grep "no interrupted operation is pending" err &&
+    command_error stg skip &&
+    command_error stg abort
+'
+
+test_expect_success 'Rebase with conflicts' '
+    orig_head=$(git rev-parse HEAD) &&
+    orig_state=$(git rev-parse refs/stacks/stack) &&
+    conflict stg rebase master &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p2 p3"
+'
+
+test_expect_success 'Rebase refused while operation pending' '
+    command_error stg rebase master 2>err &&
+    grep "stg rebase\` was interrupted" err
+'
+
+test_expect_success 'Continue with unresolved conflicts fails' '
+    command_error stg continue 2>err &&
+    grep "resolve outstanding conflicts first" err
+'
+
+test_expect_success 'Abort restores the original stack' '
+    stg abort &&
+    test "$(git rev-parse HEAD)" = "$orig_head" &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3" &&
+    test -z "$(git status --porcelain -uno)" &&
+    command_error stg abort
+'
+
+test_expect_success 'Continue after resolving conflicts' '
+    conflict stg rebase master &&
+    printf "a\nb12\nc\n" >file1 &&
+    stg add file1 &&
+    stg continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3" &&
+    test "$(stg id master)" = "$(stg id p1^)" &&
+    test "$(git show $(stg id p1):file1 | sed -n 2p)" = "b12" &&
+    test -z "$(git status --porcelain -uno)" &&
+    command_error stg continue
+'
+
+test_expect_success 'Skip the conflicting patch' '
+    stg reset --hard $orig_state &&
+    conflict stg rebase master &&
+    stg skip &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2 p3" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p1" &&
+    test "$(stg id master)" = "$(stg id p2^)" &&
+    test "$(git show $(stg id p1):file1 | sed -n 2p)" = "b1" &&
+    test -z "$(git status --porcelain -uno)"
+'
+
+test_expect_success 'Continue an interrupted push' '
+    stg reset --hard $orig_state &&
+    stg pop -a &&
+    stg new conflicting -m conflicting &&
+    printf "a\nbx\nc\n" >file1 &&
+    stg refresh &&
+    push_state=$(git rev-parse refs/stacks/stack) &&
+    conflict stg push p1 p2 &&
+    printf "a\nbxy\nc\n" >file1 &&
+    stg add file1 &&
+    stg continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "conflicting p1 p2" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p3"
+'
+
+test_expect_success 'Undo restores the pending operation' '
+    stg reset --hard $push_state &&
+    conflict stg push p1 p2 &&
+    printf "a\nbxy\nc\n" >file1 &&
+    stg add file1 &&
+    stg continue &&
+    command_error stg abort &&
+    stg undo --hard &&
+    test "$(echo $(stg series --applied --noprefix))" = "conflicting p1" &&
+    stg abort &&
+    test "$(echo $(stg series --applied --noprefix))" = "conflicting"
+'
+
+test_expect_success 'Refresh after conflicts keeps the operation pending' '
+    stg reset --hard $push_state &&
+    conflict stg push p1 p2 &&
+    printf "a\nbxy\nc\n" >file1 &&
+    stg add file1 &&
+    stg refresh &&
+    command_error stg rebase master 2>err &&
+    grep "stg push\` was interrupted" err &&
+    test "$(git show $(stg id p1):file1 | sed -n 2p)" = "bxy" &&
+    stg continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "conflicting p1 p2" &&
+    command_error stg abort 2>err &&
+    grep "no interrupted operation is pending" err
+'
+
+test_expect_success 'Popping the conflicting patch abandons the operation' '
+    stg reset --hard $push_state &&
+    conflict stg push p1 p2 &&
+    stg reset --hard &&
+    stg pop p1 &&
+    command_error stg abort 2>err &&
+    grep "no interrupted operation is pending" err &&
+    base=$(stg id conflicting^) &&
+    stg rebase $base &&
+    test "$(echo $(stg series --applied --noprefix))" = "conflicting"
+'
+
+test_expect_success 'Continue restores autostashed changes' '
+    stg reset --hard $orig_state &&
+    echo dirty >file3 &&
+    conflict stg rebase --autostash master &&
+    test "$(cat file3)" = "three" -o ! -e file3 &&
+    printf "a\nb12\nc\n" >file1 &&
+    stg add file1 &&
+    stg continue &&
+    test "$(cat file3)" = "dirty"
+'
+
+test_expect_success 'Refresh and continue restores autostashed changes' '
+    stg reset --hard $orig_state &&
+    echo dirty >file3 &&
+    conflict stg rebase --autostash master &&
+    printf "a\nb12\nc\n" >file1 &&
+    stg add file1 &&
+    stg refresh &&
+    stg continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3" &&
+    test "$(cat file3)" = "dirty" &&
+    test -z "$(git stash list)"
+'
+
+test_expect_success 'Abandoning an autostashed operation warns about the stash' '
+    git checkout file3 &&
+    stg reset --hard $orig_state &&
+    echo dirty >file3 &&
+    conflict stg rebase --autostash master &&
+    stg reset --hard &&
+    stg pop p1 2>err &&
+    grep "warning: abandoned interrupted \`stg rebase\`" err &&
+    test "$(git stash list | wc -l)" = "1" &&
+    git stash drop
+'
+
+test_done
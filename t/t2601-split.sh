@@ -0,0 +1,115 @@
+#!/bin/sh
+
+test_description='Test stg split'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize StGit stack' '
+    printf "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n" >file &&
+    git add file &&
+    git commit -m base &&
+    stg init &&
+    stg new p1 -m "first patch" &&
+    printf "1x\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12x\n" >file &&
+    echo a >a &&
+    echo b >b &&
+    stg add file a b &&
+    stg refresh &&
+    stg new p2 -m "second patch" &&
+    echo c >c &&
+    echo d >d &&
+    stg add c d &&
+    stg refresh
+'
+
+test_expect_success 'Split requires a selection' '
+    general_error stg split p1 2>err &&
+    grep "required arguments were not provided" err
+'
+
+test_expect_success 'Split off paths above the original' '
+    stg split p1 -n p1-b -m "split b" -- b &&
+    test "$(echo $(stg series --noprefix))" = "p1 p1-b p2" &&
+    test "$(echo $(stg files --bare p1))" = "a file" &&
+    test "$(echo $(stg files --bare p1-b))" = "b" &&
+    test "$(git log -1 --format=%s $(stg id p1))" = "first patch" &&
+    test "$(git log -1 --format=%s $(stg id p1-b))" = "split b" &&
+    test "$(stg top)" = "p2" &&
+    test -z "$(git status --porcelain -uno)"
+'
+
+test_expect_success 'Split off paths below the original' '
+    stg split --below p1 -n p1-a -m "split a" -- a &&
+    test "$(echo $(stg series --noprefix))" = "p1-a p1 p1-b p2" &&
+    test "$(echo $(stg files --bare p1-a))" = "a" &&
+    test "$(echo $(stg files --bare p1))" = "file"
+'
+
+test_expect_success 'Split off hunks interactively' '
+    printf "n\ny\n" | stg split -i p1 -n p1-end -m "end" >out &&
+    grep "Split off this hunk" out &&
+    test "$(echo $(stg series --noprefix))" = "p1-a p1 p1-end p1-b p2" &&
+    test "$(git show $(stg id p1):file | head -n 1)" = "1x" &&
+    test "$(git show $(stg id p1):file | tail -n 1)" = "12" &&
+    test "$(git show $(stg id p1-end):file | tail -n 1)" = "12x"
+'
+
+test_expect_success 'Split unapplied patch' '
+    stg pop -a &&
+    stg split p2 -n p2-c -m "c" -- c &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p1-a p1 p1-end p1-b p2 p2-c" &&
+    test "$(echo $(stg files --bare p2))" = "d" &&
+    test "$(echo $(stg files --bare p2-c))" = "c"
+'
+
+test_expect_success 'Split leaving nothing in the original' '
+    command_error stg split p2 -n foo -m foo -- d 2>err &&
+    grep "nothing would remain" err
+'
+
+test_expect_success 'Split without selected changes' '
+    printf "n\nn\n" | command_error stg split -i p1 -n foo -m foo 2>err &&
+    grep "no changes selected" err
+'
+
+test_expect_success 'Split to existing patch name' '
+    stg push -a &&
+    command_error stg split p1-a -n p1 -m foo -- a 2>err &&
+    grep "patch \`p1\` already exists" err
+'
+
+test_expect_success 'Split naming and describing both patches' '
+    stg new p3 -m "third patch" &&
+    echo e >e &&
+    echo f >f &&
+    echo g >g &&
+    stg add e f g &&
+    stg refresh &&
+    stg split p3 -n p3-f -m "split f" \
+        --original-name p3-e --original-message "split e" -- f &&
+    test "$(echo $(stg series --noprefix))" = "p1-a p1 p1-end p1-b p2 p2-c p3-e p3-f" &&
+    test "$(git log -1 --format=%s $(stg id p3-e))" = "split e" &&
+    test "$(git log -1 --format=%s $(stg id p3-f))" = "split f" &&
+    test "$(echo $(stg files --bare p3-e))" = "e g" &&
+    test "$(echo $(stg files --bare p3-f))" = "f" &&
+    test "$(stg top)" = "p3-f"
+'
+
+test_expect_success 'Split naming and describing both patches below' '
+    stg split --below p3-e -n p3-g -m "split g" \
+        --original-name p3-e2 --original-message "split e again" -- g &&
+    test "$(echo $(stg series --noprefix))" = "p1-a p1 p1-end p1-b p2 p2-c p3-g p3-e2 p3-f" &&
+    test "$(git log -1 --format=%s $(stg id p3-g))" = "split g" &&
+    test "$(git log -1 --format=%s $(stg id p3-e2))" = "split e again" &&
+    test "$(echo $(stg files --bare p3-g))" = "g" &&
+    test "$(echo $(stg files --bare p3-e2))" = "e"
+'
+
+test_expect_success 'Split renaming original to existing patch name' '
+    command_error stg split p3-f --original-name p1 -n foo -m foo -- f 2>err &&
+    grep "patch \`p1\` already exists" err &&
+    command_error stg split p3-e2 --original-name foo -n foo -m foo -- e 2>err &&
+    grep "cannot both be named \`foo\`" err
+'
+
+test_done
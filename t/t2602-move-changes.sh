@@ -0,0 +1,120 @@
+#!/bin/sh
+
+test_description='Test stg move-changes'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize StGit stack' '
+    printf "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n" >file &&
+    git add file &&
+    git commit -m base &&
+    stg init &&
+    stg new p1 -m "first patch" &&
+    printf "1x\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n" >file &&
+    echo a >a &&
+    stg add file a &&
+    stg refresh &&
+    stg new p2 -m "second patch" &&
+    printf "1x\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12x\n" >file &&
+    echo b >b &&
+    stg add file b &&
+    stg refresh &&
+    stg new p3 -m "third patch" &&
+    echo c >c &&
+    stg add c &&
+    stg refresh &&
+    orig_tree=$(git rev-parse HEAD^{tree})
+'
+
+test_expect_success 'Move changes requires a selection' '
+    general_error stg move-changes --to p1 2>err &&
+    grep "required arguments were not provided" err
+'
+
+test_expect_success 'Move changes to the same patch' '
+    command_error stg move-changes --from p1 --to p1 -- a 2>err &&
+    grep "cannot move changes from \`p1\` to itself" err
+'
+
+test_expect_success 'Move changes with nothing selected' '
+    command_error stg move-changes --from p2 --to p1 -- c 2>err &&
+    grep "no changes selected to move" err
+'
+
+test_expect_success 'Move paths to a lower patch' '
+    stg move-changes --from p2 --to p1 -- b &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3" &&
+    test "$(echo $(stg files --bare p1))" = "a b file" &&
+    test "$(echo $(stg files --bare p2))" = "file" &&
+    test "$(git rev-parse HEAD^{tree})" = "$orig_tree" &&
+    test -z "$(git status --porcelain -uno)"
+'
+
+test_expect_success 'Move paths to a higher patch' '
+    stg move-changes --from p1 --to p3 -- a &&
+    test "$(echo $(stg files --bare p1))" = "b file" &&
+    test "$(echo $(stg files --bare p3))" = "a c" &&
+    test "$(git rev-parse HEAD^{tree})" = "$orig_tree" &&
+    test -z "$(git status --porcelain -uno)"
+'
+
+test_expect_success 'Move hunks interactively from the topmost patch' '
+    printf "y\n" | stg move-changes -i --to p2 >out &&
+    grep "Move this hunk" out &&
+    test "$(echo $(stg files --bare p2))" = "a file" &&
+    test "$(echo $(stg files --bare p3))" = "c" &&
+    test "$(git rev-parse HEAD^{tree})" = "$orig_tree"
+'
+
+test_expect_success 'Undo move changes' '
+    stg undo &&
+    test "$(echo $(stg files --bare p2))" = "file" &&
+    test "$(echo $(stg files --bare p3))" = "a c"
+'
+
+test_expect_success 'Move changes between unapplied patches' '
+    stg pop -a &&
+    stg move-changes --from p3 --to p1 -- c &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p1 p2 p3" &&
+    test "$(echo $(stg files --bare p1))" = "b c file" &&
+    test "$(echo $(stg files --bare p3))" = "a" &&
+    stg push -a &&
+    test "$(git rev-parse HEAD^{tree})" = "$orig_tree"
+'
+
+test_expect_success 'Move changes from applied to unapplied patch' '
+    stg goto p1 &&
+    stg move-changes --from p1 --to p3 -- b &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1" &&
+    test "$(echo $(stg files --bare p1))" = "c file" &&
+    test "$(echo $(stg files --bare p3))" = "a b" &&
+    stg push -a &&
+    test "$(git rev-parse HEAD^{tree})" = "$orig_tree"
+'
+
+test_expect_success 'Move changes with conflicts and continue' '
+    stg new p4 -m "fourth patch" &&
+    printf "1x\n2x\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12x\n" >file &&
+    stg refresh &&
+    stg new p5 -m "fifth patch" &&
+    echo e >e &&
+    stg add e &&
+    stg refresh &&
+    conflict stg move-changes --from p1 --to p5 -- file &&
+    test "$(stg top)" = "p4" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p5" &&
+    printf "1\n2x\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12x\n" >file &&
+    stg add file &&
+    conflict stg continue &&
+    test "$(stg top)" = "p5" &&
+    printf "1x\n2x\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12x\n" >file &&
+    stg add file &&
+    stg continue &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3 p4 p5" &&
+    test "$(echo $(stg files --bare p1))" = "c" &&
+    test "$(git show $(stg id p5):file | head -n 1)" = "1x" &&
+    test "$(git show $(stg id p4):file | head -n 1)" = "1" &&
+    test -z "$(git status --porcelain -uno)"
+'
+
+test_done
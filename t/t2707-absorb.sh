@@ -0,0 +1,103 @@
+#!/bin/sh
+
+test_description='Test stg absorb'
+
+. ./test-lib.sh
+
+test_expect_success 'Absorb without patches' '
+    printf "a\nb\nc\nd\ne\nf\ng\nh\n" >file &&
+    echo base >other &&
+    git add file other &&
+    git commit -m base &&
+    stg init &&
+    command_error stg absorb 2>err &&
+    grep "no patches applied" err
+'
+
+test_expect_success 'Setup patches' '
+    stg new p1 -m p1 &&
+    printf "a\nb1\nc\nd\ne\nf\ng\nh\n" >file &&
+    stg refresh &&
+    stg new p2 -m p2 &&
+    printf "a\nb1\nc\nd\ne\nf2\ng\nh\n" >file &&
+    stg refresh &&
+    stg new p3 -m p3 &&
+    echo three >file3 &&
+    stg add file3 &&
+    stg refresh
+'
+
+test_expect_success 'Absorb during an in-progress merge' '
+    git rev-parse HEAD >.git/MERGE_HEAD &&
+    echo change >>file3 &&
+    command_error stg absorb 2>err &&
+    grep "complete the in-progress \`merge\` before trying again" err &&
+    rm .git/MERGE_HEAD &&
+    git checkout file3
+'
+
+test_expect_success 'Absorb without local changes' '
+    command_error stg absorb 2>err &&
+    grep "no local changes to absorb" err
+'
+
+test_expect_success 'Absorb hunks into owning patches' '
+    printf "a\nb11\nc\nd\ne\nf22\ng\nh\n" >file &&
+    echo three-fixed >file3 &&
+    stg absorb &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3" &&
+    test -z "$(git status --porcelain -uno)" &&
+    test "$(git show $(stg id p1):file | sed -n 2p)" = "b11" &&
+    test "$(git show $(stg id p1):file | sed -n 6p)" = "f" &&
+    test "$(git show $(stg id p2):file | sed -n 6p)" = "f22" &&
+    test "$(git show $(stg id p3):file3)" = "three-fixed"
+'
+
+test_expect_success 'Absorb insertion next to owned lines' '
+    printf "a\nb11\nb12\nc\nd\ne\nf22\ng\nh\n" >file &&
+    stg absorb &&
+    test -z "$(git status --porcelain -uno)" &&
+    test "$(git show $(stg id p1):file | sed -n 3p)" = "b12" &&
+    test "$(git diff --stat $(stg id p1) $(stg id p2) | tail -n 1 | grep -o "1 insertion")" = "1 insertion"
+'
+
+test_expect_success 'Leave unattributable hunks in the worktree' '
+    printf "a\nb11\nb12\nc\nd\ne\nf23\ng\nH\n" >file &&
+    echo other >other &&
+    stg absorb 2>err &&
+    grep "2 hunks left in the worktree" err &&
+    test "$(git show $(stg id p2):file | sed -n 7p)" = "f23" &&
+    test "$(git diff HEAD --name-only | tr "\n" " ")" = "file other " &&
+    test "$(git diff HEAD -U0 file | grep "^[-+][^-+]" | tr "\n" " ")" = "-h +H " &&
+    git checkout HEAD -- file other
+'
+
+test_expect_success 'Nothing attributable' '
+    echo other >other &&
+    stg absorb 2>err &&
+    grep "No changes could be attributed" err &&
+    test "$(cat other)" = "other" &&
+    git checkout other
+'
+
+test_expect_success 'Undo absorb' '
+    orig_p1=$(stg id p1) &&
+    printf "a\nB\nb12\nc\nd\ne\nf23\ng\nh\n" >file &&
+    stg absorb &&
+    test "$(stg id p1)" != "$orig_p1" &&
+    stg undo --hard &&
+    test "$(stg id p1)" = "$orig_p1"
+'
+
+test_expect_success 'Keep ambiguous staged hunks staged' '
+    printf "a\nb2\nb12\nc\nd\ne\nf23\ng\nH\n" >file &&
+    git add file &&
+    stg absorb 2>err &&
+    grep "1 hunk left in the worktree" err &&
+    test "$(git show $(stg id p1):file | sed -n 2p)" = "b2" &&
+    test -z "$(git diff --name-only)" &&
+    test "$(git diff --cached -U0 file | grep "^[-+][^-+]" | tr "\n" " ")" = "-h +H " &&
+    git reset --hard
+'
+
+test_done
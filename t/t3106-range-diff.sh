@@ -0,0 +1,112 @@
+#!/bin/sh
+
+test_description='Test stg range-diff'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    cat >>.git/info/exclude <<-\EOF &&
+	/expected.txt
+	/out.txt
+	/state
+	EOF
+    stg init
+'
+
+test_expect_success 'Create some patches' '
+    for i in 1 2 3; do
+        stg new p$i -m "patch $i" &&
+        echo "line $i" >f$i.txt &&
+        stg add f$i.txt &&
+        stg refresh || return 1
+    done &&
+    git rev-parse refs/stacks/master >state
+'
+
+test_expect_success 'No changes between identical states' '
+    stg range-diff $(cat state) $(cat state) >out.txt &&
+    cat >expected.txt <<-\EOF &&
+	= p1
+	= p2
+	= p3
+	EOF
+    test_cmp expected.txt out.txt
+'
+
+test_expect_success 'Default compares with the previous state' '
+    stg pop &&
+    stg range-diff >out.txt &&
+    cat >expected.txt <<-\EOF &&
+	= p1
+	= p2
+	= p3
+	EOF
+    test_cmp expected.txt out.txt &&
+    stg push
+'
+
+test_expect_success 'Modified patch shows interdiff' '
+    stg goto p1 &&
+    before=$(git rev-parse refs/stacks/master) &&
+    echo "more" >>f1.txt &&
+    stg refresh &&
+    stg range-diff $before >out.txt &&
+    grep "^! p1$" out.txt &&
+    grep "^    .*+more" out.txt &&
+    test "$(grep -c "^[=!<>] " out.txt)" = "3"
+'
+
+test_expect_success 'Rebased but unchanged patches are equal' '
+    stg range-diff $(cat state) >out.txt &&
+    grep "^! p1$" out.txt &&
+    grep "^= p2$" out.txt &&
+    grep "^= p3$" out.txt
+'
+
+test_expect_success 'Added, removed, and renamed patches' '
+    stg push -a &&
+    stg rename p2 p2-renamed &&
+    stg delete p3 &&
+    stg new p4 -m "patch 4" &&
+    stg range-diff $(cat state) >out.txt &&
+    grep "^= p2 -> p2-renamed (renamed)$" out.txt &&
+    grep "^> p4$" out.txt &&
+    grep "^< p3$" out.txt
+'
+
+test_expect_success 'Renames are followed through later changes' '
+    before=$(git rev-parse refs/stacks/master) &&
+    stg rename p4 p4-renamed &&
+    stg goto p4-renamed &&
+    echo "line 4" >f4.txt &&
+    stg add f4.txt &&
+    stg refresh &&
+    stg range-diff $before >out.txt &&
+    grep "^! p4 -> p4-renamed (renamed)$" out.txt &&
+    stg range-diff refs/stacks/master $before >out.txt &&
+    grep "^! p4-renamed -> p4 (renamed)$" out.txt
+'
+
+test_expect_success 'Patches with the same message are not paired' '
+    stg new same-a -m "boilerplate" &&
+    echo a >same.txt &&
+    stg add same.txt &&
+    stg refresh &&
+    before=$(git rev-parse refs/stacks/master) &&
+    stg delete same-a &&
+    stg new same-b -m "boilerplate" &&
+    echo b >same.txt &&
+    stg add same.txt &&
+    stg refresh &&
+    stg range-diff $before >out.txt &&
+    grep "^< same-a$" out.txt &&
+    grep "^> same-b$" out.txt &&
+    ! grep "(renamed)" out.txt
+'
+
+test_expect_success 'Invalid stack state' '
+    command_error stg range-diff HEAD 2>err &&
+    grep "error: \`HEAD\` is not a stack state" err
+'
+
+test_done
@@ -0,0 +1,128 @@
+#!/bin/sh
+
+test_description='Test stg undo --list, --to, and --selective'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize StGit stack' '
+    test_commit_bulk --message="base %s" 1 &&
+    stg init &&
+    for i in 1 2 3; do
+        stg new -m "patch $i" p$i &&
+        echo $i >file$i &&
+        stg add file$i &&
+        stg refresh || return 1
+    done
+'
+
+test_expect_success 'List operations' '
+    stg undo --list | sed -e "s/^ *//" >out &&
+    head -n 6 out >actual &&
+    cat >expected <<-\EOF &&
+	1 refresh p3
+	modified: p3; deleted: refresh-temp
+	2 refresh refresh-temp (create temporary patch)
+	created: refresh-temp
+	3 new: p3
+	created: p3
+	EOF
+    test_cmp expected actual
+'
+
+test_expect_success 'Undone operations are not listed' '
+    stg pop &&
+    stg undo &&
+    stg undo --list | sed -e "s/^ *//" >out &&
+    grep "^1 refresh p3" out &&
+    stg redo &&
+    stg undo --list | sed -e "s/^ *//" >out &&
+    head -n 2 out >actual &&
+    cat >expected <<-\EOF &&
+	1 redo 1
+	popped: p3
+	EOF
+    test_cmp expected actual &&
+    stg push &&
+    stg undo --list | sed -e "s/^ *//" >out &&
+    grep "^1 push" out &&
+    grep "^2 redo 1" out &&
+    grep "^3 refresh p3" out
+'
+
+test_expect_success 'Invalid options' '
+    general_error stg undo --list --number 2 &&
+    general_error stg undo --list --hard &&
+    general_error stg undo --to 0 &&
+    general_error stg undo --selective &&
+    command_error stg undo --to 100 2>err &&
+    grep "not enough undo information available" err
+'
+
+test_expect_success 'Undo to an entry' '
+    stg undo --to 3 &&
+    test "$(echo $(stg series --noprefix))" = "p1 p2 p3 refresh-temp" &&
+    test "$(stg files --bare p3)" = "" &&
+    stg redo &&
+    test "$(echo $(stg series --noprefix))" = "p1 p2 p3" &&
+    test "$(stg files --bare p3)" = "file3"
+'
+
+test_expect_success 'Selectively undo an operation' '
+    stg goto p1 &&
+    echo 1 >>file1 &&
+    stg refresh &&
+    stg push -a &&
+    stg new -m "patch 4" p4 &&
+    stg undo --list | sed -e "s/^ *//" >out &&
+    grep "^3 refresh p1" out &&
+    stg undo --to 3 --selective &&
+    test "$(stg top)" = "p4" &&
+    test "$(echo $(stg series --noprefix --all))" = "p1 p2 p3 p4 refresh-temp" &&
+    test_write_lines 1 >expected &&
+    test_cmp expected file1 &&
+    test "$(stg files --bare refresh-temp)" = "file1" &&
+    stg undo --list | sed -e "s/^ *//" >out &&
+    grep "^1 undo --selective: refresh p1" out
+'
+
+test_expect_success 'Undo a selective undo' '
+    stg undo &&
+    test "$(echo $(stg series --noprefix --all))" = "p1 p2 p3 p4" &&
+    test_write_lines 1 1 >expected &&
+    test_cmp expected file1
+'
+
+test_expect_success 'Selective undo of patch changed later' '
+    echo 3 >>file3 &&
+    stg refresh -p p3 &&
+    stg undo --list | sed -e "s/^ *//" >out &&
+    grep "^4 push" out &&
+    command_error stg undo --to 4 --selective 2>err &&
+    grep "patch \`p3\` was changed by a later operation" err
+'
+
+test_expect_success 'Selectively undo patch deletion' '
+    stg delete p2 &&
+    stg new -m "patch 5" p5 &&
+    stg undo --list | sed -e "s/^ *//" >out &&
+    head -n 4 out >actual &&
+    cat >expected <<-\EOF &&
+	1 new: p5
+	created: p5
+	2 delete
+	modified: p3, p4; deleted: p2
+	EOF
+    test_cmp expected actual &&
+    stg undo --to 2 --selective &&
+    test "$(echo $(stg series --noprefix --applied))" = "p1 p3 p4 p5" &&
+    test "$(echo $(stg series --noprefix --unapplied))" = "p2" &&
+    test "$(stg files --bare p2)" = "file2"
+'
+
+test_expect_success 'Selectively undo patch creation' '
+    stg new -m "patch 6" p6 &&
+    stg undo --to 1 --selective &&
+    test "$(echo $(stg series --noprefix --applied))" = "p1 p3 p4 p5"
+'
+
+test_done
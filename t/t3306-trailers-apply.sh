@@ -0,0 +1,140 @@
+#!/bin/sh
+
+test_description='Test stg trailers apply'
+
+. ./test-lib.sh
+
+message_id () {
+    sed -n -e "s/^Message-I[Dd]: *//p" "$1"
+}
+
+test_expect_success 'Initialize repository and import patch series' '
+    test_commit_bulk --message="base %s" 1 &&
+    stg init &&
+    git checkout -b series &&
+    for i in 1 2 3; do
+        echo $i >file$i &&
+        git add file$i &&
+        git commit -m "patch $i" -m "Description $i." \
+            -m "Signed-off-by: Author <author@example.com>" || return 1
+    done &&
+    git format-patch -q --thread --cover-letter -o series HEAD~3 &&
+    git checkout master &&
+    cat series/*.patch >series.mbox &&
+    stg import -M --message-id series.mbox &&
+    stg pop patch-3
+'
+
+test_expect_success 'Mbox option is required' '
+    general_error stg trailers apply &&
+    general_error stg trailers
+'
+
+test_expect_success 'Apply trailers from replies' '
+    cat >replies.mbox <<-EOF &&
+	From nobody Mon Sep 17 00:00:00 2001
+	From: Reviewer <reviewer@example.com>
+	Subject: Re: [PATCH 1/3] patch 1
+	Date: Thu, 1 Jan 2026 00:00:00 +0000
+	Message-ID: <reply-1@example.com>
+	In-Reply-To: $(message_id series/0001-*.patch)
+	References: $(message_id series/0000-*.patch) $(message_id series/0001-*.patch)
+
+	> Signed-off-by: Author <author@example.com>
+	> Reviewed-by: Quoted <quoted@example.com>
+
+	Reviewed-by: Reviewer <reviewer@example.com>
+
+	From nobody Mon Sep 17 00:00:00 2001
+	From: Tester <tester@example.com>
+	Subject: Re: [PATCH 1/3] patch 1
+	Date: Thu, 1 Jan 2026 00:00:00 +0000
+	Message-ID: <reply-2@example.com>
+	In-Reply-To: <reply-1@example.com>
+	References: $(message_id series/0000-*.patch) $(message_id series/0001-*.patch)
+	 <reply-1@example.com>
+
+	Tested-by: Tester <tester@example.com>
+
+	From nobody Mon Sep 17 00:00:00 2001
+	From: Acker <acker@example.com>
+	Subject: Re: [PATCH 0/3] *** SUBJECT HERE ***
+	Date: Thu, 1 Jan 2026 00:00:00 +0000
+	Message-ID: <reply-3@example.com>
+	In-Reply-To: $(message_id series/0000-*.patch)
+
+	Acked-by: Acker <acker@example.com>
+	EOF
+    cat series.mbox replies.mbox >thread.mbox &&
+    stg trailers apply --mbox thread.mbox &&
+    test "$(echo $(stg series --noprefix --applied))" = "patch-1 patch-2" &&
+    test "$(echo $(stg series --noprefix --unapplied))" = "patch-3" &&
+    cat >expected <<-EOF &&
+	patch 1
+
+	Description 1.
+
+	Signed-off-by: Author <author@example.com>
+	Message-ID: $(message_id series/0001-*.patch)
+	Reviewed-by: Reviewer <reviewer@example.com>
+	Tested-by: Tester <tester@example.com>
+	Acked-by: Acker <acker@example.com>
+
+	EOF
+    git log -1 --format=%B $(stg id patch-1) |
+    sed -e "s/^Message-Id:/Message-ID:/" >actual &&
+    test_cmp expected actual &&
+    for p in patch-2 patch-3; do
+        git log -1 --format=%B $(stg id $p) >msg &&
+        grep "^Acked-by: Acker <acker@example.com>$" msg &&
+        ! grep "Reviewed-by" msg &&
+        ! grep "Tested-by" msg || return 1
+    done &&
+    test "$(stg id patch-2^)" = "$(stg id patch-1)" &&
+    test_path_is_file file2 &&
+    test_path_is_missing file3
+'
+
+test_expect_success 'Trailers are not applied twice' '
+    stg id patch-1 >before &&
+    stg trailers apply --mbox thread.mbox 2>err &&
+    grep "No new trailers to apply" err &&
+    stg id patch-1 >after &&
+    test_cmp before after
+'
+
+test_expect_success 'Apply trailers from replies without the patches' '
+    cat >more-replies.mbox <<-EOF &&
+	From nobody Mon Sep 17 00:00:00 2001
+	From: Reviewer <reviewer@example.com>
+	Subject: Re: [PATCH 3/3] patch 3
+	Date: Thu, 1 Jan 2026 00:00:00 +0000
+	Message-ID: <reply-4@example.com>
+	In-Reply-To: $(message_id series/0003-*.patch)
+
+	Reviewed-by: Reviewer <reviewer@example.com>
+
+	From nobody Mon Sep 17 00:00:00 2001
+	From: Other <other@example.com>
+	Subject: Re: [PATCH 0/3] *** SUBJECT HERE ***
+	Date: Thu, 1 Jan 2026 00:00:00 +0000
+	Message-ID: <reply-5@example.com>
+	In-Reply-To: $(message_id series/0000-*.patch)
+
+	Acked-by: Other <other@example.com>
+	EOF
+    stg trailers apply -M more-replies.mbox &&
+    git log -1 --format=%B $(stg id patch-3) >msg &&
+    grep "^Reviewed-by: Reviewer <reviewer@example.com>$" msg &&
+    ! grep "Other" msg &&
+    test "$(echo $(stg series --noprefix --unapplied))" = "patch-3" &&
+    test "$(stg top)" = "patch-2"
+'
+
+test_expect_success 'Undo trailers apply' '
+    stg undo &&
+    git log -1 --format=%B $(stg id patch-3) >msg &&
+    ! grep "Reviewed-by" msg
+'
+
+test_done
@@ -0,0 +1,85 @@
+#!/bin/sh
+
+test_description='Test picking and importing patches from another repository'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize repositories' '
+    test_commit_bulk --message="base %s" 2 &&
+    stg init &&
+    git clone . other &&
+    (
+        cd other &&
+        git checkout -b feature &&
+        stg init &&
+        for i in 1 2 3 4; do
+            stg new -m "patch $i" p$i &&
+            echo $i >file$i &&
+            stg add file$i &&
+            stg refresh || return 1
+        done &&
+        stg edit --authname "Other Author" --authdate "2020-01-02 03:04:05 +0100" p2 &&
+        stg pop p4 &&
+        git checkout -b nostack HEAD
+    )
+'
+
+test_expect_success 'Missing stack in other repository' '
+    command_error stg pick --from-repo other p1 2>err &&
+    grep "branch \`nostack\` of \`other\` is not initialized with StGit" err &&
+    command_error stg pick --from-repo not-a-repo --branch feature p1 &&
+    general_error stg pick --branch feature p1 &&
+    general_error stg pick --from-repo other --branch feature --revert p1
+'
+
+test_expect_success 'Pick patches with exact commits' '
+    stg pick --from-repo other --branch feature p1..p2 &&
+    test "$(echo $(stg series --noprefix --applied))" = "p1 p2" &&
+    test "$(stg id p1)" = "$(git -C other rev-parse feature~2)" &&
+    test "$(stg id p2)" = "$(git -C other rev-parse feature~1)" &&
+    test "$(git log -1 --format=%an $(stg id p2))" = "Other Author" &&
+    test_write_lines 2 >expected &&
+    test_cmp expected file2
+'
+
+test_expect_success 'Pick unapplied patch without applying it' '
+    stg pick --from-repo other --branch feature --noapply p4 &&
+    test "$(echo $(stg series --noprefix --unapplied))" = "p4" &&
+    test "$(stg id p4)" = "$(cd other && stg id -b feature p4)" &&
+    test_path_is_missing file4
+'
+
+test_expect_success 'Picked patch names are made unique' '
+    stg pick --from-repo other --branch feature p1 &&
+    test "$(stg top)" = "p3" &&
+    test "$(git log -1 --format=%s $(stg id p3))" = "patch 1" &&
+    stg pick --from-repo other --branch feature --name renamed p3 &&
+    test "$(stg top)" = "renamed" &&
+    test "$(git log -1 --format=%s $(stg id renamed))" = "patch 3" &&
+    stg delete --top &&
+    stg delete p3 p4
+'
+
+test_expect_success 'Import applied patches from another repository' '
+    stg branch --create imported master~2 &&
+    stg import --from-repo other --branch feature &&
+    test "$(echo $(stg series --noprefix --applied))" = "p1 p2 p3" &&
+    test "$(stg id p3)" = "$(git -C other rev-parse feature)"
+'
+
+test_expect_success 'Import range from another repository' '
+    stg branch --create imported2 master~2 &&
+    stg import --from-repo other --branch feature p1..p2 &&
+    test "$(echo $(stg series --noprefix --applied))" = "p1 p2" &&
+    general_error stg import --from-repo other --series p1
+'
+
+test_expect_success 'Use current branch of other repository' '
+    git -C other checkout feature &&
+    stg branch --create imported3 master~2 &&
+    stg import --from-repo other p2 &&
+    test "$(echo $(stg series --noprefix))" = "p2" &&
+    test "$(git log -1 --format=%an $(stg id p2))" = "Other Author"
+'
+
+test_done
@@ -0,0 +1,127 @@
+#!/bin/sh
+
+test_description='Test patch metadata'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    test_commit_bulk --message="p%s" 3 &&
+    stg uncommit -n 3 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3"
+'
+
+test_expect_success 'No metadata by default' '
+    stg meta list p1 >out &&
+    test_must_be_empty out &&
+    command_error stg meta get p1 status 2>err &&
+    grep "error: patch \`p1\` has no \`status\` metadata" err
+'
+
+test_expect_success 'Set and get metadata' '
+    stg meta set p1 status ready &&
+    stg meta set p1 upstream-url https://example.com/p1 &&
+    stg meta set p2 status wip &&
+    test "$(stg meta get p1 status)" = "ready" &&
+    test "$(stg meta get p1 upstream-url)" = "https://example.com/p1"
+'
+
+test_expect_success 'Invalid metadata key' '
+    general_error stg meta set p1 "bad key" value 2>err &&
+    grep "invalid metadata key" err
+'
+
+test_expect_success 'List metadata' '
+    cat >expected <<-\EOF &&
+	status=ready
+	upstream-url=https://example.com/p1
+	EOF
+    stg meta list p1 >out &&
+    test_cmp expected out &&
+    cat >expected <<-\EOF &&
+	p1:status=ready
+	p1:upstream-url=https://example.com/p1
+	p2:status=wip
+	EOF
+    stg meta list >out &&
+    test_cmp expected out
+'
+
+test_expect_success 'Multi-line metadata value' '
+    stg meta set p3 notes "first line
+second line" &&
+    stg meta get p3 notes >out &&
+    test_write_lines "first line" "second line" >expected &&
+    test_cmp expected out
+'
+
+test_expect_success 'Series shows metadata columns' '
+    stg series --meta status >out &&
+    test "$(head -n1 out)" = "+ p1 # ready" &&
+    test "$(tail -n1 out)" = "> p3 # " &&
+    stg series --noprefix -M status --meta notes >out &&
+    test "$(head -n1 out)" = "p1 # ready # " &&
+    test "$(tail -n1 out)" = "p3 #       # first line"
+'
+
+test_expect_success 'Metadata follows refreshed and pushed patches' '
+    stg pop -a &&
+    stg push p2 &&
+    echo change >>p2.t &&
+    stg refresh &&
+    test "$(stg meta get p2 status)" = "wip" &&
+    stg push -a &&
+    test "$(stg meta get p1 status)" = "ready"
+'
+
+test_expect_success 'Metadata follows renamed patches' '
+    stg rename p1 first &&
+    test "$(stg meta get first status)" = "ready" &&
+    stg meta list >out &&
+    ! grep "^p1:" out
+'
+
+test_expect_success 'Undo metadata change' '
+    stg meta set first status merged &&
+    test "$(stg meta get first status)" = "merged" &&
+    stg undo &&
+    test "$(stg meta get first status)" = "ready"
+'
+
+test_expect_success 'Unset metadata' '
+    stg meta set --unset first upstream-url &&
+    stg meta list first >out &&
+    test "$(cat out)" = "status=ready" &&
+    command_error stg meta set --unset first upstream-url 2>err &&
+    grep "error: patch \`first\` has no \`upstream-url\` metadata" err
+'
+
+test_expect_success 'Metadata is discarded with deleted patches' '
+    stg delete first &&
+    stg new -m first first &&
+    stg meta list first >out &&
+    test_must_be_empty out
+'
+
+test_expect_success 'Metadata is not retained by committed patches' '
+    stg commit p2 &&
+    stg uncommit &&
+    stg meta list p2 >out &&
+    test_must_be_empty out
+'
+
+test_expect_success 'Upgrade stack state from version 5' '
+    git show refs/stacks/master:stack.json >stack.json &&
+    sed -e "s/\"version\": [0-9]*/\"version\": 5/" stack.json >stack-v5.json &&
+    blob=$(git hash-object -w stack-v5.json) &&
+    patches=$(git rev-parse refs/stacks/master:patches) &&
+    tree=$(printf "040000 tree %s\tpatches\n100644 blob %s\tstack.json\n" $patches $blob | git mktree) &&
+    commit=$(git commit-tree -p refs/stacks/master -m "version 5" $tree) &&
+    git update-ref refs/stacks/master $commit &&
+    stg series >/dev/null 2>err &&
+    grep "Upgraded master to stack format version 6" err &&
+    git show refs/stacks/master:stack.json >stack.json &&
+    grep "\"version\": 6" stack.json &&
+    test "$(stg meta get p3 notes | head -n1)" = "first line"
+'
+
+test_done
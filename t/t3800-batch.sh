@@ -0,0 +1,141 @@
+#!/bin/sh
+
+test_description='Test atomic batch scripts'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    test_commit_bulk --message="p%s" 3 &&
+    stg uncommit -n 3 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3"
+'
+
+test_expect_success 'Empty script does nothing' '
+    stg log >log-before &&
+    printf "# only a comment\n\n" | stg batch &&
+    stg log >log-after &&
+    test_cmp log-before log-after
+'
+
+test_expect_success 'Syntax errors are reported before doing anything' '
+    cat >script <<-\EOF &&
+	new p4
+	frobnicate p1
+	EOF
+    command_error stg batch script 2>err &&
+    grep "error: line 2: unrecognized subcommand .frobnicate." err &&
+    test "$(echo $(stg series --noprefix))" = "p1 p2 p3"
+'
+
+test_expect_success 'Unclosed quote is a syntax error' '
+    echo "new p4 -m \"oops" >script &&
+    command_error stg batch script 2>err &&
+    grep "error: line 1: unclosed quote" err
+'
+
+test_expect_success 'Run script from file' '
+    cat >script <<-\EOF &&
+	# Reorganize the stack
+	new p4 -m "fourth patch"
+	float p1
+	hide p2
+	rename p3 renamed
+	EOF
+    stg batch script &&
+    test "$(echo $(stg series --applied --noprefix))" = "renamed p4 p1" &&
+    test "$(echo $(stg series --hidden --noprefix))" = "p2" &&
+    test "$(git log -1 --format=%s $(stg id p4))" = "fourth patch"
+'
+
+test_expect_success 'Script produces a single log entry' '
+    test "$(stg log -n1 | sed -e "s/.*   //")" = "batch" &&
+    stg undo &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3" &&
+    test "$(echo $(stg series --hidden --noprefix))" = ""
+'
+
+test_expect_success 'Run script from stdin' '
+    printf "pop\npop p1\npush\n" | stg batch - &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2 p1" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p3" &&
+    echo "goto p3" | stg batch &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2 p1 p3"
+'
+
+test_expect_success 'Later operations see earlier ones' '
+    printf "new p4\nsink p4\nrename p4 p0\ndelete p2\n" | stg batch &&
+    test "$(echo $(stg series --applied --noprefix))" = "p0 p1 p3" &&
+    printf "new tmp\ndelete tmp\n" | stg batch &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p3" &&
+    ! git show-ref --verify --quiet refs/patches/master/tmp
+'
+
+test_expect_success 'Failing operation rolls back everything' '
+    stg log >log-before &&
+    cat >script <<-\EOF &&
+	new p5
+	float p0
+	push p1
+	EOF
+    command_error stg batch script 2>err &&
+    grep "error: line 3: patch .p1. is already applied" err &&
+    grep "batch aborted (all changes rolled back)" err &&
+    test "$(echo $(stg series --noprefix))" = "p0 p1 p3" &&
+    stg log >log-after &&
+    test_cmp log-before log-after
+'
+
+test_expect_success 'Refuse to run with local changes' '
+    echo change >>1.t &&
+    echo "pop" >script &&
+    command_error stg batch script 2>err &&
+    grep "worktree not clean" err &&
+    git checkout 1.t
+'
+
+test_expect_success 'Refresh local changes into new patch' '
+    echo "new content" >new.t &&
+    git add new.t &&
+    cat >script <<-\EOF &&
+	new p5 -m "add new file"
+	refresh
+	float p0
+	EOF
+    stg batch script &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p3 p5 p0" &&
+    test "$(echo $(stg files --bare p5))" = "new.t" &&
+    test "$(echo $(git status --porcelain --untracked-files=no))" = "" &&
+    test "$(cat new.t)" = "new content"
+'
+
+test_expect_success 'Failed refresh script keeps local changes' '
+    echo "more content" >>new.t &&
+    cat >script <<-\EOF &&
+	refresh
+	goto nonexistent
+	EOF
+    command_error stg batch script 2>err &&
+    grep "error: line 2: patch .nonexistent. does not exist" err &&
+    test_write_lines "new content" "more content" >expected &&
+    test_cmp expected new.t &&
+    git reset --hard
+'
+
+test_expect_success 'Push conflicts cause batch to fail' '
+    stg new -m conflicting conflicting &&
+    echo "conflict" >1.t &&
+    stg refresh &&
+    stg pop conflicting &&
+    echo "other" >1.t &&
+    stg new -m other other &&
+    stg refresh &&
+    stg log >log-before &&
+    echo "push conflicting" | command_error stg batch 2>err &&
+    grep "batch aborted (all changes rolled back)" err &&
+    stg log >log-after &&
+    test_cmp log-before log-after &&
+    test "$(stg top)" = "other" &&
+    test "$(echo $(git status --porcelain --untracked-files=no))" = ""
+'
+
+test_done
@@ -0,0 +1,86 @@
+#!/bin/sh
+
+test_description='Test stg exec'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize the StGit repository' '
+    cat >>.git/info/exclude <<-\EOF &&
+	/err
+	/exec.log
+	/out
+	EOF
+    stg init
+'
+
+test_expect_success 'No applied patches' '
+    command_error stg exec true 2>err &&
+    grep "no patches applied" err
+'
+
+test_expect_success 'Create some patches' '
+    for i in 1 2 3 4; do
+        stg new p$i -m "patch $i" &&
+        echo "line $i" >f$i.txt &&
+        stg add f$i.txt &&
+        stg refresh || return 1
+    done
+'
+
+test_expect_success 'Run command on each patch' '
+    stg exec -- "stg top >>exec.log" >out &&
+    test "$(echo $(cat exec.log))" = "p1 p2 p3 p4" &&
+    grep "^pass p1$" out &&
+    grep "^pass p4$" out &&
+    test "$(stg top)" = "p4" &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3 p4"
+'
+
+test_expect_success 'Run command with multiple arguments' '
+    rm exec.log &&
+    stg exec test -e f1.txt >out &&
+    test "$(grep -c "^pass" out)" = "4"
+'
+
+test_expect_success 'Stop at the first failing patch' '
+    command_error stg exec -- test -e f3.txt >out 2>err &&
+    grep "^FAIL p1 .*exited with code 1" out &&
+    test "$(grep -c "^FAIL" out)" = "1" &&
+    grep "\`test -e f3.txt\` failed for 1 patch" err &&
+    test "$(stg top)" = "p1" &&
+    test "$(echo $(stg series --unapplied --noprefix))" = "p2 p3 p4" &&
+    stg push -a
+'
+
+test_expect_success 'Keep going after failures' '
+    command_error stg exec --keep-going -- test -e f3.txt >out 2>err &&
+    grep "^FAIL p1" out &&
+    grep "^FAIL p2" out &&
+    grep "^pass p3" out &&
+    grep "^pass p4" out &&
+    grep "failed for 2 patches" err &&
+    test "$(stg top)" = "p4"
+'
+
+test_expect_success 'Run command on a range of patches' '
+    stg exec --range p2..p3 -- "stg top >>exec.log" >out &&
+    test "$(echo $(cat exec.log))" = "p2 p3" &&
+    test "$(grep -c "^pass" out)" = "2" &&
+    test "$(stg top)" = "p4"
+'
+
+test_expect_success 'Range must be applied patches' '
+    stg pop &&
+    command_error stg exec --range p4 true 2>err &&
+    grep "unapplied patch \`p4\` is not allowed" err &&
+    stg push
+'
+
+test_expect_success 'Dirty worktree is refused' '
+    echo dirty >>f1.txt &&
+    command_error stg exec true 2>err &&
+    grep "worktree not clean" err &&
+    git checkout f1.txt
+'
+
+test_done
@@ -0,0 +1,98 @@
+#!/bin/sh
+
+test_description='Test stg bisect'
+
+. ./test-lib.sh
+
+test_expect_success 'Bisect without patches' '
+    stg init &&
+    command_error stg bisect start 2>err &&
+    grep "no patches applied" err
+'
+
+test_expect_success 'Setup stack' '
+    for i in 1 2 3 4 5 6 7 8; do
+        stg new p$i -m p$i &&
+        if test $i -eq 5; then echo bug >>file; else echo $i >>file; fi &&
+        stg add file &&
+        stg refresh || return 1
+    done &&
+    write_script test-good.sh <<-\EOF
+	! grep -q bug file
+	EOF
+'
+
+test_expect_success 'No bisect in progress' '
+    command_error stg bisect good 2>err &&
+    grep "no bisect in progress" err &&
+    command_error stg bisect reset
+'
+
+test_expect_success 'Bisect interactively' '
+    stg bisect start 2>err &&
+    grep "Bisecting: 7 patches left to test; now at \`p4\`" err &&
+    test "$(stg top)" = "p4" &&
+    stg bisect good 2>err &&
+    grep "now at \`p6\`" err &&
+    stg bisect bad 2>err &&
+    grep "now at \`p5\`" err &&
+    stg bisect bad >out &&
+    grep "\`p5\` is the first bad patch" out &&
+    test "$(stg top)" = "p5"
+'
+
+test_expect_success 'Bisect already in progress' '
+    command_error stg bisect start 2>err &&
+    grep "bisect already in progress" err
+'
+
+test_expect_success 'Reset returns to the original top' '
+    stg bisect reset &&
+    test "$(stg top)" = "p8" &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2 p3 p4 p5 p6 p7 p8" &&
+    command_error stg bisect reset
+'
+
+test_expect_success 'Bisect with explicit bad and good patches' '
+    stg bisect start p7 p3 &&
+    test "$(stg top)" = "p5" &&
+    command_error stg bisect good p7 2>err &&
+    grep "good patch \`p7\` is not below bad patch \`p7\`" err &&
+    stg bisect bad &&
+    test "$(stg top)" = "p4" &&
+    stg bisect good >out &&
+    grep "\`p5\` is the first bad patch" out &&
+    stg bisect reset
+'
+
+test_expect_success 'Bisect run' '
+    stg bisect start &&
+    stg bisect run ./test-good.sh >out &&
+    grep "\`p5\` is the first bad patch" out &&
+    test "$(stg top)" = "p5" &&
+    stg bisect reset &&
+    test "$(stg top)" = "p8"
+'
+
+test_expect_success 'Bisect run with skipped patches' '
+    write_script test-skip.sh <<-\EOF &&
+	case "$(stg top)" in
+	    p4|p5) exit 125 ;;
+	esac
+	./test-good.sh
+	EOF
+    stg bisect start &&
+    stg bisect run ./test-skip.sh >out &&
+    grep "first bad patch could be any of" out &&
+    test "$(echo $(sed -e "1,/could be any of/d" out))" = "p4 p5 p6" &&
+    stg bisect reset
+'
+
+test_expect_success 'Bisect run aborts on high exit code' '
+    stg bisect start &&
+    command_error stg bisect run "exit 200" 2>err &&
+    grep "bisect run aborted" err &&
+    stg bisect reset
+'
+
+test_done
@@ -0,0 +1,89 @@
+#!/bin/sh
+
+test_description='Test stg publish-each'
+
+. ./test-lib.sh
+
+remote_id () {
+    git --git-dir=remote.git rev-parse --verify --quiet "$1"
+}
+
+test_expect_success 'Initialize StGit stack and remote' '
+    git init --bare remote.git &&
+    git remote add origin remote.git &&
+    test_commit_bulk --message="base %s" 1 &&
+    stg init &&
+    for i in 1 2 3; do
+        stg new -m "patch $i" p$i &&
+        echo $i >file$i &&
+        stg add file$i &&
+        stg refresh || return 1
+    done
+'
+
+test_expect_success 'Remote and prefix are required' '
+    general_error stg publish-each --remote origin 2>err &&
+    grep "required arguments were not provided" err
+'
+
+test_expect_success 'Publish applied patches' '
+    stg publish-each --remote origin --prefix review 2>err &&
+    grep "Publishing \`p1\` to \`refs/heads/review/p1\`" err &&
+    test "$(remote_id refs/heads/review/p1)" = "$(stg id p1)" &&
+    test "$(remote_id refs/heads/review/p2)" = "$(stg id p2)" &&
+    test "$(remote_id refs/heads/review/p3)" = "$(stg id p3)"
+'
+
+test_expect_success 'Publish with nothing changed' '
+    stg publish-each --remote origin --prefix review 2>err &&
+    grep "All applied patches are already published to \`origin\`" err
+'
+
+test_expect_success 'Publish only changed patches' '
+    stg edit p2 -m "patch 2 reworded" &&
+    stg publish-each --remote origin --prefix review 2>err &&
+    ! grep "\`p1\`" err &&
+    grep "\`p2\`" err &&
+    grep "\`p3\`" err &&
+    test "$(remote_id refs/heads/review/p2)" = "$(stg id p2)" &&
+    test "$(remote_id refs/heads/review/p3)" = "$(stg id p3)"
+'
+
+test_expect_success 'Publish deletes branches of deleted patches' '
+    stg delete p3 &&
+    stg publish-each --remote origin --prefix review 2>err &&
+    grep "Deleting \`refs/heads/review/p3\`" err &&
+    test -z "$(remote_id refs/heads/review/p3)"
+'
+
+test_expect_success 'Publish keeps branches of unapplied patches' '
+    stg pop p2 &&
+    stg publish-each --remote origin --prefix review 2>err &&
+    grep "already published" err &&
+    test "$(remote_id refs/heads/review/p2)" = "$(stg id p2)" &&
+    stg push p2
+'
+
+test_expect_success 'Dry run does not push' '
+    stg rename p1 q1 &&
+    stg publish-each -n --remote origin --prefix review 2>err &&
+    grep "Publishing \`q1\` to \`refs/heads/review/q1\`" err &&
+    grep "Deleting \`refs/heads/review/p1\`" err &&
+    test -z "$(remote_id refs/heads/review/q1)" &&
+    test -n "$(remote_id refs/heads/review/p1)"
+'
+
+test_expect_success 'Publish renamed patch' '
+    stg publish-each --remote origin --prefix review &&
+    test "$(remote_id refs/heads/review/q1)" = "$(stg id q1)" &&
+    test -z "$(remote_id refs/heads/review/p1)"
+'
+
+test_expect_success 'Publish to full reference prefix' '
+    stg publish-each --remote origin --prefix refs/for/main/ &&
+    test "$(remote_id refs/for/main/q1)" = "$(stg id q1)" &&
+    test "$(remote_id refs/for/main/p2)" = "$(stg id p2)" &&
+    test -z "$(remote_id refs/heads/review/q1)"
+'
+
+test_done
@@ -0,0 +1,110 @@
+#!/bin/sh
+
+test_description='Test stg patch-history and stg revert-patch'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize StGit stack' '
+    test_commit_bulk --message="base %s" 1 &&
+    stg init &&
+    stg new -m "patch one" p1 &&
+    echo one >file1 &&
+    stg add file1 &&
+    stg refresh &&
+    echo two >>file1 &&
+    stg refresh &&
+    stg edit -m "patch one, reworded" &&
+    stg new -m "patch two" p2 &&
+    echo a >file2 &&
+    stg add file2 &&
+    stg refresh
+'
+
+test_expect_success 'List versions of a patch' '
+    stg patch-history p1 >out &&
+    test_line_count = 4 out &&
+    grep "^p1@{0} $(git rev-parse --short $(stg id p1)) .* edit" out &&
+    grep "^p1@{1} .* refresh" out &&
+    grep "^p1@{2} .* refresh" out &&
+    grep "^p1@{3} .* new" out
+'
+
+test_expect_success 'Default to topmost patch' '
+    stg patch-history >out &&
+    test_line_count = 2 out &&
+    grep "^p2@{0} " out &&
+    grep "^p2@{1} .* new" out
+'
+
+test_expect_success 'Show interdiff between versions' '
+    stg patch-history --diff 2..1 p1 >out &&
+    grep "+two" out &&
+    stg patch-history --diff 1..0 p1 >out &&
+    grep "reworded" out
+'
+
+test_expect_success 'Invalid versions' '
+    general_error stg patch-history --diff 1-2 p1 2>err &&
+    grep "expected version range" err &&
+    command_error stg patch-history --diff 0..9 p1 2>err &&
+    grep "\`p1@{9}\` does not exist" err &&
+    general_error stg revert-patch p1 2>err &&
+    grep "expected patch version" err &&
+    command_error stg revert-patch p1@{9} 2>err &&
+    grep "\`p1@{9}\` does not exist" err
+'
+
+test_expect_success 'Revert applied patch to previous version' '
+    stg revert-patch p1@{1} &&
+    test "$(stg top)" = "p2" &&
+    test "$(git log -1 --format=%s $(stg id p1))" = "patch one" &&
+    test_write_lines one two >expected &&
+    test_cmp expected file1 &&
+    test "$(stg id p1)" = "$(git rev-parse $(stg id p2)~1)"
+'
+
+test_expect_success 'Reverting is recorded as a new version' '
+    stg patch-history p1 >out &&
+    grep "^p1@{0} .* revert-patch p1@{1}" out &&
+    grep "^p1@{1} .* edit" out &&
+    test_line_count = 4 out
+'
+
+test_expect_success 'Revert to current version' '
+    stg revert-patch p1@{0} 2>err &&
+    grep "\`p1\` is already at version @{0}" err
+'
+
+test_expect_success 'Revert unapplied patch' '
+    stg pop -a &&
+    stg revert-patch p1@{2} &&
+    test "$(stg series --applied -c)" = "0" &&
+    test "$(stg files --bare p1)" = "file1" &&
+    test "$(git show $(stg id p1):file1)" = "one" &&
+    stg undo &&
+    test "$(git show $(stg id p1):file1 | wc -l)" = "2" &&
+    stg push -a
+'
+
+test_expect_success 'Revert with conflicts' '
+    stg new -m "patch three" p3 &&
+    echo three >>file1 &&
+    stg refresh &&
+    stg goto p1 &&
+    echo four >>file1 &&
+    stg refresh &&
+    stg revert-patch p1@{1} &&
+    stg goto p3 &&
+    stg patch-history p1 >out &&
+    grep "^p1@{2} .* revert-patch p1@{2}" out &&
+    conflict stg revert-patch p1@{2} &&
+    test "$(stg top)" = "p3" &&
+    test_write_lines one three >file1 &&
+    stg add file1 &&
+    stg refresh &&
+    test "$(git show $(stg id p1):file1)" = "one" &&
+    test_write_lines one three >expected &&
+    test_cmp expected file1
+'
+
+test_done
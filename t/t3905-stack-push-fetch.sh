@@ -0,0 +1,154 @@
+#!/bin/sh
+
+test_description='Test stg stack push and stg stack fetch'
+
+. ./test-lib.sh
+
+remote_id () {
+    git --git-dir=remote.git rev-parse --verify --quiet "$1"
+}
+
+test_expect_success 'Initialize StGit stack and remote' '
+    git init --bare remote.git &&
+    git remote add origin remote.git &&
+    test_commit_bulk --message="base %s" 1 &&
+    git push origin master &&
+    stg branch --create dev &&
+    for i in 1 2 3 4; do
+        stg new -m "patch $i" p$i &&
+        echo $i >file$i &&
+        stg add file$i &&
+        stg refresh || return 1
+    done &&
+    stg pop -n 2 &&
+    stg hide p4
+'
+
+test_expect_success 'Invalid remote' '
+    command_error stg stack push nosuchremote 2>err &&
+    grep "remote \`nosuchremote\` does not exist" err &&
+    command_error stg stack fetch origin 2>err &&
+    grep "\`origin\` does not have a stack for branch \`dev\`" err
+'
+
+test_expect_success 'Push stack' '
+    stg stack push origin 2>err &&
+    grep "Pushed stack \`dev\` to \`origin\`" err &&
+    test "$(remote_id refs/stacks/dev)" = "$(git rev-parse refs/stacks/dev)" &&
+    test "$(git rev-parse refs/remote-stacks/origin/dev)" = "$(git rev-parse refs/stacks/dev)" &&
+    for i in 1 2 3 4; do
+        test "$(remote_id refs/patches/dev/p$i)" = "$(stg id p$i)" || return 1
+    done &&
+    stg stack push origin 2>err &&
+    grep "Stack \`dev\` is already up to date on \`origin\`" err
+'
+
+test_expect_success 'Fetch stack into new branch' '
+    git clone remote.git clone &&
+    (
+        cd clone &&
+        stg stack fetch origin dev 2>err &&
+        grep "Created branch \`dev\` with stack from \`origin\`" err &&
+        stg branch dev &&
+        test "$(echo $(stg series --noprefix --applied))" = "p1 p2" &&
+        test "$(echo $(stg series --noprefix --unapplied))" = "p3" &&
+        test "$(echo $(stg series --noprefix --hidden))" = "p4" &&
+        test "$(stg id p4)" = "$(git -C .. rev-parse refs/patches/dev/p4)" &&
+        test "$(git rev-parse refs/patches/dev/p3)" = "$(stg id p3)" &&
+        test_path_is_file file2 &&
+        test_path_is_missing file3
+    )
+'
+
+test_expect_success 'Push is refused when remote is ahead' '
+    stg push p3 &&
+    stg stack push origin &&
+    (
+        cd clone &&
+        command_error stg stack push origin 2>err &&
+        grep "use \`stg stack fetch\` to integrate them" err
+    )
+'
+
+test_expect_success 'Fetch fast-forwards stack' '
+    (
+        cd clone &&
+        stg stack fetch origin 2>err &&
+        grep "Updated stack \`dev\` from \`origin\`: 3 applied, 0 unapplied, 1 hidden" err &&
+        test "$(stg top)" = "p3" &&
+        test_path_is_file file3 &&
+        test "$(git rev-parse HEAD)" = "$(stg id p3)" &&
+        stg undo &&
+        test "$(stg top)" = "p2" &&
+        stg redo
+    )
+'
+
+test_expect_success 'Fetch with local stack ahead' '
+    (
+        cd clone &&
+        stg new -m "patch 5" p5 &&
+        stg stack fetch origin 2>err &&
+        grep "Stack \`dev\` is ahead of \`origin\`" err &&
+        stg stack push origin
+    ) &&
+    stg stack fetch origin &&
+    test "$(stg top)" = "p5"
+'
+
+test_expect_success 'Fetch requires clean worktree' '
+    (
+        cd clone &&
+        stg new -m "patch 6" p6 &&
+        stg stack push origin
+    ) &&
+    echo dirty >>file1 &&
+    command_error stg stack fetch origin 2>err &&
+    git checkout file1 &&
+    stg stack fetch origin &&
+    test "$(stg top)" = "p6"
+'
+
+test_expect_success 'Diverged stacks are detected' '
+    stg edit -m "patch one from origin" p1 &&
+    stg delete p6 &&
+    stg stack push origin &&
+    (
+        cd clone &&
+        stg edit -m "patch one from clone" p1 &&
+        stg edit -m "patch two from clone" p2 &&
+        command_error stg stack push origin 2>err &&
+        grep "local and \`origin\` stacks of branch \`dev\` have diverged; patches changed on both sides: \`p1\`$" err &&
+        command_error stg stack fetch origin 2>err &&
+        grep "patches changed on both sides: \`p1\`$" err
+    )
+'
+
+test_expect_success 'Force fetch of diverged stack' '
+    (
+        cd clone &&
+        stg stack fetch --force origin &&
+        test "$(echo $(stg series --noprefix --all))" = "p1 p2 p3 p5 p4" &&
+        test "$(git log -1 --format=%s $(stg id p1))" = "patch one from origin" &&
+        test "$(git log -1 --format=%s $(stg id p2))" = "patch 2" &&
+        test_must_fail git rev-parse --verify --quiet refs/patches/dev/p6 &&
+        stg stack push origin 2>err &&
+        grep "already up to date" err
+    )
+'
+
+test_expect_success 'Force push of diverged stack' '
+    stg edit -m "patch three from origin" p3 &&
+    stg stack push origin &&
+    (
+        cd clone &&
+        stg delete p4 &&
+        command_error stg stack push origin 2>err &&
+        grep "have diverged$" err &&
+        stg stack push --force origin
+    ) &&
+    test "$(remote_id refs/stacks/dev)" = "$(git -C clone rev-parse refs/stacks/dev)" &&
+    ! remote_id refs/patches/dev/p4
+'
+
+test_done
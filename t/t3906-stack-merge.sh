@@ -0,0 +1,165 @@
+#!/bin/sh
+
+test_description='Test stg stack merge'
+
+. ./test-lib.sh
+
+test_expect_success 'Initialize shared stack' '
+    git init --bare remote.git &&
+    git remote add origin remote.git &&
+    test_commit_bulk --message="base %s" 1 &&
+    git push origin master &&
+    stg branch --create dev &&
+    for i in 1 2 3; do
+        stg new -m "patch $i" p$i &&
+        test_write_lines $i $i $i >file$i &&
+        stg add file$i &&
+        stg refresh || return 1
+    done &&
+    stg stack push origin &&
+    git clone remote.git clone &&
+    (
+        cd clone &&
+        stg stack fetch origin dev &&
+        stg branch dev
+    )
+'
+
+test_expect_success 'Merge state already contained in stack' '
+    stg stack merge refs/remote-stacks/origin/dev 2>err &&
+    grep "Stack \`dev\` already contains \`refs/remote-stacks/origin/dev\`" err &&
+    command_error stg stack merge HEAD 2>err &&
+    grep "\`HEAD\` is not a stack state" err
+'
+
+test_expect_success 'Merge changes to different patches' '
+    (
+        cd clone &&
+        stg edit -m "patch one from clone" p1 &&
+        stg new -m "patch 5" p5 &&
+        echo 5 >file5 &&
+        stg add file5 &&
+        stg refresh &&
+        stg stack push origin
+    ) &&
+    stg goto p2 &&
+    echo main >>file2 &&
+    stg refresh &&
+    command_error stg stack fetch origin 2>err &&
+    grep "have diverged$" err &&
+    stg stack merge refs/remote-stacks/origin/dev &&
+    test "$(echo $(stg series --noprefix --applied))" = "p1 p2 p5" &&
+    test "$(echo $(stg series --noprefix --unapplied))" = "p3" &&
+    test "$(git log -1 --format=%s $(stg id p1))" = "patch one from clone" &&
+    test_write_lines 2 2 2 main >expected &&
+    test_cmp expected file2 &&
+    test_path_is_file file5 &&
+    test "$(stg id p2)" = "$(git rev-parse $(stg id p5)~1)"
+'
+
+test_expect_success 'Merged stack may be pushed and fetched' '
+    stg stack push origin 2>err &&
+    grep "Pushed stack \`dev\` to \`origin\`" err &&
+    (
+        cd clone &&
+        stg stack fetch origin &&
+        test "$(echo $(stg series --noprefix --applied))" = "p1 p2 p5" &&
+        test_write_lines 2 2 2 main >expected &&
+        test_cmp expected file2
+    ) &&
+    stg stack merge refs/remote-stacks/origin/dev 2>err &&
+    grep "already contains" err
+'
+
+test_expect_success 'Merge changes to the same patch' '
+    (
+        cd clone &&
+        stg goto p1 &&
+        echo clone >other1 &&
+        stg add other1 &&
+        stg refresh &&
+        stg goto p5 &&
+        stg stack push origin
+    ) &&
+    stg goto p1 &&
+    test_write_lines main 1 1 >file1 &&
+    stg refresh &&
+    stg goto p5 &&
+    command_error stg stack fetch origin 2>err &&
+    grep "patches changed on both sides: \`p1\`$" err &&
+    stg stack merge refs/remote-stacks/origin/dev &&
+    test "$(stg top)" = "p5" &&
+    test "$(echo $(stg files --bare p1))" = "file1 other1" &&
+    test_write_lines main 1 1 >expected &&
+    test_cmp expected file1 &&
+    test "$(cat other1)" = "clone"
+'
+
+test_expect_success 'Merge hidden and deleted patches' '
+    stg stack push origin &&
+    (
+        cd clone &&
+        stg stack fetch origin &&
+        stg delete p3 &&
+        stg stack push origin
+    ) &&
+    stg pop p5 &&
+    stg hide p5 &&
+    command_error stg stack fetch origin &&
+    stg stack merge refs/remote-stacks/origin/dev &&
+    test "$(echo $(stg series --noprefix --all))" = "p1 p2 p5" &&
+    test "$(echo $(stg series --noprefix --hidden))" = "p5"
+'
+
+test_expect_success 'Conflicting changes are refused' '
+    stg stack push origin &&
+    (
+        cd clone &&
+        stg stack fetch origin &&
+        stg goto p2 &&
+        test_write_lines 2 clone 2 main >file2 &&
+        stg refresh &&
+        stg stack push origin
+    ) &&
+    stg goto p2 &&
+    test_write_lines 2 ours 2 main >file2 &&
+    stg refresh &&
+    command_error stg stack fetch origin &&
+    command_error stg stack merge refs/remote-stacks/origin/dev 2>err &&
+    grep "patches changed on both sides cannot be merged: \`p2\`" err &&
+    test_write_lines 2 ours 2 main >expected &&
+    test_cmp expected file2
+'
+
+test_expect_success 'Resolve conflicting changes with ours' '
+    general_error stg stack merge --ours --theirs refs/remote-stacks/origin/dev &&
+    stg stack merge --ours refs/remote-stacks/origin/dev &&
+    test_write_lines 2 ours 2 main >expected &&
+    test_cmp expected file2 &&
+    stg stack push origin &&
+    (
+        cd clone &&
+        stg stack fetch origin &&
+        test_cmp ../expected file2
+    )
+'
+
+test_expect_success 'Resolve conflicting changes with theirs' '
+    (
+        cd clone &&
+        test_write_lines 2 theirs 2 main >file2 &&
+        stg refresh &&
+        stg stack push origin
+    ) &&
+    test_write_lines 2 ours again 2 main >file2 &&
+    stg refresh &&
+    command_error stg stack fetch origin &&
+    stg stack merge --theirs refs/remote-stacks/origin/dev &&
+    test_write_lines 2 theirs 2 main >expected &&
+    test_cmp expected file2 &&
+    stg undo &&
+    test_write_lines 2 ours again 2 main >expected &&
+    test_cmp expected file2
+'
+
+test_done
@@ -0,0 +1,140 @@
+#!/bin/sh
+
+test_description='Test StGit stack hooks'
+
+. ./test-lib.sh
+
+HOOKDIR="$(git rev-parse --git-dir)/hooks"
+HOOK_OUTPUT_FILE=$TRASH_DIRECTORY/hook-output
+
+test_expect_success 'Initialize StGit stack' '
+    test_commit_bulk --message="base %s" 2 &&
+    base=$(git rev-parse HEAD) &&
+    target=$(git rev-parse HEAD~1) &&
+    stg init &&
+    stg new -m p1 &&
+    echo a >a &&
+    stg add a &&
+    stg refresh &&
+    stg new -m p2 &&
+    echo b >b &&
+    stg add b &&
+    stg refresh &&
+    stg pop -a &&
+    mkdir -p "$HOOKDIR"
+'
+
+test_expect_success 'Pre-push hook vetoes push' '
+    write_script "$HOOKDIR/stg-pre-push" <<-\EOF &&
+	exit 1
+	EOF
+    command_error stg push p1 2>err &&
+    grep "\`stg-pre-push\` hook returned 1" err &&
+    test -z "$(stg series --applied --noprefix)" &&
+    test ! -e a
+'
+
+test_expect_success 'Pre-push and post-push hooks get pushed patches' '
+    write_script "$HOOKDIR/stg-pre-push" <<-EOF &&
+	cat >"$HOOK_OUTPUT_FILE-pre"
+	EOF
+    write_script "$HOOKDIR/stg-post-push" <<-EOF &&
+	cat >"$HOOK_OUTPUT_FILE-post"
+	EOF
+    stg push p1 p2 &&
+    cat >expected <<-EOF &&
+	$(stg id p1) $(stg id p1) p1
+	$(stg id p2) $(stg id p2) p2
+	EOF
+    test_cmp expected "$HOOK_OUTPUT_FILE-pre" &&
+    test_cmp expected "$HOOK_OUTPUT_FILE-post"
+'
+
+test_expect_success 'Post-push hook failure is a warning' '
+    stg pop p2 &&
+    write_script "$HOOKDIR/stg-post-push" <<-\EOF &&
+	exit 1
+	EOF
+    stg push p2 2>err &&
+    grep "warning: \`stg-post-push\` hook returned 1" err &&
+    test "$(stg top)" = "p2"
+'
+
+test_expect_success 'Pre-push hook vetoes goto' '
+    write_script "$HOOKDIR/stg-pre-push" <<-\EOF &&
+	exit 1
+	EOF
+    stg pop p2 &&
+    command_error stg goto p2 2>err &&
+    grep "\`stg-pre-push\` hook returned 1" err &&
+    test "$(stg top)" = "p1"
+'
+
+test_expect_success 'Push hooks get patches pushed by float' '
+    write_script "$HOOKDIR/stg-pre-push" <<-EOF &&
+	cat >"$HOOK_OUTPUT_FILE-pre"
+	EOF
+    write_script "$HOOKDIR/stg-post-push" <<-EOF &&
+	cat >"$HOOK_OUTPUT_FILE-post"
+	EOF
+    stg push p2 &&
+    old_p1=$(stg id p1) &&
+    old_p2=$(stg id p2) &&
+    stg float p1 &&
+    test "$(echo $(stg series --applied --noprefix))" = "p2 p1" &&
+    cat >expected <<-EOF &&
+	$old_p2 $(stg id p2) p2
+	$old_p1 $(stg id p1) p1
+	EOF
+    test_cmp expected "$HOOK_OUTPUT_FILE-pre" &&
+    test_cmp expected "$HOOK_OUTPUT_FILE-post" &&
+    stg float p2 &&
+    rm "$HOOKDIR/stg-pre-push" "$HOOKDIR/stg-post-push"
+'
+
+test_expect_success 'Post-refresh hook gets refreshed patch' '
+    write_script "$HOOKDIR/stg-post-refresh" <<-EOF &&
+	cat >"$HOOK_OUTPUT_FILE"
+	EOF
+    old_id=$(stg id p2) &&
+    echo bb >>b &&
+    stg refresh &&
+    echo "$old_id $(stg id p2) p2" >expected &&
+    test_cmp expected "$HOOK_OUTPUT_FILE"
+'
+
+test_expect_success 'Pre-rebase hook vetoes rebase' '
+    write_script "$HOOKDIR/stg-pre-rebase" <<-\EOF &&
+	exit 1
+	EOF
+    command_error stg rebase $target 2>err &&
+    grep "\`stg-pre-rebase\` hook returned 1" err &&
+    test "$(echo $(stg series --applied --noprefix))" = "p1 p2" &&
+    test "$(stg id {base})" = "$base"
+'
+
+test_expect_success 'Pre-rebase hook gets rebase target and patches' '
+    write_script "$HOOKDIR/stg-pre-rebase" <<-EOF &&
+	echo "\$1" >"$HOOK_OUTPUT_FILE"
+	cat >>"$HOOK_OUTPUT_FILE"
+	EOF
+    cat >expected <<-EOF &&
+	$target
+	$(stg id p1) $(stg id p1) p1
+	$(stg id p2) $(stg id p2) p2
+	EOF
+    stg rebase $target &&
+    test_cmp expected "$HOOK_OUTPUT_FILE" &&
+    test "$(stg id {base})" = "$target"
+'
+
+test_expect_success 'Post-new hook gets new patch' '
+    write_script "$HOOKDIR/stg-post-new" <<-EOF &&
+	cat >"$HOOK_OUTPUT_FILE"
+	EOF
+    stg new -m p3 &&
+    echo "$ZERO_OID $(stg id p3) p3" >expected &&
+    test_cmp expected "$HOOK_OUTPUT_FILE"
+'
+
+test_done